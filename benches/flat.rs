@@ -0,0 +1,131 @@
+use criterion::{black_box, criterion_group, Criterion};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use watson::*;
+
+// counts allocations so the two body representations can be compared by memory as well
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const MODULES: [(&str, &[u8]); 2] = [
+    (
+        "simulator",
+        include_bytes!("../examples/simulator/simulator.wasm"),
+    ),
+    ("wq", include_bytes!("../examples/wq/wq.wasm")),
+];
+
+fn code_blocks<'a>(p: &'a ProgramView) -> &'a [CodeBlock] {
+    p.sections
+        .iter()
+        .find_map(|s| match s {
+            Section::Code(c) => Some(&c.code_blocks[..]),
+            _ => None,
+        })
+        .unwrap_or(&[])
+}
+
+fn count_nested(instructions: &[Instruction]) -> usize {
+    let mut count = 0;
+    for i in instructions.iter() {
+        count += 1;
+        match i {
+            Instruction::Block(_, body) | Instruction::Loop(_, body) => count += count_nested(body),
+            Instruction::If(_, then, otherwise) => {
+                count += count_nested(then);
+                if let Some(otherwise) = otherwise {
+                    count += count_nested(otherwise);
+                }
+            }
+            _ => {}
+        }
+    }
+    count
+}
+
+fn count_flat(body: &FlatBody) -> usize {
+    body.instructions()
+        .iter()
+        .filter(|i| !matches!(i, FlatInstruction::Else { .. } | FlatInstruction::End))
+        .count()
+}
+
+// the allocations made and the bytes still held by what `f` returns
+fn measure<T>(f: impl FnOnce() -> T) -> (usize, usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let live = LIVE_BYTES.load(Ordering::Relaxed);
+    let x = f();
+    let used = (
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        LIVE_BYTES.load(Ordering::Relaxed) - live,
+    );
+    drop(x);
+    used
+}
+
+fn report_memory() {
+    for (name, bytes) in MODULES.iter() {
+        let nested = measure(|| parse(bytes).unwrap());
+        let flat = measure(|| parse_flat(bytes).unwrap());
+        println!(
+            "{}: nested {} allocations {} bytes, flat {} allocations {} bytes",
+            name, nested.0, nested.1, flat.0, flat.1
+        );
+    }
+}
+
+fn parsing(c: &mut Criterion) {
+    for (name, bytes) in MODULES.iter() {
+        c.bench_function(&format!("parse nested {}", name), |b| {
+            b.iter(|| parse(black_box(bytes)).unwrap())
+        });
+        c.bench_function(&format!("parse flat {}", name), |b| {
+            b.iter(|| parse_flat(black_box(bytes)).unwrap())
+        });
+    }
+}
+
+fn traversal(c: &mut Criterion) {
+    for (name, bytes) in MODULES.iter() {
+        let p = parse(bytes).unwrap();
+        let flat = parse_flat(bytes).unwrap();
+        let bodies = flat.bodies();
+        c.bench_function(&format!("traverse nested {}", name), |b| {
+            b.iter(|| {
+                code_blocks(black_box(&p))
+                    .iter()
+                    .map(|x| count_nested(&x.instructions))
+                    .sum::<usize>()
+            })
+        });
+        c.bench_function(&format!("traverse flat {}", name), |b| {
+            b.iter(|| black_box(bodies).iter().map(count_flat).sum::<usize>())
+        });
+    }
+}
+
+criterion_group!(benches, parsing, traversal);
+
+fn main() {
+    report_memory();
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
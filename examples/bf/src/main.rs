@@ -1,6 +1,5 @@
 use std::{env, error::Error, fs, process::exit};
 use watson::*;
-use webassembly::*;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
@@ -8,36 +7,55 @@ fn main() -> Result<(), Box<dyn Error>> {
         let buffer = fs::read(&args[1])?;
         let s = std::str::from_utf8(&buffer)?;
 
-        let mut bracket_check = 0; // used to make sure [ and ] are balanced
-
         let mut p = Program::new();
         let fn_output_byte = p.create_import("output_byte", &[ValueType::I32], &[])?;
         let fn_input_byte = p.create_import("input_byte", &[], &[ValueType::I32])?;
         p.create_memory("memory", 32, None)?;
-        let (main_code, _) = p.create_export("main", &[], &[])?;
+        let mut main = p.function_builder(&[], &[]);
+        let ptr = main.local(ValueType::I32);
+
+        let mut chars = s.chars();
+        if compile(&mut main, &mut chars, ptr, fn_output_byte, fn_input_byte) != Ok(false) {
+            eprintln!("invalid program, brackets don't match count.");
+            exit(1);
+        }
+        p.add_exported_function("main", main)?;
 
-        main_code.locals.push(LocalCount {
-            count: 1,
-            value_type: ValueType::I32,
-        });
+        fs::write(&args[2], &p.compile().map_err(|e| e.to_string())?)?;
+    } else {
+        println!("bf <input.bf> <output.wasm>")
+    }
+    Ok(())
+}
 
-        for c in s.chars() {
-            match c {
-                //	++ptr/--ptr
-                x @ '>' | x @ '<' => main_code.instructions.extend_from_slice(&[
-                    Instruction::LocalGet(0),
+// compiles until the end of the input or a closing bracket, returning whether it was a bracket
+fn compile(
+    f: &mut FunctionBuilder,
+    chars: &mut std::str::Chars,
+    ptr: LocalIdx,
+    fn_output_byte: FuncIdx,
+    fn_input_byte: FuncIdx,
+) -> Result<bool, &'static str> {
+    while let Some(c) = chars.next() {
+        match c {
+            //	++ptr/--ptr
+            x @ '>' | x @ '<' => {
+                f.emit_all(&[
+                    Instruction::LocalGet(ptr),
                     Instruction::I32Const(4),
                     if x == '>' {
                         Instruction::I32Add
                     } else {
                         Instruction::I32Sub
                     },
-                    Instruction::LocalSet(0),
-                ]),
-                // ++*ptr/--*ptr
-                x @ '+' | x @ '-' => main_code.instructions.extend_from_slice(&[
-                    Instruction::LocalGet(0),
-                    Instruction::LocalGet(0),
+                    Instruction::LocalSet(ptr),
+                ])?;
+            }
+            // ++*ptr/--*ptr
+            x @ '+' | x @ '-' => {
+                f.emit_all(&[
+                    Instruction::LocalGet(ptr),
+                    Instruction::LocalGet(ptr),
                     Instruction::I32Load(2, 0),
                     Instruction::I32Const(1),
                     if x == '+' {
@@ -46,54 +64,48 @@ fn main() -> Result<(), Box<dyn Error>> {
                         Instruction::I32Sub
                     },
                     Instruction::I32Store(2, 0),
-                ]),
-                //	putchar(*ptr)
-                '.' => main_code.instructions.extend_from_slice(&[
-                    Instruction::LocalGet(0),
+                ])?;
+            }
+            //	putchar(*ptr)
+            '.' => {
+                f.emit_all(&[
+                    Instruction::LocalGet(ptr),
                     Instruction::I32Load(2, 0),
                     Instruction::Call(fn_output_byte),
-                ]),
-                //	*ptr=getchar()
-                ',' => main_code.instructions.extend_from_slice(&[
-                    Instruction::LocalGet(0),
+                ])?;
+            }
+            //	*ptr=getchar()
+            ',' => {
+                f.emit_all(&[
+                    Instruction::LocalGet(ptr),
                     Instruction::Call(fn_input_byte),
                     Instruction::I32Store(2, 0),
-                ]),
-                //while (*ptr) {
-                '[' => {
-                    main_code.instructions.extend_from_slice(&[
-                        Instruction::Raw(BLOCK),
-                        Instruction::Raw(EMPTY),
-                        Instruction::Raw(LOOP),
-                        Instruction::Raw(EMPTY),
-                        Instruction::LocalGet(0),
-                        Instruction::I32Load(2, 0),
-                        Instruction::I32Eqz,
-                        Instruction::BrIf(1),
-                    ]);
-                    bracket_check += 1;
-                }
-                // }
-                ']' => {
-                    main_code.instructions.extend_from_slice(&[
-                        Instruction::Br(0),
-                        Instruction::Raw(END),
-                        Instruction::Raw(END),
-                    ]);
-                    bracket_check -= 1;
+                ])?;
+            }
+            //while (*ptr) { ... }
+            '[' => {
+                let mut closed = false;
+                f.block(None, |f, exit| {
+                    f.loop_(None, |f, top| {
+                        f.emit_all(&[
+                            Instruction::LocalGet(ptr),
+                            Instruction::I32Load(2, 0),
+                            Instruction::I32Eqz,
+                        ])?;
+                        f.br_if(exit)?;
+                        closed = compile(f, chars, ptr, fn_output_byte, fn_input_byte)?;
+                        f.br(top)?;
+                        Ok(())
+                    })?;
+                    Ok(())
+                })?;
+                if !closed {
+                    return Err("unclosed bracket");
                 }
-                _ => (),
             }
+            ']' => return Ok(true),
+            _ => (),
         }
-
-        if bracket_check != 0 {
-            eprintln!("invalid program, brackets don't match count.");
-            exit(1);
-        }
-
-        fs::write(&args[2], &p.compile())?;
-    } else {
-        println!("bf <input.bf> <output.wasm>")
     }
-    Ok(())
+    Ok(false)
 }
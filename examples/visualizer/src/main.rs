@@ -14,39 +14,39 @@ fn print_type_section(s: &TypeSection) {
 fn print_function_section(s: &FunctionSection) {
     println!("[{}]", "Function Section".purple());
     for i in 0..s.function_types.len() {
-        println!("{}: type[{:?}]", i, s.function_types[i]);
+        println!("{}: type[{}]", i, s.function_types[i]);
     }
 }
 
-fn print_export_section(s: &ExportSectionView) {
+fn print_export_section(s: &ExportSection) {
     println!("[{}]", "Export Section".purple());
     for i in 0..s.exports.len() {
         match &s.exports[i] {
-            WasmExportView::Function(f) => {
+            WasmExport::Function(f) => {
                 println!("{:?} function[{}]", f.name, f.index);
             }
-            WasmExportView::Memory(f) => {
+            WasmExport::Memory(f) => {
                 println!("{:?} memory[{}]", f.name, f.index);
             }
-            WasmExportView::Global(f) => {
+            WasmExport::Global(f) => {
                 println!("{:?} global[{}]", f.name, f.index);
             }
 
-            WasmExportView::Table(f) => {
+            WasmExport::Table(f) => {
                 println!("{:?} table[{}]", f.name, f.index);
             }
         }
     }
 }
 
-fn print_import_section(s: &ImportSectionView) {
+fn print_import_section(s: &ImportSection) {
     println!("[{}]", "Import Section".purple());
     for i in 0..s.imports.len() {
         match &s.imports[i] {
-            WasmImportView::Function(f) => {
+            WasmImport::Function(f) => {
                 println!("{:?}.{:?} fn type[{}]", f.module_name, f.name, f.type_index);
             }
-            WasmImportView::Memory(f) => {
+            WasmImport::Memory(f) => {
                 if f.max_pages.is_some() {
                     println!(
                         "{:?}.{:?} memory min {} max {}",
@@ -62,7 +62,7 @@ fn print_import_section(s: &ImportSectionView) {
                     );
                 }
             }
-            WasmImportView::Table(f) => {
+            WasmImport::Table(f) => {
                 if f.max.is_some() {
                     println!(
                         "{:?}.{:?} table \"ANYFUNC\" min {} max {}",
@@ -78,7 +78,7 @@ fn print_import_section(s: &ImportSectionView) {
                     );
                 }
             }
-            WasmImportView::Global(f) => {
+            WasmImport::Global(f) => {
                 if f.is_mutable {
                     println!(
                         "{:?}.{:?} global mut {:?}",
@@ -155,7 +155,7 @@ fn print_code_section(s: &CodeSection) {
     }
 }
 
-fn print_data_section(s: &DataSectionView) {
+fn print_data_section(s: &DataSection) {
     println!("[{}]", "Data Section".purple());
     for (i, d) in s.data_blocks.iter().enumerate() {
         println!(
@@ -175,34 +175,34 @@ fn print_element_section(s: &ElementSection) {
     }
 }
 
-fn print_custom_section(s: &CustomSectionView) {
+fn print_custom_section(s: &CustomSection) {
     println!("[{}]", "Custom Section".purple());
     println!("{}  data{:?}", s.name, s.data,);
 }
 
 fn print_start_section(s: &StartSection) {
     println!("[{}]", "Start Section".purple());
-    println!("{:?}", s.start_function);
+    println!("{}", s.start_function);
 }
 
-fn print_section(s: &SectionView) {
+fn print_section(s: &Section) {
     match s {
-        SectionView::Type(s) => print_type_section(&s),
-        SectionView::Function(s) => print_function_section(&s),
-        SectionView::Export(s) => print_export_section(&s),
-        SectionView::Code(s) => print_code_section(&s),
-        SectionView::Memory(s) => print_memory_section(&s),
-        SectionView::Start(s) => print_start_section(&s),
-        SectionView::Import(s) => print_import_section(&s),
-        SectionView::Table(s) => print_table_section(&s),
-        SectionView::Global(s) => print_global_section(&s),
-        SectionView::Data(s) => print_data_section(&s),
-        SectionView::Custom(s) => print_custom_section(&s),
-        SectionView::Element(s) => print_element_section(&s),
+        Section::Type(s) => print_type_section(&s),
+        Section::Function(s) => print_function_section(&s),
+        Section::Export(s) => print_export_section(&s),
+        Section::Code(s) => print_code_section(&s),
+        Section::Memory(s) => print_memory_section(&s),
+        Section::Start(s) => print_start_section(&s),
+        Section::Import(s) => print_import_section(&s),
+        Section::Table(s) => print_table_section(&s),
+        Section::Global(s) => print_global_section(&s),
+        Section::Data(s) => print_data_section(&s),
+        Section::Custom(s) => print_custom_section(&s),
+        Section::Element(s) => print_element_section(&s),
     }
 }
 
-fn print_program(program: &ProgramView) {
+fn print_program(program: &Module) {
     for s in program.sections.iter() {
         print_section(&s);
     }
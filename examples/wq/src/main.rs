@@ -12,7 +12,57 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     if args.len() < 2 {
         redirect = true;
     }
-    if args.len() == 2 {
+    if args.len() >= 3 && args[1] == "wat" {
+        let buffer = fs::read(&args[2])?;
+        let options = WatOptions {
+            folded: args.iter().skip(3).any(|x| x == "--folded"),
+        };
+        match parse(&buffer) {
+            Ok(p) => print!("{}", p.to_wat_with_options(&options)),
+            Err(e) => {
+                eprintln!("Error: {}", e.red());
+                process::exit(1);
+            }
+        };
+    } else if args.len() >= 3 && args[1] == "size" {
+        let buffer = fs::read(&args[2])?;
+        match parse(&buffer) {
+            Ok(p) => {
+                let profile = p.size_profile();
+                if args.iter().skip(3).any(|x| x == "--json") {
+                    match serde_json::to_string(&profile) {
+                        Ok(s) => println!("{}", s),
+                        Err(_) => {
+                            eprintln!("Error: failed to serialize");
+                            process::exit(1);
+                        }
+                    }
+                } else {
+                    print!("{}", profile);
+                }
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e.red());
+                process::exit(1);
+            }
+        };
+    } else if args.len() == 4 && args[1] == "diff" {
+        let old = fs::read(&args[2])?;
+        let new = fs::read(&args[3])?;
+        match (parse(&old), parse(&new)) {
+            (Ok(old), Ok(new)) => {
+                let report = diff(&old, &new);
+                print!("{}", report);
+                if !report.is_empty() {
+                    process::exit(1);
+                }
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("Error: {}", e.red());
+                process::exit(1);
+            }
+        };
+    } else if args.len() == 2 {
         let mut buffer = Vec::new();
         if redirect {
             for i in io::stdin().lock().bytes() {
@@ -23,7 +73,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
         match parse(&buffer) {
             Ok(p) => {
-                let json_string = match serde_json::to_string(&p.to_owned()) {
+                let json_string = match serde_json::to_string(&p) {
                     Ok(s) => s,
                     Err(_) => {
                         eprintln!("Error: failed to serialize");
@@ -39,14 +89,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         };
     } else if args.len() == 3 {
         let json = fs::read_to_string(&args[1])?;
-        let mut p: Program = match serde_json::from_str(&json) {
+        let p: Program = match serde_json::from_str(&json) {
             Ok(s) => s,
             Err(_) => {
                 eprintln!("Error: failed to deserialize");
                 process::exit(1);
             }
         };
-        fs::write(&args[2], p.compile())?;
+        let bytes = match p.compile() {
+            Ok(b) => b,
+            Err(e) => {
+                eprintln!("Error: {}", e.to_string().red());
+                process::exit(1);
+            }
+        };
+        fs::write(&args[2], bytes)?;
     } else {
         println!("wq <help> for help");
     }
@@ -0,0 +1,142 @@
+#!/usr/bin/env python3
+# Generates src/spec_tests/mod.rs with one test per script in spec/testsuite
+# (the official WebAssembly core testsuite) and spec/regression (our own).
+#
+#   python3 generate_spec_tests.py                      regenerate the tests
+#   python3 generate_spec_tests.py --fetch <revision>   vendor the testsuite at a commit first
+#   python3 generate_spec_tests.py --record-failures    run the tests and list what fails
+#
+# The testsuite is always fetched at a pinned commit, without one the commit in
+# spec/testsuite/REVISION is fetched again.
+#
+# Failures that are expected are listed in spec/known_failures.txt, either as
+# `testsuite/file.wast:line` for a single directive or `testsuite/file.wast`
+# for a script that is skipped entirely.
+
+import os
+import re
+import shutil
+import subprocess
+import sys
+import tempfile
+
+ROOT = os.path.dirname(os.path.abspath(__file__))
+SPEC = os.path.join(ROOT, "spec")
+TESTSUITE = os.path.join(SPEC, "testsuite")
+OUTPUT = os.path.join(ROOT, "src", "spec_tests", "mod.rs")
+KNOWN_FAILURES = os.path.join(SPEC, "known_failures.txt")
+TESTSUITE_REPO = "https://github.com/WebAssembly/testsuite.git"
+
+
+def fetch(revision):
+    with tempfile.TemporaryDirectory() as tmp:
+        subprocess.check_call(["git", "clone", "--quiet", TESTSUITE_REPO, tmp])
+        subprocess.check_call(["git", "-C", tmp, "checkout", "--quiet", revision])
+        commit = subprocess.check_output(["git", "-C", tmp, "rev-parse", "HEAD"])
+        if os.path.isdir(TESTSUITE):
+            shutil.rmtree(TESTSUITE)
+        os.makedirs(TESTSUITE)
+        # only the core scripts, proposals live in subdirectories
+        for name in sorted(os.listdir(tmp)):
+            if name.endswith(".wast"):
+                shutil.copy(os.path.join(tmp, name), TESTSUITE)
+        with open(os.path.join(TESTSUITE, "REVISION"), "w") as f:
+            f.write(commit.decode().strip() + "\n")
+
+
+def pinned_revision():
+    path = os.path.join(TESTSUITE, "REVISION")
+    if not os.path.isfile(path):
+        sys.exit("no revision given and spec/testsuite/REVISION does not exist")
+    with open(path) as f:
+        return f.read().strip()
+
+
+def scripts(directory):
+    path = os.path.join(SPEC, directory)
+    if not os.path.isdir(path):
+        return []
+    return sorted(
+        "%s/%s" % (directory, name) for name in os.listdir(path) if name.endswith(".wast")
+    )
+
+
+def test_name(script):
+    directory, name = script.split("/")
+    prefix = "spec" if directory == "testsuite" else directory
+    return prefix + "_" + re.sub(r"[^a-zA-Z0-9_]", "_", name[: -len(".wast")])
+
+
+def generate():
+    all_scripts = scripts("testsuite") + scripts("regression")
+    lines = [
+        "// generated by generate_spec_tests.py, do not edit",
+        "mod runner;",
+        "",
+        "use runner::run_spec_test;",
+    ]
+    for script in all_scripts:
+        arguments = '"%s", include_bytes!("../../spec/%s")' % (script, script)
+        call = "    run_spec_test(%s);" % arguments
+        # wrapped the way rustfmt would, arguments go on their own lines past 60 columns
+        if len(arguments) > 60 or len(call) > 100:
+            call = "\n".join(
+                [
+                    "    run_spec_test(",
+                    '        "%s",' % script,
+                    '        include_bytes!("../../spec/%s"),' % script,
+                    "    );",
+                ]
+            )
+        lines += ["", "#[test]", "fn %s() {" % test_name(script), call, "}"]
+    with open(OUTPUT, "w") as f:
+        f.write("\n".join(lines) + "\n")
+    known = 0
+    with open(KNOWN_FAILURES) as f:
+        for line in f:
+            line = line.strip()
+            if line and not line.startswith("#"):
+                known += 1
+    print("generated %d tests, %d known failures" % (len(all_scripts), known))
+    if not scripts("testsuite"):
+        print("spec/testsuite is empty, run with --fetch to vendor the testsuite")
+
+
+def record_failures():
+    # a run with nothing known shows every failure, scripts that do not parse are skipped whole
+    with open(KNOWN_FAILURES) as f:
+        header = [x for x in f.read().splitlines() if x.startswith("#")]
+    with open(KNOWN_FAILURES, "w") as f:
+        f.write("\n".join(header) + "\n")
+    run = subprocess.run(
+        ["cargo", "test", "--lib", "spec_tests", "--", "--test-threads=1"],
+        cwd=ROOT,
+        stdout=subprocess.PIPE,
+        stderr=subprocess.STDOUT,
+        env=dict(os.environ, RUST_MIN_STACK="268435456"),
+    )
+    output = run.stdout.decode()
+    failures = []
+    for line in output.splitlines():
+        unparsed = re.match(r".*?((testsuite|regression)/[^ :]+\.wast) could not be parsed", line)
+        directive = re.match(r"^((testsuite|regression)/[^ :]+\.wast:\d+) ", line)
+        if unparsed:
+            failures.append(unparsed.group(1))
+        elif directive:
+            failures.append(directive.group(1))
+    if run.returncode != 0 and not failures:
+        sys.exit(output)
+    with open(KNOWN_FAILURES, "a") as f:
+        for x in failures:
+            f.write(x + "\n")
+    print("recorded %d known failures" % len(failures))
+
+
+if __name__ == "__main__":
+    if "--fetch" in sys.argv:
+        i = sys.argv.index("--fetch")
+        given = sys.argv[i + 1 : i + 2]
+        fetch(given[0] if given and not given[0].startswith("--") else pinned_revision())
+    generate()
+    if "--record-failures" in sys.argv:
+        record_failures()
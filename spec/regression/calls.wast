@@ -0,0 +1,39 @@
+(module
+  (func $fib (export "fib") (param i64) (result i64)
+    (if (result i64) (i64.lt_u (local.get 0) (i64.const 2))
+      (then (local.get 0))
+      (else
+        (i64.add
+          (call $fib (i64.sub (local.get 0) (i64.const 1)))
+          (call $fib (i64.sub (local.get 0) (i64.const 2)))))))
+  (func $even (export "even") (param i32) (result i32)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (i32.const 1))
+      (else (call $odd (i32.sub (local.get 0) (i32.const 1))))))
+  (func $odd (param i32) (result i32)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (i32.const 0))
+      (else (call $even (i32.sub (local.get 0) (i32.const 1))))))
+  (func $swap (param i32 f64) (result f64 i32) (local.get 1) (local.get 0))
+  (func (export "args") (param i32 i64 f32 f64) (result f64)
+    (f64.add
+      (f64.add (f64.convert_i32_s (local.get 0)) (f64.convert_i64_s (local.get 1)))
+      (f64.add (f64.promote_f32 (local.get 2)) (local.get 3))))
+  (func (export "early") (param i32) (result i32)
+    (if (local.get 0) (then (return (i32.const 1))))
+    (i32.const 2))
+  (func $deep (export "deep") (param i32) (result i32)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (i32.const 0))
+      (else (i32.add (i32.const 1) (call $deep (i32.sub (local.get 0) (i32.const 1)))))))
+  (func $forever (export "forever") (param i32) (result i32)
+    (call $forever (local.get 0)))
+)
+(assert_return (invoke "fib" (i64.const 20)) (i64.const 6765))
+(assert_return (invoke "even" (i32.const 10)) (i32.const 1))
+(assert_return (invoke "even" (i32.const 7)) (i32.const 0))
+(assert_return (invoke "args" (i32.const 1) (i64.const 2) (f32.const 0.5) (f64.const 0.25)) (f64.const 3.75))
+(assert_return (invoke "early" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "early" (i32.const 0)) (i32.const 2))
+(assert_return (invoke "deep" (i32.const 1000)) (i32.const 1000))
+(assert_exhaustion (invoke "forever" (i32.const 0)) "call stack exhausted")
@@ -0,0 +1,76 @@
+(module
+  (type $i (func (param i32) (result i32)))
+  (table 2 funcref)
+  (elem (i32.const 0) $double $negate)
+  (func $double (param i32) (result i32) (i32.mul (local.get 0) (i32.const 2)))
+  (func $negate (param i32) (result i32) (i32.sub (i32.const 0) (local.get 0)))
+  (func (export "block-value") (result i32)
+    (block (result i32) (i32.const 1) (i32.const 2) (drop)))
+  (func (export "br-value") (param i32) (result i32)
+    (i32.add
+      (i32.const 100)
+      (block $outer (result i32)
+        (block $inner
+          (br_if $outer (i32.const 7) (local.get 0))
+          (br $inner))
+        (i32.const 9))))
+  (func (export "br-out-of-then") (param i32) (result i32)
+    (block $b (result i32)
+      (if (local.get 0)
+        (then (br $b (i32.const 1)) (unreachable))
+        (else (nop)))
+      (i32.const 2)))
+  (func (export "if-no-else") (param i32) (result i32) (local i32)
+    (if (local.get 0) (then (local.set 1 (i32.const 5))))
+    (i32.add (local.get 1) (i32.const 1)))
+  (func (export "if-value") (param i32) (result i32)
+    (if (result i32) (local.get 0)
+      (then (block (result i32) (i32.const 10)))
+      (else (loop (result i32) (i32.const 20)))))
+  (func (export "nested-loops") (param i32) (result i32) (local i32 i32)
+    (block $done
+      (loop $outer
+        (br_if $done (i32.ge_u (local.get 1) (local.get 0)))
+        (local.set 1 (i32.add (local.get 1) (i32.const 1)))
+        (block $skip
+          (loop $inner
+            (br_if $skip (i32.ge_u (local.get 2) (local.get 1)))
+            (local.set 2 (i32.add (local.get 2) (i32.const 1)))
+            (br $inner)))
+        (local.set 2 (i32.const 0))
+        (br $outer)))
+    (local.get 1))
+  (func (export "table") (param i32) (result i32)
+    (block $c (result i32)
+      (block $b (result i32)
+        (block $a (result i32)
+          (br_table $a $b $c (i32.const 5) (local.get 0)))
+        (return (i32.add (i32.const 100))))
+      (return (i32.add (i32.const 200))))
+    (i32.add (i32.const 300)))
+  (func (export "return-nested") (result i32)
+    (block (loop (block (return (i32.const 42))))) (i32.const 0))
+  (func (export "indirect") (param i32 i32) (result i32)
+    (call_indirect (type $i) (local.get 1) (local.get 0)))
+  (func (export "after-call") (param i32) (result i32)
+    (block (result i32) (i32.add (call $double (local.get 0)) (i32.const 1))))
+)
+(assert_return (invoke "block-value") (i32.const 1))
+(assert_return (invoke "br-value" (i32.const 1)) (i32.const 107))
+(assert_return (invoke "br-value" (i32.const 0)) (i32.const 109))
+(assert_return (invoke "br-out-of-then" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "br-out-of-then" (i32.const 0)) (i32.const 2))
+(assert_return (invoke "if-no-else" (i32.const 1)) (i32.const 6))
+(assert_return (invoke "if-no-else" (i32.const 0)) (i32.const 1))
+(assert_return (invoke "if-value" (i32.const 1)) (i32.const 10))
+(assert_return (invoke "if-value" (i32.const 0)) (i32.const 20))
+(assert_return (invoke "nested-loops" (i32.const 4)) (i32.const 4))
+(assert_return (invoke "table" (i32.const 0)) (i32.const 105))
+(assert_return (invoke "table" (i32.const 1)) (i32.const 205))
+(assert_return (invoke "table" (i32.const 2)) (i32.const 305))
+(assert_return (invoke "table" (i32.const 9)) (i32.const 305))
+(assert_return (invoke "return-nested") (i32.const 42))
+(assert_return (invoke "indirect" (i32.const 0) (i32.const 4)) (i32.const 8))
+(assert_return (invoke "indirect" (i32.const 1) (i32.const 4)) (i32.const -4))
+(assert_trap (invoke "indirect" (i32.const 2) (i32.const 4)) "undefined element")
+(assert_return (invoke "after-call" (i32.const 3)) (i32.const 7))
@@ -0,0 +1,43 @@
+(module
+  (global $a i32 (i32.const -2))
+  (global $b (mut i64) (i64.const 5))
+  (global $c (mut f32) (f32.const 1.5))
+  (global $d f64 (f64.const -0.25))
+  (global $e (mut i32) (i32.const 0))
+  (func (export "get_a") (result i32) (global.get $a))
+  (func (export "get_b") (result i64) (global.get $b))
+  (func (export "set_b") (param i64) (global.set $b (local.get 0)))
+  (func (export "add_c") (param f32) (result f32)
+    (global.set $c (f32.add (global.get $c) (local.get 0)))
+    (global.get $c))
+  (func (export "get_d") (result f64) (global.get $d))
+  (func (export "bump") (result i32)
+    (global.set $e (i32.add (global.get $e) (i32.const 1)))
+    (global.get $e))
+  (export "b" (global $b))
+  (export "e" (global $e))
+)
+(assert_return (invoke "get_a") (i32.const -2))
+(assert_return (invoke "get_b") (i64.const 5))
+(invoke "set_b" (i64.const -9))
+(assert_return (invoke "get_b") (i64.const -9))
+(assert_return (get "b") (i64.const -9))
+(assert_return (invoke "add_c" (f32.const 1.5)) (f32.const 3))
+(assert_return (invoke "get_d") (f64.const -0.25))
+(assert_return (invoke "bump") (i32.const 1))
+(assert_return (invoke "bump") (i32.const 2))
+(assert_return (get "e") (i32.const 2))
+
+(module $Host (global (export "seed") i32 (i32.const 40)))
+(register "host" $Host)
+(module
+  (import "host" "seed" (global $seed i32))
+  (import "spectest" "global_i64" (global $s i64))
+  (global $derived i32 (global.get $seed))
+  (func (export "derived") (result i32) (i32.add (global.get $derived) (i32.const 2)))
+  (func (export "spectest") (result i64) (global.get $s))
+)
+(assert_return (invoke "derived") (i32.const 42))
+(assert_return (invoke "spectest") (i64.const 666))
+(assert_unlinkable (module (import "host" "seed" (global i64))) "incompatible import type")
+(assert_unlinkable (module (import "host" "seed" (global (mut i32)))) "incompatible import type")
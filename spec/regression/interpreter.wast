@@ -0,0 +1,79 @@
+(module
+  (memory 1)
+  (global $g (mut i32) (i32.const 5))
+  (func (export "add") (param i32 i32) (result i32) local.get 0 local.get 1 i32.add)
+  (func (export "div") (param i32 i32) (result i32) (i32.div_s (local.get 0) (local.get 1)))
+  (func $fac (export "fac") (param i64) (result i64)
+    (if (result i64) (i64.eqz (local.get 0))
+      (then (i64.const 1))
+      (else (i64.mul (local.get 0) (call $fac (i64.sub (local.get 0) (i64.const 1)))))))
+  (func (export "sum") (param i32) (result i32) (local i32)
+    (block $done
+      (loop $l
+        (br_if $done (i32.eqz (local.get 0)))
+        (local.set 1 (i32.add (local.get 1) (local.get 0)))
+        (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+        (br $l)))
+    (local.get 1))
+  (func (export "pick") (param i32) (result i32)
+    (block $c (block $b (block $a (br_table $a $b $c (local.get 0)))
+      (return (i32.const 10))) (return (i32.const 11))) (i32.const 12))
+  (func (export "store") (param i32 i32) (i32.store (local.get 0) (local.get 1)))
+  (func (export "load") (param i32) (result i32) (i32.load (local.get 0)))
+  (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0)))
+  (func (export "nan") (result f32) (f32.div (f32.const 0) (f32.const 0)))
+  (func (export "inc") (global.set $g (i32.add (global.get $g) (i32.const 1))))
+  (func $r (export "recurse") (call $r))
+  (export "g" (global $g))
+)
+(assert_return (invoke "add" (i32.const 1) (i32.const 2)) (i32.const 3))
+(assert_return (invoke "add" (i32.const 0x7fffffff) (i32.const 1)) (i32.const 0x80000000))
+(assert_return (invoke "fac" (i64.const 20)) (i64.const 2432902008176640000))
+(assert_return (invoke "sum" (i32.const 10)) (i32.const 55))
+(assert_return (invoke "pick" (i32.const 0)) (i32.const 10))
+(assert_return (invoke "pick" (i32.const 1)) (i32.const 11))
+(assert_return (invoke "pick" (i32.const 7)) (i32.const 12))
+(assert_trap (invoke "div" (i32.const 1) (i32.const 0)) "integer divide by zero")
+(assert_trap (invoke "div" (i32.const 0x80000000) (i32.const -1)) "integer overflow")
+(invoke "store" (i32.const 8) (i32.const 42))
+(assert_return (invoke "load" (i32.const 8)) (i32.const 42))
+(assert_trap (invoke "load" (i32.const 65534)) "out of bounds memory access")
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "load" (i32.const 65534)) (i32.const 0))
+(assert_return (invoke "nan") (f32.const nan:canonical))
+(assert_return (invoke "nan") (f32.const nan:arithmetic))
+(invoke "inc")
+(assert_return (get "g") (i32.const 6))
+(assert_exhaustion (invoke "recurse") "call stack exhausted")
+
+(assert_malformed (module quote "(func (i32.const))") "unexpected token")
+(assert_invalid (module (func (local.get 3))) "unknown local")
+(assert_invalid
+  (module (global i32 (i32.const 0)) (func (global.set 0 (i32.const 1))))
+  "global is immutable"
+)
+
+(module $M
+  (func (export "seven") (result i32) (i32.const 7))
+  (func (export "load8") (param i32) (result i32) (i32.load8_u (local.get 0)))
+  (global (export "nine") i32 (i32.const 9))
+  (memory (export "mem") 1)
+)
+(register "M" $M)
+(module
+  (import "M" "seven" (func $seven (result i32)))
+  (import "M" "nine" (global i32))
+  (import "M" "mem" (memory 1))
+  (import "spectest" "print_i32" (func $print (param i32)))
+  (data (i32.const 0) "\2a")
+  (func (export "total") (result i32)
+    (call $print (i32.const 1))
+    (i32.add (call $seven) (global.get 0)))
+  (export "seven_again" (func $seven))
+)
+(assert_return (invoke "total") (i32.const 16))
+(assert_return (invoke "seven_again") (i32.const 7))
+(assert_return (invoke $M "load8" (i32.const 0)) (i32.const 42))
+(assert_unlinkable (module (import "M" "missing" (func))) "unknown import")
+(assert_unlinkable (module (import "M" "seven" (func (param i32)))) "incompatible import type")
+(assert_trap (module (func $f unreachable) (start $f)) "unreachable")
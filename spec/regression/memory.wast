@@ -0,0 +1,45 @@
+(module
+  (memory 1 3)
+  (data (i32.const 0) "\01\02\03\04\05\06\07\08\ff\ff")
+  (func (export "i32") (param i32) (result i32) (i32.load (local.get 0)))
+  (func (export "i64") (param i32) (result i64) (i64.load (local.get 0)))
+  (func (export "load8_s") (param i32) (result i32) (i32.load8_s (local.get 0)))
+  (func (export "load8_u") (param i32) (result i32) (i32.load8_u (local.get 0)))
+  (func (export "load16_s") (param i32) (result i32) (i32.load16_s (local.get 0)))
+  (func (export "load16_u") (param i32) (result i32) (i32.load16_u (local.get 0)))
+  (func (export "i64_load32_s") (param i32) (result i64) (i64.load32_s (local.get 0)))
+  (func (export "i64_load32_u") (param i32) (result i64) (i64.load32_u (local.get 0)))
+  (func (export "offset") (param i32) (result i32) (i32.load offset=4 (local.get 0)))
+  (func (export "store8") (param i32 i32) (i32.store8 (local.get 0) (local.get 1)))
+  (func (export "store16") (param i32 i64) (i64.store16 (local.get 0) (local.get 1)))
+  (func (export "f64") (param i32 f64) (result f64)
+    (f64.store (local.get 0) (local.get 1))
+    (f64.load (local.get 0)))
+  (func (export "size") (result i32) (memory.size))
+  (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0)))
+)
+(assert_return (invoke "i32" (i32.const 0)) (i32.const 0x04030201))
+(assert_return (invoke "i64" (i32.const 0)) (i64.const 0x0807060504030201))
+(assert_return (invoke "load8_s" (i32.const 8)) (i32.const -1))
+(assert_return (invoke "load8_u" (i32.const 8)) (i32.const 255))
+(assert_return (invoke "load16_s" (i32.const 8)) (i32.const -1))
+(assert_return (invoke "load16_u" (i32.const 8)) (i32.const 65535))
+(assert_return (invoke "i64_load32_s" (i32.const 6)) (i64.const 0xffffffffffff0807))
+(assert_return (invoke "i64_load32_u" (i32.const 6)) (i64.const 0xffff0807))
+(assert_return (invoke "offset" (i32.const 0)) (i32.const 0x08070605))
+(invoke "store8" (i32.const 0) (i32.const 0x1ff))
+(assert_return (invoke "load8_u" (i32.const 0)) (i32.const 0xff))
+(invoke "store16" (i32.const 0) (i64.const 0x12345))
+(assert_return (invoke "load16_u" (i32.const 0)) (i32.const 0x2345))
+(assert_return (invoke "f64" (i32.const 16) (f64.const 1.125)) (f64.const 1.125))
+(assert_trap (invoke "offset" (i32.const 65532)) "out of bounds memory access")
+(assert_trap (invoke "i32" (i32.const -1)) "out of bounds memory access")
+(assert_trap (invoke "store8" (i32.const 65536) (i32.const 0)) "out of bounds memory access")
+(assert_return (invoke "size") (i32.const 1))
+(assert_return (invoke "grow" (i32.const 2)) (i32.const 1))
+(assert_return (invoke "size") (i32.const 3))
+(assert_return (invoke "grow" (i32.const 1)) (i32.const -1))
+(assert_return (invoke "grow" (i32.const 0)) (i32.const 3))
+(assert_return (invoke "load8_u" (i32.const 196607)) (i32.const 0))
+
+(assert_trap (module (memory 1) (data (i32.const 65535) "ab")) "data segment does not fit")
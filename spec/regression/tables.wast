@@ -0,0 +1,22 @@
+(module
+  (type $unary (func (param i32) (result i32)))
+  (type $nullary (func (result i32)))
+  (table 4 funcref)
+  (elem (i32.const 1) $double $square $seven)
+  (func $double (type $unary) (i32.mul (local.get 0) (i32.const 2)))
+  (func $square (type $unary) (i32.mul (local.get 0) (local.get 0)))
+  (func $seven (type $nullary) (i32.const 7))
+  (func (export "unary") (param i32 i32) (result i32)
+    (call_indirect (type $unary) (local.get 1) (local.get 0)))
+  (func (export "nullary") (param i32) (result i32)
+    (call_indirect (type $nullary) (local.get 0)))
+)
+(assert_return (invoke "unary" (i32.const 1) (i32.const 5)) (i32.const 10))
+(assert_return (invoke "unary" (i32.const 2) (i32.const 5)) (i32.const 25))
+(assert_return (invoke "nullary" (i32.const 3)) (i32.const 7))
+(assert_trap (invoke "unary" (i32.const 3) (i32.const 5)) "indirect call type mismatch")
+(assert_trap (invoke "nullary" (i32.const 0)) "uninitialized element")
+(assert_trap (invoke "nullary" (i32.const 4)) "undefined element")
+(assert_trap (invoke "nullary" (i32.const -1)) "undefined element")
+
+(assert_trap (module (table 1 funcref) (func $f) (elem (i32.const 1) $f)) "elements segment does not fit")
@@ -0,0 +1,38 @@
+(assert_invalid (module (func (result i32) (i64.const 0))) "type mismatch")
+(assert_invalid (module (func (result i32) (i32.add (i32.const 1)))) "type mismatch")
+(assert_invalid (module (func (i32.const 1))) "type mismatch")
+(assert_invalid (module (func (result i32))) "type mismatch")
+(assert_invalid (module (func (param f32) (result f32) (f32.neg (local.get 1)))) "unknown local")
+(assert_invalid (module (func (if (i64.const 1) (then)))) "type mismatch")
+(assert_invalid (module (func (block (result i32) (i32.const 1) (i32.const 2)) drop)) "type mismatch")
+(assert_invalid (module (func (br 1))) "unknown label")
+(assert_invalid (module (func (param i64) (local.set 0 (i32.const 0)))) "type mismatch")
+(assert_invalid (module (global (mut f32) (f32.const 0)) (func (global.set 0 (i32.const 1)))) "type mismatch")
+(assert_invalid (module (func (drop (i32.load (i32.const 0))))) "unknown memory")
+(assert_invalid (module (memory 1) (func (drop (i32.load align=8 (i32.const 0))))) "alignment must not be larger than natural")
+(assert_invalid (module (func (call_indirect (i32.const 0)))) "unknown table")
+(assert_invalid (module (global i32 (i64.const 0))) "type mismatch")
+(assert_invalid (module (global i32 (i32.const 0) (i32.const 1))) "type mismatch")
+(assert_invalid (module (global i32 (i32.add (i32.const 0) (i32.const 1)))) "constant expression required")
+(assert_invalid (module (memory 1) (data (i64.const 0) "a")) "type mismatch")
+(assert_invalid (module (memory 2 1)) "size minimum must not be greater than maximum")
+(assert_invalid (module (memory 65537)) "size must be at most the limit")
+(assert_invalid (module (memory 1) (memory 1)) "multiple memories")
+(assert_invalid (module (func $f) (export "a" (func $f)) (export "a" (func $f))) "duplicate export name")
+(assert_invalid (module (func $f (param i32)) (start $f)) "start function")
+
+(module
+  (memory 1)
+  (global $g (mut i64) (i64.const 0))
+  (func (export "typed") (param i32) (result i64)
+    (block $out (result i64)
+      (br_if $out (i64.const 1) (local.get 0))
+      (drop)
+      (global.set $g (i64.extend_i32_u (local.get 0)))
+      (global.get $g)))
+  (func (export "unreachable_tail") (result i32)
+    (unreachable)
+    (i32.add))
+)
+(assert_return (invoke "typed" (i32.const 0)) (i64.const 0))
+(assert_return (invoke "typed" (i32.const 1)) (i64.const 1))
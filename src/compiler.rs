@@ -1,269 +1,485 @@
 use crate::core::*;
+use crate::parser::wasm::{wasm_expression, NAME_FUNCTION, NAME_LOCAL, NAME_MODULE};
+use alloc::string::String;
 use alloc::vec::Vec;
+use core::convert::TryFrom;
 use webassembly::*;
 
-impl WasmCompiler for Program {
-    fn compile(&mut self) -> Vec<u8> {
-        self.sections.sort_by(|a, b| a.id().cmp(&b.id()));
-        let mut program_bytes = vec![];
-        program_bytes.extend(MAGIC_NUMBER);
-        program_bytes.extend(VERSION_1);
-        for s in self.sections.iter() {
-            match s {
-                Section::Type(s) => {
-                    let mut sec_data = vec![];
-                    sec_data.extend(s.types.len().to_wasm_bytes());
-                    for t in s.types.iter() {
-                        sec_data.push(FUNC);
-                        sec_data.extend(t.inputs.len().to_wasm_bytes());
-                        for i in t.inputs.iter() {
-                            sec_data.push(i.into_wasm_byte());
-                        }
-                        sec_data.extend(t.outputs.len().to_wasm_bytes());
-                        for i in t.outputs.iter() {
-                            sec_data.push(i.into_wasm_byte());
-                        }
-                    }
-                    program_bytes.push(SECTION_TYPE);
-                    program_bytes.extend(sec_data.len().to_wasm_bytes());
-                    program_bytes.extend(sec_data);
+// a function body as it is stored in the code section, without its size in front
+pub(crate) fn code_block_bytes(c: &CodeBlock) -> Vec<u8> {
+    let mut code = vec![];
+    code.extend(c.locals.len().to_wasm_bytes());
+    for l in c.locals.iter() {
+        code.extend(l.count.to_wasm_bytes());
+        code.push(l.value_type.into_wasm_byte());
+    }
+    for i in c.instructions.iter() {
+        i.extend_wasm_bytes(&mut code);
+    }
+    code.push(END);
+    code
+}
+
+pub(crate) fn data_block_bytes(d: &DataBlock) -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend(d.memory.to_wasm_bytes());
+    for i in d.offset_expression.iter() {
+        i.extend_wasm_bytes(&mut bytes);
+    }
+    bytes.push(END);
+    bytes.extend(d.data.len().to_wasm_bytes());
+    bytes.extend(&*d.data);
+    bytes
+}
+
+// the id of a section and its contents, without the size in front
+pub(crate) fn section_bytes(s: &Section) -> (u8, Vec<u8>) {
+    match s {
+        Section::Type(s) => {
+            let mut sec_data = vec![];
+            sec_data.extend(s.types.len().to_wasm_bytes());
+            for t in s.types.iter() {
+                sec_data.push(FUNC);
+                sec_data.extend(t.inputs.len().to_wasm_bytes());
+                for i in t.inputs.iter() {
+                    sec_data.push(i.into_wasm_byte());
                 }
-                Section::Function(s) => {
-                    let mut sec_data = vec![];
-                    sec_data.extend(s.function_types.len().to_wasm_bytes());
-                    for f in s.function_types.iter() {
-                        sec_data.extend(f.to_wasm_bytes());
-                    }
-                    program_bytes.push(SECTION_FUNCTION);
-                    program_bytes.extend(sec_data.len().to_wasm_bytes());
-                    program_bytes.extend(sec_data);
+                sec_data.extend(t.outputs.len().to_wasm_bytes());
+                for i in t.outputs.iter() {
+                    sec_data.push(i.into_wasm_byte());
                 }
-                Section::Code(s) => {
-                    let mut sec_data = vec![];
-                    sec_data.extend(s.code_blocks.len().to_wasm_bytes());
-                    for c in s.code_blocks.iter() {
-                        let mut code = vec![];
-                        code.extend(c.locals.len().to_wasm_bytes());
-                        for l in c.locals.iter() {
-                            code.extend(l.count.to_wasm_bytes());
-                            code.push(l.value_type.into_wasm_byte());
-                        }
-                        for i in c.instructions.iter() {
-                            i.extend_wasm_bytes(&mut code);
-                        }
-                        code.push(END);
-                        sec_data.extend(code.len().to_wasm_bytes());
-                        sec_data.extend(&code);
+            }
+            (SECTION_TYPE, sec_data)
+        }
+        Section::Function(s) => {
+            let mut sec_data = vec![];
+            sec_data.extend(s.function_types.len().to_wasm_bytes());
+            for f in s.function_types.iter() {
+                sec_data.extend(f.to_wasm_bytes());
+            }
+            (SECTION_FUNCTION, sec_data)
+        }
+        Section::Code(s) => {
+            let mut sec_data = vec![];
+            sec_data.extend(s.code_blocks.len().to_wasm_bytes());
+            for c in s.code_blocks.iter() {
+                let code = code_block_bytes(c);
+                sec_data.extend(code.len().to_wasm_bytes());
+                sec_data.extend(&code);
+            }
+            (SECTION_CODE, sec_data)
+        }
+        Section::Export(s) => {
+            let mut sec_data = vec![];
+            sec_data.extend(s.exports.len().to_wasm_bytes());
+            for i in s.exports.iter() {
+                match i {
+                    WasmExport::Function(f) => {
+                        sec_data.extend(f.name.len().to_wasm_bytes());
+                        sec_data.extend(f.name.as_bytes());
+                        sec_data.push(DESC_FUNCTION);
+                        sec_data.extend(f.index.to_wasm_bytes());
                     }
-                    program_bytes.push(SECTION_CODE);
-                    program_bytes.extend(sec_data.len().to_wasm_bytes());
-                    program_bytes.extend(sec_data);
-                }
-                Section::Export(s) => {
-                    let mut sec_data = vec![];
-                    sec_data.extend(s.exports.len().to_wasm_bytes());
-                    for i in s.exports.iter() {
-                        match i {
-                            WasmExport::Function(f) => {
-                                sec_data.extend(f.name.len().to_wasm_bytes());
-                                sec_data.extend(f.name.as_bytes());
-                                sec_data.push(DESC_FUNCTION);
-                                sec_data.extend(f.index.to_wasm_bytes());
-                            }
-                            WasmExport::Global(g) => {
-                                sec_data.extend(g.name.len().to_wasm_bytes());
-                                sec_data.extend(g.name.as_bytes());
-                                sec_data.push(DESC_GLOBAL);
-                                sec_data.extend(g.index.to_wasm_bytes());
-                            }
-                            WasmExport::Table(t) => {
-                                sec_data.extend(t.name.len().to_wasm_bytes());
-                                sec_data.extend(t.name.as_bytes());
-                                sec_data.push(DESC_TABLE);
-                                sec_data.extend(t.index.to_wasm_bytes());
-                            }
-                            WasmExport::Memory(m) => {
-                                sec_data.extend(m.name.len().to_wasm_bytes());
-                                sec_data.extend(m.name.as_bytes());
-                                sec_data.push(DESC_MEMORY);
-                                sec_data.extend(m.index.to_wasm_bytes());
-                            }
-                        }
+                    WasmExport::Global(g) => {
+                        sec_data.extend(g.name.len().to_wasm_bytes());
+                        sec_data.extend(g.name.as_bytes());
+                        sec_data.push(DESC_GLOBAL);
+                        sec_data.extend(g.index.to_wasm_bytes());
                     }
-                    program_bytes.push(SECTION_EXPORT);
-                    program_bytes.extend(sec_data.len().to_wasm_bytes());
-                    program_bytes.extend(sec_data);
-                }
-                Section::Import(s) => {
-                    let mut sec_data = vec![];
-                    sec_data.extend(s.imports.len().to_wasm_bytes());
-                    for i in s.imports.iter() {
-                        match i {
-                            WasmImport::Function(f) => {
-                                sec_data.extend(f.module_name.len().to_wasm_bytes());
-                                sec_data.extend(f.module_name.as_bytes());
-                                sec_data.extend(f.name.len().to_wasm_bytes());
-                                sec_data.extend(f.name.as_bytes());
-                                sec_data.push(DESC_FUNCTION);
-                                sec_data.extend(f.type_index.to_wasm_bytes());
-                            }
-                            WasmImport::Global(g) => {
-                                sec_data.extend(g.module_name.len().to_wasm_bytes());
-                                sec_data.extend(g.module_name.as_bytes());
-                                sec_data.extend(g.name.len().to_wasm_bytes());
-                                sec_data.extend(g.name.as_bytes());
-                                sec_data.push(DESC_GLOBAL);
-                                sec_data.push(g.value_type.into_wasm_byte());
-                                if g.is_mutable {
-                                    sec_data.push(MUTABLE);
-                                } else {
-                                    sec_data.push(IMMUTABLE);
-                                }
-                            }
-                            WasmImport::Table(t) => {
-                                sec_data.extend(t.module_name.len().to_wasm_bytes());
-                                sec_data.extend(t.module_name.as_bytes());
-                                sec_data.extend(t.name.len().to_wasm_bytes());
-                                sec_data.extend(t.name.as_bytes());
-                                sec_data.push(DESC_TABLE);
-                                sec_data.push(t.element_type);
-                                if t.max.is_some() {
-                                    sec_data.push(LIMIT_MIN_MAX);
-                                    sec_data.extend(t.min.to_wasm_bytes());
-                                    sec_data.extend(t.max.unwrap().to_wasm_bytes());
-                                } else {
-                                    sec_data.push(LIMIT_MIN);
-                                    sec_data.extend(t.min.to_wasm_bytes());
-                                }
-                            }
-                            WasmImport::Memory(m) => {
-                                sec_data.extend(m.module_name.len().to_wasm_bytes());
-                                sec_data.extend(m.module_name.as_bytes());
-                                sec_data.extend(m.name.len().to_wasm_bytes());
-                                sec_data.extend(m.name.as_bytes());
-                                sec_data.push(DESC_MEMORY);
-                                if m.max_pages.is_some() {
-                                    sec_data.push(LIMIT_MIN_MAX);
-                                    sec_data.extend(m.min_pages.to_wasm_bytes());
-                                    sec_data.extend(m.max_pages.unwrap().to_wasm_bytes());
-                                } else {
-                                    sec_data.push(LIMIT_MIN);
-                                    sec_data.extend(m.min_pages.to_wasm_bytes());
-                                }
-                            }
-                        }
+                    WasmExport::Table(t) => {
+                        sec_data.extend(t.name.len().to_wasm_bytes());
+                        sec_data.extend(t.name.as_bytes());
+                        sec_data.push(DESC_TABLE);
+                        sec_data.extend(t.index.to_wasm_bytes());
                     }
-                    program_bytes.push(SECTION_IMPORT);
-                    program_bytes.extend(sec_data.len().to_wasm_bytes());
-                    program_bytes.extend(sec_data);
-                }
-                Section::Memory(s) => {
-                    let mut sec_data = vec![];
-                    sec_data.extend(s.memories.len().to_wasm_bytes());
-                    for m in s.memories.iter() {
-                        if m.max_pages.is_some() {
-                            sec_data.push(LIMIT_MIN_MAX);
-                            sec_data.extend(m.min_pages.to_wasm_bytes());
-                            sec_data.extend(m.max_pages.unwrap().to_wasm_bytes());
-                        } else {
-                            sec_data.push(LIMIT_MIN);
-                            sec_data.extend(m.min_pages.to_wasm_bytes());
-                        }
+                    WasmExport::Memory(m) => {
+                        sec_data.extend(m.name.len().to_wasm_bytes());
+                        sec_data.extend(m.name.as_bytes());
+                        sec_data.push(DESC_MEMORY);
+                        sec_data.extend(m.index.to_wasm_bytes());
                     }
-                    program_bytes.push(SECTION_MEMORY);
-                    program_bytes.extend(sec_data.len().to_wasm_bytes());
-                    program_bytes.extend(sec_data);
                 }
-                Section::Start(s) => {
-                    let mut sec_data = vec![];
-                    sec_data.extend(s.start_function.to_wasm_bytes());
-                    program_bytes.push(SECTION_START);
-                    program_bytes.extend(sec_data.len().to_wasm_bytes());
-                    program_bytes.extend(sec_data);
-                }
-                Section::Global(s) => {
-                    let mut sec_data = vec![];
-                    sec_data.extend(s.globals.len().to_wasm_bytes());
-                    for g in s.globals.iter() {
+            }
+            (SECTION_EXPORT, sec_data)
+        }
+        Section::Import(s) => {
+            let mut sec_data = vec![];
+            sec_data.extend(s.imports.len().to_wasm_bytes());
+            for i in s.imports.iter() {
+                match i {
+                    WasmImport::Function(f) => {
+                        sec_data.extend(f.module_name.len().to_wasm_bytes());
+                        sec_data.extend(f.module_name.as_bytes());
+                        sec_data.extend(f.name.len().to_wasm_bytes());
+                        sec_data.extend(f.name.as_bytes());
+                        sec_data.push(DESC_FUNCTION);
+                        sec_data.extend(f.type_index.to_wasm_bytes());
+                    }
+                    WasmImport::Global(g) => {
+                        sec_data.extend(g.module_name.len().to_wasm_bytes());
+                        sec_data.extend(g.module_name.as_bytes());
+                        sec_data.extend(g.name.len().to_wasm_bytes());
+                        sec_data.extend(g.name.as_bytes());
+                        sec_data.push(DESC_GLOBAL);
                         sec_data.push(g.value_type.into_wasm_byte());
                         if g.is_mutable {
                             sec_data.push(MUTABLE);
                         } else {
                             sec_data.push(IMMUTABLE);
                         }
-                        for i in g.value_expression.iter() {
-                            i.extend_wasm_bytes(&mut sec_data);
-                        }
-                        sec_data.push(END);
                     }
-                    program_bytes.push(SECTION_GLOBAL);
-                    program_bytes.extend(sec_data.len().to_wasm_bytes());
-                    program_bytes.extend(sec_data);
-                }
-                Section::Table(s) => {
-                    let mut sec_data = vec![];
-                    sec_data.extend(s.tables.len().to_wasm_bytes());
-                    for t in s.tables.iter() {
-                        sec_data.push(ANYFUNC);
-                        if t.max.is_some() {
+                    WasmImport::Table(t) => {
+                        sec_data.extend(t.module_name.len().to_wasm_bytes());
+                        sec_data.extend(t.module_name.as_bytes());
+                        sec_data.extend(t.name.len().to_wasm_bytes());
+                        sec_data.extend(t.name.as_bytes());
+                        sec_data.push(DESC_TABLE);
+                        sec_data.push(t.element_type);
+                        if let Some(max) = t.max {
                             sec_data.push(LIMIT_MIN_MAX);
                             sec_data.extend(t.min.to_wasm_bytes());
-                            sec_data.extend(t.max.unwrap().to_wasm_bytes());
+                            sec_data.extend(max.to_wasm_bytes());
                         } else {
                             sec_data.push(LIMIT_MIN);
                             sec_data.extend(t.min.to_wasm_bytes());
                         }
                     }
-                    program_bytes.push(SECTION_TABLE);
-                    program_bytes.extend(sec_data.len().to_wasm_bytes());
-                    program_bytes.extend(sec_data);
-                }
-                Section::Data(s) => {
-                    let mut sec_data = vec![];
-                    sec_data.extend(s.data_blocks.len().to_wasm_bytes());
-                    for d in s.data_blocks.iter() {
-                        sec_data.extend(d.memory.to_wasm_bytes());
-                        for i in d.offset_expression.iter() {
-                            i.extend_wasm_bytes(&mut sec_data);
+                    WasmImport::Memory(m) => {
+                        sec_data.extend(m.module_name.len().to_wasm_bytes());
+                        sec_data.extend(m.module_name.as_bytes());
+                        sec_data.extend(m.name.len().to_wasm_bytes());
+                        sec_data.extend(m.name.as_bytes());
+                        sec_data.push(DESC_MEMORY);
+                        if let Some(max) = m.max_pages {
+                            sec_data.push(LIMIT_MIN_MAX);
+                            sec_data.extend(m.min_pages.to_wasm_bytes());
+                            sec_data.extend(max.to_wasm_bytes());
+                        } else {
+                            sec_data.push(LIMIT_MIN);
+                            sec_data.extend(m.min_pages.to_wasm_bytes());
                         }
-                        sec_data.push(END);
-                        sec_data.extend(d.data.len().to_wasm_bytes());
-                        sec_data.extend(&d.data);
                     }
-                    program_bytes.push(SECTION_DATA);
-                    program_bytes.extend(sec_data.len().to_wasm_bytes());
-                    program_bytes.extend(sec_data);
                 }
-                Section::Custom(s) => {
-                    let mut sec_data = vec![];
-                    sec_data.extend(s.name.len().to_wasm_bytes());
-                    sec_data.extend(s.name.as_bytes());
-                    sec_data.extend(&s.data);
-                    program_bytes.push(SECTION_CUSTOM);
-                    program_bytes.extend(sec_data.len().to_wasm_bytes());
-                    program_bytes.extend(sec_data);
+            }
+            (SECTION_IMPORT, sec_data)
+        }
+        Section::Memory(s) => {
+            let mut sec_data = vec![];
+            sec_data.extend(s.memories.len().to_wasm_bytes());
+            for m in s.memories.iter() {
+                if let Some(max) = m.max_pages {
+                    sec_data.push(LIMIT_MIN_MAX);
+                    sec_data.extend(m.min_pages.to_wasm_bytes());
+                    sec_data.extend(max.to_wasm_bytes());
+                } else {
+                    sec_data.push(LIMIT_MIN);
+                    sec_data.extend(m.min_pages.to_wasm_bytes());
                 }
-                Section::Element(s) => {
-                    let mut sec_data = vec![];
-                    sec_data.extend(s.elements.len().to_wasm_bytes());
-                    for e in s.elements.iter() {
-                        sec_data.extend(e.table.to_wasm_bytes());
-                        for i in e.value_expression.iter() {
-                            i.extend_wasm_bytes(&mut sec_data);
+            }
+            (SECTION_MEMORY, sec_data)
+        }
+        Section::Start(s) => {
+            let mut sec_data = vec![];
+            sec_data.extend(s.start_function.to_wasm_bytes());
+            (SECTION_START, sec_data)
+        }
+        Section::Global(s) => {
+            let mut sec_data = vec![];
+            sec_data.extend(s.globals.len().to_wasm_bytes());
+            for g in s.globals.iter() {
+                sec_data.push(g.value_type.into_wasm_byte());
+                if g.is_mutable {
+                    sec_data.push(MUTABLE);
+                } else {
+                    sec_data.push(IMMUTABLE);
+                }
+                for i in g.value_expression.iter() {
+                    i.extend_wasm_bytes(&mut sec_data);
+                }
+                sec_data.push(END);
+            }
+            (SECTION_GLOBAL, sec_data)
+        }
+        Section::Table(s) => {
+            let mut sec_data = vec![];
+            sec_data.extend(s.tables.len().to_wasm_bytes());
+            for t in s.tables.iter() {
+                sec_data.push(ANYFUNC);
+                if let Some(max) = t.max {
+                    sec_data.push(LIMIT_MIN_MAX);
+                    sec_data.extend(t.min.to_wasm_bytes());
+                    sec_data.extend(max.to_wasm_bytes());
+                } else {
+                    sec_data.push(LIMIT_MIN);
+                    sec_data.extend(t.min.to_wasm_bytes());
+                }
+            }
+            (SECTION_TABLE, sec_data)
+        }
+        Section::Data(s) => {
+            let mut sec_data = vec![];
+            sec_data.extend(s.data_blocks.len().to_wasm_bytes());
+            for d in s.data_blocks.iter() {
+                sec_data.extend(data_block_bytes(d));
+            }
+            (SECTION_DATA, sec_data)
+        }
+        Section::Custom(s) => {
+            let mut sec_data = vec![];
+            sec_data.extend(s.name.len().to_wasm_bytes());
+            sec_data.extend(s.name.as_bytes());
+            sec_data.extend(&*s.data);
+            (SECTION_CUSTOM, sec_data)
+        }
+        Section::Element(s) => {
+            let mut sec_data = vec![];
+            sec_data.extend(s.elements.len().to_wasm_bytes());
+            for e in s.elements.iter() {
+                sec_data.extend(e.table.to_wasm_bytes());
+                for i in e.value_expression.iter() {
+                    i.extend_wasm_bytes(&mut sec_data);
+                }
+                sec_data.push(END);
+                sec_data.extend(e.functions.len().to_wasm_bytes());
+                for f in e.functions.iter() {
+                    sec_data.extend(f.to_wasm_bytes());
+                }
+            }
+            (SECTION_ELEMENT, sec_data)
+        }
+    }
+}
+
+fn fits_u32(x: usize) -> Result<(), &'static str> {
+    if x > u32::MAX as usize {
+        Err("value does not fit in u32")
+    } else {
+        Ok(())
+    }
+}
+
+fn limits_fit(min: usize, max: Option<usize>) -> Result<(), &'static str> {
+    fits_u32(min)?;
+    max.map(fits_u32).unwrap_or(Ok(()))
+}
+
+// how many items the vector a section is encoded as holds
+fn item_count(s: &Section) -> usize {
+    match s {
+        Section::Type(x) => x.types.len(),
+        Section::Import(x) => x.imports.len(),
+        Section::Function(x) => x.function_types.len(),
+        Section::Table(x) => x.tables.len(),
+        Section::Memory(x) => x.memories.len(),
+        Section::Global(x) => x.globals.len(),
+        Section::Export(x) => x.exports.len(),
+        Section::Element(x) => x.elements.len(),
+        Section::Code(x) => x.code_blocks.len(),
+        Section::Data(x) => x.data_blocks.len(),
+        Section::Start(_) | Section::Custom(_) => 0,
+    }
+}
+
+fn is_block_type(t: u8) -> bool {
+    t == EMPTY || ValueType::try_from(t).is_ok()
+}
+
+struct Encodable {
+    result: Result<(), &'static str>,
+    has_raw: bool,
+}
+
+impl Visit for Encodable {
+    fn enter_block(&mut self, _kind: BlockKind, block_type: u8) {
+        if !is_block_type(block_type) {
+            self.result = Err("invalid block type");
+        }
+    }
+
+    fn visit_control(&mut self, instruction: &Instruction) {
+        if let Instruction::BrTable(labels, _) = instruction {
+            if fits_u32(labels.len()).is_err() {
+                self.result = Err("value does not fit in u32");
+            }
+        }
+    }
+
+    fn visit_raw(&mut self, _byte: u8) {
+        self.has_raw = true;
+    }
+}
+
+// what the encoding of an instruction sequence needs beyond its types
+fn encodable(instructions: &[Instruction]) -> Result<(), &'static str> {
+    let mut check = Encodable {
+        result: Ok(()),
+        has_raw: false,
+    };
+    walk_instructions(&mut check, instructions);
+    check.result?;
+    if check.has_raw {
+        // raw bytes have to leave something the parser can read back
+        let mut bytes = vec![];
+        for i in instructions.iter() {
+            i.extend_wasm_bytes(&mut bytes);
+        }
+        bytes.push(END);
+        match wasm_expression(&bytes) {
+            Ok(([], _)) => {}
+            _ => return Err("raw bytes do not decode"),
+        }
+    }
+    Ok(())
+}
+
+impl Module<'_> {
+    fn check_encodable(&self) -> Result<(), CompileError> {
+        let mut next_function = self.imported_function_count();
+        // the sizes of the index spaces so far, imports included
+        let mut spaces = [0usize; 4];
+        for (position, s) in self.sections.iter().enumerate() {
+            let error = |message| CompileError {
+                section: position,
+                function: None,
+                message,
+            };
+            fits_u32(item_count(s)).map_err(error)?;
+            match s {
+                Section::Import(i) => {
+                    for x in i.imports.iter() {
+                        let space = match x {
+                            WasmImport::Function(_) => 0,
+                            WasmImport::Global(_) => 1,
+                            WasmImport::Table(_) => 2,
+                            WasmImport::Memory(_) => 3,
+                        };
+                        spaces[space] += 1;
+                    }
+                }
+                Section::Function(f) => spaces[0] += f.function_types.len(),
+                Section::Global(g) => spaces[1] += g.globals.len(),
+                Section::Table(t) => spaces[2] += t.tables.len(),
+                Section::Memory(m) => spaces[3] += m.memories.len(),
+                _ => {}
+            }
+            for x in spaces.iter() {
+                fits_u32(*x).map_err(error)?;
+            }
+            match s {
+                Section::Import(i) => {
+                    for x in i.imports.iter() {
+                        match x {
+                            WasmImport::Memory(m) => limits_fit(m.min_pages, m.max_pages),
+                            WasmImport::Table(t) => limits_fit(t.min, t.max),
+                            _ => Ok(()),
                         }
-                        sec_data.push(END);
-                        sec_data.extend(e.functions.len().to_wasm_bytes());
-                        for f in e.functions.iter() {
-                            sec_data.extend(f.to_wasm_bytes());
+                        .map_err(error)?;
+                    }
+                }
+                Section::Memory(m) => {
+                    for x in m.memories.iter() {
+                        limits_fit(x.min_pages, x.max_pages).map_err(error)?;
+                    }
+                }
+                Section::Table(t) => {
+                    for x in t.tables.iter() {
+                        limits_fit(x.min, x.max).map_err(error)?;
+                    }
+                }
+                Section::Global(g) => {
+                    for x in g.globals.iter() {
+                        encodable(&x.value_expression).map_err(error)?;
+                    }
+                }
+                Section::Element(e) => {
+                    for x in e.elements.iter() {
+                        encodable(&x.value_expression).map_err(error)?;
+                    }
+                }
+                Section::Data(d) => {
+                    for x in d.data_blocks.iter() {
+                        encodable(&x.offset_expression).map_err(error)?;
+                        fits_u32(x.data.len()).map_err(error)?;
+                    }
+                }
+                Section::Code(c) => {
+                    for b in c.code_blocks.iter() {
+                        if let Err(message) = encodable(&b.instructions) {
+                            return Err(CompileError {
+                                section: position,
+                                function: Some(FuncIdx::at(next_function)),
+                                message,
+                            });
                         }
+                        next_function += 1;
                     }
-                    program_bytes.push(SECTION_ELEMENT);
-                    program_bytes.extend(sec_data.len().to_wasm_bytes());
-                    program_bytes.extend(sec_data);
                 }
+                _ => {}
             }
         }
-        program_bytes
+        Ok(())
+    }
+}
+
+impl WasmCompiler for Module<'_> {
+    fn compile_with_options(&self, options: &CompileOptions) -> Result<Vec<u8>, CompileError> {
+        if options.validate {
+            self.validate()?;
+        }
+        self.check_encodable()?;
+        // sections go out ordered by id, custom sections first
+        let mut order: Vec<&Section> = self.sections.iter().collect();
+        order.sort_by_key(|a| a.id());
+        let mut program_bytes = vec![];
+        program_bytes.extend(MAGIC_NUMBER);
+        program_bytes.extend(VERSION_1);
+        for s in order.into_iter() {
+            let (id, sec_data) = section_bytes(s);
+            program_bytes.push(id);
+            program_bytes.extend(sec_data.len().to_wasm_bytes());
+            program_bytes.extend(sec_data);
+        }
+        Ok(program_bytes)
+    }
+}
+
+fn name_map_bytes<T: TypeWasmExt>(names: &[(T, String)], v: &mut Vec<u8>) {
+    v.extend(names.len().to_wasm_bytes());
+    for (i, name) in names.iter() {
+        v.extend(i.to_wasm_bytes());
+        v.extend(name.len().to_wasm_bytes());
+        v.extend(name.as_bytes());
+    }
+}
+
+impl WriteWasm for NameSection {
+    fn extend_wasm_bytes(&self, v: &mut Vec<u8>) {
+        if let Some(name) = &self.module_name {
+            let mut sub_data = vec![];
+            sub_data.extend(name.len().to_wasm_bytes());
+            sub_data.extend(name.as_bytes());
+            v.push(NAME_MODULE);
+            v.extend(sub_data.len().to_wasm_bytes());
+            v.extend(sub_data);
+        }
+        if !self.function_names.is_empty() {
+            let mut sub_data = vec![];
+            name_map_bytes(&self.function_names, &mut sub_data);
+            v.push(NAME_FUNCTION);
+            v.extend(sub_data.len().to_wasm_bytes());
+            v.extend(sub_data);
+        }
+        if !self.local_names.is_empty() {
+            let mut sub_data = vec![];
+            sub_data.extend(self.local_names.len().to_wasm_bytes());
+            for (f, locals) in self.local_names.iter() {
+                sub_data.extend(f.to_wasm_bytes());
+                name_map_bytes(locals, &mut sub_data);
+            }
+            v.push(NAME_LOCAL);
+            v.extend(sub_data.len().to_wasm_bytes());
+            v.extend(sub_data);
+        }
     }
 }
 
@@ -335,6 +551,8 @@ impl WriteWasm for Instruction {
             Instruction::CallIndirect(i) => {
                 v.push(webassembly::CALL_INDIRECT);
                 v.extend(i.to_wasm_bytes());
+                // the table index, which is reserved and always 0
+                v.push(0);
             }
             Instruction::Drop => {
                 v.push(webassembly::DROP);
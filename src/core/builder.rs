@@ -0,0 +1,373 @@
+use super::common::*;
+use super::index::*;
+use super::instructions::*;
+use super::program::*;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use webassembly::EMPTY;
+
+/// A branch target handed to the closures of `block`, `loop_` and `if_else`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Label {
+    frame: usize,
+}
+
+struct Frame {
+    instructions: Vec<Instruction>,
+    label_arity: usize,
+    results: usize,
+    height: usize,
+    unreachable: bool,
+}
+
+impl Frame {
+    fn new(label_arity: usize, results: usize) -> Self {
+        Frame {
+            instructions: Vec::new(),
+            label_arity,
+            results,
+            height: 0,
+            unreachable: false,
+        }
+    }
+}
+
+/// Builds a function body out of structured control flow while keeping count of the operand
+/// stack, so a mismatched arity is reported by the call that introduced it.
+pub struct FunctionBuilder {
+    index: FuncIdx,
+    function_type: FunctionType,
+    locals: Vec<ValueType>,
+    functions: Vec<Option<FunctionType>>,
+    types: Vec<FunctionType>,
+    frames: Vec<Frame>,
+}
+
+fn block_type(result: Option<ValueType>) -> u8 {
+    match result {
+        Some(v) => v.into_wasm_byte(),
+        None => EMPTY,
+    }
+}
+
+impl FunctionBuilder {
+    fn new(
+        index: FuncIdx,
+        function_type: FunctionType,
+        functions: Vec<Option<FunctionType>>,
+        types: Vec<FunctionType>,
+    ) -> Self {
+        let results = function_type.outputs.len();
+        FunctionBuilder {
+            index,
+            function_type,
+            locals: Vec::new(),
+            functions,
+            types,
+            frames: vec![Frame::new(results, results)],
+        }
+    }
+
+    /// The index the function will have once it is added to the program it was created from.
+    pub fn index(&self) -> FuncIdx {
+        self.index
+    }
+
+    /// The parameters are the first locals.
+    pub fn param(&self, i: usize) -> Result<LocalIdx, &'static str> {
+        if i < self.function_type.inputs.len() {
+            Ok(LocalIdx::try_from(i)?)
+        } else {
+            Err("parameter does not exist with that index")
+        }
+    }
+
+    pub fn local(&mut self, value_type: ValueType) -> LocalIdx {
+        self.locals.push(value_type);
+        LocalIdx::at(self.function_type.inputs.len() + self.locals.len() - 1)
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        let len = self.frames.len();
+        &mut self.frames[len - 1]
+    }
+
+    fn label_depth(&self, label: Label) -> Result<u32, &'static str> {
+        if label.frame >= self.frames.len() {
+            return Err("label is not in scope");
+        }
+        Ok((self.frames.len() - 1 - label.frame) as u32)
+    }
+
+    fn label_arity(&self, depth: u32) -> Result<usize, &'static str> {
+        let depth = depth as usize;
+        if depth >= self.frames.len() {
+            return Err("branch depth is out of range");
+        }
+        Ok(self.frames[self.frames.len() - 1 - depth].label_arity)
+    }
+
+    fn operand_arity(&self, instruction: &Instruction) -> Result<(usize, usize), &'static str> {
+        Ok(match instruction {
+            Instruction::Raw(_) => return Err("raw instructions can not be used in a builder"),
+            Instruction::Br(d) => (self.label_arity(*d)?, 0),
+            Instruction::BrIf(d) => {
+                let n = self.label_arity(*d)?;
+                (n + 1, n)
+            }
+            Instruction::BrTable(labels, d) => {
+                let n = self.label_arity(*d)?;
+                for l in labels.iter() {
+                    if self.label_arity(*l)? != n {
+                        return Err("branch table labels have different arities");
+                    }
+                }
+                (n + 1, 0)
+            }
+            Instruction::Return => (self.function_type.outputs.len(), 0),
+            Instruction::Call(f) => match self.functions.get(f.index()) {
+                Some(Some(t)) => (t.inputs.len(), t.outputs.len()),
+                Some(None) => return Err("function type does not exist with that index"),
+                None => return Err("function does not exist with that index"),
+            },
+            Instruction::CallIndirect(t) => match self.types.get(t.index()) {
+                Some(t) => (t.inputs.len() + 1, t.outputs.len()),
+                None => return Err("function type does not exist with that index"),
+            },
+            Instruction::LocalGet(l) | Instruction::LocalSet(l) | Instruction::LocalTee(l) => {
+                if l.index() >= self.function_type.inputs.len() + self.locals.len() {
+                    return Err("local does not exist with that index");
+                }
+                instruction.stack_effect().unwrap()
+            }
+            _ => instruction.stack_effect().unwrap(),
+        })
+    }
+
+    fn pop(&mut self, count: usize) -> Result<(), &'static str> {
+        let frame = self.frame();
+        if frame.height >= count {
+            frame.height -= count;
+        } else if frame.unreachable {
+            frame.height = 0;
+        } else {
+            return Err("not enough operands on the stack");
+        }
+        Ok(())
+    }
+
+    /// Appends an instruction, checking it has the operands it needs.
+    pub fn emit(&mut self, instruction: Instruction) -> Result<&mut Self, &'static str> {
+        let (pops, pushes) = self.operand_arity(&instruction)?;
+        self.pop(pops)?;
+        let ends_flow = matches!(
+            instruction,
+            Instruction::Unreachable
+                | Instruction::Br(_)
+                | Instruction::BrTable(_, _)
+                | Instruction::Return
+        );
+        let frame = self.frame();
+        frame.height += pushes;
+        if ends_flow {
+            frame.height = 0;
+            frame.unreachable = true;
+        }
+        frame.instructions.push(instruction);
+        Ok(self)
+    }
+
+    pub fn emit_all(&mut self, instructions: &[Instruction]) -> Result<&mut Self, &'static str> {
+        for i in instructions.iter() {
+            self.emit(i.clone())?;
+        }
+        Ok(self)
+    }
+
+    fn body<F>(
+        &mut self,
+        label_arity: usize,
+        results: usize,
+        f: F,
+    ) -> Result<Vec<Instruction>, &'static str>
+    where
+        F: FnOnce(&mut FunctionBuilder, Label) -> Result<(), &'static str>,
+    {
+        let label = Label {
+            frame: self.frames.len(),
+        };
+        self.frames.push(Frame::new(label_arity, results));
+        let result = f(self, label);
+        let frame = self.frames.pop().unwrap();
+        result?;
+        if frame.height > frame.results || (frame.height < frame.results && !frame.unreachable) {
+            return Err("block does not leave its results on the stack");
+        }
+        Ok(frame.instructions)
+    }
+
+    fn push_structured(
+        &mut self,
+        results: usize,
+        instruction: Instruction,
+    ) -> Result<&mut Self, &'static str> {
+        let frame = self.frame();
+        frame.height += results;
+        frame.instructions.push(instruction);
+        Ok(self)
+    }
+
+    /// Emits a `block`, branching to its label jumps past the end of it.
+    pub fn block<F>(&mut self, result: Option<ValueType>, f: F) -> Result<&mut Self, &'static str>
+    where
+        F: FnOnce(&mut FunctionBuilder, Label) -> Result<(), &'static str>,
+    {
+        let results = result.iter().count();
+        let body = self.body(results, results, f)?;
+        self.push_structured(results, Instruction::Block(block_type(result), body))
+    }
+
+    /// Emits a `loop`, branching to its label jumps back to the start of it.
+    pub fn loop_<F>(&mut self, result: Option<ValueType>, f: F) -> Result<&mut Self, &'static str>
+    where
+        F: FnOnce(&mut FunctionBuilder, Label) -> Result<(), &'static str>,
+    {
+        let results = result.iter().count();
+        let body = self.body(0, results, f)?;
+        self.push_structured(results, Instruction::Loop(block_type(result), body))
+    }
+
+    /// Emits an `if` without an `else` arm, it pops its condition.
+    pub fn if_<F>(&mut self, f: F) -> Result<&mut Self, &'static str>
+    where
+        F: FnOnce(&mut FunctionBuilder, Label) -> Result<(), &'static str>,
+    {
+        self.pop(1)?;
+        let body = self.body(0, 0, f)?;
+        self.push_structured(0, Instruction::If(EMPTY, body, None))
+    }
+
+    /// Emits an `if` with an `else` arm, it pops its condition.
+    pub fn if_else<F, G>(
+        &mut self,
+        result: Option<ValueType>,
+        then: F,
+        otherwise: G,
+    ) -> Result<&mut Self, &'static str>
+    where
+        F: FnOnce(&mut FunctionBuilder, Label) -> Result<(), &'static str>,
+        G: FnOnce(&mut FunctionBuilder, Label) -> Result<(), &'static str>,
+    {
+        let results = result.iter().count();
+        self.pop(1)?;
+        let then_body = self.body(results, results, then)?;
+        let else_body = self.body(results, results, otherwise)?;
+        self.push_structured(
+            results,
+            Instruction::If(block_type(result), then_body, Some(else_body)),
+        )
+    }
+
+    pub fn br(&mut self, label: Label) -> Result<&mut Self, &'static str> {
+        let depth = self.label_depth(label)?;
+        self.emit(Instruction::Br(depth))
+    }
+
+    pub fn br_if(&mut self, label: Label) -> Result<&mut Self, &'static str> {
+        let depth = self.label_depth(label)?;
+        self.emit(Instruction::BrIf(depth))
+    }
+
+    pub fn br_table(
+        &mut self,
+        labels: &[Label],
+        default: Label,
+    ) -> Result<&mut Self, &'static str> {
+        let mut depths = Vec::new();
+        for l in labels.iter() {
+            depths.push(self.label_depth(*l)?);
+        }
+        let default = self.label_depth(default)?;
+        self.emit(Instruction::BrTable(depths, default))
+    }
+
+    /// Checks the body leaves the function results on the stack and hands back its parts.
+    pub fn finish(mut self) -> Result<(FunctionType, CodeBlock), &'static str> {
+        let frame = self.frames.pop().unwrap();
+        if frame.height > frame.results || (frame.height < frame.results && !frame.unreachable) {
+            return Err("function body does not leave its results on the stack");
+        }
+        let mut locals: Vec<LocalCount> = Vec::new();
+        for t in self.locals.into_iter() {
+            match locals.last_mut() {
+                Some(l) if l.value_type == t => l.count += 1,
+                _ => locals.push(LocalCount {
+                    count: 1,
+                    value_type: t,
+                }),
+            }
+        }
+        Ok((
+            self.function_type,
+            CodeBlock {
+                locals,
+                instructions: frame.instructions,
+            },
+        ))
+    }
+}
+
+impl Module<'_> {
+    /// Starts a function that can call every function the program has so far, and itself.
+    pub fn function_builder(&self, inputs: &[ValueType], outputs: &[ValueType]) -> FunctionBuilder {
+        let types = match self.sections.iter().find_map(|x| match x {
+            Section::Type(t) => Some(t),
+            _ => None,
+        }) {
+            Some(t) => t.types.clone(),
+            None => Vec::new(),
+        };
+        let mut functions: Vec<Option<FunctionType>> = self
+            .functions()
+            .map(|(_, f)| types.get(f.type_index().index()).cloned())
+            .collect();
+        let function_type = FunctionType {
+            inputs: inputs.to_vec(),
+            outputs: outputs.to_vec(),
+        };
+        let index = FuncIdx::at(functions.len());
+        functions.push(Some(function_type.clone()));
+        FunctionBuilder::new(index, function_type, functions, types)
+    }
+
+    pub fn add_function(&mut self, f: FunctionBuilder) -> Result<FuncIdx, &'static str> {
+        self.add_built_function(None, f)
+    }
+
+    pub fn add_exported_function(
+        &mut self,
+        name: &str,
+        f: FunctionBuilder,
+    ) -> Result<FuncIdx, &'static str> {
+        self.add_built_function(Some(name), f)
+    }
+
+    fn add_built_function(
+        &mut self,
+        name: Option<&str>,
+        f: FunctionBuilder,
+    ) -> Result<FuncIdx, &'static str> {
+        if self.functions().count() != f.index().index() {
+            return Err("functions were added while the function was being built");
+        }
+        let (function_type, code) = f.finish()?;
+        let (code_block, index) = match name {
+            Some(name) => {
+                self.create_export(name, &function_type.inputs, &function_type.outputs)?
+            }
+            None => self.create_function(&function_type.inputs, &function_type.outputs)?,
+        };
+        *code_block = code;
+        Ok(index)
+    }
+}
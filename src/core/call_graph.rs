@@ -0,0 +1,382 @@
+use super::common::*;
+use super::index::*;
+use super::index_space::*;
+use super::instructions::*;
+use super::program::*;
+use super::visit::*;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub enum CallKind {
+    /// A `call` instruction.
+    Direct,
+    /// A `call_indirect` that may reach the callee, because the callee is in an element
+    /// segment and has the signature the instruction expects.
+    Indirect,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub struct CallEdge {
+    pub caller: FuncIdx,
+    pub callee: FuncIdx,
+    pub kind: CallKind,
+}
+
+/// Which functions call which, over the whole function index space. Indirect calls are
+/// resolved conservatively, so every function that can be called has an edge.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub struct CallGraph {
+    edges: Vec<CallEdge>,
+    callees: Vec<Vec<FuncIdx>>,
+    callers: Vec<Vec<FuncIdx>>,
+    imported: Vec<bool>,
+    names: Vec<Option<String>>,
+    roots: Vec<FuncIdx>,
+}
+
+// the edges leaving the body of `caller`
+struct Calls<'a, 'b> {
+    caller: FuncIdx,
+    count: usize,
+    types: &'a [FunctionType],
+    table: &'b [(FuncIdx, Option<&'a FunctionType>)],
+    edges: &'b mut Vec<CallEdge>,
+}
+
+impl<'a, 'b> Visit for Calls<'a, 'b> {
+    fn visit_call(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::Call(callee) if callee.index() < self.count => self.edges.push(CallEdge {
+                caller: self.caller,
+                callee: *callee,
+                kind: CallKind::Direct,
+            }),
+            Instruction::CallIndirect(t) => {
+                let expected = self.types.get(t.index());
+                for (callee, signature) in self.table.iter() {
+                    if expected.is_some() && *signature == expected {
+                        self.edges.push(CallEdge {
+                            caller: self.caller,
+                            callee: *callee,
+                            kind: CallKind::Indirect,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// what the graph is built from, borrowed out of a module
+struct Parts<'a> {
+    types: &'a [FunctionType],
+    functions: Vec<FunctionRef<'a>>,
+    elements: Vec<&'a WasmElement>,
+    exports: Vec<(&'a str, FuncIdx)>,
+    start: Option<FuncIdx>,
+    table_is_shared: bool,
+    names: Option<NameSection>,
+}
+
+impl<'a> Parts<'a> {
+    fn from_module(program: &'a Module) -> Self {
+        let mut parts = Parts::new(program.functions().map(|(_, f)| f).collect());
+        for s in program.sections.iter() {
+            match s {
+                Section::Type(s) => parts.types = &s.types,
+                Section::Import(s) => {
+                    parts.table_is_shared |=
+                        s.imports.iter().any(|x| matches!(x, WasmImport::Table(_)))
+                }
+                Section::Export(s) => {
+                    for x in s.exports.iter() {
+                        match x {
+                            WasmExport::Function(f) => parts.exports.push((&f.name, f.index)),
+                            WasmExport::Table(_) => parts.table_is_shared = true,
+                            _ => {}
+                        }
+                    }
+                }
+                Section::Start(s) => parts.start = Some(s.start_function),
+                Section::Element(s) => parts.elements.extend(s.elements.iter()),
+                _ => {}
+            }
+        }
+        parts.names = program.name_section();
+        parts
+    }
+
+    fn new(functions: Vec<FunctionRef<'a>>) -> Self {
+        Parts {
+            types: &[],
+            functions,
+            elements: Vec::new(),
+            exports: Vec::new(),
+            start: None,
+            table_is_shared: false,
+            names: None,
+        }
+    }
+
+    fn signature(&self, f: FuncIdx) -> Option<&'a FunctionType> {
+        let types = self.types;
+        let function = self.functions.get(f.index())?;
+        types.get(function.type_index().index())
+    }
+
+    fn into_graph(self) -> CallGraph {
+        let count = self.functions.len();
+
+        // the functions a call_indirect can land on, with their signatures
+        let mut in_table: Vec<FuncIdx> = self
+            .elements
+            .iter()
+            .flat_map(|e| e.functions.iter().copied())
+            .filter(|f| f.index() < count)
+            .collect();
+        in_table.sort_by_key(|f| f.0);
+        in_table.dedup();
+        let table: Vec<(FuncIdx, Option<&FunctionType>)> =
+            in_table.iter().map(|f| (*f, self.signature(*f))).collect();
+
+        let mut edges = Vec::new();
+        for (i, f) in self.functions.iter().enumerate() {
+            let body = match f {
+                FunctionRef::Defined { body, .. } => body,
+                FunctionRef::Imported { .. } => continue,
+            };
+            let caller = FuncIdx::at(i);
+            let mut calls = Calls {
+                caller,
+                count,
+                types: self.types,
+                table: &table,
+                edges: &mut edges,
+            };
+            walk_instructions(&mut calls, body);
+        }
+        edges.sort_by_key(|e| (e.caller.0, e.callee.0, e.kind == CallKind::Indirect));
+        edges.dedup();
+
+        let mut callees = vec![Vec::new(); count];
+        let mut callers = vec![Vec::new(); count];
+        for e in edges.iter() {
+            let out: &mut Vec<FuncIdx> = &mut callees[e.caller.index()];
+            if out.last() != Some(&e.callee) {
+                out.push(e.callee);
+            }
+            callers[e.callee.index()].push(e.caller);
+        }
+        for c in callers.iter_mut() {
+            c.sort_by_key(|f: &FuncIdx| f.0);
+            c.dedup();
+        }
+
+        // the host can call exports, the start function and whatever is in a table it can see
+        let mut roots: Vec<FuncIdx> = self.exports.iter().map(|(_, f)| *f).collect();
+        roots.extend(self.start);
+        if self.table_is_shared {
+            roots.extend(in_table.iter().copied());
+        }
+        roots.retain(|f| f.index() < count);
+        roots.sort_by_key(|f| f.0);
+        roots.dedup();
+
+        let mut names: Vec<Option<String>> = vec![None; count];
+        if let Some(n) = self.names.as_ref() {
+            for (f, name) in n.function_names.iter() {
+                if let Some(x) = names.get_mut(f.index()) {
+                    *x = Some(name.clone());
+                }
+            }
+        }
+        for (name, f) in self.exports.iter().rev() {
+            if let Some(x) = names.get_mut(f.index()) {
+                *x = Some(name.to_string());
+            }
+        }
+
+        CallGraph {
+            edges,
+            callees,
+            callers,
+            imported: self.functions.iter().map(|f| f.is_imported()).collect(),
+            names,
+            roots,
+        }
+    }
+}
+
+impl CallGraph {
+    pub fn function_count(&self) -> usize {
+        self.callees.len()
+    }
+
+    /// Every edge ordered by caller and then callee.
+    pub fn edges(&self) -> &[CallEdge] {
+        &self.edges
+    }
+
+    /// The functions `f` may call, in index order.
+    pub fn callees(&self, f: FuncIdx) -> &[FuncIdx] {
+        self.callees.get(f.index()).map(|x| &x[..]).unwrap_or(&[])
+    }
+
+    /// The functions that may call `f`, in index order.
+    pub fn callers(&self, f: FuncIdx) -> &[FuncIdx] {
+        self.callers.get(f.index()).map(|x| &x[..]).unwrap_or(&[])
+    }
+
+    /// The functions the host can call: exports, the start function and the functions in an
+    /// imported or exported table.
+    pub fn roots(&self) -> &[FuncIdx] {
+        &self.roots
+    }
+
+    /// The export name of a function, or its name from the name section.
+    pub fn name(&self, f: FuncIdx) -> Option<&str> {
+        self.names.get(f.index()).and_then(|x| x.as_deref())
+    }
+
+    pub fn is_imported(&self, f: FuncIdx) -> bool {
+        self.imported.get(f.index()).copied().unwrap_or(false)
+    }
+
+    /// Which functions can run at all, indexed by function index.
+    pub fn reachable(&self) -> Vec<bool> {
+        self.reachable_from(&self.roots)
+    }
+
+    /// Which functions can run when only `roots` are called, indexed by function index.
+    pub fn reachable_from(&self, roots: &[FuncIdx]) -> Vec<bool> {
+        let mut reached = vec![false; self.function_count()];
+        let mut work: Vec<FuncIdx> = roots.to_vec();
+        while let Some(f) = work.pop() {
+            match reached.get_mut(f.index()) {
+                Some(r) if !*r => *r = true,
+                _ => continue,
+            }
+            work.extend(self.callees(f).iter().copied());
+        }
+        reached
+    }
+
+    /// The strongly connected components, every function is in exactly one of them. Callees
+    /// come before their callers unless they are in the same component.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<FuncIdx>> {
+        // tarjan's algorithm with an explicit stack, call chains can be deep
+        let count = self.function_count();
+        let mut order: Vec<Option<usize>> = vec![None; count];
+        let mut low = vec![0; count];
+        let mut on_stack = vec![false; count];
+        let mut stack = Vec::new();
+        let mut components = Vec::new();
+        let mut next = 0;
+        for start in 0..count {
+            if order[start].is_some() {
+                continue;
+            }
+            let mut frames = vec![(start, 0)];
+            order[start] = Some(next);
+            low[start] = next;
+            next += 1;
+            stack.push(start);
+            on_stack[start] = true;
+            while let Some((v, child)) = frames.last_mut() {
+                let v = *v;
+                if let Some(w) = self.callees[v].get(*child) {
+                    *child += 1;
+                    let w = w.index();
+                    match order[w] {
+                        None => {
+                            order[w] = Some(next);
+                            low[w] = next;
+                            next += 1;
+                            stack.push(w);
+                            on_stack[w] = true;
+                            frames.push((w, 0));
+                        }
+                        Some(o) if on_stack[w] => low[v] = low[v].min(o),
+                        Some(_) => {}
+                    }
+                    continue;
+                }
+                frames.pop();
+                if let Some((u, _)) = frames.last() {
+                    low[*u] = low[*u].min(low[v]);
+                }
+                if Some(low[v]) == order[v] {
+                    let mut component = Vec::new();
+                    while let Some(w) = stack.pop() {
+                        on_stack[w] = false;
+                        component.push(FuncIdx::at(w));
+                        if w == v {
+                            break;
+                        }
+                    }
+                    component.sort_by_key(|f| f.0);
+                    components.push(component);
+                }
+            }
+        }
+        components
+    }
+
+    /// The groups of functions that can call themselves, directly or through each other.
+    pub fn recursive_components(&self) -> Vec<Vec<FuncIdx>> {
+        self.strongly_connected_components()
+            .into_iter()
+            .filter(|c| c.len() > 1 || self.callees(c[0]).contains(&c[0]))
+            .collect()
+    }
+
+    /// The graph in Graphviz DOT format. Indirect edges are dashed, imported functions are
+    /// boxes and roots have a double border.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        out.push_str("digraph calls {\n");
+        for i in 0..self.function_count() {
+            let f = FuncIdx::at(i);
+            let mut label = f.to_string();
+            if let Some(name) = self.name(f) {
+                label.push(' ');
+                for c in name.chars() {
+                    if c == '"' || c == '\\' {
+                        label.push('\\');
+                    }
+                    label.push(c);
+                }
+            }
+            let _ = write!(out, "  f{} [label=\"{}\"", i, label);
+            if self.is_imported(f) {
+                out.push_str(", shape=box");
+            }
+            if self.roots.contains(&f) {
+                out.push_str(", peripheries=2");
+            }
+            out.push_str("];\n");
+        }
+        for e in self.edges.iter() {
+            let _ = write!(out, "  f{} -> f{}", e.caller, e.callee);
+            if e.kind == CallKind::Indirect {
+                out.push_str(" [style=dashed]");
+            }
+            out.push_str(";\n");
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl Module<'_> {
+    pub fn call_graph(&self) -> CallGraph {
+        Parts::from_module(self).into_graph()
+    }
+}
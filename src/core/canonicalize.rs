@@ -0,0 +1,133 @@
+use super::common::*;
+use super::program::*;
+use alloc::vec::Vec;
+
+// appends the items of a section to one of the same kind
+fn merge<'a>(into: &mut Section<'a>, from: Section<'a>) -> Result<(), &'static str> {
+    match (into, from) {
+        (Section::Type(a), Section::Type(b)) => a.types.extend(b.types),
+        (Section::Import(a), Section::Import(b)) => a.imports.extend(b.imports),
+        (Section::Function(a), Section::Function(b)) => a.function_types.extend(b.function_types),
+        (Section::Table(a), Section::Table(b)) => a.tables.extend(b.tables),
+        (Section::Memory(a), Section::Memory(b)) => a.memories.extend(b.memories),
+        (Section::Global(a), Section::Global(b)) => a.globals.extend(b.globals),
+        (Section::Export(a), Section::Export(b)) => a.exports.extend(b.exports),
+        (Section::Start(a), Section::Start(b)) => {
+            if a.start_function != b.start_function {
+                return Err("conflicting start functions");
+            }
+        }
+        (Section::Element(a), Section::Element(b)) => a.elements.extend(b.elements),
+        (Section::Code(a), Section::Code(b)) => a.code_blocks.extend(b.code_blocks),
+        (Section::Data(a), Section::Data(b)) => a.data_blocks.extend(b.data_blocks),
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn is_empty(s: &Section) -> bool {
+    match s {
+        Section::Type(s) => s.types.is_empty(),
+        Section::Import(s) => s.imports.is_empty(),
+        Section::Function(s) => s.function_types.is_empty(),
+        Section::Table(s) => s.tables.is_empty(),
+        Section::Memory(s) => s.memories.is_empty(),
+        Section::Global(s) => s.globals.is_empty(),
+        Section::Export(s) => s.exports.is_empty(),
+        Section::Element(s) => s.elements.is_empty(),
+        Section::Code(s) => s.code_blocks.is_empty(),
+        Section::Data(s) => s.data_blocks.is_empty(),
+        Section::Start(_) | Section::Custom(_) => false,
+    }
+}
+
+fn export_name<'a>(e: &'a WasmExport) -> &'a str {
+    match e {
+        WasmExport::Function(x) => &x.name,
+        WasmExport::Table(x) => &x.name,
+        WasmExport::Memory(x) => &x.name,
+        WasmExport::Global(x) => &x.name,
+    }
+}
+
+impl Module<'_> {
+    /// Normalizes how the module is laid out, so modules built in different orders compile to
+    /// the same bytes. Sections of the same kind are merged and ordered, empty ones dropped and
+    /// custom sections moved to the end. Types are deduplicated and ordered by first use with
+    /// unused ones removed, exports are sorted by name and runs of locals of the same type are
+    /// merged. Functions, globals, tables, memories and segments keep their order and function
+    /// bodies are left alone. On an error the module is left as it was.
+    pub fn canonicalize(&mut self) -> Result<(), &'static str> {
+        let mut sections: Vec<Section> = Vec::new();
+        let mut custom = Vec::new();
+        for s in self.sections.iter().cloned() {
+            if let Section::Custom(_) = s {
+                custom.push(s);
+                continue;
+            }
+            match sections.iter_mut().find(|x| x.id() == s.id()) {
+                Some(x) => merge(x, s)?,
+                None => sections.push(s),
+            }
+        }
+        sections.sort_by_key(|s| s.id());
+        let mut module = Module { sections };
+
+        let types = module
+            .sections
+            .iter_mut()
+            .find_map(|s| match s {
+                Section::Type(t) => Some(core::mem::take(&mut t.types)),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let mut first_uses = Vec::new();
+        module.for_each_reference_mut(IndexKind::Type, &mut |i| first_uses.push(*i));
+        let mut canonical: Vec<FunctionType> = Vec::new();
+        let mut map: Vec<Option<u32>> = vec![None; types.len()];
+        for i in first_uses.into_iter() {
+            let i = i as usize;
+            if i >= types.len() {
+                return Err("type does not exist with that index");
+            }
+            if map[i].is_none() {
+                let position = match canonical.iter().position(|t| *t == types[i]) {
+                    Some(p) => p,
+                    None => {
+                        canonical.push(types[i].clone());
+                        canonical.len() - 1
+                    }
+                };
+                map[i] = Some(position as u32);
+            }
+        }
+        module.remap_indices(IndexKind::Type, &|i| map[i as usize]);
+
+        for s in module.sections.iter_mut() {
+            match s {
+                Section::Type(t) => t.types = core::mem::take(&mut canonical),
+                Section::Export(e) => e.exports.sort_by(|a, b| export_name(a).cmp(export_name(b))),
+                Section::Code(c) => {
+                    for b in c.code_blocks.iter_mut() {
+                        let mut locals: Vec<LocalCount> = Vec::new();
+                        for l in b.locals.iter().filter(|l| l.count > 0) {
+                            match locals.last_mut() {
+                                Some(x) if x.value_type == l.value_type => {
+                                    x.count =
+                                        x.count.checked_add(l.count).ok_or("too many locals")?
+                                }
+                                _ => locals.push(l.clone()),
+                            }
+                        }
+                        b.locals = locals;
+                    }
+                }
+                _ => {}
+            }
+        }
+        module.sections.retain(|s| !is_empty(s));
+        module.sections.extend(custom);
+        *self = module;
+        Ok(())
+    }
+}
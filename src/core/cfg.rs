@@ -0,0 +1,386 @@
+use super::common::*;
+use super::index::*;
+use super::index_space::*;
+use super::instructions::*;
+use super::program::*;
+use crate::wat::instruction_text;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// A straight run of instructions. A structured instruction ends the block it is in, its
+/// bodies start blocks of their own.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct BasicBlock<'a> {
+    pub instructions: Vec<&'a Instruction>,
+    pub successors: Vec<usize>,
+    pub predecessors: Vec<usize>,
+}
+
+/// A loop found from a back edge to a block that dominates its source.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NaturalLoop {
+    pub header: usize,
+    /// The blocks with a back edge to the header.
+    pub latches: Vec<usize>,
+    /// Every block in the loop including the header, in order.
+    pub blocks: Vec<usize>,
+}
+
+/// The control flow graph of a function body. Block 0 is the entry and block 1 is an empty
+/// exit that returns and falling off the end of the body lead to. Blocks after an
+/// unconditional branch that nothing jumps to have no predecessors.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cfg<'a> {
+    pub blocks: Vec<BasicBlock<'a>>,
+    idom: Vec<Option<usize>>,
+}
+
+struct Builder<'a> {
+    blocks: Vec<BasicBlock<'a>>,
+    // the block a branch to each label goes to, innermost last
+    labels: Vec<usize>,
+}
+
+impl<'a> Builder<'a> {
+    fn block(&mut self) -> usize {
+        self.blocks.push(BasicBlock::default());
+        self.blocks.len() - 1
+    }
+
+    fn edge(&mut self, from: usize, to: usize) {
+        if !self.blocks[from].successors.contains(&to) {
+            self.blocks[from].successors.push(to);
+            self.blocks[to].predecessors.push(from);
+        }
+    }
+
+    fn label(&self, depth: u32) -> Option<usize> {
+        let depth = depth as usize;
+        if depth < self.labels.len() {
+            Some(self.labels[self.labels.len() - 1 - depth])
+        } else {
+            None
+        }
+    }
+
+    fn branch(&mut self, from: usize, depth: u32) {
+        if let Some(to) = self.label(depth) {
+            self.edge(from, to);
+        }
+    }
+
+    // returns the block control is in at the end of the body, None when it can not get there
+    fn body(&mut self, instructions: &'a [Instruction], start: usize) -> Option<usize> {
+        let mut current = Some(start);
+        for i in instructions.iter() {
+            let at = match current {
+                Some(at) => at,
+                None => self.block(),
+            };
+            self.blocks[at].instructions.push(i);
+            current = match i {
+                Instruction::Block(_, body) => {
+                    let inner = self.block();
+                    let after = self.block();
+                    self.edge(at, inner);
+                    self.labels.push(after);
+                    let end = self.body(body, inner);
+                    self.labels.pop();
+                    if let Some(end) = end {
+                        self.edge(end, after);
+                    }
+                    Some(after)
+                }
+                Instruction::Loop(_, body) => {
+                    let header = self.block();
+                    self.edge(at, header);
+                    self.labels.push(header);
+                    let end = self.body(body, header);
+                    self.labels.pop();
+                    let after = self.block();
+                    if let Some(end) = end {
+                        self.edge(end, after);
+                    }
+                    Some(after)
+                }
+                Instruction::If(_, then, otherwise) => {
+                    let then_start = self.block();
+                    let after = self.block();
+                    self.edge(at, then_start);
+                    self.labels.push(after);
+                    let mut ends = vec![self.body(then, then_start)];
+                    match otherwise {
+                        Some(otherwise) => {
+                            let else_start = self.block();
+                            self.edge(at, else_start);
+                            ends.push(self.body(otherwise, else_start));
+                        }
+                        None => self.edge(at, after),
+                    }
+                    self.labels.pop();
+                    for end in ends.into_iter().flatten() {
+                        self.edge(end, after);
+                    }
+                    Some(after)
+                }
+                Instruction::Br(d) => {
+                    self.branch(at, *d);
+                    None
+                }
+                Instruction::BrIf(d) => {
+                    self.branch(at, *d);
+                    let next = self.block();
+                    self.edge(at, next);
+                    Some(next)
+                }
+                Instruction::BrTable(labels, default) => {
+                    for d in labels.iter().chain(core::iter::once(default)) {
+                        self.branch(at, *d);
+                    }
+                    None
+                }
+                Instruction::Return => {
+                    self.edge(at, 1);
+                    None
+                }
+                Instruction::Unreachable => None,
+                _ => Some(at),
+            };
+        }
+        current
+    }
+}
+
+impl<'a> Cfg<'a> {
+    pub fn new(instructions: &'a [Instruction]) -> Self {
+        let mut b = Builder {
+            blocks: Vec::new(),
+            labels: Vec::new(),
+        };
+        let entry = b.block();
+        let exit = b.block();
+        // a branch to the outermost label returns
+        b.labels.push(exit);
+        if let Some(end) = b.body(instructions, entry) {
+            b.edge(end, exit);
+        }
+        let mut cfg = Cfg {
+            blocks: b.blocks,
+            idom: Vec::new(),
+        };
+        cfg.idom = cfg.compute_dominators();
+        cfg
+    }
+
+    pub fn entry(&self) -> usize {
+        0
+    }
+
+    pub fn exit(&self) -> usize {
+        1
+    }
+
+    /// The blocks reachable from the entry, each after all of its predecessors except the
+    /// ones reaching it through a back edge.
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut order = Vec::new();
+        let mut frames = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((b, next)) = frames.last_mut() {
+            let b = *b;
+            match self.blocks[b].successors.get(*next) {
+                Some(s) => {
+                    *next += 1;
+                    if !visited[*s] {
+                        visited[*s] = true;
+                        frames.push((*s, 0));
+                    }
+                }
+                None => {
+                    order.push(b);
+                    frames.pop();
+                }
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    // cooper, harvey and kennedy's iterative algorithm
+    fn compute_dominators(&self) -> Vec<Option<usize>> {
+        let order = self.reverse_postorder();
+        let mut position = vec![usize::MAX; self.blocks.len()];
+        for (i, b) in order.iter().enumerate() {
+            position[*b] = i;
+        }
+        let mut idom: Vec<Option<usize>> = vec![None; self.blocks.len()];
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for b in order.iter().skip(1) {
+                let mut new_idom: Option<usize> = None;
+                for p in self.blocks[*b].predecessors.iter() {
+                    if idom[*p].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => *p,
+                        Some(mut a) => {
+                            let mut c = *p;
+                            while a != c {
+                                while position[a] > position[c] {
+                                    a = idom[a].unwrap();
+                                }
+                                while position[c] > position[a] {
+                                    c = idom[c].unwrap();
+                                }
+                            }
+                            a
+                        }
+                    });
+                }
+                if new_idom != idom[*b] {
+                    idom[*b] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+        idom
+    }
+
+    /// The closest block every path from the entry to `block` goes through, None for the
+    /// entry and for blocks that can not be reached.
+    pub fn immediate_dominator(&self, block: usize) -> Option<usize> {
+        self.idom.get(block).copied().flatten()
+    }
+
+    /// Whether every path from the entry to `b` goes through `a`, a block dominates itself.
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        if !self.is_reachable(b) {
+            return false;
+        }
+        let mut x = Some(b);
+        while let Some(y) = x {
+            if y == a {
+                return true;
+            }
+            x = self.immediate_dominator(y);
+        }
+        false
+    }
+
+    /// The blocks each block immediately dominates, the children in the dominator tree.
+    pub fn dominator_tree(&self) -> Vec<Vec<usize>> {
+        let mut children = vec![Vec::new(); self.blocks.len()];
+        for (b, d) in self.idom.iter().enumerate() {
+            if let Some(d) = d {
+                children[*d].push(b);
+            }
+        }
+        children
+    }
+
+    pub fn is_reachable(&self, block: usize) -> bool {
+        block == 0 || self.immediate_dominator(block).is_some()
+    }
+
+    /// The natural loops ordered by header, back edges to the same header make one loop.
+    pub fn loops(&self) -> Vec<NaturalLoop> {
+        let mut loops: Vec<NaturalLoop> = Vec::new();
+        for (b, block) in self.blocks.iter().enumerate() {
+            if !self.is_reachable(b) {
+                continue;
+            }
+            for h in block.successors.iter() {
+                if !self.dominates(*h, b) {
+                    continue;
+                }
+                match loops.iter_mut().find(|l| l.header == *h) {
+                    Some(l) => l.latches.push(b),
+                    None => loops.push(NaturalLoop {
+                        header: *h,
+                        latches: vec![b],
+                        blocks: Vec::new(),
+                    }),
+                }
+            }
+        }
+        for l in loops.iter_mut() {
+            // everything that reaches a latch without going through the header, dead code
+            // that falls into the loop is not part of it
+            let mut inside = vec![false; self.blocks.len()];
+            inside[l.header] = true;
+            let mut work = l.latches.clone();
+            while let Some(b) = work.pop() {
+                if inside[b] || !self.is_reachable(b) {
+                    continue;
+                }
+                inside[b] = true;
+                work.extend(self.blocks[b].predecessors.iter().copied());
+            }
+            l.latches.sort_unstable();
+            l.blocks = (0..self.blocks.len()).filter(|b| inside[*b]).collect();
+        }
+        loops.sort_by_key(|l| l.header);
+        loops
+    }
+
+    /// The graph in Graphviz DOT format, blocks list their instructions and structured
+    /// instructions show only their first line.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        out.push_str("digraph cfg {\n  node [shape=box];\n");
+        for (i, b) in self.blocks.iter().enumerate() {
+            let mut label = match i {
+                0 => String::from("entry\\l"),
+                1 => String::from("exit\\l"),
+                _ => format!("b{}\\l", i),
+            };
+            for x in b.instructions.iter() {
+                for c in instruction_text(x).chars() {
+                    if c == '"' || c == '\\' {
+                        label.push('\\');
+                    }
+                    label.push(c);
+                }
+                label.push_str("\\l");
+            }
+            let _ = write!(out, "  b{} [label=\"{}\"", i, label);
+            if !self.is_reachable(i) {
+                out.push_str(", style=dashed");
+            }
+            out.push_str("];\n");
+        }
+        for (i, b) in self.blocks.iter().enumerate() {
+            for s in b.successors.iter() {
+                let _ = write!(out, "  b{} -> b{}", i, s);
+                if self.dominates(*s, i) {
+                    out.push_str(" [style=bold]");
+                }
+                out.push_str(";\n");
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl CodeBlock {
+    pub fn cfg(&self) -> Cfg<'_> {
+        Cfg::new(&self.instructions)
+    }
+}
+
+impl Module<'_> {
+    /// The control flow graph of a defined function.
+    pub fn cfg(&self, index: FuncIdx) -> Result<Cfg<'_>, &'static str> {
+        match self.function(index)? {
+            FunctionRef::Defined { body, .. } => Ok(Cfg::new(body)),
+            FunctionRef::Imported { .. } => Err("imported functions have no body"),
+        }
+    }
+}
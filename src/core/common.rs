@@ -1,8 +1,11 @@
+use super::index::*;
 use super::instructions::*;
+use alloc::borrow::Cow;
 use alloc::string::String;
 use alloc::vec::Vec;
 use core::convert::TryFrom;
 use core::convert::TryInto;
+use core::fmt;
 use serde::{Deserialize, Serialize};
 use webassembly::*;
 
@@ -30,6 +33,15 @@ impl ValueType {
     }
 }
 
+pub(crate) fn value_type_name(t: ValueType) -> &'static str {
+    match t {
+        ValueType::I32 => "i32",
+        ValueType::I64 => "i64",
+        ValueType::F32 => "f32",
+        ValueType::F64 => "f64",
+    }
+}
+
 impl TryFrom<u8> for ValueType {
     type Error = &'static str;
 
@@ -84,7 +96,7 @@ pub struct TypeSection {
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
 pub struct FunctionSection {
-    pub function_types: Vec<usize>,
+    pub function_types: Vec<TypeIdx>,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -109,62 +121,62 @@ pub struct CodeSection {
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
-pub struct Export {
-    pub name: String,
-    pub index: usize,
+pub struct Export<'a, T> {
+    pub name: Cow<'a, str>,
+    pub index: T,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 //#[serde(tag = "export_type", content = "content")]
 #[repr(C)]
-pub enum WasmExport {
+pub enum WasmExport<'a> {
     //#[serde(rename = "function")]
-    Function(Export),
+    Function(Export<'a, FuncIdx>),
     //#[serde(rename = "table")]
-    Table(Export),
+    Table(Export<'a, TableIdx>),
     //#[serde(rename = "memory")]
-    Memory(Export),
+    Memory(Export<'a, MemIdx>),
     //#[serde(rename = "global")]
-    Global(Export),
+    Global(Export<'a, GlobalIdx>),
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
-pub struct ExportSection {
-    pub exports: Vec<WasmExport>,
+pub struct ExportSection<'a> {
+    pub exports: Vec<WasmExport<'a>>,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
-pub struct FunctionImport {
-    pub module_name: String,
-    pub name: String,
-    pub type_index: usize,
+pub struct FunctionImport<'a> {
+    pub module_name: Cow<'a, str>,
+    pub name: Cow<'a, str>,
+    pub type_index: TypeIdx,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
-pub struct GlobalImport {
-    pub module_name: String,
-    pub name: String,
+pub struct GlobalImport<'a> {
+    pub module_name: Cow<'a, str>,
+    pub name: Cow<'a, str>,
     pub value_type: ValueType,
     pub is_mutable: bool,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
-pub struct MemoryImport {
-    pub module_name: String,
-    pub name: String,
+pub struct MemoryImport<'a> {
+    pub module_name: Cow<'a, str>,
+    pub name: Cow<'a, str>,
     pub min_pages: usize,
     pub max_pages: Option<usize>,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
-pub struct TableImport {
-    pub module_name: String,
-    pub name: String,
+pub struct TableImport<'a> {
+    pub module_name: Cow<'a, str>,
+    pub name: Cow<'a, str>,
     pub element_type: u8,
     pub min: usize,
     pub max: Option<usize>,
@@ -173,21 +185,21 @@ pub struct TableImport {
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(tag = "import_type", content = "content")]
 #[repr(C)]
-pub enum WasmImport {
+pub enum WasmImport<'a> {
     //#[serde(rename = "function")]
-    Function(FunctionImport),
+    Function(FunctionImport<'a>),
     //#[serde(rename = "global")]
-    Global(GlobalImport),
+    Global(GlobalImport<'a>),
     //#[serde(rename = "memory")]
-    Memory(MemoryImport),
+    Memory(MemoryImport<'a>),
     //#[serde(rename = "table")]
-    Table(TableImport),
+    Table(TableImport<'a>),
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
-pub struct ImportSection {
-    pub imports: Vec<WasmImport>,
+pub struct ImportSection<'a> {
+    pub imports: Vec<WasmImport<'a>>,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -206,7 +218,7 @@ pub struct MemorySection {
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
 pub struct StartSection {
-    pub start_function: usize,
+    pub start_function: FuncIdx,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -239,31 +251,39 @@ pub struct TableSection {
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
-pub struct DataBlock {
-    pub memory: usize,
+pub struct DataBlock<'a> {
+    pub memory: MemIdx,
     pub offset_expression: Vec<Instruction>,
-    pub data: Vec<u8>,
+    pub data: Cow<'a, [u8]>,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
-pub struct DataSection {
-    pub data_blocks: Vec<DataBlock>,
+pub struct DataSection<'a> {
+    pub data_blocks: Vec<DataBlock<'a>>,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
-pub struct CustomSection {
-    pub name: String,
-    pub data: Vec<u8>,
+pub struct CustomSection<'a> {
+    pub name: Cow<'a, str>,
+    pub data: Cow<'a, [u8]>,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[repr(C)]
+pub struct NameSection {
+    pub module_name: Option<String>,
+    pub function_names: Vec<(FuncIdx, String)>,
+    pub local_names: Vec<(FuncIdx, Vec<(LocalIdx, String)>)>,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
 pub struct WasmElement {
-    pub table: usize,
+    pub table: TableIdx,
     pub value_expression: Vec<Instruction>,
-    pub functions: Vec<usize>,
+    pub functions: Vec<FuncIdx>,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -275,7 +295,7 @@ pub struct ElementSection {
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(tag = "section_type", content = "content")]
 #[repr(C)]
-pub enum Section {
+pub enum Section<'a> {
     //#[serde(rename = "type")]
     Type(TypeSection),
     //#[serde(rename = "function")]
@@ -283,9 +303,9 @@ pub enum Section {
     //#[serde(rename = "code")]
     Code(CodeSection),
     //#[serde(rename = "export")]
-    Export(ExportSection),
+    Export(ExportSection<'a>),
     //#[serde(rename = "import")]
-    Import(ImportSection),
+    Import(ImportSection<'a>),
     //#[serde(rename = "memory")]
     Memory(MemorySection),
     //#[serde(rename = "start")]
@@ -295,14 +315,14 @@ pub enum Section {
     //#[serde(rename = "table")]
     Table(TableSection),
     //#[serde(rename = "data")]
-    Data(DataSection),
+    Data(DataSection<'a>),
     //#[serde(rename = "custom")]
-    Custom(CustomSection),
+    Custom(CustomSection<'a>),
     //#[serde(rename = "element")]
     Element(ElementSection),
 }
 
-impl Section {
+impl Section<'_> {
     pub fn id(&self) -> u32 {
         match self {
             Section::Custom(_) => 0,
@@ -321,8 +341,133 @@ impl Section {
     }
 }
 
+fn owned_str(s: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(s.into_owned())
+}
+
+fn owned_bytes(b: Cow<'_, [u8]>) -> Cow<'static, [u8]> {
+    Cow::Owned(b.into_owned())
+}
+
+impl<T> Export<'_, T> {
+    pub fn into_owned(self) -> Export<'static, T> {
+        Export {
+            name: owned_str(self.name),
+            index: self.index,
+        }
+    }
+}
+
+impl WasmExport<'_> {
+    pub fn into_owned(self) -> WasmExport<'static> {
+        match self {
+            WasmExport::Function(x) => WasmExport::Function(x.into_owned()),
+            WasmExport::Table(x) => WasmExport::Table(x.into_owned()),
+            WasmExport::Memory(x) => WasmExport::Memory(x.into_owned()),
+            WasmExport::Global(x) => WasmExport::Global(x.into_owned()),
+        }
+    }
+}
+
+impl WasmImport<'_> {
+    pub fn into_owned(self) -> WasmImport<'static> {
+        match self {
+            WasmImport::Function(x) => WasmImport::Function(FunctionImport {
+                module_name: owned_str(x.module_name),
+                name: owned_str(x.name),
+                type_index: x.type_index,
+            }),
+            WasmImport::Global(x) => WasmImport::Global(GlobalImport {
+                module_name: owned_str(x.module_name),
+                name: owned_str(x.name),
+                value_type: x.value_type,
+                is_mutable: x.is_mutable,
+            }),
+            WasmImport::Memory(x) => WasmImport::Memory(MemoryImport {
+                module_name: owned_str(x.module_name),
+                name: owned_str(x.name),
+                min_pages: x.min_pages,
+                max_pages: x.max_pages,
+            }),
+            WasmImport::Table(x) => WasmImport::Table(TableImport {
+                module_name: owned_str(x.module_name),
+                name: owned_str(x.name),
+                element_type: x.element_type,
+                min: x.min,
+                max: x.max,
+            }),
+        }
+    }
+}
+
+impl Section<'_> {
+    /// Copies the names and data the section borrows.
+    pub fn into_owned(self) -> Section<'static> {
+        match self {
+            Section::Type(s) => Section::Type(s),
+            Section::Function(s) => Section::Function(s),
+            Section::Code(s) => Section::Code(s),
+            Section::Export(s) => Section::Export(ExportSection {
+                exports: s.exports.into_iter().map(|x| x.into_owned()).collect(),
+            }),
+            Section::Import(s) => Section::Import(ImportSection {
+                imports: s.imports.into_iter().map(|x| x.into_owned()).collect(),
+            }),
+            Section::Memory(s) => Section::Memory(s),
+            Section::Start(s) => Section::Start(s),
+            Section::Global(s) => Section::Global(s),
+            Section::Table(s) => Section::Table(s),
+            Section::Data(s) => Section::Data(DataSection {
+                data_blocks: s
+                    .data_blocks
+                    .into_iter()
+                    .map(|x| DataBlock {
+                        memory: x.memory,
+                        offset_expression: x.offset_expression,
+                        data: owned_bytes(x.data),
+                    })
+                    .collect(),
+            }),
+            Section::Custom(s) => Section::Custom(CustomSection {
+                name: owned_str(s.name),
+                data: owned_bytes(s.data),
+            }),
+            Section::Element(s) => Section::Element(s),
+        }
+    }
+}
+
+/// Why a module did not validate or could not be encoded, `section` is the position of the
+/// offending section in `sections` and `function` the function whose body it is in.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CompileError {
+    pub section: usize,
+    pub function: Option<FuncIdx>,
+    pub message: &'static str,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "section {}: ", self.section)?;
+        if let Some(function) = self.function {
+            write!(f, "function {}: ", function)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CompileOptions {
+    /// run `validate` before emitting anything
+    pub validate: bool,
+}
+
 pub trait WasmCompiler {
-    fn compile(&mut self) -> Vec<u8>;
+    fn compile_with_options(&self, options: &CompileOptions) -> Result<Vec<u8>, CompileError>;
+
+    fn compile(&self) -> Result<Vec<u8>, CompileError> {
+        self.compile_with_options(&CompileOptions::default())
+    }
 }
 
 pub trait WriteWasm {
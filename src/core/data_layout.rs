@@ -0,0 +1,196 @@
+use super::common::*;
+use super::index::*;
+use super::instructions::*;
+use super::program::*;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+const PAGE_SIZE: u64 = 65536;
+const MAX_PAGES: usize = 65536;
+
+/// Places data into a linear memory one allocation after another and hands back the address of
+/// each, ready for an `I32Const`. Allocations are merged into as few data segments as possible,
+/// alignment padding is filled with zeros and only reserved space separates segments. Placing
+/// anything past the 32 bit address space fails.
+#[derive(Clone, PartialEq, Debug)]
+pub struct DataLayout {
+    memory: MemIdx,
+    end: u32,
+    // the segments so far by start address, the last one grows until space is reserved
+    blocks: Vec<(u32, Vec<u8>)>,
+    open: bool,
+}
+
+impl DataLayout {
+    /// A layout for `memory` whose first allocation is at `base`.
+    pub fn new(memory: MemIdx, base: u32) -> Self {
+        DataLayout {
+            memory,
+            end: base,
+            blocks: Vec::new(),
+            open: false,
+        }
+    }
+
+    pub fn memory(&self) -> MemIdx {
+        self.memory
+    }
+
+    /// The address just after everything placed so far.
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    fn advance(&mut self, size: u32) -> Result<(), &'static str> {
+        self.end = self
+            .end
+            .checked_add(size)
+            .ok_or("data does not fit in memory")?;
+        Ok(())
+    }
+
+    /// Moves the next allocation to a multiple of `alignment`, which must be a power of two.
+    pub fn align(&mut self, alignment: u32) -> Result<(), &'static str> {
+        let alignment = alignment.max(1);
+        let padding = (alignment - self.end % alignment) % alignment;
+        self.advance(padding)
+    }
+
+    pub fn bytes(&mut self, data: &[u8]) -> Result<i32, &'static str> {
+        let address = self.end;
+        if !data.is_empty() {
+            self.advance(u32::try_from(data.len()).map_err(|_| "data does not fit in memory")?)?;
+            match self.blocks.last_mut() {
+                Some((start, block)) if self.open => {
+                    // zeros for any alignment padding since the last allocation
+                    block.resize((address - *start) as usize, 0);
+                    block.extend_from_slice(data);
+                }
+                _ => self.blocks.push((address, data.to_vec())),
+            }
+            self.open = true;
+        }
+        // addresses past 2 GiB are negative as an i32.const
+        Ok(address as i32)
+    }
+
+    /// Places a string followed by a NUL byte.
+    pub fn c_string(&mut self, s: &str) -> Result<i32, &'static str> {
+        let address = self.bytes(s.as_bytes())?;
+        self.bytes(&[0])?;
+        Ok(address)
+    }
+
+    /// Leaves `size` zeroed bytes aligned to `alignment` without putting them in a segment.
+    pub fn reserve(&mut self, size: u32, alignment: u32) -> Result<i32, &'static str> {
+        self.align(alignment)?;
+        let address = self.end;
+        if size > 0 {
+            self.advance(size)?;
+            self.open = false;
+        }
+        Ok(address as i32)
+    }
+
+    // typed arrays are aligned to the size of their elements
+    fn array(&mut self, alignment: u32, bytes: &[u8]) -> Result<i32, &'static str> {
+        self.align(alignment)?;
+        self.bytes(bytes)
+    }
+
+    pub fn i32s(&mut self, values: &[i32]) -> Result<i32, &'static str> {
+        let bytes: Vec<u8> = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+        self.array(4, &bytes)
+    }
+
+    pub fn i64s(&mut self, values: &[i64]) -> Result<i32, &'static str> {
+        let bytes: Vec<u8> = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+        self.array(8, &bytes)
+    }
+
+    pub fn f32s(&mut self, values: &[f32]) -> Result<i32, &'static str> {
+        let bytes: Vec<u8> = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+        self.array(4, &bytes)
+    }
+
+    pub fn f64s(&mut self, values: &[f64]) -> Result<i32, &'static str> {
+        let bytes: Vec<u8> = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+        self.array(8, &bytes)
+    }
+}
+
+impl Module<'_> {
+    /// Starts a layout for `memory` after the data segments it already has.
+    pub fn data_layout(&self, memory: MemIdx) -> Result<DataLayout, &'static str> {
+        if memory.index() >= self.memories().count() {
+            return Err("memory does not exist with that index");
+        }
+        let mut base = 0;
+        for s in self.sections.iter() {
+            if let Section::Data(d) = s {
+                for b in d.data_blocks.iter().filter(|b| b.memory == memory) {
+                    let end = match b.offset_expression[..] {
+                        [Instruction::I32Const(offset)] => u32::try_from(b.data.len())
+                            .ok()
+                            .and_then(|len| (offset as u32).checked_add(len)),
+                        _ => return Err("data segment offset is not constant"),
+                    };
+                    base = base.max(end.ok_or("data does not fit in memory")?);
+                }
+            }
+        }
+        Ok(DataLayout::new(memory, base))
+    }
+
+    /// Adds the data segments of a layout and grows the minimum size of its memory to hold
+    /// everything placed and reserved.
+    // div_ceil is newer than the compilers the crate supports
+    #[allow(clippy::manual_div_ceil)]
+    pub fn add_data_layout(&mut self, layout: DataLayout) -> Result<(), &'static str> {
+        let pages = usize::try_from((u64::from(layout.end) + PAGE_SIZE - 1) / PAGE_SIZE)
+            .map_err(|_| "data does not fit in memory")?;
+        let (min_pages, max_pages) = match self.memories().nth(layout.memory.index()) {
+            Some((_, m)) => m.limits(),
+            None => return Err("memory does not exist with that index"),
+        };
+        if pages > max_pages.unwrap_or(MAX_PAGES).min(MAX_PAGES) {
+            return Err("data does not fit in memory");
+        }
+        if pages > min_pages {
+            self.set_min_pages(layout.memory, pages);
+        }
+        for (address, data) in layout.blocks.iter() {
+            self.add_data_segment(
+                layout.memory,
+                &[Instruction::I32Const(*address as i32)],
+                data,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn set_min_pages(&mut self, memory: MemIdx, pages: usize) {
+        let mut i = 0;
+        for s in self.sections.iter_mut() {
+            if let Section::Import(imports) = s {
+                for x in imports.imports.iter_mut() {
+                    if let WasmImport::Memory(m) = x {
+                        if i == memory.index() {
+                            m.min_pages = pages;
+                            return;
+                        }
+                        i += 1;
+                    }
+                }
+            }
+        }
+        for s in self.sections.iter_mut() {
+            if let Section::Memory(memories) = s {
+                if let Some(m) = memories.memories.get_mut(memory.index() - i) {
+                    m.min_pages = pages;
+                }
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use super::common::*;
+use super::instructions::*;
+use super::program::*;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+/// An instruction of a flat body. Blocks, loops and ifs are a start marker followed by their
+/// instructions and an `End`, positions are indices into the same body.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "op", content = "params")]
+#[repr(C)]
+pub enum FlatInstruction {
+    /// Starts a block, `end` is the position of its `End`.
+    Block {
+        block_type: u8,
+        end: u32,
+    },
+    /// Starts a loop, `end` is the position of its `End`.
+    Loop {
+        block_type: u8,
+        end: u32,
+    },
+    /// Starts an if, `else_at` is the position of its `Else` when it has one.
+    If {
+        block_type: u8,
+        else_at: Option<u32>,
+        end: u32,
+    },
+    /// Starts the else arm of an if, `end` is the position of the if's `End`.
+    Else {
+        end: u32,
+    },
+    End,
+    /// Any instruction that is not structured.
+    Plain(Instruction),
+}
+
+/// A function body as one arena of instructions instead of a vector for every block, with the
+/// matching end and else of every marker already known.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[repr(C)]
+pub struct FlatBody {
+    instructions: Vec<FlatInstruction>,
+}
+
+// builds a body a marker at a time, keeping the markers that are not closed yet
+#[derive(Default)]
+pub(crate) struct FlatBuilder {
+    instructions: Vec<FlatInstruction>,
+    open: Vec<usize>,
+}
+
+impl FlatBuilder {
+    pub(crate) fn block(&mut self, block_type: u8) {
+        self.start(FlatInstruction::Block { block_type, end: 0 });
+    }
+
+    pub(crate) fn loop_(&mut self, block_type: u8) {
+        self.start(FlatInstruction::Loop { block_type, end: 0 });
+    }
+
+    pub(crate) fn if_(&mut self, block_type: u8) {
+        self.start(FlatInstruction::If {
+            block_type,
+            else_at: None,
+            end: 0,
+        });
+    }
+
+    fn start(&mut self, marker: FlatInstruction) {
+        self.open.push(self.instructions.len());
+        self.instructions.push(marker);
+    }
+
+    pub(crate) fn push(&mut self, instruction: Instruction) {
+        self.instructions.push(FlatInstruction::Plain(instruction));
+    }
+
+    pub(crate) fn else_(&mut self) -> Result<(), &'static str> {
+        let at = self.instructions.len() as u32;
+        let open = self.open.last().copied();
+        match open.map(|x| &mut self.instructions[x]) {
+            Some(FlatInstruction::If { else_at, .. }) if else_at.is_none() => *else_at = Some(at),
+            // the nested parser does not know an else outside of an if either
+            _ => return Err("unknown expression"),
+        }
+        self.instructions.push(FlatInstruction::Else { end: 0 });
+        Ok(())
+    }
+
+    // closes the innermost marker, false when there is none and the body itself ends
+    pub(crate) fn end(&mut self) -> bool {
+        let start = match self.open.pop() {
+            Some(x) => x,
+            None => return false,
+        };
+        let at = self.instructions.len() as u32;
+        match &mut self.instructions[start] {
+            FlatInstruction::Block { end, .. } | FlatInstruction::Loop { end, .. } => *end = at,
+            FlatInstruction::If { else_at, end, .. } => {
+                *end = at;
+                if let Some(x) = *else_at {
+                    self.instructions[x as usize] = FlatInstruction::Else { end: at };
+                }
+            }
+            _ => unreachable!(),
+        }
+        self.instructions.push(FlatInstruction::End);
+        true
+    }
+
+    pub(crate) fn finish(mut self) -> FlatBody {
+        self.instructions.shrink_to_fit();
+        FlatBody {
+            instructions: self.instructions,
+        }
+    }
+
+    fn extend(&mut self, instructions: &[Instruction]) {
+        for i in instructions.iter() {
+            match i {
+                Instruction::Block(t, body) => {
+                    self.block(*t);
+                    self.extend(body);
+                    self.end();
+                }
+                Instruction::Loop(t, body) => {
+                    self.loop_(*t);
+                    self.extend(body);
+                    self.end();
+                }
+                Instruction::If(t, then, otherwise) => {
+                    self.if_(*t);
+                    self.extend(then);
+                    if let Some(otherwise) = otherwise {
+                        let _ = self.else_();
+                        self.extend(otherwise);
+                    }
+                    self.end();
+                }
+                _ => self.push(i.clone()),
+            }
+        }
+    }
+}
+
+impl FlatBody {
+    pub fn from_instructions(instructions: &[Instruction]) -> Self {
+        let mut b = FlatBuilder::default();
+        b.extend(instructions);
+        b.finish()
+    }
+
+    /// The body in the nested form the rest of the library works with.
+    pub fn to_instructions(&self) -> Vec<Instruction> {
+        self.nested(0, self.instructions.len())
+    }
+
+    fn nested(&self, mut at: usize, until: usize) -> Vec<Instruction> {
+        let mut out = Vec::new();
+        while at < until {
+            match &self.instructions[at] {
+                FlatInstruction::Block { block_type, end } => {
+                    let end = *end as usize;
+                    out.push(Instruction::Block(*block_type, self.nested(at + 1, end)));
+                    at = end;
+                }
+                FlatInstruction::Loop { block_type, end } => {
+                    let end = *end as usize;
+                    out.push(Instruction::Loop(*block_type, self.nested(at + 1, end)));
+                    at = end;
+                }
+                FlatInstruction::If {
+                    block_type,
+                    else_at,
+                    end,
+                } => {
+                    let end = *end as usize;
+                    let then_end = else_at.map(|x| x as usize).unwrap_or(end);
+                    out.push(Instruction::If(
+                        *block_type,
+                        self.nested(at + 1, then_end),
+                        else_at.map(|x| self.nested(x as usize + 1, end)),
+                    ));
+                    at = end;
+                }
+                FlatInstruction::Plain(i) => out.push(i.clone()),
+                FlatInstruction::Else { .. } | FlatInstruction::End => {}
+            }
+            at += 1;
+        }
+        out
+    }
+
+    pub fn instructions(&self) -> &[FlatInstruction] {
+        &self.instructions
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    pub fn get(&self, position: usize) -> Option<&FlatInstruction> {
+        self.instructions.get(position)
+    }
+
+    /// The position of the `End` closing the marker at `position`.
+    pub fn matching_end(&self, position: usize) -> Option<usize> {
+        match self.instructions.get(position)? {
+            FlatInstruction::Block { end, .. }
+            | FlatInstruction::Loop { end, .. }
+            | FlatInstruction::If { end, .. }
+            | FlatInstruction::Else { end } => Some(*end as usize),
+            _ => None,
+        }
+    }
+
+    /// The position of the `Else` of the if at `position`.
+    pub fn matching_else(&self, position: usize) -> Option<usize> {
+        match self.instructions.get(position)? {
+            FlatInstruction::If { else_at, .. } => else_at.map(|x| x as usize),
+            _ => None,
+        }
+    }
+}
+
+impl CodeBlock {
+    pub fn flat_body(&self) -> FlatBody {
+        FlatBody::from_instructions(&self.instructions)
+    }
+}
+
+/// A module parsed with its function bodies in flat form. The code section is kept apart from
+/// the bodies, so the module is only a program again once `into_program` puts them back.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FlatModule<'a> {
+    module: Module<'a>,
+    bodies: Vec<FlatBody>,
+}
+
+impl<'a> FlatModule<'a> {
+    // `module` has a code block without instructions for every body
+    pub(crate) fn new(module: Module<'a>, bodies: Vec<FlatBody>) -> Self {
+        FlatModule { module, bodies }
+    }
+
+    /// The sections of the module, the code section has the locals of every function but no
+    /// instructions.
+    pub fn sections(&self) -> &[Section<'a>] {
+        &self.module.sections
+    }
+
+    /// The bodies of the defined functions in order.
+    pub fn bodies(&self) -> &[FlatBody] {
+        &self.bodies
+    }
+
+    /// The module with its bodies in nested form.
+    pub fn into_program(self) -> Module<'a> {
+        let mut module = self.module;
+        let mut bodies = self.bodies.iter();
+        for s in module.sections.iter_mut() {
+            if let Section::Code(c) = s {
+                for (b, flat) in c.code_blocks.iter_mut().zip(&mut bodies) {
+                    b.instructions = flat.to_instructions();
+                }
+            }
+        }
+        module
+    }
+}
@@ -0,0 +1,146 @@
+use super::common::*;
+use super::index::*;
+use super::instructions::*;
+use super::program::*;
+use super::visit::*;
+use alloc::vec::Vec;
+use webassembly::EMPTY;
+
+/// How `meter_gas` charges for a basic block.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GasMeter<'a> {
+    /// Calls an imported `(param i64)` function with the cost of the block.
+    Import { module: &'a str, name: &'a str },
+    /// Subtracts the cost from an exported mutable `i64` global, trapping when the gas left
+    /// does not cover it. The check is a function of its own that every block calls.
+    Global { name: &'a str, initial: i64 },
+}
+
+// stack neutral instructions taking `amount` of gas through the charge function `f`
+fn charge(f: FuncIdx, amount: u64) -> [Instruction; 2] {
+    let amount = if amount > i64::MAX as u64 {
+        i64::MAX
+    } else {
+        amount as i64
+    };
+    [Instruction::I64Const(amount), Instruction::Call(f)]
+}
+
+// whether the instruction after this one starts a new basic block
+fn ends_block(i: &Instruction) -> bool {
+    matches!(
+        i,
+        Instruction::Block(_, _)
+            | Instruction::Loop(_, _)
+            | Instruction::If(_, _, _)
+            | Instruction::Br(_)
+            | Instruction::BrIf(_)
+            | Instruction::BrTable(_, _)
+            | Instruction::Return
+            | Instruction::Unreachable
+    )
+}
+
+// charges for each basic block of a sequence whose bodies are already metered
+fn charge_blocks(
+    instructions: Vec<Instruction>,
+    charge_with: FuncIdx,
+    cost: &dyn Fn(&Instruction) -> u64,
+) -> Vec<Instruction> {
+    let mut metered = Vec::new();
+    let mut block = Vec::new();
+    let mut block_cost = 0u64;
+    for i in instructions.into_iter() {
+        block_cost = block_cost.saturating_add(cost(&i));
+        let ends = ends_block(&i);
+        block.push(i);
+        if ends {
+            if block_cost > 0 {
+                metered.extend_from_slice(&charge(charge_with, block_cost));
+            }
+            metered.append(&mut block);
+            block_cost = 0;
+        }
+    }
+    if block_cost > 0 {
+        metered.extend_from_slice(&charge(charge_with, block_cost));
+    }
+    metered.append(&mut block);
+    metered
+}
+
+struct Meter<'a> {
+    charge_with: FuncIdx,
+    cost: &'a dyn Fn(&Instruction) -> u64,
+}
+
+impl<'a> Fold for Meter<'a> {
+    fn fold_code_block(&mut self, index: FuncIdx, code_block: &mut CodeBlock) {
+        // the charge function itself is free
+        if index == self.charge_with {
+            return;
+        }
+        let instructions = core::mem::take(&mut code_block.instructions);
+        let instructions = fold_instructions(self, instructions);
+        code_block.instructions = charge_blocks(instructions, self.charge_with, self.cost);
+    }
+
+    fn fold_body(
+        &mut self,
+        kind: BlockKind,
+        block_type: u8,
+        body: Vec<Instruction>,
+    ) -> Vec<Instruction> {
+        let body = walk_fold_body(self, kind, block_type, body);
+        charge_blocks(body, self.charge_with, self.cost)
+    }
+
+    fn fold_const_expression(&mut self, _expression: &mut Vec<Instruction>) {}
+}
+
+impl Module<'_> {
+    /// Charges for every basic block at its start, so a guest is stopped before it runs what it
+    /// can not pay for. A basic block ends after each branch and structured instruction, the
+    /// bodies of `block`, `loop` and `if` are basic blocks of their own.
+    pub fn meter_gas(
+        &mut self,
+        meter_with: GasMeter,
+        cost: &dyn Fn(&Instruction) -> u64,
+    ) -> Result<(), &'static str> {
+        for s in self.sections.iter() {
+            if let Section::Code(c) = s {
+                if c.code_blocks.iter().any(|b| contains_raw(&b.instructions)) {
+                    return Err("functions with raw instructions can not be metered");
+                }
+            }
+        }
+        let charge_with = match meter_with {
+            GasMeter::Import { module, name } => {
+                self.import_function(module, name, &[ValueType::I64], &[])?
+            }
+            GasMeter::Global { name, initial } => {
+                let (_, gas) = self.create_global(
+                    Some(name),
+                    ValueType::I64,
+                    true,
+                    &[Instruction::I64Const(initial)],
+                )?;
+                let (code_block, index) = self.create_function(&[ValueType::I64], &[])?;
+                let amount = Instruction::LocalGet(LocalIdx(0));
+                code_block.instructions = vec![
+                    Instruction::GlobalGet(gas),
+                    amount.clone(),
+                    Instruction::I64LtU,
+                    Instruction::If(EMPTY, vec![Instruction::Unreachable], None),
+                    Instruction::GlobalGet(gas),
+                    amount,
+                    Instruction::I64Sub,
+                    Instruction::GlobalSet(gas),
+                ];
+                index
+            }
+        };
+        Meter { charge_with, cost }.fold_program(self);
+        Ok(())
+    }
+}
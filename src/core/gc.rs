@@ -0,0 +1,361 @@
+use super::common::*;
+use super::index::*;
+use super::index_space::*;
+use super::instructions::*;
+use super::program::*;
+use super::visit::*;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+fn mark(keep: &mut [bool], index: usize) {
+    if let Some(x) = keep.get_mut(index) {
+        *x = true;
+    }
+}
+
+fn mark_global(globals: &mut [bool], i: &Instruction) {
+    if let Instruction::GlobalGet(g) | Instruction::GlobalSet(g) = i {
+        mark(globals, g.index());
+    }
+}
+
+// the new position of every entity that is kept, in index order
+fn renumbering(keep: &[bool]) -> Vec<Option<u32>> {
+    let mut next = 0;
+    keep.iter()
+        .map(|k| {
+            if *k {
+                next += 1;
+                Some(next - 1)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn retain_flagged<T>(items: &mut Vec<T>, keep: &[bool]) {
+    let mut i = 0;
+    items.retain(|_| {
+        i += 1;
+        keep[i - 1]
+    });
+}
+
+// loads, stores, memory.size, memory.grow and call_indirect use memory 0 and table 0 without
+// naming them
+struct ImplicitUse {
+    kind: IndexKind,
+    found: bool,
+}
+
+impl Visit for ImplicitUse {
+    fn visit_memory(&mut self, _instruction: &Instruction) {
+        self.found |= self.kind == IndexKind::Memory;
+    }
+
+    fn visit_call(&mut self, instruction: &Instruction) {
+        self.found |=
+            self.kind == IndexKind::Table && matches!(instruction, Instruction::CallIndirect(_));
+    }
+}
+
+// what a reachable function body keeps alive
+struct BodyUses<'a> {
+    work: &'a mut Vec<u32>,
+    globals: &'a mut [bool],
+    types: &'a mut [bool],
+    uses_memory: &'a mut bool,
+    has_raw: bool,
+}
+
+impl<'a> Visit for BodyUses<'a> {
+    fn visit_call(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::Call(f) => self.work.push(f.0),
+            Instruction::CallIndirect(t) => mark(self.types, t.index()),
+            _ => {}
+        }
+    }
+
+    fn visit_variable(&mut self, instruction: &Instruction) {
+        mark_global(self.globals, instruction);
+    }
+
+    fn visit_memory(&mut self, _instruction: &Instruction) {
+        *self.uses_memory = true;
+    }
+
+    fn visit_raw(&mut self, _byte: u8) {
+        self.has_raw = true;
+    }
+}
+
+impl Module<'_> {
+    fn is_referenced(&mut self, kind: IndexKind, index: u32) -> bool {
+        let mut found = false;
+        self.for_each_reference_mut(kind, &mut |i| found |= *i == index);
+        if !found && index == 0 {
+            let mut implicit = ImplicitUse { kind, found };
+            implicit.visit_program(self);
+            found = implicit.found;
+        }
+        found
+    }
+
+    fn close_gap(&mut self, kind: IndexKind, index: u32) {
+        self.remap_indices(kind, &|i| match i {
+            _ if i == index => None,
+            _ if i > index => Some(i - 1),
+            _ => Some(i),
+        });
+    }
+
+    pub fn remove_export(&mut self, name: &str) -> Result<(), &'static str> {
+        for s in self.sections.iter_mut() {
+            if let Section::Export(e) = s {
+                let position = e.exports.iter().position(|x| match x {
+                    WasmExport::Function(x) => x.name == name,
+                    WasmExport::Table(x) => x.name == name,
+                    WasmExport::Memory(x) => x.name == name,
+                    WasmExport::Global(x) => x.name == name,
+                });
+                if let Some(position) = position {
+                    e.exports.remove(position);
+                    return Ok(());
+                }
+            }
+        }
+        Err("export does not exist")
+    }
+
+    /// Removes an import that nothing refers to, the imports after it in its index space
+    /// move down by one.
+    pub fn remove_import(&mut self, module_name: &str, name: &str) -> Result<(), &'static str> {
+        let imports = match self.sections.iter().find_map(|x| match x {
+            Section::Import(i) => Some(&i.imports),
+            _ => None,
+        }) {
+            Some(i) => i,
+            None => return Err("import does not exist"),
+        };
+        let position = match imports.iter().position(|x| {
+            let (m, n) = match x {
+                WasmImport::Function(x) => (&x.module_name, &x.name),
+                WasmImport::Global(x) => (&x.module_name, &x.name),
+                WasmImport::Memory(x) => (&x.module_name, &x.name),
+                WasmImport::Table(x) => (&x.module_name, &x.name),
+            };
+            m == module_name && n == name
+        }) {
+            Some(p) => p,
+            None => return Err("import does not exist"),
+        };
+        let kind = |x: &WasmImport| match x {
+            WasmImport::Function(_) => IndexKind::Function,
+            WasmImport::Global(_) => IndexKind::Global,
+            WasmImport::Memory(_) => IndexKind::Memory,
+            WasmImport::Table(_) => IndexKind::Table,
+        };
+        let import_kind = kind(&imports[position]);
+        let index = imports[..position]
+            .iter()
+            .filter(|x| kind(x) == import_kind)
+            .count() as u32;
+        self.remove_import_at(import_kind, index, position)
+    }
+
+    fn remove_import_at(
+        &mut self,
+        kind: IndexKind,
+        index: u32,
+        position: usize,
+    ) -> Result<(), &'static str> {
+        if self.is_referenced(kind, index) {
+            return Err("import is still referenced");
+        }
+        for s in self.sections.iter_mut() {
+            if let Section::Import(i) = s {
+                i.imports.remove(position);
+            }
+        }
+        self.close_gap(kind, index);
+        Ok(())
+    }
+
+    /// Removes a function that nothing refers to, the functions after it move down by one.
+    pub fn remove_function(&mut self, index: FuncIdx) -> Result<(), &'static str> {
+        if self.function(index)?.is_imported() {
+            let position = self
+                .sections
+                .iter()
+                .find_map(|x| match x {
+                    Section::Import(i) => Some(&i.imports),
+                    _ => None,
+                })
+                .and_then(|imports| {
+                    imports
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, x)| matches!(x, WasmImport::Function(_)))
+                        .nth(index.index())
+                })
+                .map(|(p, _)| p)
+                .unwrap();
+            return self.remove_import_at(IndexKind::Function, index.0, position);
+        }
+        if self.is_referenced(IndexKind::Function, index.0) {
+            return Err("function is still referenced");
+        }
+        let defined = index.index() - self.imported_function_count();
+        for s in self.sections.iter_mut() {
+            match s {
+                Section::Function(f) => {
+                    f.function_types.remove(defined);
+                }
+                Section::Code(c) => {
+                    c.code_blocks.remove(defined);
+                }
+                _ => {}
+            }
+        }
+        self.close_gap(IndexKind::Function, index.0);
+        Ok(())
+    }
+
+    /// Removes the functions, types, globals and data segments that can not be reached from
+    /// the exports, the start function or the element segments, and renumbers what is left.
+    pub fn gc(&mut self) -> Result<(), &'static str> {
+        let imported_functions = self.imported_function_count();
+        let mut declared = 0;
+        let mut bodies = 0;
+        let mut type_count = 0;
+        for s in self.sections.iter() {
+            match s {
+                Section::Function(f) => declared = f.function_types.len(),
+                Section::Code(c) => bodies = c.code_blocks.len(),
+                Section::Type(t) => type_count = t.types.len(),
+                _ => {}
+            }
+        }
+        if declared != bodies {
+            return Err("function and code section have inconsistent lengths");
+        }
+        let mut functions = vec![false; imported_functions + declared];
+        let mut globals = vec![false; self.globals().count()];
+        let mut types = vec![false; type_count];
+        let mut uses_memory = false;
+        let mut work: Vec<u32> = Vec::new();
+
+        for s in self.sections.iter() {
+            match s {
+                Section::Export(e) => {
+                    for x in e.exports.iter() {
+                        match x {
+                            WasmExport::Function(x) => work.push(x.index.0),
+                            WasmExport::Global(x) => mark(&mut globals, x.index.index()),
+                            WasmExport::Memory(_) => uses_memory = true,
+                            WasmExport::Table(_) => {}
+                        }
+                    }
+                }
+                Section::Import(i) => {
+                    uses_memory |= i.imports.iter().any(|x| matches!(x, WasmImport::Memory(_)))
+                }
+                Section::Start(s) => work.push(s.start_function.0),
+                Section::Element(e) => {
+                    for x in e.elements.iter() {
+                        work.extend(x.functions.iter().map(|f| f.0));
+                        x.value_expression
+                            .iter()
+                            .for_each(|i| mark_global(&mut globals, i));
+                    }
+                }
+                Section::Data(d) => {
+                    for x in d.data_blocks.iter() {
+                        x.offset_expression
+                            .iter()
+                            .for_each(|i| mark_global(&mut globals, i));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        while let Some(f) = work.pop() {
+            let f = f as usize;
+            if f >= functions.len() {
+                return Err("function does not exist with that index");
+            }
+            if functions[f] {
+                continue;
+            }
+            functions[f] = true;
+            let function = self.function(FuncIdx::try_from(f)?)?;
+            mark(&mut types, function.type_index().index());
+            if let FunctionRef::Defined { body, .. } = function {
+                let mut uses = BodyUses {
+                    work: &mut work,
+                    globals: &mut globals,
+                    types: &mut types,
+                    uses_memory: &mut uses_memory,
+                    has_raw: false,
+                };
+                walk_instructions(&mut uses, body);
+                if uses.has_raw {
+                    return Err("functions with raw instructions can not be analyzed");
+                }
+            }
+        }
+
+        // initializers of defined globals can only read imported globals
+        let imported_globals = self.globals().filter(|x| x.1.is_imported()).count();
+        for s in self.sections.iter() {
+            if let Section::Global(g) = s {
+                for (i, x) in g.globals.iter().enumerate() {
+                    if globals[imported_globals + i] {
+                        for i in x.value_expression.iter() {
+                            mark_global(&mut globals, i);
+                        }
+                    }
+                }
+            }
+        }
+
+        for s in self.sections.iter_mut() {
+            match s {
+                Section::Import(i) => {
+                    let (mut f, mut g) = (0, 0);
+                    i.imports.retain(|x| match x {
+                        WasmImport::Function(_) => {
+                            f += 1;
+                            functions[f - 1]
+                        }
+                        WasmImport::Global(_) => {
+                            g += 1;
+                            globals[g - 1]
+                        }
+                        _ => true,
+                    });
+                }
+                Section::Function(f) => {
+                    retain_flagged(&mut f.function_types, &functions[imported_functions..])
+                }
+                Section::Code(c) => {
+                    retain_flagged(&mut c.code_blocks, &functions[imported_functions..])
+                }
+                Section::Global(g) => retain_flagged(&mut g.globals, &globals[imported_globals..]),
+                Section::Type(t) => retain_flagged(&mut t.types, &types),
+                Section::Data(d) if !uses_memory => d.data_blocks.clear(),
+                _ => {}
+            }
+        }
+        let functions = renumbering(&functions);
+        let globals = renumbering(&globals);
+        let types = renumbering(&types);
+        self.remap_indices(IndexKind::Function, &|i| functions[i as usize]);
+        self.remap_indices(IndexKind::Global, &|i| globals[i as usize]);
+        self.remap_indices(IndexKind::Type, &|i| types[i as usize]);
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use webassembly::TypeWasmExt;
+
+macro_rules! index_type {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(
+            Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Serialize, Deserialize,
+        )]
+        #[serde(transparent)]
+        #[repr(transparent)]
+        pub struct $name(pub u32);
+
+        impl $name {
+            pub fn index(self) -> usize {
+                self.0 as usize
+            }
+
+            // a position among the items of a module, compile checks that they fit in u32
+            pub(crate) fn at(i: usize) -> Self {
+                debug_assert!(i <= u32::MAX as usize, "index does not fit in u32");
+                $name(i as u32)
+            }
+        }
+
+        impl From<u32> for $name {
+            fn from(i: u32) -> Self {
+                $name(i)
+            }
+        }
+
+        impl TryFrom<usize> for $name {
+            type Error = &'static str;
+
+            fn try_from(i: usize) -> Result<Self, Self::Error> {
+                match u32::try_from(i) {
+                    Ok(i) => Ok($name(i)),
+                    Err(_) => Err("index does not fit in u32"),
+                }
+            }
+        }
+
+        impl From<$name> for usize {
+            fn from(i: $name) -> Self {
+                i.index()
+            }
+        }
+
+        impl TypeWasmExt for $name {
+            fn to_wasm_bytes(&self) -> Vec<u8> {
+                self.0.to_wasm_bytes()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    };
+}
+
+index_type!(
+    /// An index into the type section.
+    TypeIdx
+);
+index_type!(
+    /// An index into the function index space, imported functions come first.
+    FuncIdx
+);
+index_type!(
+    /// An index into the parameters and then the locals of a function.
+    LocalIdx
+);
+index_type!(
+    /// An index into the global index space, imported globals come first.
+    GlobalIdx
+);
+index_type!(
+    /// An index into the table index space, imported tables come first.
+    TableIdx
+);
+index_type!(
+    /// An index into the memory index space, imported memories come first.
+    MemIdx
+);
+index_type!(
+    /// An index into the data section.
+    DataIdx
+);
@@ -0,0 +1,330 @@
+use super::common::*;
+use super::index::*;
+use super::instructions::*;
+use super::program::*;
+
+/// A function in the function index space, either imported or defined by the module.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FunctionRef<'a> {
+    Imported {
+        module: &'a str,
+        name: &'a str,
+        type_index: TypeIdx,
+    },
+    Defined {
+        type_index: TypeIdx,
+        locals: &'a [LocalCount],
+        body: &'a [Instruction],
+    },
+}
+
+impl<'a> FunctionRef<'a> {
+    pub fn type_index(&self) -> TypeIdx {
+        match self {
+            FunctionRef::Imported { type_index, .. } => *type_index,
+            FunctionRef::Defined { type_index, .. } => *type_index,
+        }
+    }
+
+    pub fn is_imported(&self) -> bool {
+        matches!(self, FunctionRef::Imported { .. })
+    }
+}
+
+/// A global in the global index space, either imported or defined by the module.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GlobalRef<'a> {
+    Imported {
+        module: &'a str,
+        name: &'a str,
+        value_type: ValueType,
+        is_mutable: bool,
+    },
+    Defined(&'a Global),
+}
+
+impl<'a> GlobalRef<'a> {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            GlobalRef::Imported { value_type, .. } => *value_type,
+            GlobalRef::Defined(g) => g.value_type,
+        }
+    }
+
+    pub fn is_mutable(&self) -> bool {
+        match self {
+            GlobalRef::Imported { is_mutable, .. } => *is_mutable,
+            GlobalRef::Defined(g) => g.is_mutable,
+        }
+    }
+
+    pub fn is_imported(&self) -> bool {
+        matches!(self, GlobalRef::Imported { .. })
+    }
+}
+
+/// A table in the table index space, either imported or defined by the module.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TableRef<'a> {
+    Imported {
+        module: &'a str,
+        name: &'a str,
+        element_type: u8,
+        min: usize,
+        max: Option<usize>,
+    },
+    Defined(&'a Table),
+}
+
+impl<'a> TableRef<'a> {
+    pub fn limits(&self) -> (usize, Option<usize>) {
+        match self {
+            TableRef::Imported { min, max, .. } => (*min, *max),
+            TableRef::Defined(t) => (t.min, t.max),
+        }
+    }
+
+    pub fn is_imported(&self) -> bool {
+        matches!(self, TableRef::Imported { .. })
+    }
+}
+
+/// A memory in the memory index space, either imported or defined by the module.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MemoryRef<'a> {
+    Imported {
+        module: &'a str,
+        name: &'a str,
+        min_pages: usize,
+        max_pages: Option<usize>,
+    },
+    Defined(&'a WasmMemory),
+}
+
+impl<'a> MemoryRef<'a> {
+    pub fn limits(&self) -> (usize, Option<usize>) {
+        match self {
+            MemoryRef::Imported {
+                min_pages,
+                max_pages,
+                ..
+            } => (*min_pages, *max_pages),
+            MemoryRef::Defined(m) => (m.min_pages, m.max_pages),
+        }
+    }
+
+    pub fn is_imported(&self) -> bool {
+        matches!(self, MemoryRef::Imported { .. })
+    }
+}
+
+enum ImportRef<'a> {
+    Function(FunctionRef<'a>),
+    Global(GlobalRef<'a>),
+    Table(TableRef<'a>),
+    Memory(MemoryRef<'a>),
+}
+
+// the parts of a module the index spaces are made of
+impl<'m> Module<'m> {
+    fn import_refs(&self) -> impl Iterator<Item = ImportRef<'_>> {
+        self.section_slice(|x| match x {
+            Section::Import(s) => Some(&s.imports[..]),
+            _ => None,
+        })
+        .iter()
+        .map(|x| x.import_ref())
+    }
+
+    fn section_slice<'s, T>(&'s self, f: impl Fn(&'s Section<'m>) -> Option<&'s [T]>) -> &'s [T] {
+        self.sections.iter().find_map(f).unwrap_or(&[])
+    }
+
+    fn types(&self) -> &[FunctionType] {
+        self.section_slice(|x| match x {
+            Section::Type(s) => Some(&s.types[..]),
+            _ => None,
+        })
+    }
+
+    fn function_types(&self) -> &[TypeIdx] {
+        self.section_slice(|x| match x {
+            Section::Function(s) => Some(&s.function_types[..]),
+            _ => None,
+        })
+    }
+
+    fn code_blocks(&self) -> &[CodeBlock] {
+        self.section_slice(|x| match x {
+            Section::Code(s) => Some(&s.code_blocks[..]),
+            _ => None,
+        })
+    }
+
+    fn defined_globals(&self) -> &[Global] {
+        self.section_slice(|x| match x {
+            Section::Global(s) => Some(&s.globals[..]),
+            _ => None,
+        })
+    }
+
+    fn defined_tables(&self) -> &[Table] {
+        self.section_slice(|x| match x {
+            Section::Table(s) => Some(&s.tables[..]),
+            _ => None,
+        })
+    }
+
+    fn defined_memories(&self) -> &[WasmMemory] {
+        self.section_slice(|x| match x {
+            Section::Memory(s) => Some(&s.memories[..]),
+            _ => None,
+        })
+    }
+
+    /// All functions in index space order, imported functions first.
+    pub fn functions(&self) -> impl Iterator<Item = (FuncIdx, FunctionRef<'_>)> {
+        let imports = self.import_refs().filter_map(|x| match x {
+            ImportRef::Function(f) => Some(f),
+            _ => None,
+        });
+        let defined = self
+            .function_types()
+            .iter()
+            .zip(self.code_blocks().iter())
+            .map(|(t, b)| FunctionRef::Defined {
+                type_index: *t,
+                locals: &b.locals,
+                body: &b.instructions,
+            });
+        imports
+            .chain(defined)
+            .enumerate()
+            .map(|(i, f)| (FuncIdx::at(i), f))
+    }
+
+    /// Resolves an index in the function index space.
+    pub fn function(&self, index: FuncIdx) -> Result<FunctionRef<'_>, &'static str> {
+        // imports come first, past them the index is a position in the function and code sections
+        let mut imported = 0;
+        for x in self.import_refs() {
+            if let ImportRef::Function(f) = x {
+                if imported == index.index() {
+                    return Ok(f);
+                }
+                imported += 1;
+            }
+        }
+        let i = index.index() - imported;
+        match (self.function_types().get(i), self.code_blocks().get(i)) {
+            (Some(t), Some(b)) => Ok(FunctionRef::Defined {
+                type_index: *t,
+                locals: &b.locals,
+                body: &b.instructions,
+            }),
+            _ => Err("function does not exist with that index"),
+        }
+    }
+
+    /// Resolves the signature of a function in the function index space.
+    pub fn function_type(&self, index: FuncIdx) -> Result<&FunctionType, &'static str> {
+        let type_index = self.function(index)?.type_index();
+        match self.types().get(type_index.index()) {
+            Some(t) => Ok(t),
+            None => Err("function type does not exist with that index"),
+        }
+    }
+
+    /// All globals in index space order, imported globals first.
+    pub fn globals(&self) -> impl Iterator<Item = (GlobalIdx, GlobalRef<'_>)> {
+        let imports = self.import_refs().filter_map(|x| match x {
+            ImportRef::Global(g) => Some(g),
+            _ => None,
+        });
+        let defined = self.defined_globals().iter().map(GlobalRef::Defined);
+        imports
+            .chain(defined)
+            .enumerate()
+            .map(|(i, g)| (GlobalIdx::at(i), g))
+    }
+
+    /// Resolves an index in the global index space.
+    pub fn global(&self, index: GlobalIdx) -> Result<GlobalRef<'_>, &'static str> {
+        let mut imported = 0;
+        for x in self.import_refs() {
+            if let ImportRef::Global(g) = x {
+                if imported == index.index() {
+                    return Ok(g);
+                }
+                imported += 1;
+            }
+        }
+        match self.defined_globals().get(index.index() - imported) {
+            Some(g) => Ok(GlobalRef::Defined(g)),
+            None => Err("global does not exist with that index"),
+        }
+    }
+
+    /// All tables in index space order, imported tables first.
+    pub fn tables(&self) -> impl Iterator<Item = (TableIdx, TableRef<'_>)> {
+        let imports = self.import_refs().filter_map(|x| match x {
+            ImportRef::Table(t) => Some(t),
+            _ => None,
+        });
+        let defined = self.defined_tables().iter().map(TableRef::Defined);
+        imports
+            .chain(defined)
+            .enumerate()
+            .map(|(i, t)| (TableIdx::at(i), t))
+    }
+
+    /// All memories in index space order, imported memories first.
+    pub fn memories(&self) -> impl Iterator<Item = (MemIdx, MemoryRef<'_>)> {
+        let imports = self.import_refs().filter_map(|x| match x {
+            ImportRef::Memory(m) => Some(m),
+            _ => None,
+        });
+        let defined = self.defined_memories().iter().map(MemoryRef::Defined);
+        imports
+            .chain(defined)
+            .enumerate()
+            .map(|(i, m)| (MemIdx::at(i), m))
+    }
+
+    pub fn imported_function_count(&self) -> usize {
+        self.import_refs()
+            .filter(|x| matches!(x, ImportRef::Function(_)))
+            .count()
+    }
+}
+
+impl WasmImport<'_> {
+    fn import_ref(&self) -> ImportRef<'_> {
+        match self {
+            WasmImport::Function(f) => ImportRef::Function(FunctionRef::Imported {
+                module: &f.module_name,
+                name: &f.name,
+                type_index: f.type_index,
+            }),
+            WasmImport::Global(g) => ImportRef::Global(GlobalRef::Imported {
+                module: &g.module_name,
+                name: &g.name,
+                value_type: g.value_type,
+                is_mutable: g.is_mutable,
+            }),
+            WasmImport::Table(t) => ImportRef::Table(TableRef::Imported {
+                module: &t.module_name,
+                name: &t.name,
+                element_type: t.element_type,
+                min: t.min,
+                max: t.max,
+            }),
+            WasmImport::Memory(m) => ImportRef::Memory(MemoryRef::Imported {
+                module: &m.module_name,
+                name: &m.name,
+                min_pages: m.min_pages,
+                max_pages: m.max_pages,
+            }),
+        }
+    }
+}
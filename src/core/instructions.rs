@@ -1,5 +1,7 @@
+use super::index::*;
 use alloc::vec::Vec;
 use serde::{Deserialize, Serialize};
+use webassembly::EMPTY;
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(tag = "op", content = "params")]
@@ -15,15 +17,15 @@ pub enum Instruction {
     BrIf(u32),
     BrTable(Vec<u32>, u32),
     Return,
-    Call(u32),
-    CallIndirect(u32),
+    Call(FuncIdx),
+    CallIndirect(TypeIdx),
     Drop,
     Select,
-    LocalGet(u32),
-    LocalSet(u32),
-    LocalTee(u32),
-    GlobalGet(u32),
-    GlobalSet(u32),
+    LocalGet(LocalIdx),
+    LocalSet(LocalIdx),
+    LocalTee(LocalIdx),
+    GlobalGet(GlobalIdx),
+    GlobalSet(GlobalIdx),
     I32Load(u32, u32),
     I64Load(u32, u32),
     F32Load(u32, u32),
@@ -177,3 +179,246 @@ pub enum Instruction {
     F32ReinterpretI32,
     F64ReinterpretI64,
 }
+
+/// The groups of instructions the wasm spec lists them in, structured instructions and branches
+/// count as control instructions.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InstructionCategory {
+    Control,
+    Call,
+    Parametric,
+    Variable,
+    Memory,
+    Numeric,
+    Raw,
+}
+
+pub(crate) fn block_arity(t: u8) -> usize {
+    if t == EMPTY {
+        0
+    } else {
+        1
+    }
+}
+
+// the alignment of a load or store as a power of two
+pub(crate) fn natural_alignment(i: &Instruction) -> u32 {
+    match i {
+        Instruction::I32Load8S(_, _)
+        | Instruction::I32Load8U(_, _)
+        | Instruction::I64Load8S(_, _)
+        | Instruction::I64Load8U(_, _)
+        | Instruction::I32Store8(_, _)
+        | Instruction::I64Store8(_, _) => 0,
+        Instruction::I32Load16S(_, _)
+        | Instruction::I32Load16U(_, _)
+        | Instruction::I64Load16S(_, _)
+        | Instruction::I64Load16U(_, _)
+        | Instruction::I32Store16(_, _)
+        | Instruction::I64Store16(_, _) => 1,
+        Instruction::I32Load(_, _)
+        | Instruction::F32Load(_, _)
+        | Instruction::I64Load32S(_, _)
+        | Instruction::I64Load32U(_, _)
+        | Instruction::I32Store(_, _)
+        | Instruction::F32Store(_, _)
+        | Instruction::I64Store32(_, _) => 2,
+        _ => 3,
+    }
+}
+
+// the alignment a load or store says it has
+pub(crate) fn memory_alignment(i: &Instruction) -> Option<u32> {
+    match i {
+        Instruction::I32Load(a, _)
+        | Instruction::I64Load(a, _)
+        | Instruction::F32Load(a, _)
+        | Instruction::F64Load(a, _)
+        | Instruction::I32Load8S(a, _)
+        | Instruction::I32Load8U(a, _)
+        | Instruction::I32Load16S(a, _)
+        | Instruction::I32Load16U(a, _)
+        | Instruction::I64Load8S(a, _)
+        | Instruction::I64Load8U(a, _)
+        | Instruction::I64Load16S(a, _)
+        | Instruction::I64Load16U(a, _)
+        | Instruction::I64Load32S(a, _)
+        | Instruction::I64Load32U(a, _)
+        | Instruction::I32Store(a, _)
+        | Instruction::I64Store(a, _)
+        | Instruction::F32Store(a, _)
+        | Instruction::F64Store(a, _)
+        | Instruction::I32Store8(a, _)
+        | Instruction::I32Store16(a, _)
+        | Instruction::I64Store8(a, _)
+        | Instruction::I64Store16(a, _)
+        | Instruction::I64Store32(a, _) => Some(*a),
+        _ => None,
+    }
+}
+
+impl Instruction {
+    pub fn category(&self) -> InstructionCategory {
+        match self {
+            Instruction::Raw(_) => InstructionCategory::Raw,
+            Instruction::Unreachable
+            | Instruction::Nop
+            | Instruction::Block(_, _)
+            | Instruction::Loop(_, _)
+            | Instruction::If(_, _, _)
+            | Instruction::Br(_)
+            | Instruction::BrIf(_)
+            | Instruction::BrTable(_, _)
+            | Instruction::Return => InstructionCategory::Control,
+            Instruction::Call(_) | Instruction::CallIndirect(_) => InstructionCategory::Call,
+            Instruction::Drop | Instruction::Select => InstructionCategory::Parametric,
+            Instruction::LocalGet(_)
+            | Instruction::LocalSet(_)
+            | Instruction::LocalTee(_)
+            | Instruction::GlobalGet(_)
+            | Instruction::GlobalSet(_) => InstructionCategory::Variable,
+            Instruction::I32Load(_, _)
+            | Instruction::I64Load(_, _)
+            | Instruction::F32Load(_, _)
+            | Instruction::F64Load(_, _)
+            | Instruction::I32Load8S(_, _)
+            | Instruction::I32Load8U(_, _)
+            | Instruction::I32Load16S(_, _)
+            | Instruction::I32Load16U(_, _)
+            | Instruction::I64Load8S(_, _)
+            | Instruction::I64Load8U(_, _)
+            | Instruction::I64Load16S(_, _)
+            | Instruction::I64Load16U(_, _)
+            | Instruction::I64Load32S(_, _)
+            | Instruction::I64Load32U(_, _)
+            | Instruction::I32Store(_, _)
+            | Instruction::I64Store(_, _)
+            | Instruction::F32Store(_, _)
+            | Instruction::F64Store(_, _)
+            | Instruction::I32Store8(_, _)
+            | Instruction::I32Store16(_, _)
+            | Instruction::I64Store8(_, _)
+            | Instruction::I64Store16(_, _)
+            | Instruction::I64Store32(_, _)
+            | Instruction::MemorySize
+            | Instruction::MemoryGrow => InstructionCategory::Memory,
+            _ => InstructionCategory::Numeric,
+        }
+    }
+
+    /// How many operands the instruction pops and pushes. It is `None` for branches, returns and
+    /// calls, whose effect depends on the surrounding labels or on function signatures, and for
+    /// raw bytes.
+    pub fn stack_effect(&self) -> Option<(usize, usize)> {
+        let arity = match self {
+            Instruction::Raw(_) => return None,
+            Instruction::Unreachable | Instruction::Nop => (0, 0),
+            Instruction::Block(t, _) | Instruction::Loop(t, _) => (0, block_arity(*t)),
+            Instruction::If(t, _, _) => (1, block_arity(*t)),
+            Instruction::Br(_)
+            | Instruction::BrIf(_)
+            | Instruction::BrTable(_, _)
+            | Instruction::Return
+            | Instruction::Call(_)
+            | Instruction::CallIndirect(_) => return None,
+            Instruction::Drop => (1, 0),
+            Instruction::Select => (3, 1),
+            Instruction::LocalGet(_) | Instruction::GlobalGet(_) => (0, 1),
+            Instruction::LocalSet(_) | Instruction::GlobalSet(_) => (1, 0),
+            Instruction::LocalTee(_) => (1, 1),
+            Instruction::I32Store(_, _)
+            | Instruction::I64Store(_, _)
+            | Instruction::F32Store(_, _)
+            | Instruction::F64Store(_, _)
+            | Instruction::I32Store8(_, _)
+            | Instruction::I32Store16(_, _)
+            | Instruction::I64Store8(_, _)
+            | Instruction::I64Store16(_, _)
+            | Instruction::I64Store32(_, _) => (2, 0),
+            Instruction::MemorySize
+            | Instruction::I32Const(_)
+            | Instruction::I64Const(_)
+            | Instruction::F32Const(_)
+            | Instruction::F64Const(_) => (0, 1),
+            Instruction::I32Eq
+            | Instruction::I32Ne
+            | Instruction::I32LtS
+            | Instruction::I32LtU
+            | Instruction::I32GtS
+            | Instruction::I32GtU
+            | Instruction::I32LeS
+            | Instruction::I32LeU
+            | Instruction::I32GeS
+            | Instruction::I32GeU
+            | Instruction::I64Eq
+            | Instruction::I64Ne
+            | Instruction::I64LtS
+            | Instruction::I64LtU
+            | Instruction::I64GtS
+            | Instruction::I64GtU
+            | Instruction::I64LeS
+            | Instruction::I64LeU
+            | Instruction::I64GeS
+            | Instruction::I64GeU
+            | Instruction::F32Eq
+            | Instruction::F32Ne
+            | Instruction::F32Lt
+            | Instruction::F32Gt
+            | Instruction::F32Le
+            | Instruction::F32Ge
+            | Instruction::F64Eq
+            | Instruction::F64Ne
+            | Instruction::F64Lt
+            | Instruction::F64Gt
+            | Instruction::F64Le
+            | Instruction::F64Ge
+            | Instruction::I32Add
+            | Instruction::I32Sub
+            | Instruction::I32Mul
+            | Instruction::I32DivS
+            | Instruction::I32DivU
+            | Instruction::I32RemS
+            | Instruction::I32RemU
+            | Instruction::I32And
+            | Instruction::I32Or
+            | Instruction::I32Xor
+            | Instruction::I32Shl
+            | Instruction::I32ShrS
+            | Instruction::I32ShrU
+            | Instruction::I32Rotl
+            | Instruction::I32Rotr
+            | Instruction::I64Add
+            | Instruction::I64Sub
+            | Instruction::I64Mul
+            | Instruction::I64DivS
+            | Instruction::I64DivU
+            | Instruction::I64RemS
+            | Instruction::I64RemU
+            | Instruction::I64And
+            | Instruction::I64Or
+            | Instruction::I64Xor
+            | Instruction::I64Shl
+            | Instruction::I64ShrS
+            | Instruction::I64ShrU
+            | Instruction::I64Rotl
+            | Instruction::I64Rotr
+            | Instruction::F32Add
+            | Instruction::F32Sub
+            | Instruction::F32Mul
+            | Instruction::F32Div
+            | Instruction::F32Min
+            | Instruction::F32Max
+            | Instruction::F32Copysign
+            | Instruction::F64Add
+            | Instruction::F64Sub
+            | Instruction::F64Mul
+            | Instruction::F64Div
+            | Instruction::F64Min
+            | Instruction::F64Max
+            | Instruction::F64Copysign => (2, 1),
+            // loads, memory.grow, tests, unary operators and conversions
+            _ => (1, 1),
+        };
+        Some(arity)
+    }
+}
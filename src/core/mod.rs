@@ -1,13 +1,51 @@
 pub mod common;
 pub use common::*;
 
+mod builder;
+pub use builder::*;
+
+mod call_graph;
+pub use call_graph::*;
+
+mod canonicalize;
+
+mod cfg;
+pub use cfg::*;
+
+mod data_layout;
+pub use data_layout::*;
+
+mod flat;
+pub use flat::*;
+
+mod gas;
+pub use gas::*;
+
+mod gc;
+
+pub mod index;
+pub use index::*;
+
+mod index_space;
+pub use index_space::*;
+
 mod instructions;
 pub use instructions::*;
 
 mod program;
 pub use program::*;
 
-pub mod view;
-pub use view::*;
+mod shim;
+pub use shim::*;
+
+mod stack_limit;
+
+mod stack_types;
+pub use stack_types::*;
+
+mod validate;
+
+pub mod visit;
+pub use visit::*;
 
 pub mod wast;
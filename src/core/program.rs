@@ -1,126 +1,56 @@
 use super::common::*;
-use super::view::*;
+use super::index::*;
+use super::instructions::*;
+use super::visit::*;
 use crate::alloc::string::ToString;
+use crate::parser::wasm::wasm_name_section;
 use alloc::vec::Vec;
+use core::convert::TryFrom;
 use serde::{Deserialize, Serialize};
+use webassembly::ANYFUNC;
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
-#[repr(C)]
-pub struct ProgramView<'a> {
-    #[serde(borrow)]
-    pub sections: Vec<SectionView<'a>>,
-}
-
+/// A web assembly module. Names and data either borrow from the bytes it was parsed from or
+/// are owned, so a parsed module can be changed without copying what stays the same.
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(C)]
-pub struct Program {
-    pub sections: Vec<Section>,
+#[derive(Default)]
+pub struct Module<'a> {
+    pub sections: Vec<Section<'a>>,
 }
 
-impl<'p> ProgramView<'p> {
-    pub fn find_exported_function<'a>(
-        &'a self,
-        name: &str,
-    ) -> Result<&'a ExportView, &'static str> {
-        let result = self.sections.iter().find(|x| {
-            if let SectionView::Export(_) = x {
-                true
-            } else {
-                false
-            }
-        });
-        if let Some(SectionView::Export(export_section)) = result {
-            let result = self.sections.iter().find(|x| {
-                if let SectionView::Code(_) = x {
-                    true
-                } else {
-                    false
-                }
-            });
-            if let Some(SectionView::Code(_)) = result {
-                let result = export_section.exports.iter().find(|x| {
-                    if let WasmExportView::Function(f) = x {
-                        f.name == name
-                    } else {
-                        false
-                    }
-                });
-                let main_export = match result {
-                    Some(WasmExportView::Function(f)) => f,
-                    _ => {
-                        let e = "could not find export";
-                        return Err(e);
-                    }
-                };
-                Ok(main_export)
-            } else {
-                Err("could not find code section")
-            }
-        } else {
-            Err("could not find export section")
-        }
-    }
+/// A module that owns everything in it.
+pub type Program = Module<'static>;
 
-    pub fn find_code_block<'a>(&'a self, index: usize) -> Result<&'a CodeBlock, &'static str> {
-        let result = self.sections.iter().find(|x| {
-            if let SectionView::Code(_) = x {
-                true
-            } else {
-                false
-            }
-        });
-        if let Some(SectionView::Code(code_section)) = result {
-            if index >= code_section.code_blocks.len() {
-                Err("invalid code block index")
-            } else {
-                Ok(&code_section.code_blocks[index])
-            }
-        } else {
-            Err("could find code section")
-        }
-    }
+/// A module borrowing from the bytes it was parsed from.
+pub type ProgramView<'a> = Module<'a>;
 
-    pub fn to_owned(&self) -> Program {
-        Program {
-            sections: self
-                .sections
-                .iter()
-                .map(|x| x.to_owned())
-                .collect::<Vec<Section>>(),
-        }
+impl<'m> Module<'m> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
     }
-}
 
-impl Default for Program {
-    fn default() -> Program {
-        Program {
-            sections: Vec::new(),
+    /// Copies whatever the module still borrows, so it no longer depends on the parsed bytes.
+    pub fn into_owned(self) -> Program {
+        Module {
+            sections: self.sections.into_iter().map(|x| x.into_owned()).collect(),
         }
     }
-}
 
-impl Program {
-    #[inline]
-    pub fn new() -> Self {
-        Self::default()
+    pub fn to_owned(&self) -> Program {
+        self.clone().into_owned()
     }
 
-    pub fn find_exported_function<'a>(&'a self, name: &str) -> Result<&'a Export, &'static str> {
-        let result = self.sections.iter().find(|x| {
-            if let Section::Export(_) = x {
-                true
-            } else {
-                false
-            }
-        });
+    pub fn find_exported_function<'a>(
+        &'a self,
+        name: &str,
+    ) -> Result<&'a Export<'a, FuncIdx>, &'static str> {
+        let result = self
+            .sections
+            .iter()
+            .find(|x| matches!(x, Section::Export(_)));
         if let Some(Section::Export(export_section)) = result {
-            let result = self.sections.iter().find(|x| {
-                if let Section::Code(_) = x {
-                    true
-                } else {
-                    false
-                }
-            });
+            let result = self.sections.iter().find(|x| matches!(x, Section::Code(_)));
             if let Some(Section::Code(_)) = result {
                 let result = export_section.exports.iter().find(|x| {
                     if let WasmExport::Function(f) = x {
@@ -145,14 +75,8 @@ impl Program {
         }
     }
 
-    pub fn find_code_block<'a>(&'a self, index: usize) -> Result<&'a CodeBlock, &'static str> {
-        let result = self.sections.iter().find(|x| {
-            if let Section::Code(_) = x {
-                true
-            } else {
-                false
-            }
-        });
+    pub fn find_code_block(&self, index: usize) -> Result<&CodeBlock, &'static str> {
+        let result = self.sections.iter().find(|x| matches!(x, Section::Code(_)));
         if let Some(Section::Code(code_section)) = result {
             if index >= code_section.code_blocks.len() {
                 Err("invalid code block index")
@@ -164,12 +88,17 @@ impl Program {
         }
     }
 
-    pub fn create_import<'a>(
-        &'a mut self,
+    /// Adds a function import to the `env` module, see `import_function`.
+    pub fn create_import(
+        &mut self,
         name: &str,
         inputs: &[ValueType],
         outputs: &[ValueType],
-    ) -> Result<usize, &'static str> {
+    ) -> Result<FuncIdx, &'static str> {
+        self.import_function("env", name, inputs, outputs)
+    }
+
+    fn find_or_create_type(&mut self, inputs: &[ValueType], outputs: &[ValueType]) -> TypeIdx {
         let type_section = match self
             .sections
             .iter_mut()
@@ -184,96 +113,249 @@ impl Program {
             }
         };
 
-        let type_index = if let Section::Type(s) = type_section {
+        if let Section::Type(s) = type_section {
             match s
                 .types
                 .iter()
                 .enumerate()
                 .find(|x| x.1.inputs == inputs && x.1.outputs == outputs)
             {
-                Some(x) => x.0,
+                Some(x) => TypeIdx::at(x.0),
                 None => {
                     s.types.push(FunctionType {
                         inputs: inputs.to_vec(),
                         outputs: outputs.to_vec(),
                     });
-                    s.types.len() - 1
+                    TypeIdx::at(s.types.len() - 1)
                 }
             }
         } else {
             unreachable!()
-        };
+        }
+    }
 
-        let imports_section = match self
-            .sections
-            .iter_mut()
-            .find(|x| matches!(x, Section::Import(_)))
-        {
-            Some(x) => x,
-            None => {
-                self.sections.push(Section::Import(ImportSection {
-                    imports: Vec::new(),
-                }));
-                let len = self.sections.len() - 1;
-                &mut self.sections[len]
-            }
-        };
+    pub(crate) fn name_section(&self) -> Option<NameSection> {
+        self.sections.iter().find_map(|x| match x {
+            Section::Custom(c) if c.name == "name" => wasm_name_section(&c.data).ok(),
+            _ => None,
+        })
+    }
 
-        if let Section::Import(s) = imports_section {
-            s.imports.push(WasmImport::Function(FunctionImport {
-                module_name: "env".to_string(),
-                name: name.to_string(),
-                type_index,
-            }));
-            Ok(s.imports.len() - 1)
-        } else {
-            unreachable!()
-        }
+    // imports come first in their index space, so everything defined moves up by one
+    fn push_import(&mut self, kind: IndexKind, index: u32, import: WasmImport<'m>) {
+        let (import_section, _) = self.ensure_imports();
+        import_section.imports.push(import);
+        self.remap_indices(kind, &|i| Some(if i >= index { i + 1 } else { i }));
     }
 
-    pub fn create_export<'a>(
-        &'a mut self,
+    /// Adds a function import, defined functions and every reference to them move up by one.
+    pub fn import_function(
+        &mut self,
+        module_name: &str,
         name: &str,
         inputs: &[ValueType],
         outputs: &[ValueType],
-    ) -> Result<(&'a mut CodeBlock, usize), &'static str> {
-        let import_count = {
-            let (import_section, _) = self.ensure_imports();
-            import_section.imports.len()
-        };
-        let type_section = match self
-            .sections
-            .iter_mut()
-            .find(|x| matches!(x, Section::Type(_)))
-        {
-            Some(x) => x,
-            None => {
-                self.sections
-                    .push(Section::Type(TypeSection { types: Vec::new() }));
-                let len = self.sections.len() - 1;
-                &mut self.sections[len]
+    ) -> Result<FuncIdx, &'static str> {
+        let type_index = self.find_or_create_type(inputs, outputs);
+        let index = FuncIdx::try_from(self.imported_function_count())?;
+        self.push_import(
+            IndexKind::Function,
+            index.0,
+            WasmImport::Function(FunctionImport {
+                module_name: module_name.to_string().into(),
+                name: name.to_string().into(),
+                type_index,
+            }),
+        );
+        Ok(index)
+    }
+
+    /// Adds a global import, defined globals and every reference to them move up by one.
+    pub fn import_global(
+        &mut self,
+        module_name: &str,
+        name: &str,
+        value_type: ValueType,
+        is_mutable: bool,
+    ) -> Result<GlobalIdx, &'static str> {
+        let index = GlobalIdx::try_from(self.globals().filter(|x| x.1.is_imported()).count())?;
+        self.push_import(
+            IndexKind::Global,
+            index.0,
+            WasmImport::Global(GlobalImport {
+                module_name: module_name.to_string().into(),
+                name: name.to_string().into(),
+                value_type,
+                is_mutable,
+            }),
+        );
+        Ok(index)
+    }
+
+    /// Adds a memory import, a module can only have one memory.
+    pub fn import_memory(
+        &mut self,
+        module_name: &str,
+        name: &str,
+        min: usize,
+        max: Option<usize>,
+    ) -> Result<MemIdx, &'static str> {
+        // a second memory would take index 0 from the one loads and stores use
+        if self.memories().next().is_some() {
+            return Err("multiple memories");
+        }
+        let index = MemIdx::at(0);
+        self.push_import(
+            IndexKind::Memory,
+            index.0,
+            WasmImport::Memory(MemoryImport {
+                module_name: module_name.to_string().into(),
+                name: name.to_string().into(),
+                min_pages: min,
+                max_pages: max,
+            }),
+        );
+        Ok(index)
+    }
+
+    /// Adds a table import, a module can only have one table.
+    pub fn import_table(
+        &mut self,
+        module_name: &str,
+        name: &str,
+        min: usize,
+        max: Option<usize>,
+    ) -> Result<TableIdx, &'static str> {
+        // a second table would take index 0 from the one call_indirect uses
+        if self.tables().next().is_some() {
+            return Err("multiple tables");
+        }
+        let index = TableIdx::at(0);
+        self.push_import(
+            IndexKind::Table,
+            index.0,
+            WasmImport::Table(TableImport {
+                module_name: module_name.to_string().into(),
+                name: name.to_string().into(),
+                element_type: ANYFUNC,
+                min,
+                max,
+            }),
+        );
+        Ok(index)
+    }
+
+    // visits every reference into one index space, except the ones in the name section
+    pub(crate) fn for_each_reference_mut(&mut self, kind: IndexKind, f: &mut dyn FnMut(&mut u32)) {
+        for s in self.sections.iter_mut() {
+            match s {
+                Section::Import(i) if kind == IndexKind::Type => {
+                    for x in i.imports.iter_mut() {
+                        if let WasmImport::Function(x) = x {
+                            f(&mut x.type_index.0);
+                        }
+                    }
+                }
+                Section::Function(s) if kind == IndexKind::Type => {
+                    s.function_types.iter_mut().for_each(|t| f(&mut t.0))
+                }
+                Section::Export(e) => {
+                    for x in e.exports.iter_mut() {
+                        match (kind, x) {
+                            (IndexKind::Function, WasmExport::Function(e)) => f(&mut e.index.0),
+                            (IndexKind::Global, WasmExport::Global(e)) => f(&mut e.index.0),
+                            (IndexKind::Memory, WasmExport::Memory(e)) => f(&mut e.index.0),
+                            (IndexKind::Table, WasmExport::Table(e)) => f(&mut e.index.0),
+                            _ => {}
+                        }
+                    }
+                }
+                Section::Start(s) if kind == IndexKind::Function => f(&mut s.start_function.0),
+                Section::Element(e) => {
+                    for x in e.elements.iter_mut() {
+                        match kind {
+                            IndexKind::Function => x.functions.iter_mut().for_each(|i| f(&mut i.0)),
+                            IndexKind::Table => f(&mut x.table.0),
+                            _ => x
+                                .value_expression
+                                .iter_mut()
+                                .for_each(|x| instruction_reference(kind, x, f)),
+                        }
+                    }
+                }
+                Section::Data(d) => {
+                    for x in d.data_blocks.iter_mut() {
+                        match kind {
+                            IndexKind::Memory => f(&mut x.memory.0),
+                            _ => x
+                                .offset_expression
+                                .iter_mut()
+                                .for_each(|x| instruction_reference(kind, x, f)),
+                        }
+                    }
+                }
+                Section::Global(g) => {
+                    for x in g.globals.iter_mut() {
+                        x.value_expression
+                            .iter_mut()
+                            .for_each(|x| instruction_reference(kind, x, f));
+                    }
+                }
+                Section::Code(c) => {
+                    let mut references = References { kind, f };
+                    for b in c.code_blocks.iter_mut() {
+                        walk_instructions_mut(&mut references, &mut b.instructions);
+                    }
+                }
+                _ => {}
             }
-        };
+        }
+    }
 
-        let type_index = if let Section::Type(s) = type_section {
-            match s
-                .types
-                .iter()
-                .enumerate()
-                .find(|x| x.1.inputs == inputs && x.1.outputs == outputs)
-            {
-                Some(x) => x.0,
-                None => {
-                    s.types.push(FunctionType {
-                        inputs: inputs.to_vec(),
-                        outputs: outputs.to_vec(),
-                    });
-                    s.types.len() - 1
+    // rewrites every reference into one index space, names of removed functions are dropped
+    pub(crate) fn remap_indices(&mut self, kind: IndexKind, map: &dyn Fn(u32) -> Option<u32>) {
+        self.for_each_reference_mut(kind, &mut |i| {
+            if let Some(n) = map(*i) {
+                *i = n;
+            }
+        });
+        if kind != IndexKind::Function {
+            return;
+        }
+        for s in self.sections.iter_mut() {
+            match s {
+                Section::Custom(c) if c.name == "name" => {
+                    let mut names = match wasm_name_section(&c.data) {
+                        Ok(n) => n,
+                        Err(_) => continue,
+                    };
+                    names.function_names = names
+                        .function_names
+                        .into_iter()
+                        .filter_map(|(i, name)| Some((FuncIdx(map(i.0)?), name)))
+                        .collect();
+                    names.local_names = names
+                        .local_names
+                        .into_iter()
+                        .filter_map(|(i, locals)| Some((FuncIdx(map(i.0)?), locals)))
+                        .collect();
+                    let mut data = Vec::new();
+                    names.extend_wasm_bytes(&mut data);
+                    c.data = data.into();
                 }
+                _ => {}
             }
-        } else {
-            unreachable!()
-        };
+        }
+    }
+
+    pub fn create_export<'a>(
+        &'a mut self,
+        name: &str,
+        inputs: &[ValueType],
+        outputs: &[ValueType],
+    ) -> Result<(&'a mut CodeBlock, FuncIdx), &'static str> {
+        let import_count = self.imported_function_count();
+        let type_index = self.find_or_create_type(inputs, outputs);
 
         let function_section = match self
             .sections
@@ -314,8 +396,8 @@ impl Program {
 
         if let Section::Export(s) = exports_section {
             s.exports.push(WasmExport::Function(Export {
-                name: name.to_string(),
-                index: import_count + func_index,
+                name: name.to_string().into(),
+                index: FuncIdx::try_from(import_count + func_index)?,
             }));
         } else {
             unreachable!()
@@ -342,7 +424,10 @@ impl Program {
                 instructions: Vec::new(),
             });
             let idx = s.code_blocks.len() - 1;
-            Ok((&mut s.code_blocks[idx], import_count + func_index))
+            Ok((
+                &mut s.code_blocks[idx],
+                FuncIdx::try_from(import_count + func_index)?,
+            ))
         } else {
             unreachable!()
         }
@@ -352,44 +437,9 @@ impl Program {
         &'a mut self,
         inputs: &[ValueType],
         outputs: &[ValueType],
-    ) -> Result<(&'a mut CodeBlock, usize), &'static str> {
-        let import_count = {
-            let (import_section, _) = self.ensure_imports();
-            import_section.imports.len()
-        };
-        let type_section = match self
-            .sections
-            .iter_mut()
-            .find(|x| matches!(x, Section::Type(_)))
-        {
-            Some(x) => x,
-            None => {
-                self.sections
-                    .push(Section::Type(TypeSection { types: Vec::new() }));
-                let len = self.sections.len() - 1;
-                &mut self.sections[len]
-            }
-        };
-
-        let type_index = if let Section::Type(s) = type_section {
-            match s
-                .types
-                .iter()
-                .enumerate()
-                .find(|x| x.1.inputs == inputs && x.1.outputs == outputs)
-            {
-                Some(x) => x.0,
-                None => {
-                    s.types.push(FunctionType {
-                        inputs: inputs.to_vec(),
-                        outputs: outputs.to_vec(),
-                    });
-                    s.types.len() - 1
-                }
-            }
-        } else {
-            unreachable!()
-        };
+    ) -> Result<(&'a mut CodeBlock, FuncIdx), &'static str> {
+        let import_count = self.imported_function_count();
+        let type_index = self.find_or_create_type(inputs, outputs);
 
         let function_section = match self
             .sections
@@ -434,7 +484,10 @@ impl Program {
                 instructions: Vec::new(),
             });
             let idx = s.code_blocks.len() - 1;
-            Ok((&mut s.code_blocks[idx], import_count + func_index))
+            Ok((
+                &mut s.code_blocks[idx],
+                FuncIdx::try_from(import_count + func_index)?,
+            ))
         } else {
             unreachable!()
         }
@@ -461,7 +514,7 @@ impl Program {
         }
     }
 
-    fn ensure_imports(&mut self) -> (&mut ImportSection, usize) {
+    fn ensure_imports(&mut self) -> (&mut ImportSection<'m>, usize) {
         let idx = match self
             .sections
             .iter()
@@ -482,7 +535,7 @@ impl Program {
         }
     }
 
-    fn ensure_exports(&mut self) -> (&mut ExportSection, usize) {
+    fn ensure_exports(&mut self) -> (&mut ExportSection<'m>, usize) {
         let idx = match self
             .sections
             .iter()
@@ -503,36 +556,272 @@ impl Program {
         }
     }
 
+    fn ensure_globals(&mut self) -> (&mut GlobalSection, usize) {
+        let idx = match self
+            .sections
+            .iter()
+            .enumerate()
+            .find(|x| matches!(x, (_, Section::Global(_))))
+        {
+            Some(x) => x.0,
+            None => {
+                self.sections
+                    .push(Section::Global(GlobalSection { globals: vec![] }));
+                self.sections.len() - 1
+            }
+        };
+        if let Section::Global(s) = &mut self.sections[idx] {
+            (s, idx)
+        } else {
+            unreachable!();
+        }
+    }
+
+    fn ensure_tables(&mut self) -> (&mut TableSection, usize) {
+        let idx = match self
+            .sections
+            .iter()
+            .enumerate()
+            .find(|x| matches!(x, (_, Section::Table(_))))
+        {
+            Some(x) => x.0,
+            None => {
+                self.sections
+                    .push(Section::Table(TableSection { tables: vec![] }));
+                self.sections.len() - 1
+            }
+        };
+        if let Section::Table(s) = &mut self.sections[idx] {
+            (s, idx)
+        } else {
+            unreachable!();
+        }
+    }
+
+    fn ensure_elements(&mut self) -> (&mut ElementSection, usize) {
+        let idx = match self
+            .sections
+            .iter()
+            .enumerate()
+            .find(|x| matches!(x, (_, Section::Element(_))))
+        {
+            Some(x) => x.0,
+            None => {
+                self.sections
+                    .push(Section::Element(ElementSection { elements: vec![] }));
+                self.sections.len() - 1
+            }
+        };
+        if let Section::Element(s) = &mut self.sections[idx] {
+            (s, idx)
+        } else {
+            unreachable!();
+        }
+    }
+
+    fn ensure_data(&mut self) -> (&mut DataSection<'m>, usize) {
+        let idx = match self
+            .sections
+            .iter()
+            .enumerate()
+            .find(|x| matches!(x, (_, Section::Data(_))))
+        {
+            Some(x) => x.0,
+            None => {
+                self.sections.push(Section::Data(DataSection {
+                    data_blocks: vec![],
+                }));
+                self.sections.len() - 1
+            }
+        };
+        if let Section::Data(s) = &mut self.sections[idx] {
+            (s, idx)
+        } else {
+            unreachable!();
+        }
+    }
+
+    fn export(&mut self, export: WasmExport<'m>) {
+        let (export_section, _) = self.ensure_exports();
+        export_section.exports.push(export);
+    }
+
     pub fn create_memory<'a>(
         &'a mut self,
         name: &str,
         min: usize,
         max: Option<usize>,
-    ) -> Result<(&'a mut WasmMemory, usize), &'static str> {
-        let mem_idx;
-        let mem_sec_idx;
-        {
-            let (memory_section, idx) = self.ensure_memories();
-            mem_sec_idx = idx;
-            memory_section.memories.push(WasmMemory {
-                min_pages: min,
-                max_pages: max,
-            });
-            mem_idx = memory_section.memories.len() - 1;
+    ) -> Result<(&'a mut WasmMemory, MemIdx), &'static str> {
+        if self.memories().next().is_some() {
+            return Err("multiple memories");
         }
-        {
-            let (export_section, _) = self.ensure_exports();
+        let index = MemIdx::at(0);
+        let (memory_section, mem_sec_idx) = self.ensure_memories();
+        memory_section.memories.push(WasmMemory {
+            min_pages: min,
+            max_pages: max,
+        });
+        let mem_idx = memory_section.memories.len() - 1;
+        self.export(WasmExport::Memory(Export {
+            name: name.to_string().into(),
+            index,
+        }));
+
+        if let Section::Memory(s) = &mut self.sections[mem_sec_idx] {
+            Ok((&mut s.memories[mem_idx], index))
+        } else {
+            unreachable!();
+        }
+    }
 
-            export_section.exports.push(WasmExport::Memory(Export {
-                name: name.to_string(),
-                index: mem_idx,
+    pub fn create_global<'a>(
+        &'a mut self,
+        name: Option<&str>,
+        value_type: ValueType,
+        is_mutable: bool,
+        value_expression: &[Instruction],
+    ) -> Result<(&'a mut Global, GlobalIdx), &'static str> {
+        let index = GlobalIdx::try_from(self.globals().count())?;
+        let (global_section, global_sec_idx) = self.ensure_globals();
+        global_section.globals.push(Global {
+            value_type,
+            is_mutable,
+            value_expression: value_expression.to_vec(),
+        });
+        let global_idx = global_section.globals.len() - 1;
+        if let Some(name) = name {
+            self.export(WasmExport::Global(Export {
+                name: name.to_string().into(),
+                index,
             }));
         }
 
-        if let Section::Memory(s) = &mut self.sections[mem_sec_idx] {
-            Ok((&mut s.memories[mem_idx], mem_idx))
+        if let Section::Global(s) = &mut self.sections[global_sec_idx] {
+            Ok((&mut s.globals[global_idx], index))
+        } else {
+            unreachable!();
+        }
+    }
+
+    pub fn create_table<'a>(
+        &'a mut self,
+        name: Option<&str>,
+        min: usize,
+        max: Option<usize>,
+    ) -> Result<(&'a mut Table, TableIdx), &'static str> {
+        if self.tables().next().is_some() {
+            return Err("multiple tables");
+        }
+        let index = TableIdx::at(0);
+        let (table_section, table_sec_idx) = self.ensure_tables();
+        table_section.tables.push(Table {
+            element_type: ANYFUNC,
+            min,
+            max,
+        });
+        let table_idx = table_section.tables.len() - 1;
+        if let Some(name) = name {
+            self.export(WasmExport::Table(Export {
+                name: name.to_string().into(),
+                index,
+            }));
+        }
+
+        if let Section::Table(s) = &mut self.sections[table_sec_idx] {
+            Ok((&mut s.tables[table_idx], index))
         } else {
             unreachable!();
         }
     }
+
+    /// Places functions into a table starting at the offset the expression evaluates to.
+    pub fn add_element_segment(
+        &mut self,
+        table: TableIdx,
+        offset_expression: &[Instruction],
+        functions: &[FuncIdx],
+    ) -> Result<(), &'static str> {
+        if table.index() >= self.tables().count() {
+            return Err("table does not exist with that index");
+        }
+        let function_count = self.functions().count();
+        if functions.iter().any(|x| x.index() >= function_count) {
+            return Err("function does not exist with that index");
+        }
+        let (element_section, _) = self.ensure_elements();
+        element_section.elements.push(WasmElement {
+            table,
+            value_expression: offset_expression.to_vec(),
+            functions: functions.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Places bytes into a memory starting at the offset the expression evaluates to.
+    pub fn add_data_segment(
+        &mut self,
+        memory: MemIdx,
+        offset_expression: &[Instruction],
+        data: &[u8],
+    ) -> Result<DataIdx, &'static str> {
+        if memory.index() >= self.memories().count() {
+            return Err("memory does not exist with that index");
+        }
+        let (data_section, _) = self.ensure_data();
+        data_section.data_blocks.push(DataBlock {
+            memory,
+            offset_expression: offset_expression.to_vec(),
+            data: data.to_vec().into(),
+        });
+        DataIdx::try_from(data_section.data_blocks.len() - 1)
+    }
+
+    /// Sets the function run when the module is instantiated, replacing any previous one.
+    pub fn set_start(&mut self, function: FuncIdx) -> Result<(), &'static str> {
+        let function_type = self.function_type(function)?;
+        if !function_type.inputs.is_empty() || !function_type.outputs.is_empty() {
+            return Err("start function can not have parameters or results");
+        }
+        for s in self.sections.iter_mut() {
+            if let Section::Start(start_section) = s {
+                start_section.start_function = function;
+                return Ok(());
+            }
+        }
+        self.sections.push(Section::Start(StartSection {
+            start_function: function,
+        }));
+        Ok(())
+    }
+}
+
+fn instruction_reference(kind: IndexKind, x: &mut Instruction, f: &mut dyn FnMut(&mut u32)) {
+    match (kind, x) {
+        (IndexKind::Function, Instruction::Call(i)) => f(&mut i.0),
+        (IndexKind::Global, Instruction::GlobalGet(i))
+        | (IndexKind::Global, Instruction::GlobalSet(i)) => f(&mut i.0),
+        (IndexKind::Type, Instruction::CallIndirect(i)) => f(&mut i.0),
+        _ => {}
+    }
+}
+
+struct References<'a> {
+    kind: IndexKind,
+    f: &'a mut dyn FnMut(&mut u32),
+}
+
+impl<'a> VisitMut for References<'a> {
+    fn visit_instruction_mut(&mut self, instruction: &mut Instruction) {
+        instruction_reference(self.kind, instruction, self.f);
+        walk_instruction_mut(self, instruction);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum IndexKind {
+    Function,
+    Global,
+    Memory,
+    Table,
+    Type,
 }
@@ -0,0 +1,151 @@
+use super::common::*;
+use super::index::*;
+use super::index_space::*;
+use super::instructions::*;
+use super::program::*;
+use super::visit::*;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+/// Where the hooks of `shim_imports` are imported from. A pre-hook gets the import id followed
+/// by the arguments, a post-hook the import id followed by the results. Each signature gets its
+/// own hook import, named after the hook and the value types, e.g. `before_i32_f64`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ShimHooks<'a> {
+    pub module: &'a str,
+    pub pre_hook: &'a str,
+    pub post_hook: &'a str,
+}
+
+fn hook_name(hook: &str, types: &[ValueType]) -> String {
+    let mut name = String::from(hook);
+    for t in types.iter() {
+        name.push('_');
+        name.push_str(value_type_name(*t));
+    }
+    name
+}
+
+fn hook_inputs(types: &[ValueType]) -> Vec<ValueType> {
+    let mut inputs = vec![ValueType::I32];
+    inputs.extend_from_slice(types);
+    inputs
+}
+
+// points calls to imports at their wrappers, except those made by the wrappers themselves
+struct Redirect<'a> {
+    wrappers: &'a [FuncIdx],
+}
+
+impl<'a> Redirect<'a> {
+    fn redirect(&self, f: &mut FuncIdx) {
+        if let Some(w) = self.wrappers.get(f.index()) {
+            *f = *w;
+        }
+    }
+}
+
+impl<'a> VisitMut for Redirect<'a> {
+    fn visit_code_block_mut(&mut self, index: FuncIdx, code_block: &mut CodeBlock) {
+        if !self.wrappers.contains(&index) {
+            walk_instructions_mut(self, &mut code_block.instructions);
+        }
+    }
+
+    fn visit_call_mut(&mut self, instruction: &mut Instruction) {
+        if let Instruction::Call(f) = instruction {
+            self.redirect(f);
+        }
+    }
+}
+
+impl Module<'_> {
+    fn hook(
+        &mut self,
+        module_name: &str,
+        hook: &str,
+        types: &[ValueType],
+    ) -> Result<FuncIdx, &'static str> {
+        let name = hook_name(hook, types);
+        let existing = self.functions().find(|(_, f)| {
+            matches!(f, FunctionRef::Imported { module, name: n, .. }
+                if *module == module_name && *n == name)
+        });
+        match existing {
+            Some((i, _)) => Ok(i),
+            None => self.import_function(module_name, &name, &hook_inputs(types), &[]),
+        }
+    }
+
+    /// Puts a wrapper in front of every imported function that calls the pre-hook, the import
+    /// and then the post-hook. Calls and table entries that pointed at an import point at its
+    /// wrapper afterwards, the import id handed to the hooks is the index the import had before.
+    /// Returns the wrappers in import id order.
+    pub fn shim_imports(&mut self, hooks: ShimHooks) -> Result<Vec<FuncIdx>, &'static str> {
+        let mut imports: Vec<FunctionType> = Vec::new();
+        for (i, _) in self.functions().take(self.imported_function_count()) {
+            imports.push(self.function_type(i)?.clone());
+        }
+
+        // hooks are imported after the existing imports, so those keep their indices
+        let mut hook_indices = Vec::new();
+        for t in imports.iter() {
+            hook_indices.push((
+                self.hook(hooks.module, hooks.pre_hook, &t.inputs)?,
+                self.hook(hooks.module, hooks.post_hook, &t.outputs)?,
+            ));
+        }
+        let mut wrappers = Vec::new();
+        for (id, t) in imports.iter().enumerate() {
+            let (pre, post) = hook_indices[id];
+            let mut body = vec![Instruction::I32Const(id as i32)];
+            let params = t.inputs.len() as u32;
+            for i in 0..params {
+                body.push(Instruction::LocalGet(LocalIdx(i)));
+            }
+            body.push(Instruction::Call(pre));
+            for i in 0..params {
+                body.push(Instruction::LocalGet(LocalIdx(i)));
+            }
+            body.push(Instruction::Call(FuncIdx::try_from(id)?));
+            // results are parked in locals so the post-hook can see them
+            let results = t.outputs.len() as u32;
+            for i in (0..results).rev() {
+                body.push(Instruction::LocalSet(LocalIdx(params + i)));
+            }
+            body.push(Instruction::I32Const(id as i32));
+            for i in 0..results {
+                body.push(Instruction::LocalGet(LocalIdx(params + i)));
+            }
+            body.push(Instruction::Call(post));
+            for i in 0..results {
+                body.push(Instruction::LocalGet(LocalIdx(params + i)));
+            }
+            let (code_block, index) = self.create_function(&t.inputs, &t.outputs)?;
+            code_block.locals = t
+                .outputs
+                .iter()
+                .map(|v| LocalCount {
+                    count: 1,
+                    value_type: *v,
+                })
+                .collect();
+            code_block.instructions = body;
+            wrappers.push(index);
+        }
+
+        let mut redirect = Redirect {
+            wrappers: &wrappers,
+        };
+        redirect.visit_program_mut(self);
+        for s in self.sections.iter_mut() {
+            if let Section::Element(e) = s {
+                for x in e.elements.iter_mut() {
+                    x.functions.iter_mut().for_each(|f| redirect.redirect(f));
+                }
+            }
+        }
+        Ok(wrappers)
+    }
+}
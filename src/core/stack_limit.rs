@@ -0,0 +1,243 @@
+use super::common::*;
+use super::index::*;
+use super::index_space::*;
+use super::instructions::*;
+use super::program::*;
+use super::visit::*;
+use alloc::vec::Vec;
+use webassembly::EMPTY;
+
+struct HeightContext<'a> {
+    program: &'a Module<'a>,
+    results: usize,
+    labels: Vec<usize>,
+    max: usize,
+}
+
+impl<'a> HeightContext<'a> {
+    fn label(&self, depth: u32) -> Result<usize, &'static str> {
+        let depth = depth as usize;
+        if depth >= self.labels.len() {
+            return Err("branch depth is out of range");
+        }
+        Ok(self.labels[self.labels.len() - 1 - depth])
+    }
+
+    fn stack_effect(&self, i: &Instruction) -> Result<(usize, usize), &'static str> {
+        Ok(match i {
+            Instruction::Br(d) => (self.label(*d)?, 0),
+            Instruction::BrIf(d) => {
+                let n = self.label(*d)?;
+                (n + 1, n)
+            }
+            Instruction::BrTable(_, d) => (self.label(*d)? + 1, 0),
+            Instruction::Return => (self.results, 0),
+            Instruction::Call(f) => {
+                let t = self.program.function_type(*f)?;
+                (t.inputs.len(), t.outputs.len())
+            }
+            Instruction::CallIndirect(t) => {
+                let types = self.program.sections.iter().find_map(|x| match x {
+                    Section::Type(t) => Some(&t.types),
+                    _ => None,
+                });
+                match types.and_then(|types| types.get(t.index())) {
+                    Some(t) => (t.inputs.len() + 1, t.outputs.len()),
+                    None => return Err("function type does not exist with that index"),
+                }
+            }
+            _ => match i.stack_effect() {
+                Some(e) => e,
+                None => return Err("functions with raw instructions can not be analyzed"),
+            },
+        })
+    }
+
+    // walks a block that starts with `height` operands on the stack, code after a branch is
+    // treated like any other so the result can only be too high
+    fn walk(&mut self, instructions: &[Instruction], height: usize) -> Result<(), &'static str> {
+        let mut height = height;
+        for i in instructions.iter() {
+            let (pops, pushes) = self.stack_effect(i)?;
+            height = height.saturating_sub(pops);
+            match i {
+                Instruction::Block(t, body) => self.walk_block(block_arity(*t), body, height)?,
+                Instruction::Loop(_, body) => self.walk_block(0, body, height)?,
+                Instruction::If(t, then, otherwise) => {
+                    self.walk_block(block_arity(*t), then, height)?;
+                    if let Some(otherwise) = otherwise {
+                        self.walk_block(block_arity(*t), otherwise, height)?;
+                    }
+                }
+                _ => {}
+            }
+            height += pushes;
+            if height > self.max {
+                self.max = height;
+            }
+        }
+        Ok(())
+    }
+
+    fn walk_block(
+        &mut self,
+        label: usize,
+        instructions: &[Instruction],
+        height: usize,
+    ) -> Result<(), &'static str> {
+        self.labels.push(label);
+        let result = self.walk(instructions, height);
+        self.labels.pop();
+        result
+    }
+}
+
+fn adjust_height(height: GlobalIdx, cost: u32, grow: bool) -> Vec<Instruction> {
+    vec![
+        Instruction::GlobalGet(height),
+        Instruction::I32Const(cost as i32),
+        if grow {
+            Instruction::I32Add
+        } else {
+            Instruction::I32Sub
+        },
+        Instruction::GlobalSet(height),
+    ]
+}
+
+// a call that counts the frame of the callee against the limit for as long as it runs
+fn limited_call(f: FuncIdx, cost: u32, height: GlobalIdx, limit: u32) -> Vec<Instruction> {
+    let mut call = adjust_height(height, cost, true);
+    call.extend(vec![
+        Instruction::GlobalGet(height),
+        Instruction::I32Const(limit as i32),
+        Instruction::I32GtU,
+        Instruction::If(EMPTY, vec![Instruction::Unreachable], None),
+        Instruction::Call(f),
+    ]);
+    call.extend(adjust_height(height, cost, false));
+    call
+}
+
+// `call_indirect` is left alone, it can only reach table entries and those become thunks
+struct LimitCalls<'a> {
+    costs: &'a [u32],
+    height: GlobalIdx,
+    limit: u32,
+}
+
+impl<'a> Fold for LimitCalls<'a> {
+    fn fold_instruction(&mut self, instruction: Instruction) -> Vec<Instruction> {
+        match instruction {
+            Instruction::Call(f) if self.costs[f.index()] > 0 => {
+                limited_call(f, self.costs[f.index()], self.height, self.limit)
+            }
+            i => vec![i],
+        }
+    }
+}
+
+impl Module<'_> {
+    /// The most operands the body of a defined function can have on the stack at once.
+    pub fn max_stack_height(&self, index: FuncIdx) -> Result<usize, &'static str> {
+        let body = match self.function(index)? {
+            FunctionRef::Defined { body, .. } => body,
+            FunctionRef::Imported { .. } => return Err("imported functions have no body"),
+        };
+        let mut context = HeightContext {
+            program: self,
+            results: self.function_type(index)?.outputs.len(),
+            labels: Vec::new(),
+            max: 0,
+        };
+        context.walk_block(context.results, body, 0)?;
+        Ok(context.max)
+    }
+
+    /// Counts the operands and locals of every running function in a global and traps once a
+    /// call would take it past `limit`. Calls between defined functions are counted where they
+    /// happen, exports, table entries and the start function are routed through thunks that
+    /// count the call. `call_indirect` is not instrumented, every function it can reach is a
+    /// table entry and so already a thunk.
+    pub fn limit_stack_height(&mut self, limit: u32) -> Result<(), &'static str> {
+        let mut costs = Vec::new();
+        for (i, f) in self.functions() {
+            costs.push(match f {
+                FunctionRef::Imported { .. } => 0,
+                FunctionRef::Defined { locals, .. } => {
+                    let locals: usize = locals.iter().map(|l| l.count as usize).sum();
+                    let params = self.function_type(i)?.inputs.len();
+                    (self.max_stack_height(i)? + params + locals) as u32
+                }
+            });
+        }
+        let (_, height) =
+            self.create_global(None, ValueType::I32, true, &[Instruction::I32Const(0)])?;
+        LimitCalls {
+            costs: &costs,
+            height,
+            limit,
+        }
+        .fold_program(self);
+
+        let mut entries: Vec<FuncIdx> = Vec::new();
+        for s in self.sections.iter() {
+            match s {
+                Section::Export(e) => entries.extend(e.exports.iter().filter_map(|x| match x {
+                    WasmExport::Function(x) => Some(x.index),
+                    _ => None,
+                })),
+                Section::Element(e) => {
+                    for x in e.elements.iter() {
+                        entries.extend(x.functions.iter().copied());
+                    }
+                }
+                Section::Start(s) => entries.push(s.start_function),
+                _ => {}
+            }
+        }
+        let mut thunks: Vec<(FuncIdx, FuncIdx)> = Vec::new();
+        for f in entries.into_iter() {
+            let cost = match costs.get(f.index()) {
+                Some(c) => *c,
+                None => return Err("function does not exist with that index"),
+            };
+            if cost == 0 || thunks.iter().any(|(x, _)| *x == f) {
+                continue;
+            }
+            let t = self.function_type(f)?.clone();
+            let (code_block, thunk) = self.create_function(&t.inputs, &t.outputs)?;
+            code_block.instructions = (0..t.inputs.len() as u32)
+                .map(|i| Instruction::LocalGet(LocalIdx(i)))
+                .collect();
+            code_block
+                .instructions
+                .extend(limited_call(f, cost, height, limit));
+            thunks.push((f, thunk));
+        }
+        let thunk = |f: &mut FuncIdx| {
+            if let Some((_, t)) = thunks.iter().find(|(x, _)| x == f) {
+                *f = *t;
+            }
+        };
+        for s in self.sections.iter_mut() {
+            match s {
+                Section::Export(e) => {
+                    for x in e.exports.iter_mut() {
+                        if let WasmExport::Function(x) = x {
+                            thunk(&mut x.index);
+                        }
+                    }
+                }
+                Section::Element(e) => {
+                    for x in e.elements.iter_mut() {
+                        x.functions.iter_mut().for_each(thunk);
+                    }
+                }
+                Section::Start(s) => thunk(&mut s.start_function),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,499 @@
+use super::common::*;
+use super::index::*;
+use super::index_space::*;
+use super::instructions::*;
+use super::program::*;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use serde::{Deserialize, Serialize};
+use webassembly::EMPTY;
+
+/// The operand stack around one instruction, bottom first. A type is `None` where code after
+/// an unconditional branch pops more operands than it pushed, any type would do there.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub struct StackTypes {
+    pub before: Vec<Option<ValueType>>,
+    pub after: Vec<Option<ValueType>>,
+    /// Whether the instruction can run at all.
+    pub reachable: bool,
+}
+
+// the operands an instruction pops and pushes when they do not depend on its context
+fn signature(i: &Instruction) -> Option<(&'static [ValueType], &'static [ValueType])> {
+    Some(match i {
+        Instruction::MemorySize | Instruction::I32Const(_) => (&[], &[ValueType::I32]),
+        Instruction::I64Const(_) => (&[], &[ValueType::I64]),
+        Instruction::F32Const(_) => (&[], &[ValueType::F32]),
+        Instruction::F64Const(_) => (&[], &[ValueType::F64]),
+        Instruction::MemoryGrow
+        | Instruction::I32Eqz
+        | Instruction::I32Clz
+        | Instruction::I32Ctz
+        | Instruction::I32Popcnt
+        | Instruction::I32Load(_, _)
+        | Instruction::I32Load8S(_, _)
+        | Instruction::I32Load8U(_, _)
+        | Instruction::I32Load16S(_, _)
+        | Instruction::I32Load16U(_, _) => (&[ValueType::I32], &[ValueType::I32]),
+        Instruction::I64Load(_, _)
+        | Instruction::I64Load8S(_, _)
+        | Instruction::I64Load8U(_, _)
+        | Instruction::I64Load16S(_, _)
+        | Instruction::I64Load16U(_, _)
+        | Instruction::I64Load32S(_, _)
+        | Instruction::I64Load32U(_, _)
+        | Instruction::I64ExtendSI32
+        | Instruction::I64ExtendUI32 => (&[ValueType::I32], &[ValueType::I64]),
+        Instruction::F32Load(_, _)
+        | Instruction::F32ConvertSI32
+        | Instruction::F32ConvertUI32
+        | Instruction::F32ReinterpretI32 => (&[ValueType::I32], &[ValueType::F32]),
+        Instruction::F64Load(_, _) | Instruction::F64ConvertSI32 | Instruction::F64ConvertUI32 => {
+            (&[ValueType::I32], &[ValueType::F64])
+        }
+        Instruction::I32Store(_, _)
+        | Instruction::I32Store8(_, _)
+        | Instruction::I32Store16(_, _) => (&[ValueType::I32, ValueType::I32], &[]),
+        Instruction::I64Store(_, _)
+        | Instruction::I64Store8(_, _)
+        | Instruction::I64Store16(_, _)
+        | Instruction::I64Store32(_, _) => (&[ValueType::I32, ValueType::I64], &[]),
+        Instruction::F32Store(_, _) => (&[ValueType::I32, ValueType::F32], &[]),
+        Instruction::F64Store(_, _) => (&[ValueType::I32, ValueType::F64], &[]),
+        Instruction::I32Eq
+        | Instruction::I32Ne
+        | Instruction::I32LtS
+        | Instruction::I32LtU
+        | Instruction::I32GtS
+        | Instruction::I32GtU
+        | Instruction::I32LeS
+        | Instruction::I32LeU
+        | Instruction::I32GeS
+        | Instruction::I32GeU
+        | Instruction::I32Add
+        | Instruction::I32Sub
+        | Instruction::I32Mul
+        | Instruction::I32DivS
+        | Instruction::I32DivU
+        | Instruction::I32RemS
+        | Instruction::I32RemU
+        | Instruction::I32And
+        | Instruction::I32Or
+        | Instruction::I32Xor
+        | Instruction::I32Shl
+        | Instruction::I32ShrS
+        | Instruction::I32ShrU
+        | Instruction::I32Rotl
+        | Instruction::I32Rotr => (&[ValueType::I32, ValueType::I32], &[ValueType::I32]),
+        Instruction::I64Eqz | Instruction::I32wrapF64 => (&[ValueType::I64], &[ValueType::I32]),
+        Instruction::I64Eq
+        | Instruction::I64Ne
+        | Instruction::I64LtS
+        | Instruction::I64LtU
+        | Instruction::I64GtS
+        | Instruction::I64GtU
+        | Instruction::I64LeS
+        | Instruction::I64LeU
+        | Instruction::I64GeS
+        | Instruction::I64GeU => (&[ValueType::I64, ValueType::I64], &[ValueType::I32]),
+        Instruction::I64Clz | Instruction::I64Ctz | Instruction::I64Popcnt => {
+            (&[ValueType::I64], &[ValueType::I64])
+        }
+        Instruction::I64Add
+        | Instruction::I64Sub
+        | Instruction::I64Mul
+        | Instruction::I64DivS
+        | Instruction::I64DivU
+        | Instruction::I64RemS
+        | Instruction::I64RemU
+        | Instruction::I64And
+        | Instruction::I64Or
+        | Instruction::I64Xor
+        | Instruction::I64Shl
+        | Instruction::I64ShrS
+        | Instruction::I64ShrU
+        | Instruction::I64Rotl
+        | Instruction::I64Rotr => (&[ValueType::I64, ValueType::I64], &[ValueType::I64]),
+        Instruction::F32Eq
+        | Instruction::F32Ne
+        | Instruction::F32Lt
+        | Instruction::F32Gt
+        | Instruction::F32Le
+        | Instruction::F32Ge => (&[ValueType::F32, ValueType::F32], &[ValueType::I32]),
+        Instruction::F64Eq
+        | Instruction::F64Ne
+        | Instruction::F64Lt
+        | Instruction::F64Gt
+        | Instruction::F64Le
+        | Instruction::F64Ge => (&[ValueType::F64, ValueType::F64], &[ValueType::I32]),
+        Instruction::F32Abs
+        | Instruction::F32Neg
+        | Instruction::F32Ceil
+        | Instruction::F32Floor
+        | Instruction::F32Trunc
+        | Instruction::F32Nearest
+        | Instruction::F32Sqrt => (&[ValueType::F32], &[ValueType::F32]),
+        Instruction::F32Add
+        | Instruction::F32Sub
+        | Instruction::F32Mul
+        | Instruction::F32Div
+        | Instruction::F32Min
+        | Instruction::F32Max
+        | Instruction::F32Copysign => (&[ValueType::F32, ValueType::F32], &[ValueType::F32]),
+        Instruction::F64Abs
+        | Instruction::F64Neg
+        | Instruction::F64Ceil
+        | Instruction::F64Floor
+        | Instruction::F64Trunc
+        | Instruction::F64Nearest
+        | Instruction::F64Sqrt => (&[ValueType::F64], &[ValueType::F64]),
+        Instruction::F64Add
+        | Instruction::F64Sub
+        | Instruction::F64Mul
+        | Instruction::F64Div
+        | Instruction::F64Min
+        | Instruction::F64Max
+        | Instruction::F64Copysign => (&[ValueType::F64, ValueType::F64], &[ValueType::F64]),
+        Instruction::I32TruncSF32 | Instruction::I32TruncUF32 | Instruction::I32ReinterpretF32 => {
+            (&[ValueType::F32], &[ValueType::I32])
+        }
+        Instruction::I32TruncSF64 | Instruction::I32TruncUF64 => {
+            (&[ValueType::F64], &[ValueType::I32])
+        }
+        Instruction::I64TruncSF32 | Instruction::I64TruncUF32 => {
+            (&[ValueType::F32], &[ValueType::I64])
+        }
+        Instruction::I64TruncSF64 | Instruction::I64TruncUF64 | Instruction::I64ReinterpretF64 => {
+            (&[ValueType::F64], &[ValueType::I64])
+        }
+        Instruction::F32ConvertSI64 | Instruction::F32ConvertUI64 => {
+            (&[ValueType::I64], &[ValueType::F32])
+        }
+        Instruction::F32DemoteF64 => (&[ValueType::F64], &[ValueType::F32]),
+        Instruction::F64ConvertSI64
+        | Instruction::F64ConvertUI64
+        | Instruction::F64ReinterpretI64 => (&[ValueType::I64], &[ValueType::F64]),
+        Instruction::F64PromoteF32 => (&[ValueType::F32], &[ValueType::F64]),
+        _ => return None,
+    })
+}
+
+fn block_results(t: u8) -> Result<Vec<ValueType>, &'static str> {
+    if t == EMPTY {
+        Ok(Vec::new())
+    } else {
+        Ok(vec![ValueType::try_from(t)?])
+    }
+}
+
+struct Frame {
+    labels: Vec<ValueType>,
+    results: Vec<ValueType>,
+    height: usize,
+    // the validation algorithm's flag, the stack below the frame is unknown after a branch
+    unreachable: bool,
+    // whether the current position in the frame can be reached and whether its end can
+    live: bool,
+    end_reached: bool,
+    is_loop: bool,
+}
+
+struct Typing<'a> {
+    program: &'a Module<'a>,
+    types: &'a [FunctionType],
+    locals: Vec<ValueType>,
+    has_memory: bool,
+    has_table: bool,
+    values: Vec<Option<ValueType>>,
+    frames: Vec<Frame>,
+    out: Vec<StackTypes>,
+}
+
+impl<'a> Typing<'a> {
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn push(&mut self, t: Option<ValueType>) {
+        self.values.push(t);
+    }
+
+    fn pop(&mut self) -> Result<Option<ValueType>, &'static str> {
+        let frame = self.frames.last().unwrap();
+        if self.values.len() == frame.height {
+            if frame.unreachable {
+                return Ok(None);
+            }
+            return Err("type mismatch");
+        }
+        Ok(self.values.pop().unwrap())
+    }
+
+    fn pop_expect(&mut self, expected: ValueType) -> Result<(), &'static str> {
+        match self.pop()? {
+            Some(t) if t != expected => Err("type mismatch"),
+            _ => Ok(()),
+        }
+    }
+
+    fn pop_all(&mut self, expected: &[ValueType]) -> Result<(), &'static str> {
+        for t in expected.iter().rev() {
+            self.pop_expect(*t)?;
+        }
+        Ok(())
+    }
+
+    fn push_all(&mut self, types: &[ValueType]) {
+        for t in types.iter() {
+            self.push(Some(*t));
+        }
+    }
+
+    fn unreachable(&mut self) {
+        let frame = self.frames.last_mut().unwrap();
+        frame.unreachable = true;
+        frame.live = false;
+        let height = frame.height;
+        self.values.truncate(height);
+    }
+
+    fn enter(&mut self, labels: Vec<ValueType>, results: Vec<ValueType>, is_loop: bool) {
+        let live = self.frames.last().map(|f| f.live).unwrap_or(true);
+        self.frames.push(Frame {
+            labels,
+            results,
+            height: self.values.len(),
+            unreachable: false,
+            live,
+            end_reached: false,
+            is_loop,
+        });
+    }
+
+    fn exit(&mut self) -> Result<Frame, &'static str> {
+        let results = self.frames.last().unwrap().results.clone();
+        self.pop_all(&results)?;
+        let mut frame = self.frames.pop().unwrap();
+        if self.values.len() != frame.height {
+            return Err("type mismatch");
+        }
+        frame.end_reached |= frame.live;
+        Ok(frame)
+    }
+
+    // the types a branch to the label carries, marking where it lands as reachable
+    fn branch(&mut self, depth: u32) -> Result<Vec<ValueType>, &'static str> {
+        let depth = depth as usize;
+        if depth >= self.frames.len() {
+            return Err("unknown label");
+        }
+        let live = self.frames.last().unwrap().live;
+        let index = self.frames.len() - 1 - depth;
+        let target = &mut self.frames[index];
+        if live && !target.is_loop {
+            target.end_reached = true;
+        }
+        Ok(target.labels.clone())
+    }
+
+    fn local(&self, l: LocalIdx) -> Result<ValueType, &'static str> {
+        match self.locals.get(l.index()) {
+            Some(t) => Ok(*t),
+            None => Err("unknown local"),
+        }
+    }
+
+    fn instructions(&mut self, instructions: &[Instruction]) -> Result<(), &'static str> {
+        for i in instructions.iter() {
+            let index = self.out.len();
+            self.out.push(StackTypes {
+                before: self.values.clone(),
+                after: Vec::new(),
+                reachable: self.frames.last().unwrap().live,
+            });
+            self.instruction(i)?;
+            self.out[index].after = self.values.clone();
+        }
+        Ok(())
+    }
+
+    fn body(&mut self, t: u8, body: &[Instruction], is_loop: bool) -> Result<Frame, &'static str> {
+        let results = block_results(t)?;
+        let labels = if is_loop { Vec::new() } else { results.clone() };
+        self.enter(labels, results, is_loop);
+        self.instructions(body)?;
+        self.exit()
+    }
+
+    // what follows a block is reachable when its end is
+    fn after_block(&mut self, t: u8, end_reached: bool) -> Result<(), &'static str> {
+        self.push_all(&block_results(t)?);
+        self.frame().live = end_reached;
+        Ok(())
+    }
+
+    fn instruction(&mut self, i: &Instruction) -> Result<(), &'static str> {
+        if let Some((pops, pushes)) = signature(i) {
+            if i.category() == InstructionCategory::Memory && !self.has_memory {
+                return Err("unknown memory");
+            }
+            self.pop_all(pops)?;
+            self.push_all(pushes);
+            return Ok(());
+        }
+        match i {
+            Instruction::Raw(_) => {
+                return Err("functions with raw instructions can not be analyzed");
+            }
+            Instruction::Unreachable => self.unreachable(),
+            Instruction::Nop => {}
+            Instruction::Block(t, body) => {
+                let frame = self.body(*t, body, false)?;
+                self.after_block(*t, frame.end_reached)?;
+            }
+            Instruction::Loop(t, body) => {
+                let frame = self.body(*t, body, true)?;
+                self.after_block(*t, frame.end_reached)?;
+            }
+            Instruction::If(t, then, otherwise) => {
+                self.pop_expect(ValueType::I32)?;
+                let live = self.frame().live;
+                let then = self.body(*t, then, false)?;
+                let end_reached = match otherwise {
+                    Some(otherwise) => {
+                        let otherwise = self.body(*t, otherwise, false)?;
+                        then.end_reached || otherwise.end_reached
+                    }
+                    None => {
+                        if !block_results(*t)?.is_empty() {
+                            return Err("type mismatch");
+                        }
+                        then.end_reached || live
+                    }
+                };
+                self.after_block(*t, end_reached)?;
+            }
+            Instruction::Br(d) => {
+                let labels = self.branch(*d)?;
+                self.pop_all(&labels)?;
+                self.unreachable();
+            }
+            Instruction::BrIf(d) => {
+                self.pop_expect(ValueType::I32)?;
+                let labels = self.branch(*d)?;
+                self.pop_all(&labels)?;
+                self.push_all(&labels);
+            }
+            Instruction::BrTable(targets, default) => {
+                self.pop_expect(ValueType::I32)?;
+                let labels = self.branch(*default)?;
+                for d in targets.iter() {
+                    if self.branch(*d)? != labels {
+                        return Err("type mismatch");
+                    }
+                }
+                self.pop_all(&labels)?;
+                self.unreachable();
+            }
+            Instruction::Return => {
+                let results = self.frames[0].results.clone();
+                self.pop_all(&results)?;
+                self.unreachable();
+            }
+            Instruction::Call(f) => {
+                let t = self.program.function_type(*f)?;
+                self.pop_all(&t.inputs)?;
+                self.push_all(&t.outputs);
+            }
+            Instruction::CallIndirect(t) => {
+                if !self.has_table {
+                    return Err("unknown table");
+                }
+                let t = match self.types.get(t.index()) {
+                    Some(t) => t,
+                    None => return Err("unknown type"),
+                };
+                self.pop_expect(ValueType::I32)?;
+                self.pop_all(&t.inputs)?;
+                self.push_all(&t.outputs);
+            }
+            Instruction::Drop => {
+                self.pop()?;
+            }
+            Instruction::Select => {
+                self.pop_expect(ValueType::I32)?;
+                let a = self.pop()?;
+                let b = self.pop()?;
+                if a.is_some() && b.is_some() && a != b {
+                    return Err("type mismatch");
+                }
+                self.push(a.or(b));
+            }
+            Instruction::LocalGet(l) => {
+                let t = self.local(*l)?;
+                self.push(Some(t));
+            }
+            Instruction::LocalSet(l) => {
+                let t = self.local(*l)?;
+                self.pop_expect(t)?;
+            }
+            Instruction::LocalTee(l) => {
+                let t = self.local(*l)?;
+                self.pop_expect(t)?;
+                self.push(Some(t));
+            }
+            Instruction::GlobalGet(g) => match self.program.global(*g) {
+                Ok(global) => self.push(Some(global.value_type())),
+                Err(_) => return Err("unknown global"),
+            },
+            Instruction::GlobalSet(g) => match self.program.global(*g) {
+                Ok(global) if global.is_mutable() => self.pop_expect(global.value_type())?,
+                Ok(_) => return Err("global is immutable"),
+                Err(_) => return Err("unknown global"),
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl Module<'_> {
+    /// Types the operand stack around every instruction of a defined function with the
+    /// validation rules of the spec, in the order the instructions appear in the text format.
+    /// Fails the way validation would for a function that is not well typed.
+    pub fn stack_types(&self, index: FuncIdx) -> Result<Vec<StackTypes>, &'static str> {
+        let (locals, body) = match self.function(index)? {
+            FunctionRef::Defined { locals, body, .. } => (locals, body),
+            FunctionRef::Imported { .. } => return Err("imported functions have no body"),
+        };
+        let function_type = self.function_type(index)?;
+        let mut typing = Typing {
+            program: self,
+            types: self
+                .sections
+                .iter()
+                .find_map(|x| match x {
+                    Section::Type(t) => Some(&t.types[..]),
+                    _ => None,
+                })
+                .unwrap_or(&[]),
+            locals: function_type.inputs.clone(),
+            has_memory: self.memories().next().is_some(),
+            has_table: self.tables().next().is_some(),
+            values: Vec::new(),
+            frames: Vec::new(),
+            out: Vec::new(),
+        };
+        for l in locals.iter() {
+            for _ in 0..l.count {
+                typing.locals.push(l.value_type);
+            }
+        }
+        let results = function_type.outputs.clone();
+        typing.enter(results.clone(), results, false);
+        typing.instructions(body)?;
+        typing.exit()?;
+        Ok(typing.out)
+    }
+}
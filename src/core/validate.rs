@@ -0,0 +1,270 @@
+use super::common::*;
+use super::index::*;
+use super::instructions::*;
+use super::program::*;
+use super::visit::*;
+use alloc::vec::Vec;
+
+const MAX_PAGES: usize = 65536;
+
+fn error(section: usize, message: &'static str) -> CompileError {
+    CompileError {
+        section,
+        function: None,
+        message,
+    }
+}
+
+fn limits(min: usize, max: Option<usize>, limit: usize) -> Result<(), &'static str> {
+    if min > limit || max.map(|x| x > limit) == Some(true) {
+        return Err("size must be at most the limit");
+    }
+    if max.map(|x| x < min) == Some(true) {
+        return Err("size minimum must not be greater than maximum");
+    }
+    Ok(())
+}
+
+// finds the first load or store that claims more than its natural alignment
+struct Alignment {
+    result: Result<(), &'static str>,
+}
+
+impl Visit for Alignment {
+    fn visit_memory(&mut self, instruction: &Instruction) {
+        if let Some(align) = memory_alignment(instruction) {
+            if align > natural_alignment(instruction) && self.result.is_ok() {
+                self.result = Err("alignment must not be larger than natural");
+            }
+        }
+    }
+}
+
+impl Default for Alignment {
+    fn default() -> Self {
+        Alignment { result: Ok(()) }
+    }
+}
+
+impl Module<'_> {
+    // constant expressions are a single constant or an immutable imported global
+    fn const_expression(
+        &self,
+        expression: &[Instruction],
+        imported_globals: usize,
+        expected: ValueType,
+    ) -> Result<(), &'static str> {
+        let value_type = match expression {
+            [Instruction::I32Const(_)] => ValueType::I32,
+            [Instruction::I64Const(_)] => ValueType::I64,
+            [Instruction::F32Const(_)] => ValueType::F32,
+            [Instruction::F64Const(_)] => ValueType::F64,
+            [Instruction::GlobalGet(g)] => match self.global(*g) {
+                Ok(global) if g.index() < imported_globals => {
+                    if global.is_mutable() {
+                        return Err("constant expression required");
+                    }
+                    global.value_type()
+                }
+                _ => return Err("unknown global"),
+            },
+            _ => {
+                let constant = expression.iter().all(|i| {
+                    matches!(
+                        i,
+                        Instruction::I32Const(_)
+                            | Instruction::I64Const(_)
+                            | Instruction::F32Const(_)
+                            | Instruction::F64Const(_)
+                            | Instruction::GlobalGet(_)
+                    )
+                });
+                if constant {
+                    return Err("type mismatch");
+                }
+                return Err("constant expression required");
+            }
+        };
+        if value_type != expected {
+            return Err("type mismatch");
+        }
+        Ok(())
+    }
+
+    /// Checks the module the way a runtime does before instantiating it: duplicate sections,
+    /// lengths, limits, indices, constant expressions, exports and the operand types of every
+    /// function body. The order of `sections` is not checked, `compile` emits them in the order
+    /// of the spec. The error names the position of the offending section in `sections` and the
+    /// function when it is about a body.
+    pub fn validate(&self) -> Result<(), CompileError> {
+        let types = self
+            .sections
+            .iter()
+            .find_map(|s| match s {
+                Section::Type(t) => Some(&t.types[..]),
+                _ => None,
+            })
+            .unwrap_or(&[]);
+        let imported_globals = self.globals().filter(|(_, g)| g.is_imported()).count();
+        let imported_functions = self.imported_function_count();
+        let mut function_types: Vec<TypeIdx> = Vec::new();
+        let (mut tables, mut memories) = (0, 0);
+        let (mut function_section, mut code) = (None, None);
+        for (i, s) in self.sections.iter().enumerate() {
+            let is_custom = matches!(s, Section::Custom(_));
+            if !is_custom && self.sections[..i].iter().any(|x| x.id() == s.id()) {
+                return Err(error(i, "duplicate section"));
+            }
+            match s {
+                Section::Import(x) => {
+                    for import in x.imports.iter() {
+                        match import {
+                            WasmImport::Function(f) => function_types.push(f.type_index),
+                            WasmImport::Table(t) => {
+                                limits(t.min, t.max, u32::MAX as usize).map_err(|e| error(i, e))?;
+                                tables += 1;
+                            }
+                            WasmImport::Memory(m) => {
+                                limits(m.min_pages, m.max_pages, MAX_PAGES)
+                                    .map_err(|e| error(i, e))?;
+                                memories += 1;
+                            }
+                            WasmImport::Global(_) => {}
+                        }
+                    }
+                }
+                Section::Function(f) => {
+                    function_section = Some(i);
+                    function_types.extend(f.function_types.iter());
+                }
+                Section::Table(t) => {
+                    for x in t.tables.iter() {
+                        limits(x.min, x.max, u32::MAX as usize).map_err(|e| error(i, e))?;
+                    }
+                    tables += t.tables.len();
+                }
+                Section::Memory(m) => {
+                    for x in m.memories.iter() {
+                        limits(x.min_pages, x.max_pages, MAX_PAGES).map_err(|e| error(i, e))?;
+                    }
+                    memories += m.memories.len();
+                }
+                Section::Global(g) => {
+                    for x in g.globals.iter() {
+                        self.const_expression(&x.value_expression, imported_globals, x.value_type)
+                            .map_err(|e| error(i, e))?;
+                    }
+                }
+                Section::Code(c) => code = Some((i, c)),
+                _ => {}
+            }
+            if tables > 1 {
+                return Err(error(i, "multiple tables"));
+            }
+            if memories > 1 {
+                return Err(error(i, "multiple memories"));
+            }
+            if function_types.iter().any(|t| t.index() >= types.len()) {
+                return Err(error(i, "unknown type"));
+            }
+        }
+        let bodies = code.map(|(_, c)| c.code_blocks.len()).unwrap_or(0);
+        if bodies != function_types.len() - imported_functions {
+            return Err(error(
+                code.map(|(i, _)| i).or(function_section).unwrap_or(0),
+                "function and code section have inconsistent lengths",
+            ));
+        }
+
+        let globals = self.globals().count();
+        let mut names: Vec<&str> = Vec::new();
+        for (i, s) in self.sections.iter().enumerate() {
+            match s {
+                Section::Export(e) => {
+                    for x in e.exports.iter() {
+                        let (name, index, count, message) = match x {
+                            WasmExport::Function(e) => (
+                                &*e.name,
+                                e.index.index(),
+                                function_types.len(),
+                                "unknown function",
+                            ),
+                            WasmExport::Global(e) => {
+                                (&*e.name, e.index.index(), globals, "unknown global")
+                            }
+                            WasmExport::Table(e) => {
+                                (&*e.name, e.index.index(), tables, "unknown table")
+                            }
+                            WasmExport::Memory(e) => {
+                                (&*e.name, e.index.index(), memories, "unknown memory")
+                            }
+                        };
+                        if index >= count {
+                            return Err(error(i, message));
+                        }
+                        if names.contains(&name) {
+                            return Err(error(i, "duplicate export name"));
+                        }
+                        names.push(name);
+                    }
+                }
+                Section::Start(x) => match function_types.get(x.start_function.index()) {
+                    Some(t)
+                        if types[t.index()].inputs.is_empty()
+                            && types[t.index()].outputs.is_empty() => {}
+                    Some(_) => return Err(error(i, "start function")),
+                    None => return Err(error(i, "unknown function")),
+                },
+                Section::Element(e) => {
+                    for x in e.elements.iter() {
+                        if x.table.index() >= tables {
+                            return Err(error(i, "unknown table"));
+                        }
+                        self.const_expression(
+                            &x.value_expression,
+                            imported_globals,
+                            ValueType::I32,
+                        )
+                        .map_err(|e| error(i, e))?;
+                        if x.functions
+                            .iter()
+                            .any(|f| f.index() >= function_types.len())
+                        {
+                            return Err(error(i, "unknown function"));
+                        }
+                    }
+                }
+                Section::Data(d) => {
+                    for x in d.data_blocks.iter() {
+                        if x.memory.index() >= memories {
+                            return Err(error(i, "unknown memory"));
+                        }
+                        self.const_expression(
+                            &x.offset_expression,
+                            imported_globals,
+                            ValueType::I32,
+                        )
+                        .map_err(|e| error(i, e))?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((section, c)) = code {
+            for (i, b) in c.code_blocks.iter().enumerate() {
+                let index = FuncIdx::at(imported_functions + i);
+                let located = |message| CompileError {
+                    section,
+                    function: Some(index),
+                    message,
+                };
+                let mut alignment = Alignment::default();
+                alignment.visit_code_block(index, b);
+                alignment.result.map_err(located)?;
+                self.stack_types(index).map_err(located)?;
+            }
+        }
+        Ok(())
+    }
+}
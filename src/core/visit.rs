@@ -0,0 +1,337 @@
+use super::common::*;
+use super::index::*;
+use super::instructions::*;
+use super::program::*;
+use alloc::vec::Vec;
+
+/// Which body `enter_block` and `exit_block` are about, the else arm of an `if` is a block
+/// of its own that follows the then arm.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BlockKind {
+    Block,
+    Loop,
+    If,
+    Else,
+}
+
+/// Walks instructions in order, calling the method of each instruction's category. Structured
+/// instructions are passed to `visit_control` before their bodies are walked.
+pub trait Visit {
+    fn visit_program(&mut self, program: &Module) {
+        walk_program(self, program)
+    }
+
+    fn visit_code_block(&mut self, _index: FuncIdx, code_block: &CodeBlock) {
+        walk_instructions(self, &code_block.instructions)
+    }
+
+    /// Initializers of globals and offsets of element and data segments.
+    fn visit_const_expression(&mut self, expression: &[Instruction]) {
+        walk_instructions(self, expression)
+    }
+
+    fn visit_instruction(&mut self, instruction: &Instruction) {
+        walk_instruction(self, instruction)
+    }
+
+    fn enter_block(&mut self, _kind: BlockKind, _block_type: u8) {}
+    fn exit_block(&mut self, _kind: BlockKind) {}
+    fn visit_control(&mut self, _instruction: &Instruction) {}
+    fn visit_call(&mut self, _instruction: &Instruction) {}
+    fn visit_parametric(&mut self, _instruction: &Instruction) {}
+    fn visit_variable(&mut self, _instruction: &Instruction) {}
+    fn visit_memory(&mut self, _instruction: &Instruction) {}
+    fn visit_numeric(&mut self, _instruction: &Instruction) {}
+    fn visit_raw(&mut self, _byte: u8) {}
+}
+
+pub fn walk_program<V: Visit + ?Sized>(v: &mut V, program: &Module) {
+    let mut next_function = program.imported_function_count();
+    for s in program.sections.iter() {
+        match s {
+            Section::Code(c) => {
+                for b in c.code_blocks.iter() {
+                    v.visit_code_block(FuncIdx::at(next_function), b);
+                    next_function += 1;
+                }
+            }
+            Section::Global(g) => {
+                for x in g.globals.iter() {
+                    v.visit_const_expression(&x.value_expression);
+                }
+            }
+            Section::Element(e) => {
+                for x in e.elements.iter() {
+                    v.visit_const_expression(&x.value_expression);
+                }
+            }
+            Section::Data(d) => {
+                for x in d.data_blocks.iter() {
+                    v.visit_const_expression(&x.offset_expression);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn walk_instructions<V: Visit + ?Sized>(v: &mut V, instructions: &[Instruction]) {
+    for i in instructions.iter() {
+        v.visit_instruction(i);
+    }
+}
+
+fn walk_body<V: Visit + ?Sized>(v: &mut V, kind: BlockKind, t: u8, body: &[Instruction]) {
+    v.enter_block(kind, t);
+    walk_instructions(v, body);
+    v.exit_block(kind);
+}
+
+pub fn walk_instruction<V: Visit + ?Sized>(v: &mut V, instruction: &Instruction) {
+    match instruction.category() {
+        InstructionCategory::Control => v.visit_control(instruction),
+        InstructionCategory::Call => v.visit_call(instruction),
+        InstructionCategory::Parametric => v.visit_parametric(instruction),
+        InstructionCategory::Variable => v.visit_variable(instruction),
+        InstructionCategory::Memory => v.visit_memory(instruction),
+        InstructionCategory::Numeric => v.visit_numeric(instruction),
+        InstructionCategory::Raw => {
+            if let Instruction::Raw(b) = instruction {
+                v.visit_raw(*b)
+            }
+        }
+    }
+    match instruction {
+        Instruction::Block(t, body) => walk_body(v, BlockKind::Block, *t, body),
+        Instruction::Loop(t, body) => walk_body(v, BlockKind::Loop, *t, body),
+        Instruction::If(t, then, otherwise) => {
+            walk_body(v, BlockKind::If, *t, then);
+            if let Some(otherwise) = otherwise {
+                walk_body(v, BlockKind::Else, *t, otherwise);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `Visit` with mutable access, instructions can be changed in place but not added or removed.
+pub trait VisitMut {
+    fn visit_program_mut(&mut self, program: &mut Module) {
+        walk_program_mut(self, program)
+    }
+
+    fn visit_code_block_mut(&mut self, _index: FuncIdx, code_block: &mut CodeBlock) {
+        walk_instructions_mut(self, &mut code_block.instructions)
+    }
+
+    fn visit_const_expression_mut(&mut self, expression: &mut [Instruction]) {
+        walk_instructions_mut(self, expression)
+    }
+
+    fn visit_instruction_mut(&mut self, instruction: &mut Instruction) {
+        walk_instruction_mut(self, instruction)
+    }
+
+    fn enter_block(&mut self, _kind: BlockKind, _block_type: u8) {}
+    fn exit_block(&mut self, _kind: BlockKind) {}
+    fn visit_control_mut(&mut self, _instruction: &mut Instruction) {}
+    fn visit_call_mut(&mut self, _instruction: &mut Instruction) {}
+    fn visit_parametric_mut(&mut self, _instruction: &mut Instruction) {}
+    fn visit_variable_mut(&mut self, _instruction: &mut Instruction) {}
+    fn visit_memory_mut(&mut self, _instruction: &mut Instruction) {}
+    fn visit_numeric_mut(&mut self, _instruction: &mut Instruction) {}
+    fn visit_raw_mut(&mut self, _byte: &mut u8) {}
+}
+
+pub fn walk_program_mut<V: VisitMut + ?Sized>(v: &mut V, program: &mut Module) {
+    let mut next_function = program.imported_function_count();
+    for s in program.sections.iter_mut() {
+        match s {
+            Section::Code(c) => {
+                for b in c.code_blocks.iter_mut() {
+                    v.visit_code_block_mut(FuncIdx::at(next_function), b);
+                    next_function += 1;
+                }
+            }
+            Section::Global(g) => {
+                for x in g.globals.iter_mut() {
+                    v.visit_const_expression_mut(&mut x.value_expression);
+                }
+            }
+            Section::Element(e) => {
+                for x in e.elements.iter_mut() {
+                    v.visit_const_expression_mut(&mut x.value_expression);
+                }
+            }
+            Section::Data(d) => {
+                for x in d.data_blocks.iter_mut() {
+                    v.visit_const_expression_mut(&mut x.offset_expression);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn walk_instructions_mut<V: VisitMut + ?Sized>(v: &mut V, instructions: &mut [Instruction]) {
+    for i in instructions.iter_mut() {
+        v.visit_instruction_mut(i);
+    }
+}
+
+fn walk_body_mut<V: VisitMut + ?Sized>(
+    v: &mut V,
+    kind: BlockKind,
+    t: u8,
+    body: &mut [Instruction],
+) {
+    v.enter_block(kind, t);
+    walk_instructions_mut(v, body);
+    v.exit_block(kind);
+}
+
+pub fn walk_instruction_mut<V: VisitMut + ?Sized>(v: &mut V, instruction: &mut Instruction) {
+    match instruction.category() {
+        InstructionCategory::Control => v.visit_control_mut(instruction),
+        InstructionCategory::Call => v.visit_call_mut(instruction),
+        InstructionCategory::Parametric => v.visit_parametric_mut(instruction),
+        InstructionCategory::Variable => v.visit_variable_mut(instruction),
+        InstructionCategory::Memory => v.visit_memory_mut(instruction),
+        InstructionCategory::Numeric => v.visit_numeric_mut(instruction),
+        InstructionCategory::Raw => {
+            if let Instruction::Raw(b) = instruction {
+                v.visit_raw_mut(b)
+            }
+        }
+    }
+    match instruction {
+        Instruction::Block(t, body) => walk_body_mut(v, BlockKind::Block, *t, body),
+        Instruction::Loop(t, body) => walk_body_mut(v, BlockKind::Loop, *t, body),
+        Instruction::If(t, then, otherwise) => {
+            walk_body_mut(v, BlockKind::If, *t, then);
+            if let Some(otherwise) = otherwise {
+                walk_body_mut(v, BlockKind::Else, *t, otherwise);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Rebuilds instruction sequences, every instruction is replaced by what `fold_instruction`
+/// returns for it, which may be any number of instructions. The bodies of a structured
+/// instruction are folded by `fold_body` before the instruction itself.
+pub trait Fold {
+    fn fold_program(&mut self, program: &mut Module) {
+        walk_fold_program(self, program)
+    }
+
+    fn fold_code_block(&mut self, _index: FuncIdx, code_block: &mut CodeBlock) {
+        let instructions = core::mem::take(&mut code_block.instructions);
+        code_block.instructions = fold_instructions(self, instructions);
+    }
+
+    fn fold_const_expression(&mut self, expression: &mut Vec<Instruction>) {
+        let instructions = core::mem::take(expression);
+        *expression = fold_instructions(self, instructions);
+    }
+
+    /// The body of a `block`, `loop` or either arm of an `if`, for folds that work on whole
+    /// sequences rather than single instructions.
+    fn fold_body(
+        &mut self,
+        kind: BlockKind,
+        block_type: u8,
+        body: Vec<Instruction>,
+    ) -> Vec<Instruction> {
+        walk_fold_body(self, kind, block_type, body)
+    }
+
+    fn fold_instruction(&mut self, instruction: Instruction) -> Vec<Instruction> {
+        vec![instruction]
+    }
+
+    fn enter_block(&mut self, _kind: BlockKind, _block_type: u8) {}
+    fn exit_block(&mut self, _kind: BlockKind) {}
+}
+
+pub fn walk_fold_program<F: Fold + ?Sized>(f: &mut F, program: &mut Module) {
+    let mut next_function = program.imported_function_count();
+    for s in program.sections.iter_mut() {
+        match s {
+            Section::Code(c) => {
+                for b in c.code_blocks.iter_mut() {
+                    f.fold_code_block(FuncIdx::at(next_function), b);
+                    next_function += 1;
+                }
+            }
+            Section::Global(g) => {
+                for x in g.globals.iter_mut() {
+                    f.fold_const_expression(&mut x.value_expression);
+                }
+            }
+            Section::Element(e) => {
+                for x in e.elements.iter_mut() {
+                    f.fold_const_expression(&mut x.value_expression);
+                }
+            }
+            Section::Data(d) => {
+                for x in d.data_blocks.iter_mut() {
+                    f.fold_const_expression(&mut x.offset_expression);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn walk_fold_body<F: Fold + ?Sized>(
+    f: &mut F,
+    kind: BlockKind,
+    t: u8,
+    body: Vec<Instruction>,
+) -> Vec<Instruction> {
+    f.enter_block(kind, t);
+    let body = fold_instructions(f, body);
+    f.exit_block(kind);
+    body
+}
+
+pub fn fold_instructions<F: Fold + ?Sized>(
+    f: &mut F,
+    instructions: Vec<Instruction>,
+) -> Vec<Instruction> {
+    let mut folded = Vec::with_capacity(instructions.len());
+    for i in instructions.into_iter() {
+        let i = match i {
+            Instruction::Block(t, body) => {
+                Instruction::Block(t, f.fold_body(BlockKind::Block, t, body))
+            }
+            Instruction::Loop(t, body) => {
+                Instruction::Loop(t, f.fold_body(BlockKind::Loop, t, body))
+            }
+            Instruction::If(t, then, otherwise) => {
+                let then = f.fold_body(BlockKind::If, t, then);
+                let otherwise = otherwise.map(|o| f.fold_body(BlockKind::Else, t, o));
+                Instruction::If(t, then, otherwise)
+            }
+            i => i,
+        };
+        folded.extend(f.fold_instruction(i));
+    }
+    folded
+}
+
+struct FindRaw(bool);
+
+impl Visit for FindRaw {
+    fn visit_raw(&mut self, _byte: u8) {
+        self.0 = true;
+    }
+}
+
+// whether a sequence or any body nested in it has raw instructions
+pub(crate) fn contains_raw(instructions: &[Instruction]) -> bool {
+    let mut find = FindRaw(false);
+    walk_instructions(&mut find, instructions);
+    find.0
+}
@@ -1 +1,100 @@
-pub struct Wast {}
+use crate::core::ValueType;
+use crate::interpreter::WasmValue;
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "module_type", content = "content")]
+#[repr(C)]
+pub enum WastModule {
+    Text(String),
+    Binary(Vec<u8>),
+    Quote(String),
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub struct WastModuleDefinition {
+    pub id: Option<String>,
+    pub module: WastModule,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "action_type", content = "content")]
+#[repr(C)]
+pub enum WastAction {
+    Invoke {
+        module: Option<String>,
+        name: String,
+        args: Vec<WasmValue>,
+    },
+    Get {
+        module: Option<String>,
+        name: String,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub enum WastExpected {
+    Value(WasmValue),
+    NanCanonical(ValueType),
+    NanArithmetic(ValueType),
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub enum WastExecute {
+    Action(WastAction),
+    Module(WastModuleDefinition),
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "command_type", content = "content")]
+#[repr(C)]
+pub enum WastCommand {
+    Module(WastModuleDefinition),
+    Register {
+        name: String,
+        module: Option<String>,
+    },
+    Action(WastAction),
+    AssertReturn {
+        action: WastAction,
+        expected: Vec<WastExpected>,
+    },
+    AssertTrap {
+        execute: WastExecute,
+        message: String,
+    },
+    AssertExhaustion {
+        action: WastAction,
+        message: String,
+    },
+    AssertMalformed {
+        module: WastModuleDefinition,
+        message: String,
+    },
+    AssertInvalid {
+        module: WastModuleDefinition,
+        message: String,
+    },
+    AssertUnlinkable {
+        module: WastModuleDefinition,
+        message: String,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub struct WastDirective {
+    pub line: usize,
+    pub command: WastCommand,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub struct Wast {
+    pub directives: Vec<WastDirective>,
+}
@@ -0,0 +1,748 @@
+use crate::core::*;
+use crate::wat::instruction_lines;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Change<T> {
+    Added(T),
+    Removed(T),
+    Changed(T, T),
+}
+
+/// A line of a function in flat text form and whether it is in both versions.
+#[derive(Clone, PartialEq, Debug)]
+pub enum LineDiff {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// A defined function that is only in one of the programs or differs between them. The first
+/// lines are its signature and locals, the rest its body.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FunctionDiff {
+    pub name: String,
+    pub old: Option<FuncIdx>,
+    pub new: Option<FuncIdx>,
+    pub lines: Vec<LineDiff>,
+}
+
+/// An import with the signature of an imported function, type indices alone can not be
+/// compared across programs.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ImportEntry<'a> {
+    pub import: WasmImport<'a>,
+    pub function_type: Option<FunctionType>,
+}
+
+impl ImportEntry<'_> {
+    fn same(&self, other: &ImportEntry) -> bool {
+        match (&self.import, &other.import) {
+            (WasmImport::Function(_), WasmImport::Function(_)) => {
+                self.function_type == other.function_type
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ModuleDiff<'a> {
+    pub types: Vec<Change<FunctionType>>,
+    pub imports: Vec<Change<ImportEntry<'a>>>,
+    pub exports: Vec<Change<WasmExport<'a>>>,
+    pub globals: Vec<Change<Global>>,
+    pub memories: Vec<Change<WasmMemory>>,
+    pub data: Vec<Change<DataBlock<'a>>>,
+    pub functions: Vec<FunctionDiff>,
+}
+
+impl ModuleDiff<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+            && self.imports.is_empty()
+            && self.exports.is_empty()
+            && self.globals.is_empty()
+            && self.memories.is_empty()
+            && self.data.is_empty()
+            && self.functions.is_empty()
+    }
+}
+
+fn import_key<'a>(i: &'a WasmImport) -> (&'a str, &'a str) {
+    match i {
+        WasmImport::Function(x) => (&x.module_name, &x.name),
+        WasmImport::Global(x) => (&x.module_name, &x.name),
+        WasmImport::Memory(x) => (&x.module_name, &x.name),
+        WasmImport::Table(x) => (&x.module_name, &x.name),
+    }
+}
+
+fn export_name<'a>(e: &'a WasmExport) -> &'a str {
+    match e {
+        WasmExport::Function(x) => &x.name,
+        WasmExport::Global(x) => &x.name,
+        WasmExport::Memory(x) => &x.name,
+        WasmExport::Table(x) => &x.name,
+    }
+}
+
+fn section_items<'a, 'm, T>(
+    program: &'a Module<'m>,
+    f: impl Fn(&'a Section<'m>) -> Option<&'a [T]>,
+) -> &'a [T] {
+    program.sections.iter().find_map(f).unwrap_or(&[])
+}
+
+// pairs up items with the same key, the rest were added or removed
+fn keyed_changes<T: Clone, K: PartialEq>(
+    old: &[T],
+    new: &[T],
+    key: impl Fn(&T) -> K,
+    same: impl Fn(&T, &T) -> bool,
+) -> Vec<Change<T>> {
+    let mut changes = Vec::new();
+    for o in old.iter() {
+        match new.iter().find(|n| key(n) == key(o)) {
+            Some(n) if !same(o, n) => changes.push(Change::Changed(o.clone(), n.clone())),
+            Some(_) => {}
+            None => changes.push(Change::Removed(o.clone())),
+        }
+    }
+    for n in new.iter() {
+        if !old.iter().any(|o| key(o) == key(n)) {
+            changes.push(Change::Added(n.clone()));
+        }
+    }
+    changes
+}
+
+// compares items by their position, like globals whose index is their identity
+fn positional_changes<T: Clone + PartialEq>(old: &[T], new: &[T]) -> Vec<Change<T>> {
+    let mut changes = Vec::new();
+    for i in 0..old.len().max(new.len()) {
+        match (old.get(i), new.get(i)) {
+            (Some(o), Some(n)) if o != n => changes.push(Change::Changed(o.clone(), n.clone())),
+            (Some(o), None) => changes.push(Change::Removed(o.clone())),
+            (None, Some(n)) => changes.push(Change::Added(n.clone())),
+            _ => {}
+        }
+    }
+    changes
+}
+
+fn value_types(keyword: &str, types: &[ValueType]) -> String {
+    if types.is_empty() {
+        return String::new();
+    }
+    let mut s = format!(" ({}", keyword);
+    for t in types.iter() {
+        s.push(' ');
+        s.push_str(value_type_name(*t));
+    }
+    s.push(')');
+    s
+}
+
+fn function_type_text(t: &FunctionType) -> String {
+    format!(
+        "(func{}{})",
+        value_types("param", &t.inputs),
+        value_types("result", &t.outputs)
+    )
+}
+
+fn expression_text(e: &[Instruction]) -> String {
+    instruction_lines(e)
+        .iter()
+        .map(|x| format!("({})", x.trim()))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn limits_text(min: usize, max: Option<usize>) -> String {
+    match max {
+        Some(max) => format!("{} {}", min, max),
+        None => format!("{}", min),
+    }
+}
+
+fn import_text(entry: &ImportEntry) -> String {
+    let i = &entry.import;
+    let (module, name) = import_key(i);
+    let kind = match i {
+        WasmImport::Function(x) => match &entry.function_type {
+            Some(t) => function_type_text(t),
+            None => format!("(func (type {}))", x.type_index),
+        },
+        WasmImport::Global(x) => {
+            format!("(global {})", global_type_text(x.value_type, x.is_mutable))
+        }
+        WasmImport::Memory(x) => format!("(memory {})", limits_text(x.min_pages, x.max_pages)),
+        WasmImport::Table(x) => format!("(table {} funcref)", limits_text(x.min, x.max)),
+    };
+    format!("{:?} {:?} {}", module, name, kind)
+}
+
+fn export_text(e: &WasmExport) -> String {
+    let kind = match e {
+        WasmExport::Function(x) => format!("(func {})", x.index),
+        WasmExport::Global(x) => format!("(global {})", x.index),
+        WasmExport::Memory(x) => format!("(memory {})", x.index),
+        WasmExport::Table(x) => format!("(table {})", x.index),
+    };
+    format!("{:?} {}", export_name(e), kind)
+}
+
+fn global_type_text(value_type: ValueType, is_mutable: bool) -> String {
+    if is_mutable {
+        format!("(mut {})", value_type_name(value_type))
+    } else {
+        value_type_name(value_type).to_string()
+    }
+}
+
+fn global_text(g: &Global) -> String {
+    format!(
+        "{} {}",
+        global_type_text(g.value_type, g.is_mutable),
+        expression_text(&g.value_expression)
+    )
+}
+
+fn data_text(d: &DataBlock) -> String {
+    format!(
+        "(memory {}) {} {} bytes",
+        d.memory,
+        expression_text(&d.offset_expression),
+        d.data.len()
+    )
+}
+
+struct Function<'a> {
+    index: FuncIdx,
+    names: Vec<&'a str>,
+    body: &'a [Instruction],
+    // the signature and locals, the lines of the body follow them
+    header: usize,
+    lines: Vec<String>,
+    // the lines with references in the index spaces of the old program, for comparing
+    keys: Vec<String>,
+}
+
+fn defined_functions<'a>(program: &'a Module, names: &'a Option<NameSection>) -> Vec<Function<'a>> {
+    let exports = section_items(program, |s| match s {
+        Section::Export(e) => Some(&e.exports[..]),
+        _ => None,
+    });
+    let mut functions = Vec::new();
+    for (index, f) in program.functions() {
+        let (locals, body) = match f {
+            FunctionRef::Defined { locals, body, .. } => (locals, body),
+            FunctionRef::Imported { .. } => continue,
+        };
+        let mut function_names: Vec<&str> = exports
+            .iter()
+            .filter_map(|e| match e {
+                WasmExport::Function(x) if x.index == index => Some(&x.name[..]),
+                _ => None,
+            })
+            .collect();
+        if let Some(n) = names {
+            if let Some((_, name)) = n.function_names.iter().find(|(i, _)| *i == index) {
+                function_names.push(name);
+            }
+        }
+        let mut lines = vec![match program.function_type(index) {
+            Ok(t) => function_type_text(t),
+            Err(_) => "(func (type ?))".to_string(),
+        }];
+        let mut local_types = Vec::new();
+        for l in locals.iter() {
+            for _ in 0..l.count {
+                local_types.push(l.value_type);
+            }
+        }
+        if !local_types.is_empty() {
+            lines.push(value_types("local", &local_types).trim().to_string());
+        }
+        let header = lines.len();
+        lines.extend(instruction_lines(body));
+        functions.push(Function {
+            index,
+            names: function_names,
+            body,
+            header,
+            keys: lines.clone(),
+            lines,
+        });
+    }
+    functions
+}
+
+// where the functions, globals and types of the new program are in the old one, what has no
+// counterpart gets an index past the end of the old index space
+struct IndexMaps {
+    functions: Vec<u32>,
+    globals: Vec<u32>,
+    types: Vec<u32>,
+}
+
+impl IndexMaps {
+    fn new(old: &Module, new: &Module, pairs: &[(FuncIdx, FuncIdx)]) -> Self {
+        let old_functions: Vec<(FuncIdx, FunctionRef)> = old.functions().collect();
+        let functions = new
+            .functions()
+            .map(|(i, f)| {
+                let found = match f {
+                    FunctionRef::Imported { module, name, .. } => {
+                        old_functions.iter().find_map(|(o, x)| match x {
+                            FunctionRef::Imported {
+                                module: m, name: n, ..
+                            } if (*m, *n) == (module, name) => Some(o.0),
+                            _ => None,
+                        })
+                    }
+                    FunctionRef::Defined { .. } => {
+                        pairs.iter().find(|(_, n)| *n == i).map(|(o, _)| o.0)
+                    }
+                };
+                found.unwrap_or(old_functions.len() as u32 + i.0)
+            })
+            .collect();
+
+        let old_globals: Vec<(GlobalIdx, GlobalRef)> = old.globals().collect();
+        let old_imported = old_globals.iter().filter(|x| x.1.is_imported()).count();
+        let new_imported = new.globals().filter(|x| x.1.is_imported()).count();
+        let globals = new
+            .globals()
+            .map(|(i, g)| {
+                let found = match g {
+                    GlobalRef::Imported { module, name, .. } => {
+                        old_globals.iter().find_map(|(o, x)| match x {
+                            GlobalRef::Imported {
+                                module: m, name: n, ..
+                            } if (*m, *n) == (module, name) => Some(o.0),
+                            _ => None,
+                        })
+                    }
+                    // defined globals are compared by position
+                    GlobalRef::Defined(_) => {
+                        let position = old_imported + i.index() - new_imported;
+                        Some(position as u32).filter(|p| (*p as usize) < old_globals.len())
+                    }
+                };
+                found.unwrap_or(old_globals.len() as u32 + i.0)
+            })
+            .collect();
+
+        let type_list = |p| {
+            section_items(p, |s| match s {
+                Section::Type(t) => Some(&t.types[..]),
+                _ => None,
+            })
+        };
+        let old_types = type_list(old);
+        let types = type_list(new)
+            .iter()
+            .enumerate()
+            .map(|(i, t)| match old_types.iter().position(|x| x == t) {
+                Some(o) => o as u32,
+                None => (old_types.len() + i) as u32,
+            })
+            .collect();
+        IndexMaps {
+            functions,
+            globals,
+            types,
+        }
+    }
+}
+
+impl VisitMut for IndexMaps {
+    fn visit_call_mut(&mut self, instruction: &mut Instruction) {
+        match instruction {
+            Instruction::Call(f) => {
+                if let Some(x) = self.functions.get(f.index()) {
+                    f.0 = *x;
+                }
+            }
+            Instruction::CallIndirect(t) => {
+                if let Some(x) = self.types.get(t.index()) {
+                    t.0 = *x;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_variable_mut(&mut self, instruction: &mut Instruction) {
+        if let Instruction::GlobalGet(g) | Instruction::GlobalSet(g) = instruction {
+            if let Some(x) = self.globals.get(g.index()) {
+                g.0 = *x;
+            }
+        }
+    }
+}
+
+fn index_pairs(
+    old: &[Function],
+    new: &[Function],
+    pairs: &[(usize, usize)],
+) -> Vec<(FuncIdx, FuncIdx)> {
+    pairs
+        .iter()
+        .map(|(i, j)| (old[*i].index, new[*j].index))
+        .collect()
+}
+
+// compare the bodies of the new program as if they were written against the old one
+fn map_keys(functions: &mut [Function], maps: &mut IndexMaps) {
+    for f in functions.iter_mut() {
+        let mut body = f.body.to_vec();
+        walk_instructions_mut(maps, &mut body);
+        f.keys.truncate(f.header);
+        f.keys.extend(instruction_lines(&body));
+    }
+}
+
+// how alike two bodies are from 0 to 1, counting the lines they have in common
+fn similarity(a: &[String], b: &[String]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let mut a: Vec<&String> = a.iter().collect();
+    let mut b: Vec<&String> = b.iter().collect();
+    a.sort();
+    b.sort();
+    let (mut i, mut j, mut common) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(b[j]) {
+            core::cmp::Ordering::Less => i += 1,
+            core::cmp::Ordering::Greater => j += 1,
+            core::cmp::Ordering::Equal => {
+                common += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    2.0 * common as f64 / (a.len() + b.len()) as f64
+}
+
+// the longest common subsequence table gets too big for long bodies, those are shown as
+// replaced once their common start and end are taken off
+const MAX_LINE_DIFF_CELLS: usize = 1 << 22;
+
+// diffs the lines of two versions of a function by their keys, lines in both are shown as
+// they are in the new one
+fn line_diff(old: &Function, new: &Function) -> Vec<LineDiff> {
+    let (old_keys, new_keys) = (&old.keys[..], &new.keys[..]);
+    let prefix = old_keys
+        .iter()
+        .zip(new_keys.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_keys[prefix..]
+        .iter()
+        .rev()
+        .zip(new_keys[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old_keys[prefix..old_keys.len() - suffix];
+    let b = &new_keys[prefix..new_keys.len() - suffix];
+    let (old_lines, new_lines) = (&old.lines[prefix..], &new.lines[prefix..]);
+    let mut lines: Vec<LineDiff> = new.lines[..prefix]
+        .iter()
+        .cloned()
+        .map(LineDiff::Same)
+        .collect();
+    if (a.len() + 1) * (b.len() + 1) > MAX_LINE_DIFF_CELLS {
+        lines.extend(old_lines[..a.len()].iter().cloned().map(LineDiff::Removed));
+        lines.extend(new_lines[..b.len()].iter().cloned().map(LineDiff::Added));
+    } else {
+        let width = b.len() + 1;
+        let mut table = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                table[i * width + j] = if a[i] == b[j] {
+                    table[(i + 1) * width + j + 1] + 1
+                } else {
+                    table[(i + 1) * width + j].max(table[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                lines.push(LineDiff::Same(new_lines[j].clone()));
+                i += 1;
+                j += 1;
+            } else if i < a.len()
+                && (j == b.len() || table[(i + 1) * width + j] >= table[i * width + j + 1])
+            {
+                lines.push(LineDiff::Removed(old_lines[i].clone()));
+                i += 1;
+            } else {
+                lines.push(LineDiff::Added(new_lines[j].clone()));
+                j += 1;
+            }
+        }
+    }
+    lines.extend(
+        new.lines[new.lines.len() - suffix..]
+            .iter()
+            .cloned()
+            .map(LineDiff::Same),
+    );
+    lines
+}
+
+fn function_label(f: &Function) -> String {
+    match f.names.first() {
+        Some(name) => name.to_string(),
+        None => format!("#{}", f.index),
+    }
+}
+
+fn function_changes(old: &Module, new: &Module) -> Vec<FunctionDiff> {
+    let (old_names, new_names) = (old.name_section(), new.name_section());
+    let old_functions = defined_functions(old, &old_names);
+    let mut new_functions = defined_functions(new, &new_names);
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    let mut old_matched = vec![false; old_functions.len()];
+    let mut new_matched = vec![false; new_functions.len()];
+
+    // export names and then name section names, both are in the names of a function
+    for (i, o) in old_functions.iter().enumerate() {
+        let found = new_functions
+            .iter()
+            .enumerate()
+            .find(|(j, n)| !new_matched[*j] && o.names.iter().any(|x| n.names.contains(x)));
+        if let Some((j, _)) = found {
+            old_matched[i] = true;
+            new_matched[j] = true;
+            pairs.push((i, j));
+        }
+    }
+    // what is left is paired with the most similar body, if it is similar enough
+    let mut maps = IndexMaps::new(
+        old,
+        new,
+        &index_pairs(&old_functions, &new_functions, &pairs),
+    );
+    map_keys(&mut new_functions, &mut maps);
+    let mut candidates = Vec::new();
+    for (i, o) in old_functions
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !old_matched[*i])
+    {
+        for (j, n) in new_functions
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| !new_matched[*j])
+        {
+            let s = similarity(&o.keys, &n.keys);
+            if s >= 0.5 {
+                candidates.push((s, i, j));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(core::cmp::Ordering::Equal));
+    for (_, i, j) in candidates.into_iter() {
+        if !old_matched[i] && !new_matched[j] {
+            old_matched[i] = true;
+            new_matched[j] = true;
+            pairs.push((i, j));
+        }
+    }
+    pairs.sort();
+    // calls between paired functions match now too
+    let mut maps = IndexMaps::new(
+        old,
+        new,
+        &index_pairs(&old_functions, &new_functions, &pairs),
+    );
+    map_keys(&mut new_functions, &mut maps);
+
+    let mut changes = Vec::new();
+    for (i, o) in old_functions.iter().enumerate() {
+        if !old_matched[i] {
+            changes.push(FunctionDiff {
+                name: function_label(o),
+                old: Some(o.index),
+                new: None,
+                lines: o.lines.iter().cloned().map(LineDiff::Removed).collect(),
+            });
+        }
+    }
+    for (i, j) in pairs.into_iter() {
+        let (o, n) = (&old_functions[i], &new_functions[j]);
+        if o.keys != n.keys {
+            changes.push(FunctionDiff {
+                name: function_label(n),
+                old: Some(o.index),
+                new: Some(n.index),
+                lines: line_diff(o, n),
+            });
+        }
+    }
+    for (j, n) in new_functions.iter().enumerate() {
+        if !new_matched[j] {
+            changes.push(FunctionDiff {
+                name: function_label(n),
+                old: None,
+                new: Some(n.index),
+                lines: n.lines.iter().cloned().map(LineDiff::Added).collect(),
+            });
+        }
+    }
+    changes
+}
+
+/// Compares two programs by what they contain rather than how they are encoded. Imports and
+/// exports are matched by name, globals, memories and data segments by position and defined
+/// functions by export name, then by name section name and then by how alike their bodies are.
+/// Function bodies are compared with their calls, globals and types renumbered to what they
+/// were matched with, so an added import does not change every call after it.
+pub fn diff<'a>(old: &Module<'a>, new: &Module<'a>) -> ModuleDiff<'a> {
+    let types = |p| {
+        section_items(p, |s| match s {
+            Section::Type(t) => Some(&t.types[..]),
+            _ => None,
+        })
+    };
+    let imports = |p: &Module<'a>| -> Vec<ImportEntry<'a>> {
+        let types = section_items(p, |s| match s {
+            Section::Type(t) => Some(&t.types[..]),
+            _ => None,
+        });
+        let imports = section_items(p, |s| match s {
+            Section::Import(i) => Some(&i.imports[..]),
+            _ => None,
+        });
+        imports
+            .iter()
+            .map(|i| ImportEntry {
+                import: i.clone(),
+                function_type: match i {
+                    WasmImport::Function(f) => types.get(f.type_index.index()).cloned(),
+                    _ => None,
+                },
+            })
+            .collect()
+    };
+    let exports = |p| {
+        section_items(p, |s| match s {
+            Section::Export(e) => Some(&e.exports[..]),
+            _ => None,
+        })
+    };
+    let globals = |p| {
+        section_items(p, |s| match s {
+            Section::Global(g) => Some(&g.globals[..]),
+            _ => None,
+        })
+    };
+    let memories = |p| {
+        section_items(p, |s| match s {
+            Section::Memory(m) => Some(&m.memories[..]),
+            _ => None,
+        })
+    };
+    let data = |p| {
+        section_items(p, |s| match s {
+            Section::Data(d) => Some(&d.data_blocks[..]),
+            _ => None,
+        })
+    };
+    let mut type_changes: Vec<Change<FunctionType>> = Vec::new();
+    for t in types(old).iter().filter(|t| !types(new).contains(t)) {
+        type_changes.push(Change::Removed(t.clone()));
+    }
+    for t in types(new).iter().filter(|t| !types(old).contains(t)) {
+        type_changes.push(Change::Added(t.clone()));
+    }
+    ModuleDiff {
+        types: type_changes,
+        imports: keyed_changes(
+            &imports(old),
+            &imports(new),
+            |i| {
+                let (module, name) = import_key(&i.import);
+                (module.to_string(), name.to_string())
+            },
+            ImportEntry::same,
+        ),
+        exports: keyed_changes(
+            exports(old),
+            exports(new),
+            |e| export_name(e).to_string(),
+            |a, b| a == b,
+        ),
+        globals: positional_changes(globals(old), globals(new)),
+        memories: positional_changes(memories(old), memories(new)),
+        data: positional_changes(data(old), data(new)),
+        functions: function_changes(old, new),
+    }
+}
+
+fn write_changes<T>(
+    f: &mut fmt::Formatter,
+    title: &str,
+    changes: &[Change<T>],
+    text: impl Fn(&T) -> String,
+) -> fmt::Result {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    writeln!(f, "{}:", title)?;
+    for c in changes.iter() {
+        match c {
+            Change::Added(x) => writeln!(f, "  + {}", text(x))?,
+            Change::Removed(x) => writeln!(f, "  - {}", text(x))?,
+            Change::Changed(o, n) => {
+                writeln!(f, "  - {}", text(o))?;
+                writeln!(f, "  + {}", text(n))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for ModuleDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_changes(f, "types", &self.types, function_type_text)?;
+        write_changes(f, "imports", &self.imports, import_text)?;
+        write_changes(f, "exports", &self.exports, export_text)?;
+        write_changes(f, "globals", &self.globals, global_text)?;
+        write_changes(f, "memories", &self.memories, |m| {
+            limits_text(m.min_pages, m.max_pages)
+        })?;
+        write_changes(f, "data", &self.data, data_text)?;
+        for d in self.functions.iter() {
+            let index = |i: Option<FuncIdx>| match i {
+                Some(i) => format!("{}", i),
+                None => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "function {} ({} -> {}):",
+                d.name,
+                index(d.old),
+                index(d.new)
+            )?;
+            for l in d.lines.iter() {
+                match l {
+                    LineDiff::Same(x) => writeln!(f, "    {}", x)?,
+                    LineDiff::Added(x) => writeln!(f, "  + {}", x)?,
+                    LineDiff::Removed(x) => writeln!(f, "  - {}", x)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
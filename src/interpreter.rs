@@ -1,17 +1,28 @@
+use crate::core::block_arity;
 use crate::core::*;
+use crate::math::*;
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use core::convert::TryInto;
+use core::convert::{TryFrom, TryInto};
 use serde::{Deserialize, Serialize};
 use spin::Mutex;
+use webassembly::{DESC_FUNCTION, DESC_GLOBAL, DESC_MEMORY, DESC_TABLE};
+
+const PAGE_SIZE: usize = 65536;
+const MAX_PAGES: usize = 65536;
+const MAX_CALL_DEPTH: usize = 1024;
 
 pub struct Interpreter<T>
 where
     T: InterpretableProgram,
 {
     pub memory: Arc<Mutex<Vec<u8>>>,
+    pub globals: Arc<Mutex<Vec<WasmValue>>>,
+    pub table: Arc<Mutex<Vec<Option<FuncIdx>>>>,
     pub program: Arc<Mutex<T>>,
+    // the bodies of the defined functions in flat form, built once for every execution
+    bodies: Arc<Vec<FlatBody>>,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
@@ -34,7 +45,7 @@ impl ToWasmValue for usize {
 
 impl ToWasmValue for u32 {
     fn to_wasm_value(&self) -> WasmValue {
-        WasmValue::I32((*self as u32).try_into().unwrap())
+        WasmValue::I32((*self).try_into().unwrap())
     }
 }
 
@@ -63,6 +74,15 @@ impl ToWasmValue for f64 {
 }
 
 impl WasmValue {
+    pub fn zero(value_type: &ValueType) -> WasmValue {
+        match value_type {
+            ValueType::I32 => WasmValue::I32(0),
+            ValueType::I64 => WasmValue::I64(0),
+            ValueType::F32 => WasmValue::F32(0.0),
+            ValueType::F64 => WasmValue::F64(0.0),
+        }
+    }
+
     pub fn to_i32(&self) -> i32 {
         match self {
             WasmValue::I32(i) => *i,
@@ -109,7 +129,7 @@ pub struct ImportCall {
 
 #[derive(Debug)]
 pub struct Call {
-    pub fn_index: u32,
+    pub fn_index: FuncIdx,
     pub params: Vec<WasmValue>,
 }
 
@@ -117,13 +137,17 @@ pub enum ExecutionResponse {
     DoNothing,
     AddValues(Vec<WasmValue>),
     ValueStackModification(fn(&mut Vec<WasmValue>) -> Result<(), &'static str>),
-    GetRegister(u32),
-    SetRegister(u32),
-    TeeRegister(u32),
+    GetRegister(LocalIdx),
+    SetRegister(LocalIdx),
+    TeeRegister(LocalIdx),
+    GetGlobal(GlobalIdx),
+    SetGlobal(GlobalIdx),
+    LoadMemory(u32, usize, fn(&[u8]) -> WasmValue),
+    StoreMemory(u32, usize, fn(WasmValue, &mut [u8])),
     ThrowError(&'static str),
     GetMemorySize,
     GetMemoryGrow,
-    EnterFunction(u32, Vec<WasmValue>),
+    EnterFunction(FuncIdx, Vec<WasmValue>),
     ExitFunction(Vec<WasmValue>),
 }
 
@@ -136,295 +160,206 @@ pub enum ExecutionUnit {
     Complete(Vec<WasmValue>),
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Label {
+    pub arity: usize,
+    pub stack_height: usize,
+    /// The position of the block, loop or if marker in the flat body.
+    pub start: usize,
+    pub is_loop: bool,
+}
+
 pub trait InterpretableProgram {
-    fn load_data_into_memory(&self, mem: &mut Vec<u8>) -> Result<(), &'static str>;
+    fn load_data_into_memory(
+        &self,
+        mem: &mut [u8],
+        globals: &[WasmValue],
+    ) -> Result<(), &'static str>;
     fn initial_memory_size(&self) -> usize;
-    fn fn_details(&self, index: usize) -> Result<(usize, usize), &'static str>;
-    fn import_fn_details(&self, index: usize) -> Result<(&str, &str, usize, usize), &'static str>;
+    fn max_memory_pages(&self) -> Option<usize>;
+    fn function(&self, index: FuncIdx) -> Result<FunctionRef<'_>, &'static str>;
+    fn type_details(&self, index: TypeIdx) -> Result<&FunctionType, &'static str>;
     fn import_fn_count(&self) -> usize;
-    fn fetch_export_fn_index(&self, name: &str) -> Result<(usize, usize), &'static str>;
+    fn import_global_types(&self) -> Vec<ValueType>;
+    fn fetch_export(&self, name: &str) -> Result<(u8, usize), &'static str>;
     fn fetch_code_section_index(&self) -> Result<usize, &'static str>;
-    fn fetch_instruction<'a>(
-        &'a self,
-        position: &[usize],
-    ) -> Result<Option<&'a Instruction>, &'static str>;
-    fn create_locals(&self, position: &[usize]) -> Result<Vec<WasmValue>, &'static str>;
-}
-
-impl InterpretableProgram for Program {
-    fn fn_details(&self, index: usize) -> Result<(usize, usize), &'static str> {
-        let mut fn_type_index = 0;
-        let mut found_fn_section = false;
-        {
-            for s in self.sections.iter() {
-                if let Section::Function(function_section) = s {
-                    found_fn_section = true;
-                    fn_type_index = function_section.function_types[index]
-                }
-            }
-        }
-        if !found_fn_section {
-            return Err("function section does not exist");
-        }
-        {
-            for s in self.sections.iter() {
-                if let Section::Type(type_section) = s {
-                    let fn_type = &type_section.types[fn_type_index];
-                    return Ok((fn_type.inputs.len(), fn_type.outputs.len()));
-                }
-            }
-        }
-        Err("function type section does not exist")
-    }
-
-    fn import_fn_details(&self, index: usize) -> Result<(&str, &str, usize, usize), &'static str> {
-        for s in self.sections.iter() {
-            if let Section::Import(import_section) = s {
-                let l: Vec<_> = import_section
-                    .imports
-                    .iter()
-                    .filter(|x| matches!(x, WasmImport::Function(_)))
-                    .collect();
-                if index < l.len() {
-                    if let WasmImport::Function(x) = l[index] {
-                        let (param_ct, return_ct) = self.fn_details(index)?;
-                        return Ok((&x.module_name, &x.name, param_ct, return_ct));
-                    }
-                } else {
-                    return Err("import does not exist with that index");
-                }
-            }
-        }
-        Err("import section does not exist")
-    }
-
-    fn load_data_into_memory(&self, mem: &mut Vec<u8>) -> Result<(), &'static str> {
-        for s in self.sections.iter() {
-            if let Section::Data(d) = s {
-                for db in d.data_blocks.iter() {
-                    match db.offset_expression[0] {
-                        Instruction::I32Const(x) => {
-                            let offset = x as usize;
-                            for (i, b) in db.data.iter().enumerate() {
-                                mem[offset + i] = *b;
-                            }
-                        }
-                        _ => return Err("I don't know how to build this memory yet"),
-                    }
-                }
-            }
-        }
-        Ok(())
-    }
+    fn initial_globals(&self, imported: &[WasmValue]) -> Result<Vec<WasmValue>, &'static str>;
+    fn initial_table(&self, globals: &[WasmValue]) -> Result<Vec<Option<FuncIdx>>, &'static str>;
+    fn start_fn_index(&self) -> Option<FuncIdx>;
 
-    fn initial_memory_size(&self) -> usize {
-        for s in self.sections.iter() {
-            if let Section::Memory(m) = s {
-                if !m.memories.is_empty() {
-                    return m.memories[0].min_pages * 1024;
-                }
-            }
-        }
-        0
+    fn fn_type(&self, index: FuncIdx) -> Result<&FunctionType, &'static str> {
+        self.type_details(self.function(index)?.type_index())
     }
 
-    fn import_fn_count(&self) -> usize {
-        for s in self.sections.iter() {
-            if let Section::Import(import_section) = s {
-                let l: Vec<_> = import_section
-                    .imports
-                    .iter()
-                    .filter(|x| matches!(x, WasmImport::Function(_)))
-                    .collect();
-                return l.len();
-            }
-        }
-        0
+    fn fn_details(&self, index: FuncIdx) -> Result<(usize, usize), &'static str> {
+        let fn_type = self.fn_type(index)?;
+        Ok((fn_type.inputs.len(), fn_type.outputs.len()))
     }
 
     fn fetch_export_fn_index(&self, name: &str) -> Result<(usize, usize), &'static str> {
         let ct = self.import_fn_count();
-        let code_section_idx = self.fetch_code_section_index()?;
-        for s in self.sections.iter() {
-            if let Section::Export(export_section) = s {
-                for e in export_section.exports.iter() {
-                    if let WasmExport::Function(f) = e {
-                        if f.name == name {
-                            return Ok((code_section_idx, f.index - ct));
-                        }
-                    }
-                }
+        match self.fetch_export(name)? {
+            (DESC_FUNCTION, index) if index >= ct => {
+                Ok((self.fetch_code_section_index()?, index - ct))
             }
+            (DESC_FUNCTION, _) => Err("export refers to an imported function"),
+            _ => Err("export is not a function"),
         }
-        Err("could not find export section")
     }
+}
 
-    fn fetch_code_section_index(&self) -> Result<usize, &'static str> {
-        let ct = self.import_fn_count();
-        let result = self
-            .sections
-            .iter()
-            .enumerate()
-            .find(|(_, x)| matches!(x, Section::Code(_)));
-        let code_section_idx = match result {
-            Some((i, _)) => i,
-            None => return Err("Code section did not exist"),
-        };
-        return Ok(code_section_idx);
+fn evaluate_const_expression(
+    expression: &[Instruction],
+    globals: &[WasmValue],
+) -> Result<WasmValue, &'static str> {
+    match expression {
+        [Instruction::I32Const(x)] => Ok(x.to_wasm_value()),
+        [Instruction::I64Const(x)] => Ok(x.to_wasm_value()),
+        [Instruction::F32Const(x)] => Ok(x.to_wasm_value()),
+        [Instruction::F64Const(x)] => Ok(x.to_wasm_value()),
+        [Instruction::GlobalGet(i)] => match globals.get(i.index()) {
+            Some(v) => Ok(*v),
+            None => Err("unknown global"),
+        },
+        _ => Err("unsupported constant expression"),
     }
+}
 
-    fn fetch_instruction<'a>(
-        &'a self,
-        position: &[usize],
-    ) -> Result<Option<&'a Instruction>, &'static str> {
-        if let Section::Code(code_section) = &self.sections[position[0]] {
-            let b = &code_section.code_blocks[position[1]];
-            // TODO: handle nesting
-            if position.len() > 2 {
-                let i = 2;
-                let instruction_index = position[i];
-                let len = &b.instructions.len();
-                if instruction_index < *len {
-                    Ok(Some(&b.instructions[instruction_index]))
-                } else {
-                    Ok(None)
-                }
-            } else {
-                Ok(None)
-            }
-        } else {
-            Err("cannot find code section")
+fn fill_table(
+    table: &mut [Option<FuncIdx>],
+    elements: &[WasmElement],
+    globals: &[WasmValue],
+) -> Result<(), &'static str> {
+    for e in elements.iter() {
+        let offset = evaluate_const_expression(&e.value_expression, globals)?.to_i32() as u32;
+        let offset = offset as usize;
+        if offset + e.functions.len() > table.len() {
+            return Err("elements segment does not fit");
+        }
+        for (i, f) in e.functions.iter().enumerate() {
+            table[offset + i] = Some(*f);
         }
     }
+    Ok(())
+}
 
-    fn create_locals(&self, position: &[usize]) -> Result<Vec<WasmValue>, &'static str> {
-        let mut locals = vec![];
-        if let Section::Code(code_section) = &self.sections[position[0]] {
-            let b = &code_section.code_blocks[position[1]];
-            for l in b.locals.iter() {
-                for _ in 0..l.count {
-                    let v = match l.value_type {
-                        ValueType::I32 => 0i32.to_wasm_value(),
-                        ValueType::I64 => 0i64.to_wasm_value(),
-                        ValueType::F32 => 0f32.to_wasm_value(),
-                        ValueType::F64 => 0f64.to_wasm_value(),
-                    };
-                    locals.push(v);
-                }
-            }
-        } else {
-            return Err("cannot find code section");
-        }
-        Ok(locals)
+fn fill_memory(
+    mem: &mut [u8],
+    expression: &[Instruction],
+    data: &[u8],
+    globals: &[WasmValue],
+) -> Result<(), &'static str> {
+    let offset = evaluate_const_expression(expression, globals)?.to_i32() as u32 as usize;
+    if offset + data.len() > mem.len() {
+        return Err("data segment does not fit");
     }
+    mem[offset..offset + data.len()].copy_from_slice(data);
+    Ok(())
 }
 
-impl InterpretableProgram for ProgramView<'_> {
-    fn fn_details(&self, index: usize) -> Result<(usize, usize), &'static str> {
-        let mut fn_type_index = 0;
-        let mut found_fn_section = false;
-        {
-            for s in self.sections.iter() {
-                if let SectionView::Function(function_section) = s {
-                    found_fn_section = true;
-                    fn_type_index = function_section.function_types[index]
-                }
-            }
-        }
-        if !found_fn_section {
-            return Err("function section does not exist");
-        }
-        {
-            for s in self.sections.iter() {
-                if let SectionView::Type(type_section) = s {
-                    let fn_type = &type_section.types[fn_type_index];
-                    return Ok((fn_type.inputs.len(), fn_type.outputs.len()));
-                }
+impl InterpretableProgram for Module<'_> {
+    fn function(&self, index: FuncIdx) -> Result<FunctionRef<'_>, &'static str> {
+        Self::function(self, index)
+    }
+
+    fn type_details(&self, index: TypeIdx) -> Result<&FunctionType, &'static str> {
+        for s in self.sections.iter() {
+            if let Section::Type(type_section) = s {
+                return match type_section.types.get(index.index()) {
+                    Some(t) => Ok(t),
+                    None => Err("function type does not exist with that index"),
+                };
             }
         }
         Err("function type section does not exist")
     }
 
-    fn import_fn_details(&self, index: usize) -> Result<(&str, &str, usize, usize), &'static str> {
+    fn load_data_into_memory(
+        &self,
+        mem: &mut [u8],
+        globals: &[WasmValue],
+    ) -> Result<(), &'static str> {
         for s in self.sections.iter() {
-            if let SectionView::Import(import_section) = s {
-                let l: Vec<_> = import_section
-                    .imports
-                    .iter()
-                    .filter(|x| matches!(x, WasmImportView::Function(_)))
-                    .collect();
-                if index < l.len() {
-                    let (param_ct, return_ct) = self.fn_details(index)?;
-                    if let WasmImportView::Function(x) = l[index] {
-                        return Ok((x.module_name, x.name, param_ct, return_ct));
-                    }
-                } else {
-                    return Err("import does not exist with that index");
+            if let Section::Data(d) = s {
+                for db in d.data_blocks.iter() {
+                    fill_memory(mem, &db.offset_expression, &db.data, globals)?;
                 }
             }
         }
-        Err("import section does not exist")
+        Ok(())
     }
 
-    fn load_data_into_memory(&self, mem: &mut Vec<u8>) -> Result<(), &'static str> {
+    fn initial_memory_size(&self) -> usize {
         for s in self.sections.iter() {
-            if let SectionView::Data(d) = s {
-                for db in d.data_blocks.iter() {
-                    match db.offset_expression[0] {
-                        Instruction::I32Const(x) => {
-                            let offset = x as usize;
-                            for (i, b) in db.data.iter().enumerate() {
-                                mem[offset + i] = *b;
-                            }
+            match s {
+                Section::Memory(m) if !m.memories.is_empty() => {
+                    return m.memories[0].min_pages * PAGE_SIZE;
+                }
+                Section::Import(i) => {
+                    for x in i.imports.iter() {
+                        if let WasmImport::Memory(m) = x {
+                            return m.min_pages * PAGE_SIZE;
                         }
-                        _ => return Err("I don't know how to build this memory yet"),
                     }
                 }
+                _ => {}
             }
         }
-        Ok(())
+        0
     }
 
-    fn initial_memory_size(&self) -> usize {
+    fn max_memory_pages(&self) -> Option<usize> {
         for s in self.sections.iter() {
-            if let SectionView::Memory(m) = s {
-                if !m.memories.is_empty() {
-                    return m.memories[0].min_pages * 1024;
+            match s {
+                Section::Memory(m) if !m.memories.is_empty() => return m.memories[0].max_pages,
+                Section::Import(i) => {
+                    for x in i.imports.iter() {
+                        if let WasmImport::Memory(m) = x {
+                            return m.max_pages;
+                        }
+                    }
                 }
+                _ => {}
             }
         }
-        0
+        None
     }
 
     fn import_fn_count(&self) -> usize {
+        self.imported_function_count()
+    }
+
+    fn import_global_types(&self) -> Vec<ValueType> {
+        let mut types = vec![];
         for s in self.sections.iter() {
-            if let SectionView::Import(import_section) = s {
-                let l: Vec<_> = import_section
-                    .imports
-                    .iter()
-                    .filter(|x| matches!(x, WasmImportView::Function(_)))
-                    .collect();
-                return l.len();
+            if let Section::Import(import_section) = s {
+                for x in import_section.imports.iter() {
+                    if let WasmImport::Global(g) = x {
+                        types.push(g.value_type);
+                    }
+                }
             }
         }
-        0
+        types
     }
 
-    fn fetch_export_fn_index(&self, name: &str) -> Result<(usize, usize), &'static str> {
-        let ct = self.import_fn_count();
-        let code_section_idx = self.fetch_code_section_index()?;
+    fn fetch_export(&self, name: &str) -> Result<(u8, usize), &'static str> {
         for s in self.sections.iter() {
-            if let SectionView::Export(export_section) = s {
+            if let Section::Export(export_section) = s {
                 for e in export_section.exports.iter() {
-                    if let WasmExportView::Function(f) = e {
-                        if f.name == name {
-                            return Ok((code_section_idx, f.index - ct));
-                        }
+                    let (desc, export_name, index) = match e {
+                        WasmExport::Function(x) => (DESC_FUNCTION, &*x.name, x.index.index()),
+                        WasmExport::Table(x) => (DESC_TABLE, &*x.name, x.index.index()),
+                        WasmExport::Memory(x) => (DESC_MEMORY, &*x.name, x.index.index()),
+                        WasmExport::Global(x) => (DESC_GLOBAL, &*x.name, x.index.index()),
+                    };
+                    if export_name == name {
+                        return Ok((desc, index));
                     }
                 }
             }
         }
-        Err("could not find export section")
+        Err("export does not exist")
     }
 
     fn fetch_code_section_index(&self) -> Result<usize, &'static str> {
@@ -432,57 +367,58 @@ impl InterpretableProgram for ProgramView<'_> {
             .sections
             .iter()
             .enumerate()
-            .find(|(_, x)| matches!(x, SectionView::Code(_)));
+            .find(|(_, x)| matches!(x, Section::Code(_)));
         let code_section_idx = match result {
             Some((i, _)) => i,
             None => return Err("Code section did not exist"),
         };
-        return Ok(code_section_idx);
+        Ok(code_section_idx)
     }
 
-    fn fetch_instruction<'a>(
-        &'a self,
-        position: &[usize],
-    ) -> Result<Option<&'a Instruction>, &'static str> {
-        if let SectionView::Code(code_section) = &self.sections[position[0]] {
-            let b = &code_section.code_blocks[position[1]];
-            // TODO: handle nesting
-            if position.len() > 2 {
-                let i = 2;
-                let instruction_index = position[i];
-                let len = &b.instructions.len();
-                if instruction_index < *len {
-                    Ok(Some(&b.instructions[instruction_index]))
-                } else {
-                    Ok(None)
+    fn initial_globals(&self, imported: &[WasmValue]) -> Result<Vec<WasmValue>, &'static str> {
+        let mut globals = imported.to_vec();
+        for s in self.sections.iter() {
+            if let Section::Global(global_section) = s {
+                for g in global_section.globals.iter() {
+                    let v = evaluate_const_expression(&g.value_expression, imported)?;
+                    globals.push(v);
                 }
-            } else {
-                Ok(None)
             }
-        } else {
-            Err("cannot find code section")
         }
+        Ok(globals)
     }
 
-    fn create_locals(&self, position: &[usize]) -> Result<Vec<WasmValue>, &'static str> {
-        let mut locals = vec![];
-        if let SectionView::Code(code_section) = &self.sections[position[0]] {
-            let b = &code_section.code_blocks[position[1]];
-            for l in b.locals.iter() {
-                for _ in 0..l.count {
-                    let v = match l.value_type {
-                        ValueType::I32 => 0i32.to_wasm_value(),
-                        ValueType::I64 => 0i64.to_wasm_value(),
-                        ValueType::F32 => 0f32.to_wasm_value(),
-                        ValueType::F64 => 0f64.to_wasm_value(),
-                    };
-                    locals.push(v);
+    fn initial_table(&self, globals: &[WasmValue]) -> Result<Vec<Option<FuncIdx>>, &'static str> {
+        let mut size = 0;
+        for s in self.sections.iter() {
+            match s {
+                Section::Table(t) if !t.tables.is_empty() => size = t.tables[0].min,
+                Section::Import(i) => {
+                    for x in i.imports.iter() {
+                        if let WasmImport::Table(t) = x {
+                            size = t.min;
+                        }
+                    }
                 }
+                _ => {}
             }
-        } else {
-            return Err("cannot find code section");
         }
-        Ok(locals)
+        let mut table = vec![None; size];
+        for s in self.sections.iter() {
+            if let Section::Element(e) = s {
+                fill_table(&mut table, &e.elements, globals)?;
+            }
+        }
+        Ok(table)
+    }
+
+    fn start_fn_index(&self) -> Option<FuncIdx> {
+        for s in self.sections.iter() {
+            if let Section::Start(start_section) = s {
+                return Some(start_section.start_function);
+            }
+        }
+        None
     }
 }
 
@@ -491,20 +427,78 @@ where
     T: InterpretableProgram,
 {
     pub fn new(p: T) -> Result<Self, &'static str> {
+        let imported_globals: Vec<WasmValue> = p
+            .import_global_types()
+            .iter()
+            .map(WasmValue::zero)
+            .collect();
+        Interpreter::new_with_imported_globals(p, &imported_globals)
+    }
+
+    pub fn new_with_imported_globals(
+        p: T,
+        imported_globals: &[WasmValue],
+    ) -> Result<Self, &'static str> {
+        if imported_globals.len() != p.import_global_types().len() {
+            return Err("wrong number of imported globals");
+        }
+        let globals = p.initial_globals(imported_globals)?;
+        let table = p.initial_table(&globals)?;
+        let mut bodies = Vec::new();
+        let mut next = p.import_fn_count();
+        while let Ok(FunctionRef::Defined { body, .. }) = p.function(FuncIdx::try_from(next)?) {
+            bodies.push(FlatBody::from_instructions(body));
+            next += 1;
+        }
         let mem_size = p.initial_memory_size();
         let mut mem = vec![0; mem_size];
-        p.load_data_into_memory(&mut mem)?;
+        p.load_data_into_memory(&mut mem, &globals)?;
         Ok(Interpreter {
             memory: Arc::new(Mutex::new(mem)),
+            globals: Arc::new(Mutex::new(globals)),
+            table: Arc::new(Mutex::new(table)),
             program: Arc::new(Mutex::new(p)),
+            bodies: Arc::new(bodies),
         })
     }
+
     pub fn call(
         &mut self,
         name: &str,
         params: &[WasmValue],
     ) -> Result<WasmExecution<T>, &'static str> {
-        WasmExecution::new(name, params, self.program.clone(), self.memory.clone())
+        let export = self.program.lock().fetch_export(name)?;
+        match export {
+            (DESC_FUNCTION, fn_index) => self.call_index(FuncIdx::try_from(fn_index)?, params),
+            _ => Err("export is not a function"),
+        }
+    }
+
+    pub fn call_index(
+        &mut self,
+        fn_index: FuncIdx,
+        params: &[WasmValue],
+    ) -> Result<WasmExecution<T>, &'static str> {
+        WasmExecution::new(fn_index, params, self)
+    }
+
+    pub fn start(&mut self) -> Result<Option<WasmExecution<T>>, &'static str> {
+        let start = self.program.lock().start_fn_index();
+        match start {
+            Some(fn_index) => Ok(Some(self.call_index(fn_index, &[])?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn global(&self, name: &str) -> Result<WasmValue, &'static str> {
+        let export = self.program.lock().fetch_export(name)?;
+        match export {
+            (DESC_GLOBAL, index) => match self.globals.lock().get(index) {
+                Some(v) => Ok(*v),
+                None => Err("global does not exist with that index"),
+            },
+            _ => Err("export is not a global"),
+        }
     }
 }
 
@@ -513,16 +507,46 @@ pub struct WasmExecution<T>
 where
     T: InterpretableProgram,
 {
-    #[serde(skip)]
     import_fn_count: usize,
-    pub call_stack: Vec<(usize, Vec<WasmValue>)>,
+    pub call_stack: Vec<(FuncIdx, Vec<WasmValue>)>,
     pub value_stack: Vec<Vec<WasmValue>>,
-    pub current_position: Vec<Vec<usize>>,
+    pub label_stack: Vec<Vec<Label>>,
+    /// Where each function on the call stack is in its flat body.
+    pub current_position: Vec<usize>,
     #[serde(skip)]
     pub memory: Arc<Mutex<Vec<u8>>>,
     #[serde(skip)]
+    pub globals: Arc<Mutex<Vec<WasmValue>>>,
+    #[serde(skip)]
+    pub table: Arc<Mutex<Vec<Option<FuncIdx>>>>,
+    #[serde(skip)]
     pub program: Arc<Mutex<T>>,
-    code_section_idx: usize,
+    #[serde(skip)]
+    bodies: Arc<Vec<FlatBody>>,
+}
+
+// the registers of a call, its parameters followed by its zeroed locals
+fn registers<T: InterpretableProgram>(
+    p: &T,
+    fn_index: FuncIdx,
+    params: &[WasmValue],
+) -> Result<Vec<WasmValue>, &'static str> {
+    let mut registers = params.to_vec();
+    if let FunctionRef::Defined { locals, .. } = p.function(fn_index)? {
+        for l in locals.iter() {
+            for _ in 0..l.count {
+                registers.push(WasmValue::zero(&l.value_type));
+            }
+        }
+    }
+    Ok(registers)
+}
+
+fn take_values(stack: &mut Vec<WasmValue>, ct: usize) -> Result<Vec<WasmValue>, &'static str> {
+    if stack.len() < ct {
+        return Err("ran out of values on value stack");
+    }
+    Ok(stack.split_off(stack.len() - ct))
 }
 
 impl<T> WasmExecution<T>
@@ -530,149 +554,324 @@ where
     T: InterpretableProgram,
 {
     pub fn new(
-        name: &str,
+        fn_index: FuncIdx,
         params: &[WasmValue],
-        program: Arc<Mutex<T>>,
-        memory: Arc<Mutex<Vec<u8>>>,
+        interpreter: &Interpreter<T>,
     ) -> Result<Self, &'static str> {
-        let p = program.lock();
+        let p = interpreter.program.lock();
+        if p.function(fn_index)?.is_imported() {
+            return Err("cannot execute an imported function");
+        }
         let import_fn_count = p.import_fn_count();
-        let (code_section_idx, function_idx) = p.fetch_export_fn_index(name)?;
-        let position = vec![code_section_idx, function_idx];
-        let locals = p.create_locals(&position)?;
-        let mut registers = params.to_vec();
-        registers.extend(locals);
-        println!("calling export {}", name);
-        println!("{:#?}", params);
+        if p.fn_type(fn_index)?.inputs.len() != params.len() {
+            return Err("wrong number of parameters");
+        }
         Ok(WasmExecution {
-            call_stack: vec![(function_idx, registers)],
+            call_stack: vec![(fn_index, registers(&*p, fn_index, params)?)],
             import_fn_count,
             value_stack: vec![vec![]],
-            current_position: vec![position],
-            memory,
-            program: program.clone(),
-            code_section_idx,
+            label_stack: vec![vec![]],
+            current_position: vec![0],
+            memory: interpreter.memory.clone(),
+            globals: interpreter.globals.clone(),
+            table: interpreter.table.clone(),
+            program: interpreter.program.clone(),
+            bodies: interpreter.bodies.clone(),
         })
     }
 
     fn enter_function_context(
         &mut self,
-        function_idx: usize,
+        function_idx: FuncIdx,
         params: &[WasmValue],
     ) -> Result<(), &'static str> {
-        println!("entering function context");
-        let p = self.program.lock();
-        let position = vec![self.code_section_idx, function_idx];
-        let locals = p.create_locals(&position)?;
-        let mut registers = params.to_vec();
-        registers.extend(locals);
-        println!("{:#?}", registers);
+        if self.call_stack.len() >= MAX_CALL_DEPTH {
+            return Err("call stack exhausted");
+        }
+        let registers = registers(&*self.program.lock(), function_idx, params)?;
         self.call_stack.push((function_idx, registers));
         self.value_stack.push(vec![]);
-        self.current_position.push(position);
+        self.label_stack.push(vec![]);
+        self.current_position.push(0);
         Ok(())
     }
 
     fn exit_function_context(&mut self, params: &[WasmValue]) -> Result<(), &'static str> {
-        println!("exiting function context");
-        self.call_stack.drain(0..1);
-        self.value_stack.drain(0..1);
-        self.current_position.drain(0..1);
-        for v in params.iter() {
-            self.value_stack[0].push(v.clone())
+        self.call_stack.pop();
+        self.value_stack.pop();
+        self.label_stack.pop();
+        self.current_position.pop();
+        if let Some(stack) = self.value_stack.last_mut() {
+            stack.extend_from_slice(params);
         }
         Ok(())
     }
 
-    pub fn next_unit(&mut self) -> Result<ExecutionUnit, &'static str> {
-        let p = self.program.lock();
-        if self.current_position[0].len() == 2 {
-            self.current_position[0].push(0);
+    // returns the results if the outermost function is the one returning
+    fn return_from_function(&mut self, p: &T) -> Result<Option<Vec<WasmValue>>, &'static str> {
+        let frame = self.call_stack.len() - 1;
+        let (_, result_ct) = p.fn_details(self.call_stack[frame].0)?;
+        let results = take_values(&mut self.value_stack[frame], result_ct)?;
+        if frame == 0 {
+            // park past the end of the body so asking again completes again
+            self.label_stack[0].clear();
+            self.value_stack[0] = results.clone();
+            self.current_position[0] = usize::MAX;
+            return Ok(Some(results));
+        }
+        self.exit_function_context(&results)?;
+        Ok(None)
+    }
+
+    fn enter_block(&mut self, arity: usize, is_loop: bool) {
+        let frame = self.call_stack.len() - 1;
+        self.label_stack[frame].push(Label {
+            arity,
+            stack_height: self.value_stack[frame].len(),
+            start: self.current_position[frame],
+            is_loop,
+        });
+    }
+
+    fn branch(
+        &mut self,
+        p: &T,
+        body: &FlatBody,
+        depth: u32,
+    ) -> Result<Option<Vec<WasmValue>>, &'static str> {
+        let frame = self.call_stack.len() - 1;
+        let depth = depth as usize;
+        let labels = &mut self.label_stack[frame];
+        if depth == labels.len() {
+            return self.return_from_function(p);
+        } else if depth > labels.len() {
+            return Err("invalid branch depth");
+        }
+        let index = labels.len() - 1 - depth;
+        let label = labels[index];
+        let stack = &mut self.value_stack[frame];
+        let values = take_values(stack, label.arity)?;
+        stack.truncate(label.stack_height);
+        stack.extend(values);
+        if label.is_loop {
+            labels.truncate(index + 1);
+            self.current_position[frame] = label.start + 1;
         } else {
-            let len = self.current_position[0].len() - 1;
-            self.current_position[0][len] += 1;
+            labels.truncate(index);
+            match body.matching_end(label.start) {
+                Some(end) => self.current_position[frame] = end + 1,
+                None => return Err("invalid instruction position"),
+            }
         }
-        let first_function_instruction = p.fetch_instruction(&self.current_position[0])?;
-        if let Some(instruction) = first_function_instruction {
-            let unit = match instruction {
-                Instruction::Call(fn_index) => {
-                    if ((*fn_index) as usize) < self.import_fn_count {
-                        let (module_name, name, param_ct, _) =
-                            p.import_fn_details((*fn_index) as usize)?;
-                        let mut params = vec![];
-                        for _ in 0..param_ct {
-                            let p = match self.value_stack[0].pop() {
-                                Some(p) => p,
-                                None => return Err("ran out of values on value stack"),
-                            };
-                            params.push(p);
-                        }
-                        ExecutionUnit::CallImport(ImportCall {
-                            module_name: module_name.to_string(),
-                            name: name.to_string(),
-                            params,
-                        })
+        Ok(None)
+    }
+
+    fn call_unit(&mut self, p: &T, fn_index: FuncIdx) -> Result<ExecutionUnit, &'static str> {
+        let frame = self.call_stack.len() - 1;
+        let function = p.function(fn_index)?;
+        let param_ct = p.type_details(function.type_index())?.inputs.len();
+        let params = take_values(&mut self.value_stack[frame], param_ct)?;
+        match function {
+            FunctionRef::Imported { module, name, .. } => {
+                Ok(ExecutionUnit::CallImport(ImportCall {
+                    module_name: module.to_string(),
+                    name: name.to_string(),
+                    params,
+                }))
+            }
+            FunctionRef::Defined { .. } => Ok(ExecutionUnit::Call(Call { fn_index, params })),
+        }
+    }
+
+    pub fn next_unit(&mut self) -> Result<ExecutionUnit, &'static str> {
+        if self.call_stack.is_empty() {
+            return Err("execution has already completed");
+        }
+        let program = self.program.clone();
+        let p = program.lock();
+        let bodies = self.bodies.clone();
+        loop {
+            let frame = self.call_stack.len() - 1;
+            let body = match bodies.get(self.call_stack[frame].0.index() - self.import_fn_count) {
+                Some(b) => b,
+                None => return Err("function does not exist with that index"),
+            };
+            let instruction = match body.get(self.current_position[frame]) {
+                Some(FlatInstruction::Plain(i)) => i,
+                Some(FlatInstruction::Block { block_type, .. }) => {
+                    self.enter_block(block_arity(*block_type), false);
+                    self.current_position[frame] += 1;
+                    continue;
+                }
+                Some(FlatInstruction::Loop { .. }) => {
+                    self.enter_block(0, true);
+                    self.current_position[frame] += 1;
+                    continue;
+                }
+                Some(FlatInstruction::If {
+                    block_type,
+                    else_at,
+                    end,
+                }) => {
+                    let condition = pop_i32(&mut self.value_stack[frame])?;
+                    if condition != 0 {
+                        self.enter_block(block_arity(*block_type), false);
+                        self.current_position[frame] += 1;
+                    } else if let Some(else_at) = else_at {
+                        self.enter_block(block_arity(*block_type), false);
+                        self.current_position[frame] = *else_at as usize + 1;
                     } else {
-                        let (param_ct, _) = p.fn_details((*fn_index) as usize)?;
-                        let mut params = vec![];
-                        for _ in 0..param_ct {
-                            let p = match self.value_stack[0].pop() {
-                                Some(p) => p,
-                                None => return Err("ran out of values on value stack"),
-                            };
-                            params.push(p);
-                        }
-                        ExecutionUnit::Call(Call {
-                            fn_index: *fn_index,
-                            params,
-                        })
+                        self.current_position[frame] = *end as usize + 1;
+                    }
+                    continue;
+                }
+                // the then arm is done, the end of the if takes its label
+                Some(FlatInstruction::Else { end }) => {
+                    self.current_position[frame] = *end as usize;
+                    continue;
+                }
+                Some(FlatInstruction::End) => {
+                    self.label_stack[frame].pop();
+                    self.current_position[frame] += 1;
+                    continue;
+                }
+                None => {
+                    if let Some(results) = self.return_from_function(&*p)? {
+                        return Ok(ExecutionUnit::Complete(results));
                     }
+                    continue;
                 }
-                Instruction::Unreachable => ExecutionUnit::Unreachable,
-                x => ExecutionUnit::BasicInstruction(x.clone()),
             };
-            Ok(unit)
-        } else {
-            // TODO: handle nested function call
-            Ok(ExecutionUnit::Complete(self.value_stack[0].clone()))
+            let result = match instruction {
+                Instruction::Br(depth) => self.branch(&*p, body, *depth)?,
+                Instruction::BrIf(depth) => {
+                    if pop_i32(&mut self.value_stack[frame])? != 0 {
+                        self.branch(&*p, body, *depth)?
+                    } else {
+                        self.current_position[frame] += 1;
+                        None
+                    }
+                }
+                Instruction::BrTable(labels, default_label) => {
+                    let i = pop_i32(&mut self.value_stack[frame])? as u32 as usize;
+                    let depth = *labels.get(i).unwrap_or(default_label);
+                    self.branch(&*p, body, depth)?
+                }
+                Instruction::Return => self.return_from_function(&*p)?,
+                Instruction::Call(fn_index) => {
+                    self.current_position[frame] += 1;
+                    return self.call_unit(&*p, *fn_index);
+                }
+                Instruction::CallIndirect(type_index) => {
+                    self.current_position[frame] += 1;
+                    let element = pop_i32(&mut self.value_stack[frame])? as u32 as usize;
+                    let fn_index = match self.table.lock().get(element) {
+                        Some(Some(f)) => *f,
+                        Some(None) => return Err("uninitialized element"),
+                        None => return Err("undefined element"),
+                    };
+                    if p.fn_type(fn_index)? != p.type_details(*type_index)? {
+                        return Err("indirect call type mismatch");
+                    }
+                    return self.call_unit(&*p, fn_index);
+                }
+                Instruction::Unreachable => {
+                    self.current_position[frame] += 1;
+                    return Ok(ExecutionUnit::Unreachable);
+                }
+                x => {
+                    self.current_position[frame] += 1;
+                    return Ok(ExecutionUnit::BasicInstruction(x.clone()));
+                }
+            };
+            if let Some(results) = result {
+                return Ok(ExecutionUnit::Complete(results));
+            }
         }
     }
 
     pub fn execute(&mut self, r: ExecutionResponse) -> Result<(), &'static str> {
+        let frame = match self.call_stack.len() {
+            0 => return Err("execution has already completed"),
+            len => len - 1,
+        };
         match r {
-            ExecutionResponse::GetMemorySize => self.value_stack[0].push(
-                (self.memory.lock().len().to_wasm_value().to_i32() / 1024i32).to_wasm_value(),
-            ),
+            ExecutionResponse::GetMemorySize => {
+                let pages = self.memory.lock().len() / PAGE_SIZE;
+                self.value_stack[frame].push(pages.to_wasm_value())
+            }
             ExecutionResponse::GetMemoryGrow => {
-                let _page_delta = self.value_stack[0].pop().unwrap().to_i32();
-                return Err("do not know how to extend memory");
+                let page_delta = pop_i32(&mut self.value_stack[frame])? as u32 as usize;
+                let max_pages = match self.program.lock().max_memory_pages() {
+                    Some(max) if max < MAX_PAGES => max,
+                    _ => MAX_PAGES,
+                };
+                let mut mem = self.memory.lock();
+                let pages = mem.len() / PAGE_SIZE;
+                if pages + page_delta > max_pages {
+                    self.value_stack[frame].push(WasmValue::I32(-1));
+                } else {
+                    mem.resize((pages + page_delta) * PAGE_SIZE, 0);
+                    self.value_stack[frame].push(pages.to_wasm_value());
+                }
             }
-            ExecutionResponse::ValueStackModification(f) => f(&mut self.value_stack[0])?,
+            ExecutionResponse::ValueStackModification(f) => f(&mut self.value_stack[frame])?,
             ExecutionResponse::AddValues(mut v) => {
                 while let Some(wv) = v.pop() {
-                    self.value_stack[0].push(wv);
+                    self.value_stack[frame].push(wv);
                 }
             }
-            ExecutionResponse::GetRegister(v) => {
-                self.value_stack[0].push(self.call_stack[0].1[v as usize]);
-            }
+            ExecutionResponse::GetRegister(v) => match self.call_stack[frame].1.get(v.index()) {
+                Some(p) => self.value_stack[frame].push(*p),
+                None => return Err("register does not exist"),
+            },
             ExecutionResponse::SetRegister(v) => {
-                if let Some(p) = self.value_stack[0].pop() {
-                    self.call_stack[0].1[v as usize] = p;
+                if let Some(p) = self.value_stack[frame].pop() {
+                    self.call_stack[frame].1[v.index()] = p;
                 } else {
                     return Err("can't set register because value stack is empty");
                 }
             }
             ExecutionResponse::TeeRegister(v) => {
-                if let Some(p) = self.value_stack[0].pop() {
-                    self.call_stack[0].1[v as usize] = p;
-                    self.value_stack[0].push(p);
+                if let Some(p) = self.value_stack[frame].pop() {
+                    self.call_stack[frame].1[v.index()] = p;
+                    self.value_stack[frame].push(p);
                 } else {
                     return Err("can't tee register because value stack is empty");
                 }
             }
+            ExecutionResponse::GetGlobal(i) => match self.globals.lock().get(i.index()) {
+                Some(g) => self.value_stack[frame].push(*g),
+                None => return Err("global does not exist"),
+            },
+            ExecutionResponse::SetGlobal(i) => {
+                let v = pop_value(&mut self.value_stack[frame])?;
+                match self.globals.lock().get_mut(i.index()) {
+                    Some(g) => *g = v,
+                    None => return Err("global does not exist"),
+                }
+            }
+            ExecutionResponse::LoadMemory(offset, width, decode) => {
+                let address = pop_i32(&mut self.value_stack[frame])? as u32 as usize;
+                let start = address + offset as usize;
+                let mem = self.memory.lock();
+                if start + width > mem.len() {
+                    return Err("out of bounds memory access");
+                }
+                self.value_stack[frame].push(decode(&mem[start..start + width]));
+            }
+            ExecutionResponse::StoreMemory(offset, width, encode) => {
+                let v = pop_value(&mut self.value_stack[frame])?;
+                let address = pop_i32(&mut self.value_stack[frame])? as u32 as usize;
+                let start = address + offset as usize;
+                let mut mem = self.memory.lock();
+                if start + width > mem.len() {
+                    return Err("out of bounds memory access");
+                }
+                encode(v, &mut mem[start..start + width]);
+            }
             ExecutionResponse::EnterFunction(fn_idx, params) => {
-                self.enter_function_context(fn_idx as usize, &params)?
+                self.enter_function_context(fn_idx, &params)?
             }
             ExecutionResponse::ExitFunction(params) => self.exit_function_context(&params)?,
             ExecutionResponse::ThrowError(msg) => return Err(msg),
@@ -686,53 +885,143 @@ where
     }
 }
 
+fn pop_value(stack: &mut Vec<WasmValue>) -> Result<WasmValue, &'static str> {
+    match stack.pop() {
+        Some(v) => Ok(v),
+        None => Err("ran out of values on value stack"),
+    }
+}
+
+fn pop_i32(stack: &mut Vec<WasmValue>) -> Result<i32, &'static str> {
+    match pop_value(stack)? {
+        WasmValue::I32(v) => Ok(v),
+        _ => Err("expected i32 on value stack"),
+    }
+}
+
+fn pop_i64(stack: &mut Vec<WasmValue>) -> Result<i64, &'static str> {
+    match pop_value(stack)? {
+        WasmValue::I64(v) => Ok(v),
+        _ => Err("expected i64 on value stack"),
+    }
+}
+
+fn pop_f32(stack: &mut Vec<WasmValue>) -> Result<f32, &'static str> {
+    match pop_value(stack)? {
+        WasmValue::F32(v) => Ok(v),
+        _ => Err("expected f32 on value stack"),
+    }
+}
+
+fn pop_f64(stack: &mut Vec<WasmValue>) -> Result<f64, &'static str> {
+    match pop_value(stack)? {
+        WasmValue::F64(v) => Ok(v),
+        _ => Err("expected f64 on value stack"),
+    }
+}
+
+type Pop<A> = fn(&mut Vec<WasmValue>) -> Result<A, &'static str>;
+
+fn unary<A, R: ToWasmValue>(
+    stack: &mut Vec<WasmValue>,
+    pop: Pop<A>,
+    op: impl Fn(A) -> Result<R, &'static str>,
+) -> Result<(), &'static str> {
+    let a = pop(stack)?;
+    stack.push(op(a)?.to_wasm_value());
+    Ok(())
+}
+
+fn binary<A, R: ToWasmValue>(
+    stack: &mut Vec<WasmValue>,
+    pop: Pop<A>,
+    op: impl Fn(A, A) -> Result<R, &'static str>,
+) -> Result<(), &'static str> {
+    let b = pop(stack)?;
+    let a = pop(stack)?;
+    stack.push(op(a, b)?.to_wasm_value());
+    Ok(())
+}
+
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn le64(b: &[u8]) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(b);
+    u64::from_le_bytes(bytes)
+}
+
+fn le16(b: &[u8]) -> u16 {
+    u16::from_le_bytes([b[0], b[1]])
+}
+
+fn div_s32(a: i32, b: i32) -> Result<i32, &'static str> {
+    if b == 0 {
+        Err("integer divide by zero")
+    } else {
+        a.checked_div(b).ok_or("integer overflow")
+    }
+}
+
+fn div_s64(a: i64, b: i64) -> Result<i64, &'static str> {
+    if b == 0 {
+        Err("integer divide by zero")
+    } else {
+        a.checked_div(b).ok_or("integer overflow")
+    }
+}
+
+// truncates a float, checking the result fits in the range [min, max)
+fn trunc_check(x: f64, min: f64, max: f64) -> Result<f64, &'static str> {
+    let t = trunc_f64(x);
+    if x.is_nan() {
+        Err("invalid conversion to integer")
+    } else if t < min || t >= max {
+        Err("integer overflow")
+    } else {
+        Ok(t)
+    }
+}
+
+const I32_MIN: f64 = -2147483648.0;
+const I32_MAX: f64 = 2147483648.0;
+const U32_MAX: f64 = 4294967296.0;
+const I64_MIN: f64 = -9223372036854775808.0;
+const I64_MAX: f64 = 9223372036854775808.0;
+const U64_MAX: f64 = 18446744073709551616.0;
+
 impl ExecutionUnit {
     pub fn evaluate(&mut self) -> Result<ExecutionResponse, &'static str> {
+        use ExecutionResponse::{LoadMemory, StoreMemory, ValueStackModification};
         let response = match self {
             ExecutionUnit::Unreachable => ExecutionResponse::ThrowError("Reached unreachable"),
             ExecutionUnit::BasicInstruction(i) => match i {
-                Instruction::Raw(b) => {
+                Instruction::Raw(_) => {
                     return Err("Cannot handle raw instruction.");
                 }
-                Instruction::Unreachable => {
-                    return Err("Cannot handle unreachable.");
-                }
                 Instruction::Nop => ExecutionResponse::DoNothing,
-                Instruction::Block(block_type, instructions) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::Loop(block_type, instructions) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::If(block_type, if_instructions, else_instructions) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::Br(i) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::BrIf(i) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::BrTable(labels, label_index) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::Return => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::Call(i) => {
-                    return Err("Cannot handle call.");
-                }
-                Instruction::CallIndirect(i) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::Drop => ExecutionResponse::ValueStackModification(|stack| {
-                    stack.pop();
+                Instruction::Unreachable
+                | Instruction::Block(_, _)
+                | Instruction::Loop(_, _)
+                | Instruction::If(_, _, _)
+                | Instruction::Br(_)
+                | Instruction::BrIf(_)
+                | Instruction::BrTable(_, _)
+                | Instruction::Return
+                | Instruction::Call(_)
+                | Instruction::CallIndirect(_) => {
+                    return Err("control instructions are handled by the execution");
+                }
+                Instruction::Drop => ValueStackModification(|stack| {
+                    pop_value(stack)?;
                     Ok(())
                 }),
-                Instruction::Select => ExecutionResponse::ValueStackModification(|stack| {
-                    let cond = stack.pop().unwrap().to_i32();
-                    let b = stack.pop().unwrap();
-                    let a = stack.pop().unwrap();
+                Instruction::Select => ValueStackModification(|stack| {
+                    let cond = pop_i32(stack)?;
+                    let b = pop_value(stack)?;
+                    let a = pop_value(stack)?;
                     if cond != 0 {
                         stack.push(a);
                     } else {
@@ -743,81 +1032,65 @@ impl ExecutionUnit {
                 Instruction::LocalGet(i) => ExecutionResponse::GetRegister(*i),
                 Instruction::LocalSet(i) => ExecutionResponse::SetRegister(*i),
                 Instruction::LocalTee(i) => ExecutionResponse::TeeRegister(*i),
-                Instruction::GlobalGet(i) => {
-                    return Err("no default evaluation for basic instruction yet");
+                Instruction::GlobalGet(i) => ExecutionResponse::GetGlobal(*i),
+                Instruction::GlobalSet(i) => ExecutionResponse::SetGlobal(*i),
+                Instruction::I32Load(_, o) => LoadMemory(*o, 4, |b| WasmValue::I32(le32(b) as i32)),
+                Instruction::I64Load(_, o) => LoadMemory(*o, 8, |b| WasmValue::I64(le64(b) as i64)),
+                Instruction::F32Load(_, o) => {
+                    LoadMemory(*o, 4, |b| WasmValue::F32(f32::from_bits(le32(b))))
                 }
-                Instruction::GlobalSet(i) => {
-                    return Err("no default evaluation for basic instruction yet");
+                Instruction::F64Load(_, o) => {
+                    LoadMemory(*o, 8, |b| WasmValue::F64(f64::from_bits(le64(b))))
                 }
-                Instruction::I32Load(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
+                Instruction::I32Load8S(_, o) => {
+                    LoadMemory(*o, 1, |b| WasmValue::I32(b[0] as i8 as i32))
                 }
-                Instruction::I64Load(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
+                Instruction::I32Load8U(_, o) => LoadMemory(*o, 1, |b| WasmValue::I32(b[0] as i32)),
+                Instruction::I32Load16S(_, o) => {
+                    LoadMemory(*o, 2, |b| WasmValue::I32(le16(b) as i16 as i32))
                 }
-                Instruction::F32Load(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
+                Instruction::I32Load16U(_, o) => {
+                    LoadMemory(*o, 2, |b| WasmValue::I32(le16(b) as i32))
                 }
-                Instruction::F64Load(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
+                Instruction::I64Load8S(_, o) => {
+                    LoadMemory(*o, 1, |b| WasmValue::I64(b[0] as i8 as i64))
                 }
-                Instruction::I32Load8S(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
+                Instruction::I64Load8U(_, o) => LoadMemory(*o, 1, |b| WasmValue::I64(b[0] as i64)),
+                Instruction::I64Load16S(_, o) => {
+                    LoadMemory(*o, 2, |b| WasmValue::I64(le16(b) as i16 as i64))
                 }
-                Instruction::I32Load8U(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
+                Instruction::I64Load16U(_, o) => {
+                    LoadMemory(*o, 2, |b| WasmValue::I64(le16(b) as i64))
                 }
-                Instruction::I32Load16S(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
+                Instruction::I64Load32S(_, o) => {
+                    LoadMemory(*o, 4, |b| WasmValue::I64(le32(b) as i32 as i64))
                 }
-                Instruction::I32Load16U(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
+                Instruction::I64Load32U(_, o) => {
+                    LoadMemory(*o, 4, |b| WasmValue::I64(le32(b) as i64))
                 }
-                Instruction::I64Load8S(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
+                Instruction::I32Store(_, o) => {
+                    StoreMemory(*o, 4, |v, b| b.copy_from_slice(&v.to_i32().to_le_bytes()))
                 }
-                Instruction::I64Load8U(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64Load16S(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64Load16U(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64Load32S(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64Load32U(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32Store(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64Store(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::F32Store(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::F64Store(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32Store8(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32Store16(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64Store8(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64Store16(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64Store32(align, offset) => {
-                    return Err("no default evaluation for basic instruction yet");
+                Instruction::I64Store(_, o) => {
+                    StoreMemory(*o, 8, |v, b| b.copy_from_slice(&v.to_i64().to_le_bytes()))
                 }
+                Instruction::F32Store(_, o) => StoreMemory(*o, 4, |v, b| {
+                    b.copy_from_slice(&v.to_f32().to_bits().to_le_bytes())
+                }),
+                Instruction::F64Store(_, o) => StoreMemory(*o, 8, |v, b| {
+                    b.copy_from_slice(&v.to_f64().to_bits().to_le_bytes())
+                }),
+                Instruction::I32Store8(_, o) => StoreMemory(*o, 1, |v, b| b[0] = v.to_i32() as u8),
+                Instruction::I32Store16(_, o) => StoreMemory(*o, 2, |v, b| {
+                    b.copy_from_slice(&(v.to_i32() as u16).to_le_bytes())
+                }),
+                Instruction::I64Store8(_, o) => StoreMemory(*o, 1, |v, b| b[0] = v.to_i64() as u8),
+                Instruction::I64Store16(_, o) => StoreMemory(*o, 2, |v, b| {
+                    b.copy_from_slice(&(v.to_i64() as u16).to_le_bytes())
+                }),
+                Instruction::I64Store32(_, o) => StoreMemory(*o, 4, |v, b| {
+                    b.copy_from_slice(&(v.to_i64() as u32).to_le_bytes())
+                }),
                 Instruction::MemorySize => ExecutionResponse::GetMemorySize,
                 Instruction::MemoryGrow => ExecutionResponse::GetMemoryGrow,
                 Instruction::I32Const(i) => ExecutionResponse::AddValues(vec![i.to_wasm_value()]),
@@ -825,373 +1098,399 @@ impl ExecutionUnit {
                 Instruction::F32Const(f) => ExecutionResponse::AddValues(vec![f.to_wasm_value()]),
                 Instruction::F64Const(f) => ExecutionResponse::AddValues(vec![f.to_wasm_value()]),
                 Instruction::I32Eqz => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i32, |a| Ok((a == 0) as i32)))
                 }
                 Instruction::I32Eq => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i32, |a, b| Ok((a == b) as i32)))
                 }
                 Instruction::I32Ne => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i32, |a, b| Ok((a != b) as i32)))
                 }
                 Instruction::I32LtS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32LtU => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i32, |a, b| Ok((a < b) as i32)))
                 }
+                Instruction::I32LtU => ValueStackModification(|s| {
+                    binary(s, pop_i32, |a, b| Ok(((a as u32) < b as u32) as i32))
+                }),
                 Instruction::I32GtS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32GtU => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i32, |a, b| Ok((a > b) as i32)))
                 }
+                Instruction::I32GtU => ValueStackModification(|s| {
+                    binary(s, pop_i32, |a, b| Ok((a as u32 > b as u32) as i32))
+                }),
                 Instruction::I32LeS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32LeU => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i32, |a, b| Ok((a <= b) as i32)))
                 }
+                Instruction::I32LeU => ValueStackModification(|s| {
+                    binary(s, pop_i32, |a, b| Ok((a as u32 <= b as u32) as i32))
+                }),
                 Instruction::I32GeS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32GeU => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i32, |a, b| Ok((a >= b) as i32)))
                 }
+                Instruction::I32GeU => ValueStackModification(|s| {
+                    binary(s, pop_i32, |a, b| Ok((a as u32 >= b as u32) as i32))
+                }),
                 Instruction::I64Eqz => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i64, |a| Ok((a == 0) as i32)))
                 }
                 Instruction::I64Eq => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i64, |a, b| Ok((a == b) as i32)))
                 }
                 Instruction::I64Ne => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i64, |a, b| Ok((a != b) as i32)))
                 }
                 Instruction::I64LtS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64LtU => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i64, |a, b| Ok((a < b) as i32)))
                 }
+                Instruction::I64LtU => ValueStackModification(|s| {
+                    binary(s, pop_i64, |a, b| Ok(((a as u64) < b as u64) as i32))
+                }),
                 Instruction::I64GtS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64GtU => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i64, |a, b| Ok((a > b) as i32)))
                 }
+                Instruction::I64GtU => ValueStackModification(|s| {
+                    binary(s, pop_i64, |a, b| Ok((a as u64 > b as u64) as i32))
+                }),
                 Instruction::I64LeS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64LeU => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i64, |a, b| Ok((a <= b) as i32)))
                 }
+                Instruction::I64LeU => ValueStackModification(|s| {
+                    binary(s, pop_i64, |a, b| Ok((a as u64 <= b as u64) as i32))
+                }),
                 Instruction::I64GeS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64GeU => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i64, |a, b| Ok((a >= b) as i32)))
                 }
+                Instruction::I64GeU => ValueStackModification(|s| {
+                    binary(s, pop_i64, |a, b| Ok((a as u64 >= b as u64) as i32))
+                }),
                 Instruction::F32Eq => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok((a == b) as i32)))
                 }
                 Instruction::F32Ne => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok((a != b) as i32)))
                 }
                 Instruction::F32Lt => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok((a < b) as i32)))
                 }
                 Instruction::F32Gt => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok((a > b) as i32)))
                 }
                 Instruction::F32Le => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok((a <= b) as i32)))
                 }
                 Instruction::F32Ge => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok((a >= b) as i32)))
                 }
                 Instruction::F64Eq => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok((a == b) as i32)))
                 }
                 Instruction::F64Ne => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok((a != b) as i32)))
                 }
                 Instruction::F64Lt => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok((a < b) as i32)))
                 }
                 Instruction::F64Gt => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok((a > b) as i32)))
                 }
                 Instruction::F64Le => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok((a <= b) as i32)))
                 }
                 Instruction::F64Ge => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok((a >= b) as i32)))
                 }
                 Instruction::I32Clz => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i32, |a| Ok(a.leading_zeros() as i32)))
                 }
                 Instruction::I32Ctz => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i32, |a| Ok(a.trailing_zeros() as i32)))
                 }
                 Instruction::I32Popcnt => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i32, |a| Ok(a.count_ones() as i32)))
                 }
                 Instruction::I32Add => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i32, |a, b| Ok(a.wrapping_add(b))))
                 }
                 Instruction::I32Sub => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i32, |a, b| Ok(a.wrapping_sub(b))))
                 }
                 Instruction::I32Mul => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32DivS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32DivU => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32RemS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32RemU => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
+                    ValueStackModification(|s| binary(s, pop_i32, |a, b| Ok(a.wrapping_mul(b))))
+                }
+                Instruction::I32DivS => ValueStackModification(|s| binary(s, pop_i32, div_s32)),
+                Instruction::I32DivU => ValueStackModification(|s| {
+                    binary(s, pop_i32, |a, b| match (a as u32).checked_div(b as u32) {
+                        Some(x) => Ok(x as i32),
+                        None => Err("integer divide by zero"),
+                    })
+                }),
+                Instruction::I32RemS => ValueStackModification(|s| {
+                    binary(s, pop_i32, |a, b| match b {
+                        0 => Err("integer divide by zero"),
+                        _ => Ok(a.wrapping_rem(b)),
+                    })
+                }),
+                Instruction::I32RemU => ValueStackModification(|s| {
+                    binary(s, pop_i32, |a, b| match (a as u32).checked_rem(b as u32) {
+                        Some(x) => Ok(x as i32),
+                        None => Err("integer divide by zero"),
+                    })
+                }),
                 Instruction::I32And => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i32, |a, b| Ok(a & b)))
                 }
                 Instruction::I32Or => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i32, |a, b| Ok(a | b)))
                 }
                 Instruction::I32Xor => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32Shl => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32ShrS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32ShrU => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32Rotl => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32Rotr => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i32, |a, b| Ok(a ^ b)))
                 }
+                Instruction::I32Shl => ValueStackModification(|s| {
+                    binary(s, pop_i32, |a, b| Ok(a.wrapping_shl(b as u32)))
+                }),
+                Instruction::I32ShrS => ValueStackModification(|s| {
+                    binary(s, pop_i32, |a, b| Ok(a.wrapping_shr(b as u32)))
+                }),
+                Instruction::I32ShrU => ValueStackModification(|s| {
+                    binary(s, pop_i32, |a, b| {
+                        Ok((a as u32).wrapping_shr(b as u32) as i32)
+                    })
+                }),
+                Instruction::I32Rotl => ValueStackModification(|s| {
+                    binary(s, pop_i32, |a, b| Ok(a.rotate_left(b as u32)))
+                }),
+                Instruction::I32Rotr => ValueStackModification(|s| {
+                    binary(s, pop_i32, |a, b| Ok(a.rotate_right(b as u32)))
+                }),
                 Instruction::I64Clz => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i64, |a| Ok(a.leading_zeros() as i64)))
                 }
                 Instruction::I64Ctz => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i64, |a| Ok(a.trailing_zeros() as i64)))
                 }
                 Instruction::I64Popcnt => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i64, |a| Ok(a.count_ones() as i64)))
                 }
                 Instruction::I64Add => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i64, |a, b| Ok(a.wrapping_add(b))))
                 }
                 Instruction::I64Sub => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i64, |a, b| Ok(a.wrapping_sub(b))))
                 }
                 Instruction::I64Mul => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64DivS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64DivU => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64RemS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64RemU => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
+                    ValueStackModification(|s| binary(s, pop_i64, |a, b| Ok(a.wrapping_mul(b))))
+                }
+                Instruction::I64DivS => ValueStackModification(|s| binary(s, pop_i64, div_s64)),
+                Instruction::I64DivU => ValueStackModification(|s| {
+                    binary(s, pop_i64, |a, b| match (a as u64).checked_div(b as u64) {
+                        Some(x) => Ok(x as i64),
+                        None => Err("integer divide by zero"),
+                    })
+                }),
+                Instruction::I64RemS => ValueStackModification(|s| {
+                    binary(s, pop_i64, |a, b| match b {
+                        0 => Err("integer divide by zero"),
+                        _ => Ok(a.wrapping_rem(b)),
+                    })
+                }),
+                Instruction::I64RemU => ValueStackModification(|s| {
+                    binary(s, pop_i64, |a, b| match (a as u64).checked_rem(b as u64) {
+                        Some(x) => Ok(x as i64),
+                        None => Err("integer divide by zero"),
+                    })
+                }),
                 Instruction::I64And => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i64, |a, b| Ok(a & b)))
                 }
                 Instruction::I64Or => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i64, |a, b| Ok(a | b)))
                 }
                 Instruction::I64Xor => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64Shl => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64ShrS => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64ShrU => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64Rotl => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64Rotr => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_i64, |a, b| Ok(a ^ b)))
                 }
+                Instruction::I64Shl => ValueStackModification(|s| {
+                    binary(s, pop_i64, |a, b| Ok(a.wrapping_shl(b as u32)))
+                }),
+                Instruction::I64ShrS => ValueStackModification(|s| {
+                    binary(s, pop_i64, |a, b| Ok(a.wrapping_shr(b as u32)))
+                }),
+                Instruction::I64ShrU => ValueStackModification(|s| {
+                    binary(s, pop_i64, |a, b| {
+                        Ok((a as u64).wrapping_shr(b as u32) as i64)
+                    })
+                }),
+                Instruction::I64Rotl => ValueStackModification(|s| {
+                    binary(s, pop_i64, |a, b| Ok(a.rotate_left((b & 63) as u32)))
+                }),
+                Instruction::I64Rotr => ValueStackModification(|s| {
+                    binary(s, pop_i64, |a, b| Ok(a.rotate_right((b & 63) as u32)))
+                }),
                 Instruction::F32Abs => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::F32Neg => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f32, |a| Ok(a.abs())))
                 }
+                Instruction::F32Neg => ValueStackModification(|s| unary(s, pop_f32, |a| Ok(-a))),
                 Instruction::F32Ceil => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f32, |a| Ok(ceil_f32(a))))
                 }
                 Instruction::F32Floor => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f32, |a| Ok(floor_f32(a))))
                 }
                 Instruction::F32Trunc => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f32, |a| Ok(trunc_f32(a))))
                 }
                 Instruction::F32Nearest => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f32, |a| Ok(nearest_f32(a))))
                 }
                 Instruction::F32Sqrt => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f32, |a| Ok(sqrt_f32(a))))
                 }
                 Instruction::F32Add => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok(a + b)))
                 }
                 Instruction::F32Sub => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok(a - b)))
                 }
                 Instruction::F32Mul => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok(a * b)))
                 }
                 Instruction::F32Div => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok(a / b)))
                 }
                 Instruction::F32Min => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok(min_f32(a, b))))
                 }
                 Instruction::F32Max => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok(max_f32(a, b))))
                 }
                 Instruction::F32Copysign => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f32, |a, b| Ok(a.copysign(b))))
                 }
                 Instruction::F64Abs => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::F64Neg => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f64, |a| Ok(a.abs())))
                 }
+                Instruction::F64Neg => ValueStackModification(|s| unary(s, pop_f64, |a| Ok(-a))),
                 Instruction::F64Ceil => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f64, |a| Ok(ceil_f64(a))))
                 }
                 Instruction::F64Floor => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f64, |a| Ok(floor_f64(a))))
                 }
                 Instruction::F64Trunc => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f64, |a| Ok(trunc_f64(a))))
                 }
                 Instruction::F64Nearest => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f64, |a| Ok(nearest_f64(a))))
                 }
                 Instruction::F64Sqrt => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f64, |a| Ok(sqrt_f64(a))))
                 }
                 Instruction::F64Add => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok(a + b)))
                 }
                 Instruction::F64Sub => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok(a - b)))
                 }
                 Instruction::F64Mul => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok(a * b)))
                 }
                 Instruction::F64Div => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok(a / b)))
                 }
                 Instruction::F64Min => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok(min_f64(a, b))))
                 }
                 Instruction::F64Max => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok(max_f64(a, b))))
                 }
                 Instruction::F64Copysign => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| binary(s, pop_f64, |a, b| Ok(a.copysign(b))))
                 }
                 Instruction::I32wrapF64 => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32TruncSF32 => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32TruncUF32 => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32TruncSF64 => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I32TruncUF64 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i64, |a| Ok(a as i32)))
                 }
+                Instruction::I32TruncSF32 => ValueStackModification(|s| {
+                    unary(s, pop_f32, |a| {
+                        Ok(trunc_check(a as f64, I32_MIN, I32_MAX)? as i32)
+                    })
+                }),
+                Instruction::I32TruncUF32 => ValueStackModification(|s| {
+                    unary(s, pop_f32, |a| {
+                        Ok(trunc_check(a as f64, 0.0, U32_MAX)? as u32 as i32)
+                    })
+                }),
+                Instruction::I32TruncSF64 => ValueStackModification(|s| {
+                    unary(s, pop_f64, |a| Ok(trunc_check(a, I32_MIN, I32_MAX)? as i32))
+                }),
+                Instruction::I32TruncUF64 => ValueStackModification(|s| {
+                    unary(s, pop_f64, |a| {
+                        Ok(trunc_check(a, 0.0, U32_MAX)? as u32 as i32)
+                    })
+                }),
                 Instruction::I64ExtendSI32 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i32, |a| Ok(a as i64)))
                 }
                 Instruction::I64ExtendUI32 => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64TruncSF32 => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64TruncUF32 => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64TruncSF64 => {
-                    return Err("no default evaluation for basic instruction yet");
-                }
-                Instruction::I64TruncUF64 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i32, |a| Ok(a as u32 as i64)))
                 }
+                Instruction::I64TruncSF32 => ValueStackModification(|s| {
+                    unary(s, pop_f32, |a| {
+                        Ok(trunc_check(a as f64, I64_MIN, I64_MAX)? as i64)
+                    })
+                }),
+                Instruction::I64TruncUF32 => ValueStackModification(|s| {
+                    unary(s, pop_f32, |a| {
+                        Ok(trunc_check(a as f64, 0.0, U64_MAX)? as u64 as i64)
+                    })
+                }),
+                Instruction::I64TruncSF64 => ValueStackModification(|s| {
+                    unary(s, pop_f64, |a| Ok(trunc_check(a, I64_MIN, I64_MAX)? as i64))
+                }),
+                Instruction::I64TruncUF64 => ValueStackModification(|s| {
+                    unary(s, pop_f64, |a| {
+                        Ok(trunc_check(a, 0.0, U64_MAX)? as u64 as i64)
+                    })
+                }),
                 Instruction::F32ConvertSI32 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i32, |a| Ok(a as f32)))
                 }
                 Instruction::F32ConvertUI32 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i32, |a| Ok(a as u32 as f32)))
                 }
                 Instruction::F32ConvertSI64 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i64, |a| Ok(a as f32)))
                 }
                 Instruction::F32ConvertUI64 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i64, |a| Ok(a as u64 as f32)))
                 }
                 Instruction::F32DemoteF64 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f64, |a| Ok(a as f32)))
                 }
                 Instruction::F64ConvertSI32 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i32, |a| Ok(a as f64)))
                 }
                 Instruction::F64ConvertUI32 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i32, |a| Ok(a as u32 as f64)))
                 }
                 Instruction::F64ConvertSI64 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i64, |a| Ok(a as f64)))
                 }
                 Instruction::F64ConvertUI64 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i64, |a| Ok(a as u64 as f64)))
                 }
                 Instruction::F64PromoteF32 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f32, |a| Ok(a as f64)))
                 }
                 Instruction::I32ReinterpretF32 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f32, |a| Ok(a.to_bits() as i32)))
                 }
                 Instruction::I64ReinterpretF64 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_f64, |a| Ok(a.to_bits() as i64)))
                 }
                 Instruction::F32ReinterpretI32 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i32, |a| Ok(f32::from_bits(a as u32))))
                 }
                 Instruction::F64ReinterpretI64 => {
-                    return Err("no default evaluation for basic instruction yet");
+                    ValueStackModification(|s| unary(s, pop_i64, |a| Ok(f64::from_bits(a as u64))))
                 }
             },
             ExecutionUnit::Call(x) => {
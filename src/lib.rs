@@ -6,25 +6,62 @@ extern crate webassembly;
 
 mod compiler;
 mod core;
+mod diff;
 mod interpreter;
+mod link;
+mod math;
+mod optimize;
 mod parser;
-mod util;
+mod size;
 #[cfg(test)]
 mod spec_tests;
+#[cfg(test)]
+mod tests;
+mod util;
+mod wast;
+mod wat;
 
 pub use crate::core::common::*;
-pub use crate::core::view::*;
-use crate::core::wast::Wast;
+pub use crate::core::index::*;
+pub use crate::core::visit::*;
+pub use crate::core::wast::*;
+pub use crate::core::DataLayout;
+pub use crate::core::GasMeter;
 pub use crate::core::Instruction;
+pub use crate::core::InstructionCategory;
+pub use crate::core::Module;
 pub use crate::core::Program;
 pub use crate::core::ProgramView;
+pub use crate::core::ShimHooks;
+pub use crate::core::StackTypes;
+pub use crate::core::{BasicBlock, Cfg, NaturalLoop};
+pub use crate::core::{CallEdge, CallGraph, CallKind};
+pub use crate::core::{FlatBody, FlatInstruction, FlatModule};
+pub use crate::core::{FunctionBuilder, Label};
+pub use crate::core::{FunctionRef, GlobalRef, MemoryRef, TableRef};
+pub use crate::diff::*;
 pub use crate::interpreter::*;
+pub use crate::link::*;
+pub use crate::optimize::*;
+pub use crate::size::*;
+pub use crate::wast::*;
+pub use crate::wat::*;
 
-pub fn parse<'p>(input: &'p [u8]) -> Result<core::ProgramView<'p>, &'static str> {
+pub fn parse(input: &[u8]) -> Result<core::ProgramView<'_>, &'static str> {
     parser::wasm::wasm_module(input)
 }
 
-pub fn parse_wast<'p>(input: &'p [u8]) -> Result<Wast, &'static str> {
+/// Parses a module with its function bodies in flat form, without building the nested
+/// instructions first.
+pub fn parse_flat(input: &[u8]) -> Result<FlatModule<'_>, &'static str> {
+    parser::wasm::wasm_module_flat(input)
+}
+
+pub fn parse_wat(input: &[u8]) -> Result<core::Program, &'static str> {
+    parser::wat::wat_module(input)
+}
+
+pub fn parse_wast(input: &[u8]) -> Result<Wast, &'static str> {
     parser::wast::wast_file(input)
 }
 
@@ -35,5 +72,5 @@ pub fn parse_wast<'p>(input: &'p [u8]) -> Result<Wast, &'static str> {
 #[cfg(feature = "c_extern")]
 pub unsafe fn c_parse_web_assembly(ptr_wasm_bytes: *mut u8, len: usize) -> core::Program {
     let wasm_bytes = Vec::from_raw_parts(ptr_wasm_bytes, len, len);
-    parser::wasm_module(&wasm_bytes).unwrap().to_owned()
+    parser::wasm::wasm_module(&wasm_bytes).unwrap().into_owned()
 }
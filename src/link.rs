@@ -0,0 +1,548 @@
+use crate::core::*;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Why `link` could not merge the modules, `module` is the position of the offending
+/// program and `name` the import or export involved.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LinkError {
+    pub module: usize,
+    pub name: String,
+    pub message: &'static str,
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "module {}: {}: {}", self.module, self.name, self.message)
+    }
+}
+
+const KINDS: [IndexKind; 4] = [
+    IndexKind::Function,
+    IndexKind::Global,
+    IndexKind::Memory,
+    IndexKind::Table,
+];
+
+fn kind_slot(kind: IndexKind) -> usize {
+    match kind {
+        IndexKind::Function => 0,
+        IndexKind::Global => 1,
+        IndexKind::Memory => 2,
+        IndexKind::Table => 3,
+        IndexKind::Type => unreachable!(),
+    }
+}
+
+fn import_kind(import: &WasmImport) -> IndexKind {
+    match import {
+        WasmImport::Function(_) => IndexKind::Function,
+        WasmImport::Global(_) => IndexKind::Global,
+        WasmImport::Memory(_) => IndexKind::Memory,
+        WasmImport::Table(_) => IndexKind::Table,
+    }
+}
+
+fn import_names<'a>(import: &'a WasmImport) -> (&'a str, &'a str) {
+    match import {
+        WasmImport::Function(x) => (&x.module_name, &x.name),
+        WasmImport::Global(x) => (&x.module_name, &x.name),
+        WasmImport::Memory(x) => (&x.module_name, &x.name),
+        WasmImport::Table(x) => (&x.module_name, &x.name),
+    }
+}
+
+fn export_of(program: &Module, name: &str, kind: IndexKind) -> Option<u32> {
+    for s in program.sections.iter() {
+        if let Section::Export(e) = s {
+            for x in e.exports.iter() {
+                match (kind, x) {
+                    (IndexKind::Function, WasmExport::Function(x)) if x.name == name => {
+                        return Some(x.index.0)
+                    }
+                    (IndexKind::Global, WasmExport::Global(x)) if x.name == name => {
+                        return Some(x.index.0)
+                    }
+                    (IndexKind::Memory, WasmExport::Memory(x)) if x.name == name => {
+                        return Some(x.index.0)
+                    }
+                    (IndexKind::Table, WasmExport::Table(x)) if x.name == name => {
+                        return Some(x.index.0)
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    None
+}
+
+fn export_name(program: &Module, kind: IndexKind, index: u32) -> Option<String> {
+    for s in program.sections.iter() {
+        if let Section::Export(e) = s {
+            for x in e.exports.iter() {
+                match (kind, x) {
+                    (IndexKind::Memory, WasmExport::Memory(x)) if x.index.0 == index => {
+                        return Some(x.name.to_string())
+                    }
+                    (IndexKind::Table, WasmExport::Table(x)) if x.index.0 == index => {
+                        return Some(x.name.to_string())
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    None
+}
+
+fn limits_fit(
+    min: usize,
+    max: Option<usize>,
+    import_min: usize,
+    import_max: Option<usize>,
+) -> bool {
+    min >= import_min
+        && match (max, import_max) {
+            (_, None) => true,
+            (Some(max), Some(import_max)) => max <= import_max,
+            (None, Some(_)) => false,
+        }
+}
+
+// whether what `program` has at `index` can stand in for `import` of `importer`
+fn compatible(
+    importer: &Module,
+    import: &WasmImport,
+    program: &Module,
+    index: u32,
+) -> Result<bool, &'static str> {
+    Ok(match import {
+        WasmImport::Function(f) => {
+            let types = importer.sections.iter().find_map(|x| match x {
+                Section::Type(t) => Some(&t.types),
+                _ => None,
+            });
+            match types.and_then(|t| t.get(f.type_index.index())) {
+                Some(t) => t == program.function_type(FuncIdx(index))?,
+                None => return Err("function type does not exist with that index"),
+            }
+        }
+        WasmImport::Global(g) => match program.globals().nth(index as usize) {
+            Some((_, x)) => x.value_type() == g.value_type && x.is_mutable() == g.is_mutable,
+            None => return Err("global does not exist with that index"),
+        },
+        WasmImport::Memory(m) => match program.memories().nth(index as usize) {
+            Some((_, x)) => {
+                let (min, max) = x.limits();
+                limits_fit(min, max, m.min_pages, m.max_pages)
+            }
+            None => return Err("memory does not exist with that index"),
+        },
+        WasmImport::Table(t) => match program.tables().nth(index as usize) {
+            Some((_, x)) => {
+                let (min, max) = x.limits();
+                limits_fit(min, max, t.min, t.max)
+            }
+            None => return Err("table does not exist with that index"),
+        },
+    })
+}
+
+#[derive(Clone, Copy)]
+enum Target {
+    // an import of the linked program
+    Import(u32),
+    // an entity of another program, which may itself be an import
+    Export(usize, u32),
+}
+
+struct Linker<'p, 'a> {
+    programs: &'p [Module<'a>],
+    module_names: Vec<Option<String>>,
+    types: Vec<FunctionType>,
+    type_maps: Vec<Vec<u32>>,
+    imports: Vec<WasmImport<'a>>,
+    import_counts: [u32; 4],
+    // per program and kind, where each of its imports went
+    targets: Vec<[Vec<Target>; 4]>,
+    defined_offsets: Vec<[u32; 4]>,
+}
+
+impl<'p, 'a> Linker<'p, 'a> {
+    fn error(&self, module: usize, name: &str, message: &'static str) -> LinkError {
+        LinkError {
+            module,
+            name: name.to_string(),
+            message,
+        }
+    }
+
+    fn merge_types(&mut self) {
+        for p in self.programs.iter() {
+            let mut map = Vec::new();
+            for s in p.sections.iter() {
+                if let Section::Type(t) = s {
+                    for t in t.types.iter() {
+                        let index = match self.types.iter().position(|x| x == t) {
+                            Some(i) => i,
+                            None => {
+                                self.types.push(t.clone());
+                                self.types.len() - 1
+                            }
+                        };
+                        map.push(index as u32);
+                    }
+                }
+            }
+            self.type_maps.push(map);
+        }
+    }
+
+    fn find_exporter(
+        &self,
+        module: usize,
+        import: &WasmImport,
+    ) -> Result<Option<(usize, u32)>, LinkError> {
+        let (module_name, name) = import_names(import);
+        let kind = import_kind(import);
+        let mut named = Vec::new();
+        let mut unnamed = Vec::new();
+        for (n, p) in self.programs.iter().enumerate() {
+            if n == module {
+                continue;
+            }
+            if let Some(index) = export_of(p, name, kind) {
+                match &self.module_names[n] {
+                    Some(m) if m == module_name => named.push((n, index)),
+                    Some(_) => {}
+                    None => unnamed.push((n, index)),
+                }
+            }
+        }
+        let candidates = if named.is_empty() { unnamed } else { named };
+        if candidates.len() > 1 {
+            return Err(self.error(
+                module,
+                name,
+                "import matches exports of more than one module",
+            ));
+        }
+        Ok(candidates.first().copied())
+    }
+
+    fn resolve_imports(&mut self) -> Result<(), LinkError> {
+        for (m, p) in self.programs.iter().enumerate() {
+            let mut targets: [Vec<Target>; 4] = Default::default();
+            let imports = p.sections.iter().find_map(|x| match x {
+                Section::Import(i) => Some(&i.imports[..]),
+                _ => None,
+            });
+            for import in imports.unwrap_or(&[]).iter() {
+                let (module_name, name) = import_names(import);
+                let kind = import_kind(import);
+                let target = match self.find_exporter(m, import)? {
+                    Some((n, index)) => {
+                        match compatible(p, import, &self.programs[n], index) {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                return Err(self.error(m, name, "incompatible import type"))
+                            }
+                            Err(e) => return Err(self.error(m, name, e)),
+                        }
+                        Target::Export(n, index)
+                    }
+                    None => {
+                        let mut import = import.clone();
+                        if let WasmImport::Function(f) = &mut import {
+                            f.type_index = match self.type_maps[m].get(f.type_index.index()) {
+                                Some(t) => TypeIdx(*t),
+                                None => return Err(self.error(m, name, "unknown type")),
+                            };
+                        }
+                        let existing = self.imports.iter().filter(|x| import_kind(x) == kind);
+                        let mut index = None;
+                        for (i, x) in existing.enumerate() {
+                            if import_names(x) == (module_name, name) {
+                                if *x != import {
+                                    return Err(self.error(
+                                        m,
+                                        name,
+                                        "import is declared with different types",
+                                    ));
+                                }
+                                index = Some(i as u32);
+                            }
+                        }
+                        match index {
+                            Some(i) => Target::Import(i),
+                            None => {
+                                self.imports.push(import);
+                                let slot = &mut self.import_counts[kind_slot(kind)];
+                                *slot += 1;
+                                Target::Import(*slot - 1)
+                            }
+                        }
+                    }
+                };
+                targets[kind_slot(kind)].push(target);
+            }
+            self.targets.push(targets);
+        }
+        Ok(())
+    }
+
+    fn layout_definitions(&mut self) {
+        let mut next = self.import_counts;
+        for (m, p) in self.programs.iter().enumerate() {
+            self.defined_offsets.push(next);
+            let imported = [
+                self.targets[m][0].len(),
+                self.targets[m][1].len(),
+                self.targets[m][2].len(),
+                self.targets[m][3].len(),
+            ];
+            let counts = [
+                p.functions().count(),
+                p.globals().count(),
+                p.memories().count(),
+                p.tables().count(),
+            ];
+            for i in 0..4 {
+                next[i] += (counts[i] - imported[i]) as u32;
+            }
+        }
+    }
+
+    // the MVP allows one memory and one table, find the program that would add a second
+    fn check_single(&self, kind: IndexKind) -> Result<(), LinkError> {
+        let slot = kind_slot(kind);
+        let message = match kind {
+            IndexKind::Memory => "linked modules would need more than one memory",
+            _ => "linked modules would need more than one table",
+        };
+        let mut imported = Vec::new();
+        let mut defined = 0;
+        for (m, p) in self.programs.iter().enumerate() {
+            let targets = &self.targets[m][slot];
+            let imports = p.sections.iter().find_map(|x| match x {
+                Section::Import(i) => Some(&i.imports[..]),
+                _ => None,
+            });
+            let mut own = imports
+                .unwrap_or(&[])
+                .iter()
+                .filter(|x| import_kind(x) == kind);
+            for target in targets.iter() {
+                let import = own.next();
+                if let Target::Import(i) = target {
+                    if !imported.contains(i) {
+                        imported.push(*i);
+                        if imported.len() + defined > 1 {
+                            let name = import.map(|x| import_names(x).1).unwrap_or("");
+                            return Err(self.error(m, name, message));
+                        }
+                    }
+                }
+            }
+            let count = match kind {
+                IndexKind::Memory => p.memories().count(),
+                _ => p.tables().count(),
+            };
+            for index in targets.len()..count {
+                defined += 1;
+                if imported.len() + defined > 1 {
+                    let name = export_name(p, kind, index as u32).unwrap_or_default();
+                    return Err(self.error(m, &name, message));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // the index something of program `module` ends up with in the linked program
+    fn final_index(&self, module: usize, kind: IndexKind, index: u32) -> Result<u32, LinkError> {
+        let slot = kind_slot(kind);
+        let (mut module, mut index) = (module, index);
+        for _ in 0..=self.programs.len() {
+            let targets = &self.targets[module][slot];
+            match targets.get(index as usize) {
+                Some(Target::Import(i)) => return Ok(*i),
+                Some(Target::Export(n, e)) => {
+                    module = *n;
+                    index = *e;
+                }
+                None => {
+                    return Ok(self.defined_offsets[module][slot] + index - targets.len() as u32)
+                }
+            }
+        }
+        Err(self.error(module, "", "imports resolve to each other in a cycle"))
+    }
+}
+
+/// Merges programs into one, resolving the imports of each program against the exports of
+/// the others. An import matches an export of the same name and kind in a program whose name
+/// section has the import's module name, or in a program without a module name when no named
+/// program matches. Imports that match nothing stay imports of the linked program, the start
+/// functions are all run in order.
+pub fn link<'a>(programs: &[Module<'a>]) -> Result<Module<'a>, LinkError> {
+    let mut linker = Linker {
+        programs,
+        module_names: programs
+            .iter()
+            .map(|p| p.name_section().and_then(|x| x.module_name))
+            .collect(),
+        types: Vec::new(),
+        type_maps: Vec::new(),
+        imports: Vec::new(),
+        import_counts: [0; 4],
+        targets: Vec::new(),
+        defined_offsets: Vec::new(),
+    };
+    linker.merge_types();
+    linker.resolve_imports()?;
+    linker.layout_definitions();
+    linker.check_single(IndexKind::Memory)?;
+    linker.check_single(IndexKind::Table)?;
+
+    let mut function_types = Vec::new();
+    let mut code_blocks = Vec::new();
+    let mut globals = Vec::new();
+    let mut memories = Vec::new();
+    let mut tables = Vec::new();
+    let mut exports: Vec<WasmExport> = Vec::new();
+    let mut elements = Vec::new();
+    let mut data_blocks = Vec::new();
+    let mut starts = Vec::new();
+    let mut customs = Vec::new();
+    let mut names = NameSection::default();
+    for (m, p) in programs.iter().enumerate() {
+        let mut p = p.clone();
+        for kind in KINDS.iter() {
+            let count = match kind {
+                IndexKind::Function => p.functions().count(),
+                IndexKind::Global => p.globals().count(),
+                IndexKind::Memory => p.memories().count(),
+                _ => p.tables().count(),
+            };
+            let mut map = Vec::new();
+            for i in 0..count {
+                map.push(linker.final_index(m, *kind, i as u32)?);
+            }
+            p.remap_indices(*kind, &|i| map.get(i as usize).copied());
+        }
+        let type_map = &linker.type_maps[m];
+        p.remap_indices(IndexKind::Type, &|i| type_map.get(i as usize).copied());
+        if let Some(n) = p.name_section() {
+            names.function_names.extend(n.function_names);
+            names.local_names.extend(n.local_names);
+        }
+        for s in p.sections.into_iter() {
+            match s {
+                Section::Function(f) => function_types.extend(f.function_types),
+                Section::Code(c) => code_blocks.extend(c.code_blocks),
+                Section::Global(g) => globals.extend(g.globals),
+                Section::Memory(x) => memories.extend(x.memories),
+                Section::Table(t) => tables.extend(t.tables),
+                Section::Export(e) => {
+                    for x in e.exports.into_iter() {
+                        let name = match &x {
+                            WasmExport::Function(x) => x.name.clone(),
+                            WasmExport::Global(x) => x.name.clone(),
+                            WasmExport::Memory(x) => x.name.clone(),
+                            WasmExport::Table(x) => x.name.clone(),
+                        };
+                        let duplicate = exports.iter().any(|e| match e {
+                            WasmExport::Function(e) => e.name == name,
+                            WasmExport::Global(e) => e.name == name,
+                            WasmExport::Memory(e) => e.name == name,
+                            WasmExport::Table(e) => e.name == name,
+                        });
+                        if duplicate {
+                            return Err(linker.error(m, &name, "duplicate export name"));
+                        }
+                        exports.push(x);
+                    }
+                }
+                Section::Element(e) => elements.extend(e.elements),
+                Section::Data(d) => data_blocks.extend(d.data_blocks),
+                Section::Start(s) => starts.push(s.start_function),
+                Section::Custom(c) if c.name != "name" => customs.push(c),
+                _ => {}
+            }
+        }
+    }
+    let mut types = linker.types;
+    let start = match starts.len() {
+        0 => None,
+        1 => Some(starts[0]),
+        _ => {
+            // a start function of its own that runs every start function in order
+            let empty = FunctionType {
+                inputs: Vec::new(),
+                outputs: Vec::new(),
+            };
+            let type_index = match types.iter().position(|x| *x == empty) {
+                Some(i) => i,
+                None => {
+                    types.push(empty);
+                    types.len() - 1
+                }
+            };
+            function_types.push(TypeIdx::at(type_index));
+            code_blocks.push(CodeBlock {
+                locals: Vec::new(),
+                instructions: starts.iter().map(|f| Instruction::Call(*f)).collect(),
+            });
+            let imported_functions = linker.import_counts[kind_slot(IndexKind::Function)];
+            Some(FuncIdx(
+                imported_functions + function_types.len() as u32 - 1,
+            ))
+        }
+    };
+
+    let mut sections = vec![Section::Type(TypeSection { types })];
+    if !linker.imports.is_empty() {
+        sections.push(Section::Import(ImportSection {
+            imports: linker.imports,
+        }));
+    }
+    sections.push(Section::Function(FunctionSection { function_types }));
+    if !tables.is_empty() {
+        sections.push(Section::Table(TableSection { tables }));
+    }
+    if !memories.is_empty() {
+        sections.push(Section::Memory(MemorySection { memories }));
+    }
+    if !globals.is_empty() {
+        sections.push(Section::Global(GlobalSection { globals }));
+    }
+    sections.push(Section::Export(ExportSection { exports }));
+    if let Some(start_function) = start {
+        sections.push(Section::Start(StartSection { start_function }));
+    }
+    if !elements.is_empty() {
+        sections.push(Section::Element(ElementSection { elements }));
+    }
+    sections.push(Section::Code(CodeSection { code_blocks }));
+    if !data_blocks.is_empty() {
+        sections.push(Section::Data(DataSection { data_blocks }));
+    }
+    if !names.function_names.is_empty() || !names.local_names.is_empty() {
+        // resolved imports share the index of what they resolved to
+        names.function_names.sort_by_key(|x| x.0);
+        names.function_names.dedup_by_key(|x| x.0);
+        names.local_names.sort_by_key(|x| x.0);
+        names.local_names.dedup_by_key(|x| x.0);
+        let mut data = Vec::new();
+        names.extend_wasm_bytes(&mut data);
+        sections.push(Section::Custom(CustomSection {
+            name: "name".to_string().into(),
+            data: data.into(),
+        }));
+    }
+    sections.extend(customs.into_iter().map(Section::Custom));
+    Ok(Module { sections })
+}
@@ -0,0 +1,264 @@
+// float operations that core does not provide without std, implemented with
+// the exact rounding WebAssembly requires
+
+pub fn trunc_f32(x: f32) -> f32 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127;
+    if exponent >= 23 {
+        // already integral, infinite or NaN
+        x
+    } else if exponent < 0 {
+        f32::from_bits(bits & 0x8000_0000)
+    } else {
+        let mask = (1u32 << (23 - exponent)) - 1;
+        f32::from_bits(bits & !mask)
+    }
+}
+
+pub fn trunc_f64(x: f64) -> f64 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i64 - 1023;
+    if exponent >= 52 {
+        x
+    } else if exponent < 0 {
+        f64::from_bits(bits & 0x8000_0000_0000_0000)
+    } else {
+        let mask = (1u64 << (52 - exponent)) - 1;
+        f64::from_bits(bits & !mask)
+    }
+}
+
+pub fn floor_f32(x: f32) -> f32 {
+    let t = trunc_f32(x);
+    if x < 0.0 && t != x {
+        t - 1.0
+    } else {
+        t
+    }
+}
+
+pub fn floor_f64(x: f64) -> f64 {
+    let t = trunc_f64(x);
+    if x < 0.0 && t != x {
+        t - 1.0
+    } else {
+        t
+    }
+}
+
+pub fn ceil_f32(x: f32) -> f32 {
+    let t = trunc_f32(x);
+    if x > 0.0 && t != x {
+        t + 1.0
+    } else {
+        t
+    }
+}
+
+pub fn ceil_f64(x: f64) -> f64 {
+    let t = trunc_f64(x);
+    if x > 0.0 && t != x {
+        t + 1.0
+    } else {
+        t
+    }
+}
+
+// round to nearest, ties to even
+pub fn nearest_f32(x: f32) -> f32 {
+    if x.is_nan() || x.abs() >= 8_388_608.0 {
+        return x;
+    }
+    let t = trunc_f32(x);
+    let diff = (x - t).abs();
+    let step = if x < 0.0 { -1.0 } else { 1.0 };
+    if diff > 0.5 || (diff == 0.5 && (t as i32) % 2 != 0) {
+        t + step
+    } else {
+        t
+    }
+}
+
+pub fn nearest_f64(x: f64) -> f64 {
+    if x.is_nan() || x.abs() >= 4_503_599_627_370_496.0 {
+        return x;
+    }
+    let t = trunc_f64(x);
+    let diff = (x - t).abs();
+    let step = if x < 0.0 { -1.0 } else { 1.0 };
+    if diff > 0.5 || (diff == 0.5 && (t as i64) % 2 != 0) {
+        t + step
+    } else {
+        t
+    }
+}
+
+fn isqrt(n: u128) -> u128 {
+    let mut x = n;
+    let mut r = 0u128;
+    let mut bit = 1u128 << 126;
+    while bit > n {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if x >= r + bit {
+            x -= r + bit;
+            r = (r >> 1) + bit;
+        } else {
+            r >>= 1;
+        }
+        bit >>= 2;
+    }
+    r
+}
+
+pub fn sqrt_f64(x: f64) -> f64 {
+    if x.is_nan() || x == 0.0 || x == f64::INFINITY {
+        return x;
+    }
+    if x < 0.0 {
+        return f64::NAN;
+    }
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    let fraction = (bits & 0x000f_ffff_ffff_ffff) as u128;
+    let (mut m, mut e) = if exponent == 0 {
+        (fraction, -1074)
+    } else {
+        (fraction | 1 << 52, exponent - 1075)
+    };
+    if e % 2 != 0 {
+        m <<= 1;
+        e -= 1;
+    }
+    // widen the mantissa so the root carries enough bits to round from
+    let k = (120 - (128 - m.leading_zeros() as i32)) / 2;
+    let s = m << (2 * k);
+    let r = isqrt(s);
+    let sticky = r * r != s;
+    match compose_float(false, r, (e - 2 * k) / 2, sticky, 52, 11) {
+        Some(b) => f64::from_bits(b),
+        None => f64::INFINITY,
+    }
+}
+
+// sqrt is correctly rounded when computed in double precision and rounded
+// again to single precision
+pub fn sqrt_f32(x: f32) -> f32 {
+    sqrt_f64(x as f64) as f32
+}
+
+pub fn min_f32(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f32::from_bits(a.to_bits() | b.to_bits())
+    } else if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+pub fn max_f32(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f32::from_bits(a.to_bits() & b.to_bits())
+    } else if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+pub fn min_f64(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f64::from_bits(a.to_bits() | b.to_bits())
+    } else if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+pub fn max_f64(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f64::from_bits(a.to_bits() & b.to_bits())
+    } else if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+/// Builds the bits of a float with `mantissa_bits` fraction bits and
+/// `exponent_bits` exponent bits whose value is `mantissa * 2^exponent`,
+/// rounded to nearest even. `sticky` marks nonzero bits below the mantissa.
+/// Returns `None` when the value overflows to infinity.
+pub fn compose_float(
+    negative: bool,
+    mantissa: u128,
+    exponent: i32,
+    sticky: bool,
+    mantissa_bits: u32,
+    exponent_bits: u32,
+) -> Option<u64> {
+    let sign = if negative {
+        1u64 << (mantissa_bits + exponent_bits)
+    } else {
+        0
+    };
+    if mantissa == 0 {
+        return Some(sign);
+    }
+    let mut m = mantissa;
+    let mut e = exponent;
+    let len = 128 - m.leading_zeros() as i32;
+    if len < 120 {
+        m <<= 120 - len;
+        e -= 120 - len;
+    }
+    let len = 128 - m.leading_zeros() as i32;
+    let bias = (1i32 << (exponent_bits - 1)) - 1;
+    let min_exponent = 1 - bias;
+    let leading = e + len - 1;
+    let lsb = if leading < min_exponent {
+        min_exponent - mantissa_bits as i32
+    } else {
+        leading - mantissa_bits as i32
+    };
+    let shift = lsb - e;
+    if shift >= 127 {
+        return Some(sign);
+    }
+    let removed = m & ((1u128 << shift) - 1);
+    let half = 1u128 << (shift - 1);
+    let mut q = m >> shift;
+    if removed > half || (removed == half && (sticky || q & 1 == 1)) {
+        q += 1;
+    }
+    let mut biased = if leading < min_exponent {
+        0
+    } else {
+        leading + bias
+    };
+    if q >> (mantissa_bits + 1) != 0 {
+        q >>= 1;
+        biased += 1;
+    }
+    if biased >= (1 << exponent_bits) - 1 {
+        return None;
+    }
+    let fraction = (q as u64) & ((1u64 << mantissa_bits) - 1);
+    if biased == 0 {
+        // subnormal, or rounded up into the smallest normal
+        Some(sign | q as u64)
+    } else {
+        Some(sign | (biased as u64) << mantissa_bits | fraction)
+    }
+}
@@ -0,0 +1,299 @@
+use crate::core::*;
+use crate::interpreter::*;
+use alloc::vec::Vec;
+
+/// Which passes `Program::optimize` runs, every pass keeps the behaviour of the program
+/// including its traps.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Optimizations {
+    /// Evaluates numeric instructions whose operands are constants.
+    pub constant_folding: bool,
+    /// Removes integer operations with a neutral constant, like `i32.const 0 i32.add`.
+    pub identities: bool,
+    /// Removes what follows `unreachable`, `br`, `br_table` and `return` in a block.
+    pub dead_code: bool,
+    /// Turns `local.set x local.get x` into `local.tee x`.
+    pub local_tee: bool,
+    /// Removes locals that are never read, writes to them become drops.
+    pub unused_locals: bool,
+}
+
+impl Optimizations {
+    /// Level 0 runs nothing, level 1 the passes that only shrink instruction sequences and
+    /// level 2 and above every pass.
+    pub fn level(level: u8) -> Self {
+        Optimizations {
+            constant_folding: level >= 1,
+            identities: level >= 1,
+            dead_code: level >= 1,
+            local_tee: level >= 2,
+            unused_locals: level >= 2,
+        }
+    }
+}
+
+fn constant(i: &Instruction) -> Option<WasmValue> {
+    match i {
+        Instruction::I32Const(x) => Some(WasmValue::I32(*x)),
+        Instruction::I64Const(x) => Some(WasmValue::I64(*x)),
+        Instruction::F32Const(x) => Some(WasmValue::F32(*x)),
+        Instruction::F64Const(x) => Some(WasmValue::F64(*x)),
+        _ => None,
+    }
+}
+
+fn constant_instruction(v: WasmValue) -> Instruction {
+    match v {
+        WasmValue::I32(x) => Instruction::I32Const(x),
+        WasmValue::I64(x) => Instruction::I64Const(x),
+        WasmValue::F32(x) => Instruction::F32Const(x),
+        WasmValue::F64(x) => Instruction::F64Const(x),
+    }
+}
+
+// evaluates the instruction on top of the constants before it the way the interpreter does,
+// None when it would trap or is not a numeric instruction
+fn fold_constants(tail: &[Instruction]) -> Option<(usize, Instruction)> {
+    let (last, operands) = tail.split_last()?;
+    if last.category() != InstructionCategory::Numeric {
+        return None;
+    }
+    let pops = match last.stack_effect() {
+        Some((pops, 1)) if pops > 0 && pops <= operands.len() => pops,
+        _ => return None,
+    };
+    let mut stack = Vec::new();
+    for i in operands[operands.len() - pops..].iter() {
+        stack.push(constant(i)?);
+    }
+    let modification = match ExecutionUnit::BasicInstruction(last.clone()).evaluate() {
+        Ok(ExecutionResponse::ValueStackModification(f)) => f,
+        _ => return None,
+    };
+    modification(&mut stack).ok()?;
+    match stack[..] {
+        [v] => Some((pops + 1, constant_instruction(v))),
+        _ => None,
+    }
+}
+
+// how many instructions at the end of the sequence do nothing to the value under them
+fn identity(tail: &[Instruction]) -> Option<usize> {
+    let (last, operands) = tail.split_last()?;
+    let neutral = match last {
+        Instruction::I32Add
+        | Instruction::I32Sub
+        | Instruction::I32Or
+        | Instruction::I32Xor
+        | Instruction::I32Shl
+        | Instruction::I32ShrS
+        | Instruction::I32ShrU
+        | Instruction::I32Rotl
+        | Instruction::I32Rotr => Instruction::I32Const(0),
+        Instruction::I32Mul | Instruction::I32DivS | Instruction::I32DivU => {
+            Instruction::I32Const(1)
+        }
+        Instruction::I32And => Instruction::I32Const(-1),
+        Instruction::I64Add
+        | Instruction::I64Sub
+        | Instruction::I64Or
+        | Instruction::I64Xor
+        | Instruction::I64Shl
+        | Instruction::I64ShrS
+        | Instruction::I64ShrU
+        | Instruction::I64Rotl
+        | Instruction::I64Rotr => Instruction::I64Const(0),
+        Instruction::I64Mul | Instruction::I64DivS | Instruction::I64DivU => {
+            Instruction::I64Const(1)
+        }
+        Instruction::I64And => Instruction::I64Const(-1),
+        Instruction::Drop => {
+            return match operands.last()? {
+                Instruction::LocalGet(_) | Instruction::GlobalGet(_) => Some(2),
+                x => constant(x).map(|_| 2),
+            }
+        }
+        _ => return None,
+    };
+    if *operands.last()? == neutral {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+// applies the peephole passes to the end of the sequence until none of them applies
+fn simplify_tail(o: &Optimizations, instructions: &mut Vec<Instruction>) {
+    loop {
+        if o.constant_folding {
+            if let Some((len, folded)) = fold_constants(instructions) {
+                instructions.truncate(instructions.len() - len);
+                instructions.push(folded);
+                continue;
+            }
+        }
+        if o.identities {
+            if let Some(len) = identity(instructions) {
+                instructions.truncate(instructions.len() - len);
+                continue;
+            }
+        }
+        if o.local_tee {
+            if let [.., Instruction::LocalSet(s), Instruction::LocalGet(g)] = &instructions[..] {
+                if s == g {
+                    let local = *s;
+                    instructions.truncate(instructions.len() - 2);
+                    instructions.push(Instruction::LocalTee(local));
+                    continue;
+                }
+            }
+        }
+        return;
+    }
+}
+
+// runs the peephole passes over a sequence whose bodies are already optimized
+fn optimize_instructions(o: &Optimizations, instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let mut optimized = Vec::with_capacity(instructions.len());
+    for i in instructions.into_iter() {
+        let ends_flow = matches!(
+            i,
+            Instruction::Unreachable
+                | Instruction::Br(_)
+                | Instruction::BrTable(_, _)
+                | Instruction::Return
+        );
+        optimized.push(i);
+        simplify_tail(o, &mut optimized);
+        if o.dead_code && ends_flow {
+            break;
+        }
+    }
+    optimized
+}
+
+fn remove_unused_locals(params: usize, code_block: &mut CodeBlock) {
+    let mut locals: Vec<ValueType> = Vec::new();
+    for l in code_block.locals.iter() {
+        for _ in 0..l.count {
+            locals.push(l.value_type);
+        }
+    }
+    let mut read = vec![false; params + locals.len()];
+    Visit::visit_code_block(&mut ReadLocals(&mut read), FuncIdx(0), code_block);
+    for r in read[..params].iter_mut() {
+        *r = true;
+    }
+    if read.iter().all(|r| *r) {
+        return;
+    }
+    let mut next = 0;
+    let renumbered: Vec<Option<u32>> = read
+        .iter()
+        .map(|r| {
+            if *r {
+                next += 1;
+                Some(next - 1)
+            } else {
+                None
+            }
+        })
+        .collect();
+    let instructions = core::mem::take(&mut code_block.instructions);
+    code_block.instructions = fold_instructions(&mut RenumberLocals(&renumbered), instructions);
+    code_block.locals = Vec::new();
+    for (t, r) in locals.into_iter().zip(read[params..].iter()) {
+        if !*r {
+            continue;
+        }
+        match code_block.locals.last_mut() {
+            Some(l) if l.value_type == t => l.count += 1,
+            _ => code_block.locals.push(LocalCount {
+                count: 1,
+                value_type: t,
+            }),
+        }
+    }
+}
+
+struct ReadLocals<'a>(&'a mut [bool]);
+
+impl<'a> Visit for ReadLocals<'a> {
+    fn visit_variable(&mut self, i: &Instruction) {
+        if let Instruction::LocalGet(l) = i {
+            if let Some(r) = self.0.get_mut(l.index()) {
+                *r = true;
+            }
+        }
+    }
+}
+
+struct RenumberLocals<'a>(&'a [Option<u32>]);
+
+impl<'a> Fold for RenumberLocals<'a> {
+    fn fold_instruction(&mut self, i: Instruction) -> Vec<Instruction> {
+        let local = |l: LocalIdx| self.0.get(l.index()).copied().flatten().map(LocalIdx);
+        match i {
+            Instruction::LocalGet(l) => vec![Instruction::LocalGet(local(l).unwrap_or(l))],
+            Instruction::LocalSet(l) => match local(l) {
+                Some(l) => vec![Instruction::LocalSet(l)],
+                None => vec![Instruction::Drop],
+            },
+            Instruction::LocalTee(l) => match local(l) {
+                Some(l) => vec![Instruction::LocalTee(l)],
+                None => vec![],
+            },
+            i => vec![i],
+        }
+    }
+}
+
+struct Optimizer<'a> {
+    optimizations: &'a Optimizations,
+    params: Vec<Option<usize>>,
+}
+
+impl<'a> Fold for Optimizer<'a> {
+    fn fold_code_block(&mut self, index: FuncIdx, code_block: &mut CodeBlock) {
+        if contains_raw(&code_block.instructions) {
+            return;
+        }
+        if self.optimizations.unused_locals {
+            if let Some(Some(params)) = self.params.get(index.index()) {
+                remove_unused_locals(*params, code_block);
+            }
+        }
+        let instructions = core::mem::take(&mut code_block.instructions);
+        let instructions = fold_instructions(self, instructions);
+        code_block.instructions = optimize_instructions(self.optimizations, instructions);
+    }
+
+    fn fold_body(
+        &mut self,
+        kind: BlockKind,
+        block_type: u8,
+        body: Vec<Instruction>,
+    ) -> Vec<Instruction> {
+        let body = walk_fold_body(self, kind, block_type, body);
+        optimize_instructions(self.optimizations, body)
+    }
+
+    // constant expressions are already as short as they can be
+    fn fold_const_expression(&mut self, _expression: &mut Vec<Instruction>) {}
+}
+
+impl Module<'_> {
+    /// Simplifies the bodies of the defined functions, functions containing raw instructions
+    /// are left alone.
+    pub fn optimize(&mut self, optimizations: &Optimizations) {
+        let params = self
+            .functions()
+            .map(|(i, _)| self.function_type(i).ok().map(|t| t.inputs.len()))
+            .collect();
+        let mut optimizer = Optimizer {
+            optimizations,
+            params,
+        };
+        optimizer.fold_program(self);
+    }
+}
@@ -1,2 +1,4 @@
+pub mod sexpr;
 pub mod wasm;
 pub mod wast;
+pub mod wat;
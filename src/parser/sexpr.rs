@@ -0,0 +1,239 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum SExpr {
+    Atom(String),
+    Str(Vec<u8>),
+    List(List),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct List {
+    pub items: Vec<SExpr>,
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl SExpr {
+    pub fn atom(&self) -> Option<&str> {
+        match self {
+            SExpr::Atom(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn list(&self) -> Option<&List> {
+        match self {
+            SExpr::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self) -> Option<&[u8]> {
+        match self {
+            SExpr::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn is_id(&self) -> bool {
+        matches!(self.atom(), Some(a) if a.starts_with('$'))
+    }
+}
+
+impl List {
+    pub fn head(&self) -> Option<&str> {
+        self.items.first().and_then(|x| x.atom())
+    }
+}
+
+struct Lexer<'a> {
+    input: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn skip_whitespace(&mut self) -> Result<(), &'static str> {
+        loop {
+            match self.input.get(self.pos) {
+                Some(b'\n') => {
+                    self.line += 1;
+                    self.pos += 1;
+                }
+                Some(b' ') | Some(b'\t') | Some(b'\r') => self.pos += 1,
+                Some(b';') if self.input.get(self.pos + 1) == Some(&b';') => {
+                    while self.pos < self.input.len() && self.input[self.pos] != b'\n' {
+                        self.pos += 1;
+                    }
+                }
+                Some(b'(') if self.input.get(self.pos + 1) == Some(&b';') => {
+                    self.block_comment()?
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn block_comment(&mut self) -> Result<(), &'static str> {
+        let mut depth = 0;
+        loop {
+            let rest = &self.input[self.pos..];
+            if rest.starts_with(b"(;") {
+                depth += 1;
+                self.pos += 2;
+            } else if rest.starts_with(b";)") {
+                depth -= 1;
+                self.pos += 2;
+                if depth == 0 {
+                    return Ok(());
+                }
+            } else if rest.is_empty() {
+                return Err("unclosed comment");
+            } else {
+                if rest[0] == b'\n' {
+                    self.line += 1;
+                }
+                self.pos += 1;
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, &'static str> {
+        let mut bytes = vec![];
+        self.pos += 1;
+        loop {
+            let c = match self.input.get(self.pos) {
+                Some(c) => *c,
+                None => return Err("unclosed string"),
+            };
+            self.pos += 1;
+            match c {
+                b'"' => return Ok(bytes),
+                b'\n' => return Err("newline in string"),
+                b'\\' => self.escape(&mut bytes)?,
+                c if c < 0x20 || c == 0x7f => return Err("illegal character in string"),
+                c => bytes.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self, bytes: &mut Vec<u8>) -> Result<(), &'static str> {
+        let c = match self.input.get(self.pos) {
+            Some(c) => *c,
+            None => return Err("unclosed string"),
+        };
+        self.pos += 1;
+        match c {
+            b't' => bytes.push(b'\t'),
+            b'n' => bytes.push(b'\n'),
+            b'r' => bytes.push(b'\r'),
+            b'"' | b'\'' | b'\\' => bytes.push(c),
+            b'u' => {
+                if self.input.get(self.pos) != Some(&b'{') {
+                    return Err("malformed unicode escape");
+                }
+                let end = match self.input[self.pos..].iter().position(|x| *x == b'}') {
+                    Some(e) => self.pos + e,
+                    None => return Err("malformed unicode escape"),
+                };
+                let digits = match core::str::from_utf8(&self.input[self.pos + 1..end]) {
+                    Ok(d) => d.replace('_', ""),
+                    Err(_) => return Err("malformed unicode escape"),
+                };
+                let ch = u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(core::char::from_u32)
+                    .ok_or("malformed unicode escape")?;
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                self.pos = end + 1;
+            }
+            h if h.is_ascii_hexdigit() => {
+                let l = match self.input.get(self.pos) {
+                    Some(l) if l.is_ascii_hexdigit() => *l,
+                    _ => return Err("illegal escape"),
+                };
+                self.pos += 1;
+                bytes.push(hex_digit(h) << 4 | hex_digit(l));
+            }
+            _ => return Err("illegal escape"),
+        }
+        Ok(())
+    }
+
+    fn atom(&mut self) -> Result<String, &'static str> {
+        let start = self.pos;
+        while let Some(c) = self.input.get(self.pos) {
+            match c {
+                b' ' | b'\t' | b'\r' | b'\n' | b'(' | b')' | b';' => break,
+                b'"' => return Err("unexpected quote in token"),
+                c if *c < 0x21 || *c > 0x7e => return Err("unknown character in token"),
+                _ => self.pos += 1,
+            }
+        }
+        if start == self.pos {
+            return Err("unexpected character");
+        }
+        match core::str::from_utf8(&self.input[start..self.pos]) {
+            Ok(s) => Ok(s.to_string()),
+            Err(_) => Err("unknown character in token"),
+        }
+    }
+
+    fn expr(&mut self) -> Result<SExpr, &'static str> {
+        match self.input[self.pos] {
+            b'(' => {
+                let start = self.pos;
+                let line = self.line;
+                self.pos += 1;
+                let mut items = vec![];
+                loop {
+                    self.skip_whitespace()?;
+                    match self.input.get(self.pos) {
+                        Some(b')') => {
+                            self.pos += 1;
+                            return Ok(SExpr::List(List {
+                                items,
+                                line,
+                                start,
+                                end: self.pos,
+                            }));
+                        }
+                        Some(_) => items.push(self.expr()?),
+                        None => return Err("unclosed parenthesis"),
+                    }
+                }
+            }
+            b')' => Err("unexpected closing parenthesis"),
+            b'"' => Ok(SExpr::Str(self.string()?)),
+            _ => Ok(SExpr::Atom(self.atom()?)),
+        }
+    }
+}
+
+fn hex_digit(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        _ => c - b'A' + 10,
+    }
+}
+
+pub fn sexpr_file(input: &[u8]) -> Result<Vec<SExpr>, &'static str> {
+    let mut lexer = Lexer {
+        input,
+        pos: 0,
+        line: 1,
+    };
+    let mut exprs = vec![];
+    loop {
+        lexer.skip_whitespace()?;
+        if lexer.pos >= input.len() {
+            return Ok(exprs);
+        }
+        exprs.push(lexer.expr()?);
+    }
+}
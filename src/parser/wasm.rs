@@ -1,55 +1,45 @@
 use crate::core::*;
 use crate::util::*;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::convert::TryInto;
 use webassembly::*;
 
+pub(crate) const NAME_MODULE: u8 = 0;
+pub(crate) const NAME_FUNCTION: u8 = 1;
+pub(crate) const NAME_LOCAL: u8 = 2;
+
 fn wasm_u32(input: &[u8]) -> Result<(&[u8], u32), &'static str> {
-    let (i, byte_count) = match input.try_extract_u32(0) {
-        Ok(r) => r,
-        Err(e) => return Err(e),
-    };
-    let (input, _) = take(byte_count as usize)(input)?;
+    let (i, byte_count) = input.try_extract_u32(0)?;
+    let (input, _) = take(byte_count)(input)?;
     Ok((input, i))
 }
 
 fn wasm_i32(input: &[u8]) -> Result<(&[u8], i32, &[u8]), &'static str> {
     let original_input = input;
-    let (i, byte_count) = match input.try_extract_i32(0) {
-        Ok(r) => r,
-        Err(e) => return Err(e),
-    };
-    let (input, _) = take(byte_count as usize)(input)?;
+    let (i, byte_count) = input.try_extract_i32(0)?;
+    let (input, _) = take(byte_count)(input)?;
     Ok((input, i, &original_input[..byte_count]))
 }
 
 fn wasm_i64(input: &[u8]) -> Result<(&[u8], i64, &[u8]), &'static str> {
     let original_input = input;
-    let (i, byte_count) = match input.try_extract_i64(0) {
-        Ok(r) => r,
-        Err(e) => return Err(e),
-    };
-    let (input, _) = take(byte_count as usize)(input)?;
+    let (i, byte_count) = input.try_extract_i64(0)?;
+    let (input, _) = take(byte_count)(input)?;
     Ok((input, i, &original_input[..byte_count]))
 }
 
 fn wasm_f32(input: &[u8]) -> Result<(&[u8], f32, &[u8]), &'static str> {
     let original_input = input;
-    let (i, byte_count) = match input.try_extract_f32(0) {
-        Ok(r) => r,
-        Err(e) => return Err(e),
-    };
-    let (input, _) = take(byte_count as usize)(input)?;
+    let (i, byte_count) = input.try_extract_f32(0)?;
+    let (input, _) = take(byte_count)(input)?;
     Ok((input, i, &original_input[..byte_count]))
 }
 
 fn wasm_f64(input: &[u8]) -> Result<(&[u8], f64, &[u8]), &'static str> {
     let original_input = input;
-    let (i, byte_count) = match input.try_extract_f64(0) {
-        Ok(r) => r,
-        Err(e) => return Err(e),
-    };
-    let (input, _) = take(byte_count as usize)(input)?;
+    let (i, byte_count) = input.try_extract_f64(0)?;
+    let (input, _) = take(byte_count)(input)?;
     Ok((input, i, &original_input[..byte_count]))
 }
 
@@ -89,7 +79,7 @@ fn wasm_limit(input: &[u8]) -> Result<(&[u8], usize, Option<usize>), &'static st
     }
 }
 
-fn wasm_instruction(op: u8, input: &[u8]) -> Result<(&[u8], Instruction), &'static str> {
+pub(crate) fn wasm_instruction(op: u8, input: &[u8]) -> Result<(&[u8], Instruction), &'static str> {
     let mut ip = input;
     let instruction;
 
@@ -142,14 +132,14 @@ fn wasm_instruction(op: u8, input: &[u8]) -> Result<(&[u8], Instruction), &'stat
 
         CALL => {
             let (input, idx) = wasm_u32(input)?;
-            instruction = Instruction::Call(idx);
+            instruction = Instruction::Call(FuncIdx(idx));
             ip = input;
         }
 
         CALL_INDIRECT => {
             let (input, idx) = wasm_u32(input)?;
             let (input, _) = take(1)(input)?;
-            instruction = Instruction::CallIndirect(idx);
+            instruction = Instruction::CallIndirect(TypeIdx(idx));
             ip = input;
         }
 
@@ -179,27 +169,27 @@ fn wasm_instruction(op: u8, input: &[u8]) -> Result<(&[u8], Instruction), &'stat
         }
         LOCAL_GET => {
             let (input, idx) = wasm_u32(input)?;
-            instruction = Instruction::LocalGet(idx);
+            instruction = Instruction::LocalGet(LocalIdx(idx));
             ip = input;
         }
         LOCAL_SET => {
             let (input, idx) = wasm_u32(input)?;
-            instruction = Instruction::LocalSet(idx);
+            instruction = Instruction::LocalSet(LocalIdx(idx));
             ip = input;
         }
         LOCAL_TEE => {
             let (input, idx) = wasm_u32(input)?;
-            instruction = Instruction::LocalTee(idx);
+            instruction = Instruction::LocalTee(LocalIdx(idx));
             ip = input;
         }
         GLOBAL_GET => {
             let (input, idx) = wasm_u32(input)?;
-            instruction = Instruction::GlobalGet(idx);
+            instruction = Instruction::GlobalGet(GlobalIdx(idx));
             ip = input;
         }
         GLOBAL_SET => {
             let (input, idx) = wasm_u32(input)?;
-            instruction = Instruction::GlobalSet(idx);
+            instruction = Instruction::GlobalSet(GlobalIdx(idx));
             ip = input;
         }
         I32_LOAD => {
@@ -502,7 +492,7 @@ fn wasm_instruction(op: u8, input: &[u8]) -> Result<(&[u8], Instruction), &'stat
     Ok((ip, instruction))
 }
 
-fn wasm_expression(input: &[u8]) -> Result<(&[u8], Vec<Instruction>), &'static str> {
+pub(crate) fn wasm_expression(input: &[u8]) -> Result<(&[u8], Vec<Instruction>), &'static str> {
     let mut instructions = vec![];
     let mut ip = input;
     loop {
@@ -524,6 +514,79 @@ fn wasm_expression(input: &[u8]) -> Result<(&[u8], Vec<Instruction>), &'static s
     Ok((ip, instructions))
 }
 
+// the same as wasm_expression, but into one arena
+fn wasm_flat_expression(input: &[u8]) -> Result<(&[u8], FlatBody), &'static str> {
+    let mut body = FlatBuilder::default();
+    let mut ip = input;
+    loop {
+        let (input, op) = take(1)(ip)?;
+        ip = input;
+        match op[0] {
+            BLOCK | LOOP | IF => {
+                let (input, block_type) = take(1)(ip)?;
+                match op[0] {
+                    BLOCK => body.block(block_type[0]),
+                    LOOP => body.loop_(block_type[0]),
+                    _ => body.if_(block_type[0]),
+                }
+                ip = input;
+            }
+            ELSE => body.else_()?,
+            END => {
+                if !body.end() {
+                    break;
+                }
+            }
+            _ => {
+                let (input, instruction) = wasm_instruction(op[0], ip)?;
+                body.push(instruction);
+                ip = input;
+            }
+        }
+    }
+    Ok((ip, body.finish()))
+}
+
+fn wasm_locals(input: &[u8]) -> Result<(&[u8], Vec<LocalCount>), &'static str> {
+    let (input, num_local_vecs) = wasm_u32(input)?;
+    let parse_local_vecs = many_n(num_local_vecs as usize, |input| {
+        let (input, num_locals) = wasm_u32(input)?;
+        let (input, local_type) = take(1_usize)(input)?;
+        Ok((
+            input,
+            LocalCount {
+                count: num_locals,
+                value_type: local_type[0].try_into()?,
+            },
+        ))
+    });
+    parse_local_vecs(input)
+}
+
+// a code section whose bodies go to `bodies` in flat form, its code blocks are left empty
+fn flat_code_section<'a>(
+    input: &'a [u8],
+    bodies: &mut Vec<FlatBody>,
+) -> Result<(&'a [u8], Section<'a>), &'static str> {
+    let (input, _) = take(1)(input)?;
+    let (input, _) = wasm_u32(input)?;
+    let (input, num_items) = wasm_u32(input)?;
+    let mut code_blocks = vec![];
+    let mut ip = input;
+    for _ in 0..num_items {
+        let (input, _) = wasm_u32(ip)?;
+        let (input, locals) = wasm_locals(input)?;
+        let (input, body) = wasm_flat_expression(input)?;
+        code_blocks.push(CodeBlock {
+            locals,
+            instructions: vec![],
+        });
+        bodies.push(body);
+        ip = input;
+    }
+    Ok((ip, Section::Code(CodeSection { code_blocks })))
+}
+
 type Instructions = Vec<Instruction>;
 
 fn wasm_if_else(input: &[u8]) -> Result<(&[u8], Instructions, Option<Instructions>), &'static str> {
@@ -572,7 +635,7 @@ fn wasm_if_else(input: &[u8]) -> Result<(&[u8], Instructions, Option<Instruction
     }
 }
 
-fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
+fn section(input: &[u8]) -> Result<(&[u8], Section<'_>), &'static str> {
     let (input, id) = take(1)(input)?;
     let (input, section_length) = wasm_u32(input)?;
     match id[0] {
@@ -598,21 +661,21 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
                 }
             });
             let (input, items) = parse_items(input)?;
-            Ok((input, SectionView::Type(TypeSection { types: items })))
+            Ok((input, Section::Type(TypeSection { types: items })))
         }
         SECTION_FUNCTION => {
             let (input, num_items) = wasm_u32(input)?;
             let parse_items = many_n(num_items as usize, |input| {
                 let r = wasm_u32(input);
                 match r {
-                    Ok(n) => Ok((n.0, n.1 as usize)),
+                    Ok(n) => Ok((n.0, TypeIdx(n.1))),
                     Err(e) => Err(e),
                 }
             });
             let (input, items) = parse_items(input)?;
             Ok((
                 input,
-                SectionView::Function(FunctionSection {
+                Section::Function(FunctionSection {
                     function_types: items,
                 }),
             ))
@@ -621,8 +684,8 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
             let (input, start_function) = wasm_u32(input)?;
             Ok((
                 input,
-                SectionView::Start(StartSection {
-                    start_function: start_function as usize,
+                Section::Start(StartSection {
+                    start_function: FuncIdx(start_function),
                 }),
             ))
         }
@@ -635,70 +698,54 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
                 match export_type[0] {
                     DESC_FUNCTION => Ok((
                         input,
-                        WasmExportView::Function(ExportView {
-                            name,
-                            index: export_index as usize,
+                        WasmExport::Function(Export {
+                            name: name.into(),
+                            index: export_index.into(),
                         }),
                     )),
                     DESC_MEMORY => Ok((
                         input,
-                        WasmExportView::Memory(ExportView {
-                            name,
-                            index: export_index as usize,
+                        WasmExport::Memory(Export {
+                            name: name.into(),
+                            index: export_index.into(),
                         }),
                     )),
                     DESC_GLOBAL => Ok((
                         input,
-                        WasmExportView::Global(ExportView {
-                            name,
-                            index: export_index as usize,
+                        WasmExport::Global(Export {
+                            name: name.into(),
+                            index: export_index.into(),
                         }),
                     )),
                     DESC_TABLE => Ok((
                         input,
-                        WasmExportView::Table(ExportView {
-                            name,
-                            index: export_index as usize,
+                        WasmExport::Table(Export {
+                            name: name.into(),
+                            index: export_index.into(),
                         }),
                     )),
                     _ => Err("unknown export"),
                 }
             });
             let (input, items) = parse_items(input)?;
-            Ok((
-                input,
-                SectionView::Export(ExportSectionView { exports: items }),
-            ))
+            Ok((input, Section::Export(ExportSection { exports: items })))
         }
         SECTION_CODE => {
             let (input, num_items) = wasm_u32(input)?;
             let parse_items = many_n(num_items as usize, |input| {
                 let (input, _) = wasm_u32(input)?;
-                let (input, num_local_vecs) = wasm_u32(input)?;
-                let parse_local_vecs = many_n(num_local_vecs as usize, |input| {
-                    let (input, num_locals) = wasm_u32(input)?;
-                    let (input, local_type) = take(1 as usize)(input)?;
-                    Ok((
-                        input,
-                        LocalCount {
-                            count: num_locals,
-                            value_type: local_type[0].try_into()?,
-                        },
-                    ))
-                });
-                let (input, local_vectors) = parse_local_vecs(input)?;
-
+                let (input, locals) = wasm_locals(input)?;
                 let (input, instructions) = wasm_expression(input)?;
                 Ok((
                     input,
                     CodeBlock {
-                        locals: local_vectors,
+                        locals,
                         instructions,
                     },
                 ))
             });
             let (input, items) = parse_items(input)?;
-            Ok((input, SectionView::Code(CodeSection { code_blocks: items })))
+            Ok((input, Section::Code(CodeSection { code_blocks: items })))
         }
         SECTION_IMPORT => {
             let (input, num_items) = wasm_u32(input)?;
@@ -711,10 +758,10 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
                         let (input, type_index) = wasm_u32(input)?;
                         Ok((
                             input,
-                            WasmImportView::Function(FunctionImportView {
-                                module_name,
-                                name,
-                                type_index: type_index as usize,
+                            WasmImport::Function(FunctionImport {
+                                module_name: module_name.into(),
+                                name: name.into(),
+                                type_index: TypeIdx(type_index),
                             }),
                         ))
                     }
@@ -722,9 +769,9 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
                         let (input, min_pages, max_pages) = wasm_limit(input)?;
                         Ok((
                             input,
-                            WasmImportView::Memory(MemoryImportView {
-                                module_name,
-                                name,
+                            WasmImport::Memory(MemoryImport {
+                                module_name: module_name.into(),
+                                name: name.into(),
                                 min_pages,
                                 max_pages,
                             }),
@@ -735,9 +782,9 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
                         let (input, min, max) = wasm_limit(input)?;
                         Ok((
                             input,
-                            WasmImportView::Table(TableImportView {
-                                module_name,
-                                name,
+                            WasmImport::Table(TableImport {
+                                module_name: module_name.into(),
+                                name: name.into(),
                                 element_type: element_type[0],
                                 min,
                                 max,
@@ -748,9 +795,9 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
                         let (input, value_type, is_mutable) = wasm_global_type(input)?;
                         Ok((
                             input,
-                            WasmImportView::Global(GlobalImportView {
-                                module_name,
-                                name,
+                            WasmImport::Global(GlobalImport {
+                                module_name: module_name.into(),
+                                name: name.into(),
                                 value_type,
                                 is_mutable,
                             }),
@@ -760,10 +807,7 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
                 }
             });
             let (input, items) = parse_items(input)?;
-            Ok((
-                input,
-                SectionView::Import(ImportSectionView { imports: items }),
-            ))
+            Ok((input, Section::Import(ImportSection { imports: items })))
         }
         SECTION_GLOBAL => {
             let (input, num_items) = wasm_u32(input)?;
@@ -780,26 +824,25 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
                 ))
             });
             let (input, items) = parse_items(input)?;
-            Ok((input, SectionView::Global(GlobalSection { globals: items })))
+            Ok((input, Section::Global(GlobalSection { globals: items })))
         }
         SECTION_CUSTOM => {
             let mut name_bytes_length = 0;
-            let (num_chars, byte_count) = match input.try_extract_u32(0) {
-                Ok(r) => r,
-                Err(e) => return Err(e),
-            };
-            let (input, _) = take(byte_count as usize)(input)?;
+            let (num_chars, byte_count) = input.try_extract_u32(0)?;
+            let (input, _) = take(byte_count)(input)?;
             let (input, chars) = take(num_chars as usize)(input)?;
             name_bytes_length += byte_count + num_chars as usize;
             let name = match core::str::from_utf8(chars) {
                 Ok(b) => b,
                 Err(_) => return Err("could not parse utf8 string"),
             };
-            let (input, bytes) =
-                take((section_length as usize - name_bytes_length) as usize)(input)?;
+            let (input, bytes) = take(section_length as usize - name_bytes_length)(input)?;
             Ok((
                 input,
-                SectionView::Custom(CustomSectionView { name, data: bytes }),
+                Section::Custom(CustomSection {
+                    name: name.into(),
+                    data: bytes.into(),
+                }),
             ))
         }
         SECTION_TABLE => {
@@ -821,7 +864,7 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
                 }
             });
             let (input, items) = parse_items(input)?;
-            Ok((input, SectionView::Table(TableSection { tables: items })))
+            Ok((input, Section::Table(TableSection { tables: items })))
         }
         SECTION_DATA => {
             let (input, num_items) = wasm_u32(input)?;
@@ -832,18 +875,15 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
                 let (input, data) = take(data_len as usize)(input)?;
                 Ok((
                     input,
-                    DataBlockView {
-                        memory: mem_index as usize,
+                    DataBlock {
+                        memory: MemIdx(mem_index),
                         offset_expression,
-                        data,
+                        data: data.into(),
                     },
                 ))
             });
             let (input, items) = parse_items(input)?;
-            Ok((
-                input,
-                SectionView::Data(DataSectionView { data_blocks: items }),
-            ))
+            Ok((input, Section::Data(DataSection { data_blocks: items })))
         }
         SECTION_MEMORY => {
             let (input, num_items) = wasm_u32(input)?;
@@ -858,10 +898,7 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
                 ))
             });
             let (input, items) = parse_items(input)?;
-            Ok((
-                input,
-                SectionView::Memory(MemorySection { memories: items }),
-            ))
+            Ok((input, Section::Memory(MemorySection { memories: items })))
         }
         SECTION_ELEMENT => {
             let (input, num_items) = wasm_u32(input)?;
@@ -871,36 +908,50 @@ fn section(input: &[u8]) -> Result<(&[u8], SectionView), &'static str> {
                 let (input, num_functions) = wasm_u32(input)?;
                 let parse_functions = many_n(num_functions as usize, |input| {
                     let (input, i) = wasm_u32(input)?;
-                    Ok((input, i as usize))
+                    Ok((input, FuncIdx(i)))
                 });
                 let (input, functions) = parse_functions(input)?;
                 Ok((
                     input,
                     WasmElement {
-                        table: table as usize,
+                        table: TableIdx(table),
                         value_expression: expression,
                         functions,
                     },
                 ))
             });
             let (input, items) = parse_items(input)?;
-            Ok((
-                input,
-                SectionView::Element(ElementSection { elements: items }),
-            ))
+            Ok((input, Section::Element(ElementSection { elements: items })))
         }
         _ => Err("unknow section"),
     }
 }
 
-pub fn wasm_module(input: &[u8]) -> Result<ProgramView, &'static str> {
+pub fn wasm_module(input: &[u8]) -> Result<ProgramView<'_>, &'static str> {
+    wasm_sections(input, None)
+}
+
+pub fn wasm_module_flat(input: &[u8]) -> Result<FlatModule<'_>, &'static str> {
+    let mut bodies = vec![];
+    let p = wasm_sections(input, Some(&mut bodies))?;
+    Ok(FlatModule::new(p, bodies))
+}
+
+fn wasm_sections<'a>(
+    input: &'a [u8],
+    mut flat_bodies: Option<&mut Vec<FlatBody>>,
+) -> Result<ProgramView<'a>, &'static str> {
     let (input, _) = tag(MAGIC_NUMBER)(input)?;
     let (input, _) = tag(VERSION_1)(input)?;
     let mut sections = vec![];
     let mut ip = input;
     let mut p = ProgramView { sections: vec![] };
     loop {
-        match section(ip) {
+        let parsed = match flat_bodies.as_deref_mut() {
+            Some(bodies) if ip.first() == Some(&SECTION_CODE) => flat_code_section(ip, bodies),
+            _ => section(ip),
+        };
+        match parsed {
             Ok((input, item)) => {
                 sections.push(item);
                 ip = input;
@@ -917,3 +968,49 @@ pub fn wasm_module(input: &[u8]) -> Result<ProgramView, &'static str> {
     p.sections = sections;
     Ok(p)
 }
+
+type NameMap<T> = Vec<(T, String)>;
+
+fn wasm_name_map<T: From<u32>>(input: &[u8]) -> Result<(&[u8], NameMap<T>), &'static str> {
+    let (input, num_items) = wasm_u32(input)?;
+    let parse_items = many_n(num_items as usize, |input| {
+        let (input, idx) = wasm_u32(input)?;
+        let (input, name) = wasm_string(input)?;
+        Ok((input, (T::from(idx), name.to_string())))
+    });
+    parse_items(input)
+}
+
+pub fn wasm_name_section(input: &[u8]) -> Result<NameSection, &'static str> {
+    let mut names = NameSection::default();
+    let mut ip = input;
+    while !ip.is_empty() {
+        let (input, id) = take(1)(ip)?;
+        let (input, len) = wasm_u32(input)?;
+        let (rest, content) = take(len as usize)(input)?;
+        match id[0] {
+            NAME_MODULE => {
+                let (_, name) = wasm_string(content)?;
+                names.module_name = Some(name.to_string());
+            }
+            NAME_FUNCTION => {
+                let (_, function_names) = wasm_name_map(content)?;
+                names.function_names = function_names;
+            }
+            NAME_LOCAL => {
+                let (input, num_items) = wasm_u32(content)?;
+                let parse_items = many_n(num_items as usize, |input| {
+                    let (input, idx) = wasm_u32(input)?;
+                    let (input, locals) = wasm_name_map(input)?;
+                    Ok((input, (FuncIdx(idx), locals)))
+                });
+                let (_, local_names) = parse_items(input)?;
+                names.local_names = local_names;
+            }
+            // unknown subsections are skipped as the spec allows
+            _ => {}
+        }
+        ip = rest;
+    }
+    Ok(names)
+}
@@ -1,5 +1,186 @@
-use crate::core::wast::Wast;
+use crate::core::wast::*;
+use crate::core::ValueType;
+use crate::interpreter::WasmValue;
+use crate::parser::sexpr::*;
+use crate::parser::wat::{parse_float, parse_int};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+fn text(e: Option<&SExpr>) -> Result<String, &'static str> {
+    match e.and_then(|x| x.string()) {
+        Some(s) => match String::from_utf8(s.to_vec()) {
+            Ok(s) => Ok(s),
+            Err(_) => Err("malformed UTF-8 encoding"),
+        },
+        None => Err("expected a string"),
+    }
+}
+
+fn id(e: Option<&SExpr>) -> Option<String> {
+    match e {
+        Some(SExpr::Atom(a)) if a.starts_with('$') => Some(a.to_string()),
+        _ => None,
+    }
+}
+
+fn strings(items: &[SExpr]) -> Result<Vec<u8>, &'static str> {
+    let mut bytes = vec![];
+    for s in items.iter() {
+        match s.string() {
+            Some(s) => bytes.extend_from_slice(s),
+            None => return Err("expected a string"),
+        }
+    }
+    Ok(bytes)
+}
+
+fn list(e: Option<&SExpr>) -> Result<&List, &'static str> {
+    match e.and_then(|x| x.list()) {
+        Some(l) => Ok(l),
+        None => Err("expected a list"),
+    }
+}
+
+fn wast_module(input: &[u8], l: &List) -> Result<WastModuleDefinition, &'static str> {
+    if l.head() != Some("module") {
+        return Err("expected a module");
+    }
+    let module_id = id(l.items.get(1));
+    let i = if module_id.is_some() { 2 } else { 1 };
+    let module = match l.items.get(i).and_then(|x| x.atom()) {
+        Some("binary") => WastModule::Binary(strings(&l.items[i + 1..])?),
+        Some("quote") => match String::from_utf8(strings(&l.items[i + 1..])?) {
+            Ok(s) => WastModule::Quote(s),
+            Err(_) => return Err("malformed UTF-8 encoding"),
+        },
+        _ => match core::str::from_utf8(&input[l.start..l.end]) {
+            Ok(s) => WastModule::Text(s.to_string()),
+            Err(_) => return Err("malformed UTF-8 encoding"),
+        },
+    };
+    Ok(WastModuleDefinition {
+        id: module_id,
+        module,
+    })
+}
+
+fn wast_const(e: &SExpr) -> Result<WastExpected, &'static str> {
+    let l = match e.list() {
+        Some(l) if l.items.len() == 2 => l,
+        _ => return Err("expected a constant"),
+    };
+    let value = match l.items[1].atom() {
+        Some(v) => v,
+        None => return Err("expected a constant"),
+    };
+    let value_type = match l.head() {
+        Some("i32.const") => {
+            return Ok(WastExpected::Value(WasmValue::I32(
+                parse_int(value, 32)? as i32
+            )))
+        }
+        Some("i64.const") => {
+            return Ok(WastExpected::Value(WasmValue::I64(
+                parse_int(value, 64)? as i64
+            )))
+        }
+        Some("f32.const") => ValueType::F32,
+        Some("f64.const") => ValueType::F64,
+        _ => return Err("unknown constant type"),
+    };
+    match value {
+        "nan:canonical" => Ok(WastExpected::NanCanonical(value_type)),
+        "nan:arithmetic" => Ok(WastExpected::NanArithmetic(value_type)),
+        _ if value_type == ValueType::F32 => Ok(WastExpected::Value(WasmValue::F32(
+            f32::from_bits(parse_float(value, 23, 8)? as u32),
+        ))),
+        _ => Ok(WastExpected::Value(WasmValue::F64(f64::from_bits(
+            parse_float(value, 52, 11)?,
+        )))),
+    }
+}
+
+fn wast_action(l: &List) -> Result<WastAction, &'static str> {
+    let module = id(l.items.get(1));
+    let i = if module.is_some() { 2 } else { 1 };
+    let name = text(l.items.get(i))?;
+    match l.head() {
+        Some("invoke") => {
+            let mut args = vec![];
+            for a in l.items[i + 1..].iter() {
+                match wast_const(a)? {
+                    WastExpected::Value(v) => args.push(v),
+                    _ => return Err("arguments can not be NaN patterns"),
+                }
+            }
+            Ok(WastAction::Invoke { module, name, args })
+        }
+        Some("get") => Ok(WastAction::Get { module, name }),
+        _ => Err("unknown action"),
+    }
+}
+
+fn wast_command(input: &[u8], l: &List) -> Result<WastCommand, &'static str> {
+    let item = |i: usize| l.items.get(i);
+    let command = match l.head() {
+        Some("module") => WastCommand::Module(wast_module(input, l)?),
+        Some("register") => WastCommand::Register {
+            name: text(item(1))?,
+            module: id(item(2)),
+        },
+        Some("invoke") | Some("get") => WastCommand::Action(wast_action(l)?),
+        Some("assert_return") => {
+            let mut expected = vec![];
+            for e in l.items[2..].iter() {
+                expected.push(wast_const(e)?);
+            }
+            WastCommand::AssertReturn {
+                action: wast_action(list(item(1))?)?,
+                expected,
+            }
+        }
+        Some("assert_trap") => {
+            let execute = match item(1).and_then(|x| x.list()).and_then(|x| x.head()) {
+                Some("module") => WastExecute::Module(wast_module(input, list(item(1))?)?),
+                _ => WastExecute::Action(wast_action(list(item(1))?)?),
+            };
+            WastCommand::AssertTrap {
+                execute,
+                message: text(item(2))?,
+            }
+        }
+        Some("assert_exhaustion") => WastCommand::AssertExhaustion {
+            action: wast_action(list(item(1))?)?,
+            message: text(item(2))?,
+        },
+        Some("assert_malformed") => WastCommand::AssertMalformed {
+            module: wast_module(input, list(item(1))?)?,
+            message: text(item(2))?,
+        },
+        Some("assert_invalid") => WastCommand::AssertInvalid {
+            module: wast_module(input, list(item(1))?)?,
+            message: text(item(2))?,
+        },
+        Some("assert_unlinkable") => WastCommand::AssertUnlinkable {
+            module: wast_module(input, list(item(1))?)?,
+            message: text(item(2))?,
+        },
+        _ => return Err("unknown script directive"),
+    };
+    Ok(command)
+}
 
 pub fn wast_file(input: &[u8]) -> Result<Wast, &'static str> {
-    Ok(Wast {})
+    let mut directives = vec![];
+    for e in sexpr_file(input)?.iter() {
+        let l = match e.list() {
+            Some(l) => l,
+            None => return Err("expected a script directive"),
+        };
+        directives.push(WastDirective {
+            line: l.line,
+            command: wast_command(input, l)?,
+        });
+    }
+    Ok(Wast { directives })
 }
@@ -0,0 +1,1358 @@
+use crate::core::*;
+use crate::math::compose_float;
+use crate::parser::sexpr::*;
+use crate::parser::wasm::{wasm_instruction, wasm_module};
+use crate::wat::instruction_mnemonic;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::{TryFrom, TryInto};
+use webassembly::*;
+
+type Names = BTreeMap<String, usize>;
+
+// fields that can only be finished once every name in the module is known
+enum Pending<'a> {
+    Func(&'a [SExpr], usize, Names),
+    Global(&'a [SExpr], usize, usize),
+    TableElem(&'a [SExpr], usize),
+    MemoryData(Vec<u8>, usize),
+    Export(&'a List),
+    Start(&'a List),
+    Elem(&'a List),
+    Data(&'a List),
+}
+
+#[derive(Default)]
+struct ModuleBuilder<'a> {
+    opcodes: BTreeMap<&'static str, u8>,
+    types: Vec<FunctionType>,
+    type_names: Names,
+    imports: Vec<WasmImport<'static>>,
+    functions: Vec<usize>,
+    tables: Vec<Table>,
+    memories: Vec<WasmMemory>,
+    globals: Vec<Global>,
+    exports: Vec<WasmExport<'static>>,
+    start: Option<usize>,
+    elements: Vec<WasmElement>,
+    data_blocks: Vec<DataBlock<'static>>,
+    code_blocks: Vec<CodeBlock>,
+    customs: Vec<CustomSection<'static>>,
+    func_names: Names,
+    table_names: Names,
+    memory_names: Names,
+    global_names: Names,
+    func_ct: usize,
+    table_ct: usize,
+    memory_ct: usize,
+    global_ct: usize,
+    pending: Vec<Pending<'a>>,
+}
+
+#[derive(Default)]
+struct FunctionContext {
+    locals: Names,
+    labels: Vec<Option<String>>,
+}
+
+fn list_with_head<'a>(e: Option<&'a SExpr>, head: &str) -> Option<&'a List> {
+    match e.and_then(|x| x.list()) {
+        Some(l) if l.head() == Some(head) => Some(l),
+        _ => None,
+    }
+}
+
+fn atom(e: Option<&SExpr>) -> Result<&str, &'static str> {
+    match e.and_then(|x| x.atom()) {
+        Some(a) => Ok(a),
+        None => Err("unexpected token"),
+    }
+}
+
+fn name(e: Option<&SExpr>) -> Result<String, &'static str> {
+    match e.and_then(|x| x.string()) {
+        Some(s) => match String::from_utf8(s.to_vec()) {
+            Ok(s) => Ok(s),
+            Err(_) => Err("malformed UTF-8 encoding"),
+        },
+        None => Err("expected a name string"),
+    }
+}
+
+fn take_id(items: &[SExpr], i: &mut usize) -> Option<String> {
+    match items.get(*i) {
+        Some(SExpr::Atom(a)) if a.starts_with('$') => {
+            *i += 1;
+            Some(a.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn value_type(e: Option<&SExpr>) -> Result<ValueType, &'static str> {
+    match atom(e)? {
+        "i32" => Ok(ValueType::I32),
+        "i64" => Ok(ValueType::I64),
+        "f32" => Ok(ValueType::F32),
+        "f64" => Ok(ValueType::F64),
+        _ => Err("unknown value type"),
+    }
+}
+
+fn digits(s: &str, radix: u32) -> Result<u128, &'static str> {
+    if s.is_empty() || s.starts_with('_') || s.ends_with('_') || s.contains("__") {
+        return Err("unknown operator");
+    }
+    let mut n: u128 = 0;
+    for c in s.chars().filter(|c| *c != '_') {
+        let d = match c.to_digit(radix) {
+            Some(d) => d as u128,
+            None => return Err("unknown operator"),
+        };
+        n = match n.checked_mul(radix as u128).and_then(|n| n.checked_add(d)) {
+            Some(n) => n,
+            None => return Err("constant out of range"),
+        };
+    }
+    Ok(n)
+}
+
+fn split_sign(s: &str) -> (bool, &str) {
+    if let Some(rest) = s.strip_prefix('-') {
+        (true, rest)
+    } else if let Some(rest) = s.strip_prefix('+') {
+        (false, rest)
+    } else {
+        (false, s)
+    }
+}
+
+fn nat(s: &str) -> Result<u128, &'static str> {
+    match s.strip_prefix("0x") {
+        Some(hex) => digits(hex, 16),
+        None => digits(s, 10),
+    }
+}
+
+fn parse_u32(s: &str) -> Result<u32, &'static str> {
+    match u32::try_from(nat(s)?) {
+        Ok(n) => Ok(n),
+        Err(_) => Err("constant out of range"),
+    }
+}
+
+// parses an integer literal of the given bit width, accepting both the signed
+// and unsigned range, and returns its two's complement bits
+pub(crate) fn parse_int(s: &str, bits: u32) -> Result<u64, &'static str> {
+    let (negative, rest) = split_sign(s);
+    let n = nat(rest)?;
+    let limit = if negative {
+        1u128 << (bits - 1)
+    } else {
+        (1u128 << bits) - 1
+    };
+    if n > limit {
+        return Err("constant out of range");
+    }
+    let n = if negative { n.wrapping_neg() } else { n };
+    Ok(n as u64)
+}
+
+fn parse_hex_float(
+    negative: bool,
+    s: &str,
+    mantissa_bits: u32,
+    exponent_bits: u32,
+) -> Result<u64, &'static str> {
+    let (number, exponent) = match s.find(['p', 'P']) {
+        Some(p) => {
+            let (sign, e) = split_sign(&s[p + 1..]);
+            let e = digits(e, 10)?.min(1 << 20) as i64;
+            (&s[..p], if sign { -e } else { e })
+        }
+        None => (s, 0),
+    };
+    let (int_part, frac_part) = match number.find('.') {
+        Some(d) => (&number[..d], &number[d + 1..]),
+        None => (number, ""),
+    };
+    digits(int_part, 16).or_else(|e| {
+        if e == "constant out of range" {
+            Ok(0)
+        } else {
+            Err(e)
+        }
+    })?;
+    if !frac_part.is_empty() {
+        digits(frac_part, 16).or_else(|e| {
+            if e == "constant out of range" {
+                Ok(0)
+            } else {
+                Err(e)
+            }
+        })?;
+    }
+    let mut mantissa: u128 = 0;
+    let mut e = exponent;
+    let mut sticky = false;
+    for (c, fractional) in int_part
+        .chars()
+        .map(|c| (c, false))
+        .chain(frac_part.chars().map(|c| (c, true)))
+    {
+        let d = match c.to_digit(16) {
+            Some(d) => d as u128,
+            None => continue,
+        };
+        if mantissa >> 116 == 0 {
+            mantissa = mantissa * 16 + d;
+            if fractional {
+                e -= 4;
+            }
+        } else {
+            sticky |= d != 0;
+            if !fractional {
+                e += 4;
+            }
+        }
+    }
+    let e = e.clamp(-(1 << 22), 1 << 22) as i32;
+    match compose_float(negative, mantissa, e, sticky, mantissa_bits, exponent_bits) {
+        Some(bits) => Ok(bits),
+        None => Err("constant out of range"),
+    }
+}
+
+// parses a float literal into the bits of a float with the given layout
+pub(crate) fn parse_float(
+    s: &str,
+    mantissa_bits: u32,
+    exponent_bits: u32,
+) -> Result<u64, &'static str> {
+    let (negative, rest) = split_sign(s);
+    let sign = if negative {
+        1u64 << (mantissa_bits + exponent_bits)
+    } else {
+        0
+    };
+    let infinity = ((1u64 << exponent_bits) - 1) << mantissa_bits;
+    if rest == "inf" {
+        return Ok(sign | infinity);
+    }
+    if rest == "nan" {
+        return Ok(sign | infinity | 1 << (mantissa_bits - 1));
+    }
+    if let Some(payload) = rest.strip_prefix("nan:0x") {
+        let payload = digits(payload, 16)?;
+        if payload == 0 || payload >> mantissa_bits != 0 {
+            return Err("constant out of range");
+        }
+        return Ok(sign | infinity | payload as u64);
+    }
+    if let Some(hex) = rest.strip_prefix("0x") {
+        return parse_hex_float(negative, hex, mantissa_bits, exponent_bits);
+    }
+    if !rest.starts_with(|c: char| c.is_ascii_digit())
+        || rest.contains("__")
+        || rest.contains("_.")
+        || rest.contains("._")
+        || rest.ends_with('_')
+        || rest.contains(|c: char| !(c.is_ascii_digit() || "._eE+-".contains(c)))
+    {
+        return Err("unknown operator");
+    }
+    let cleaned = rest.replace('_', "");
+    let bits = if mantissa_bits == 23 {
+        match cleaned.parse::<f32>() {
+            Ok(f) if f.is_finite() => f.to_bits() as u64,
+            Ok(_) => return Err("constant out of range"),
+            Err(_) => return Err("unknown operator"),
+        }
+    } else {
+        match cleaned.parse::<f64>() {
+            Ok(f) if f.is_finite() => f.to_bits(),
+            Ok(_) => return Err("constant out of range"),
+            Err(_) => return Err("unknown operator"),
+        }
+    };
+    Ok(sign | bits)
+}
+
+fn resolve(e: Option<&SExpr>, names: &Names) -> Result<usize, &'static str> {
+    let a = atom(e)?;
+    if a.starts_with('$') {
+        match names.get(a) {
+            Some(i) => Ok(*i),
+            None => Err("unknown identifier"),
+        }
+    } else {
+        Ok(parse_u32(a)? as usize)
+    }
+}
+
+fn limits(items: &[SExpr], i: &mut usize) -> Result<(usize, Option<usize>), &'static str> {
+    let min = parse_u32(atom(items.get(*i))?)? as usize;
+    *i += 1;
+    let max = match items.get(*i).and_then(|x| x.atom()) {
+        Some(a) if a.starts_with(|c: char| c.is_ascii_digit()) => {
+            *i += 1;
+            Some(parse_u32(a)? as usize)
+        }
+        _ => None,
+    };
+    Ok((min, max))
+}
+
+fn element_type(e: Option<&SExpr>) -> Result<u8, &'static str> {
+    match atom(e)? {
+        "funcref" | "anyfunc" => Ok(ANYFUNC),
+        _ => Err("unknown element type"),
+    }
+}
+
+fn global_type(e: Option<&SExpr>) -> Result<(ValueType, bool), &'static str> {
+    match list_with_head(e, "mut") {
+        Some(l) => Ok((value_type(l.items.get(1))?, true)),
+        None => Ok((value_type(e)?, false)),
+    }
+}
+
+fn strings(items: &[SExpr]) -> Result<Vec<u8>, &'static str> {
+    let mut bytes = vec![];
+    for s in items.iter() {
+        match s.string() {
+            Some(s) => bytes.extend_from_slice(s),
+            None => return Err("expected a string"),
+        }
+    }
+    Ok(bytes)
+}
+
+fn legacy_mnemonic(name: &str) -> &str {
+    match name {
+        "get_local" => "local.get",
+        "set_local" => "local.set",
+        "tee_local" => "local.tee",
+        "get_global" => "global.get",
+        "set_global" => "global.set",
+        "current_memory" => "memory.size",
+        "grow_memory" => "memory.grow",
+        x => x,
+    }
+}
+
+impl<'a> ModuleBuilder<'a> {
+    fn new() -> Self {
+        let mut opcodes = BTreeMap::new();
+        for op in 0..=255u8 {
+            if let Ok((_, i)) = wasm_instruction(op, &[0, 0]) {
+                opcodes.insert(instruction_mnemonic(&i), op);
+            }
+        }
+        ModuleBuilder {
+            opcodes,
+            ..Default::default()
+        }
+    }
+
+    fn function_type(
+        &self,
+        items: &[SExpr],
+        i: &mut usize,
+        mut param_names: Option<&mut Names>,
+    ) -> Result<Option<FunctionType>, &'static str> {
+        let mut fn_type = FunctionType {
+            inputs: vec![],
+            outputs: vec![],
+        };
+        let mut inline = false;
+        while let Some(l) = items.get(*i).and_then(|x| x.list()) {
+            match l.head() {
+                Some("param") if !fn_type.outputs.is_empty() => {
+                    return Err("unexpected token");
+                }
+                Some("param") => {
+                    if l.items.get(1).map(|x| x.is_id()) == Some(true) {
+                        if l.items.len() != 3 {
+                            return Err("unexpected token");
+                        }
+                        if let Some(names) = param_names.as_mut() {
+                            let id = atom(l.items.get(1))?.to_string();
+                            if names.insert(id, fn_type.inputs.len()).is_some() {
+                                return Err("duplicate local");
+                            }
+                        }
+                        fn_type.inputs.push(value_type(l.items.get(2))?);
+                    } else {
+                        for x in l.items[1..].iter() {
+                            fn_type.inputs.push(value_type(Some(x))?);
+                        }
+                    }
+                }
+                Some("result") => {
+                    for x in l.items[1..].iter() {
+                        fn_type.outputs.push(value_type(Some(x))?);
+                    }
+                }
+                _ => break,
+            }
+            inline = true;
+            *i += 1;
+        }
+        Ok(if inline { Some(fn_type) } else { None })
+    }
+
+    // resolves `(type $t)? (param ..)* (result ..)*` to a type index, adding
+    // the type when it is only written inline
+    fn type_use(
+        &mut self,
+        items: &[SExpr],
+        i: &mut usize,
+        param_names: Option<&mut Names>,
+    ) -> Result<usize, &'static str> {
+        let explicit = match list_with_head(items.get(*i), "type") {
+            Some(l) => {
+                *i += 1;
+                let t = resolve(l.items.get(1), &self.type_names)?;
+                if t >= self.types.len() {
+                    return Err("unknown type");
+                }
+                Some(t)
+            }
+            None => None,
+        };
+        let inline = self.function_type(items, i, param_names)?;
+        match (explicit, inline) {
+            (Some(t), Some(f)) if self.types[t] != f => Err("inconsistent type"),
+            (Some(t), _) => Ok(t),
+            (None, f) => {
+                let f = f.unwrap_or(FunctionType {
+                    inputs: vec![],
+                    outputs: vec![],
+                });
+                match self.types.iter().position(|x| *x == f) {
+                    Some(t) => Ok(t),
+                    None => {
+                        self.types.push(f);
+                        Ok(self.types.len() - 1)
+                    }
+                }
+            }
+        }
+    }
+
+    fn inline_exports(
+        &mut self,
+        items: &[SExpr],
+        i: &mut usize,
+        kind: u8,
+        index: usize,
+    ) -> Result<(), &'static str> {
+        while let Some(l) = list_with_head(items.get(*i), "export") {
+            self.add_export(kind, name(l.items.get(1))?, index);
+            *i += 1;
+        }
+        Ok(())
+    }
+
+    fn add_export(&mut self, kind: u8, name: String, index: usize) {
+        let index = index as u32;
+        self.exports.push(match kind {
+            DESC_FUNCTION => WasmExport::Function(Export {
+                name: name.into(),
+                index: FuncIdx(index),
+            }),
+            DESC_TABLE => WasmExport::Table(Export {
+                name: name.into(),
+                index: TableIdx(index),
+            }),
+            DESC_MEMORY => WasmExport::Memory(Export {
+                name: name.into(),
+                index: MemIdx(index),
+            }),
+            _ => WasmExport::Global(Export {
+                name: name.into(),
+                index: GlobalIdx(index),
+            }),
+        });
+    }
+
+    fn add_name(names: &mut Names, id: Option<String>, index: usize) -> Result<(), &'static str> {
+        if let Some(id) = id {
+            if names.insert(id, index).is_some() {
+                return Err("duplicate identifier");
+            }
+        }
+        Ok(())
+    }
+
+    fn check_import_order(&self, kind: u8) -> Result<(), &'static str> {
+        let defined = match kind {
+            DESC_FUNCTION => !self.functions.is_empty(),
+            DESC_TABLE => !self.tables.is_empty(),
+            DESC_MEMORY => !self.memories.is_empty(),
+            _ => !self.globals.is_empty(),
+        };
+        if defined {
+            return Err(match kind {
+                DESC_FUNCTION => "import after function",
+                DESC_TABLE => "import after table",
+                DESC_MEMORY => "import after memory",
+                _ => "import after global",
+            });
+        }
+        Ok(())
+    }
+
+    fn import(
+        &mut self,
+        module_name: String,
+        import_name: String,
+        kind: u8,
+        id: Option<String>,
+        items: &[SExpr],
+        mut i: usize,
+    ) -> Result<usize, &'static str> {
+        self.check_import_order(kind)?;
+        let index = match kind {
+            DESC_FUNCTION => {
+                let type_index = self.type_use(items, &mut i, None)?;
+                self.imports.push(WasmImport::Function(FunctionImport {
+                    module_name: module_name.into(),
+                    name: import_name.into(),
+                    type_index: type_index.try_into()?,
+                }));
+                self.func_ct += 1;
+                Self::add_name(&mut self.func_names, id, self.func_ct - 1)?;
+                self.func_ct - 1
+            }
+            DESC_TABLE => {
+                let (min, max) = limits(items, &mut i)?;
+                let element_type = element_type(items.get(i))?;
+                i += 1;
+                self.imports.push(WasmImport::Table(TableImport {
+                    module_name: module_name.into(),
+                    name: import_name.into(),
+                    element_type,
+                    min,
+                    max,
+                }));
+                self.table_ct += 1;
+                Self::add_name(&mut self.table_names, id, self.table_ct - 1)?;
+                self.table_ct - 1
+            }
+            DESC_MEMORY => {
+                let (min_pages, max_pages) = limits(items, &mut i)?;
+                self.imports.push(WasmImport::Memory(MemoryImport {
+                    module_name: module_name.into(),
+                    name: import_name.into(),
+                    min_pages,
+                    max_pages,
+                }));
+                self.memory_ct += 1;
+                Self::add_name(&mut self.memory_names, id, self.memory_ct - 1)?;
+                self.memory_ct - 1
+            }
+            _ => {
+                let (value_type, is_mutable) = global_type(items.get(i))?;
+                i += 1;
+                self.imports.push(WasmImport::Global(GlobalImport {
+                    module_name: module_name.into(),
+                    name: import_name.into(),
+                    value_type,
+                    is_mutable,
+                }));
+                self.global_ct += 1;
+                Self::add_name(&mut self.global_names, id, self.global_ct - 1)?;
+                self.global_ct - 1
+            }
+        };
+        if i != items.len() {
+            return Err("unexpected token");
+        }
+        Ok(index)
+    }
+
+    fn type_field(&mut self, l: &List) -> Result<(), &'static str> {
+        let mut i = 1;
+        let id = take_id(&l.items, &mut i);
+        let f = match list_with_head(l.items.get(i), "func") {
+            Some(f) => f,
+            None => return Err("unexpected token"),
+        };
+        let mut j = 1;
+        let fn_type = self.function_type(&f.items, &mut j, None)?;
+        if j != f.items.len() || i + 1 != l.items.len() {
+            return Err("unexpected token");
+        }
+        Self::add_name(&mut self.type_names, id, self.types.len())?;
+        self.types.push(fn_type.unwrap_or(FunctionType {
+            inputs: vec![],
+            outputs: vec![],
+        }));
+        Ok(())
+    }
+
+    fn field(&mut self, l: &'a List) -> Result<(), &'static str> {
+        let items = &l.items;
+        let mut i = 1;
+        match l.head() {
+            Some("type") => {}
+            // `(@custom "name" (placement)? "data"*)`, placement does not matter as custom
+            // sections are written first
+            Some("@custom") => {
+                let name = name(items.get(1))?;
+                let mut j = 2;
+                if let Some(SExpr::List(_)) = items.get(j) {
+                    j += 1;
+                }
+                let data = strings(&items[j..])?;
+                self.customs.push(CustomSection {
+                    name: name.into(),
+                    data: data.into(),
+                });
+            }
+            Some("import") => {
+                let module_name = name(items.get(1))?;
+                let import_name = name(items.get(2))?;
+                let desc = match items.get(3).and_then(|x| x.list()) {
+                    Some(d) if items.len() == 4 => d,
+                    _ => return Err("unexpected token"),
+                };
+                let kind = match desc.head() {
+                    Some("func") => DESC_FUNCTION,
+                    Some("table") => DESC_TABLE,
+                    Some("memory") => DESC_MEMORY,
+                    Some("global") => DESC_GLOBAL,
+                    _ => return Err("unexpected token"),
+                };
+                let mut j = 1;
+                let id = take_id(&desc.items, &mut j);
+                self.import(module_name, import_name, kind, id, &desc.items, j)?;
+            }
+            Some(kind_name) => {
+                let kind = match kind_name {
+                    "func" => DESC_FUNCTION,
+                    "table" => DESC_TABLE,
+                    "memory" => DESC_MEMORY,
+                    "global" => DESC_GLOBAL,
+                    "export" => {
+                        self.pending.push(Pending::Export(l));
+                        return Ok(());
+                    }
+                    "start" => {
+                        self.pending.push(Pending::Start(l));
+                        return Ok(());
+                    }
+                    "elem" => {
+                        self.pending.push(Pending::Elem(l));
+                        return Ok(());
+                    }
+                    "data" => {
+                        self.pending.push(Pending::Data(l));
+                        return Ok(());
+                    }
+                    _ => return Err("unknown module field"),
+                };
+                let id = take_id(items, &mut i);
+                let index = match kind {
+                    DESC_FUNCTION => self.func_ct,
+                    DESC_TABLE => self.table_ct,
+                    DESC_MEMORY => self.memory_ct,
+                    _ => self.global_ct,
+                };
+                self.inline_exports(items, &mut i, kind, index)?;
+                if let Some(import) = list_with_head(items.get(i), "import") {
+                    let module_name = name(import.items.get(1))?;
+                    let import_name = name(import.items.get(2))?;
+                    self.import(module_name, import_name, kind, id, items, i + 1)?;
+                    return Ok(());
+                }
+                self.definition(kind, id, items, i)?;
+            }
+            None => return Err("unknown module field"),
+        }
+        Ok(())
+    }
+
+    fn definition(
+        &mut self,
+        kind: u8,
+        id: Option<String>,
+        items: &'a [SExpr],
+        mut i: usize,
+    ) -> Result<(), &'static str> {
+        match kind {
+            DESC_FUNCTION => {
+                let mut param_names = Names::new();
+                let type_index = self.type_use(items, &mut i, Some(&mut param_names))?;
+                Self::add_name(&mut self.func_names, id, self.func_ct)?;
+                self.functions.push(type_index);
+                self.func_ct += 1;
+                self.pending.push(Pending::Func(items, i, param_names));
+            }
+            DESC_TABLE => {
+                Self::add_name(&mut self.table_names, id, self.table_ct)?;
+                let table = if items
+                    .get(i)
+                    .and_then(|x| x.atom())
+                    .map(|x| x.starts_with(|c: char| c.is_ascii_digit()))
+                    == Some(true)
+                {
+                    let (min, max) = limits(items, &mut i)?;
+                    let element_type = element_type(items.get(i))?;
+                    if i + 1 != items.len() {
+                        return Err("unexpected token");
+                    }
+                    Table {
+                        element_type,
+                        min,
+                        max,
+                    }
+                } else {
+                    let element_type = element_type(items.get(i))?;
+                    let elem = match list_with_head(items.get(i + 1), "elem") {
+                        Some(e) if i + 2 == items.len() => e,
+                        _ => return Err("unexpected token"),
+                    };
+                    let ct = elem.items.len() - 1;
+                    self.pending
+                        .push(Pending::TableElem(&elem.items[1..], self.table_ct));
+                    Table {
+                        element_type,
+                        min: ct,
+                        max: Some(ct),
+                    }
+                };
+                self.tables.push(table);
+                self.table_ct += 1;
+            }
+            DESC_MEMORY => {
+                Self::add_name(&mut self.memory_names, id, self.memory_ct)?;
+                let memory = match list_with_head(items.get(i), "data") {
+                    Some(d) => {
+                        let data = strings(&d.items[1..])?;
+                        let pages = data.len().div_ceil(65536);
+                        self.pending.push(Pending::MemoryData(data, self.memory_ct));
+                        WasmMemory {
+                            min_pages: pages,
+                            max_pages: Some(pages),
+                        }
+                    }
+                    None => {
+                        let (min_pages, max_pages) = limits(items, &mut i)?;
+                        if i != items.len() {
+                            return Err("unexpected token");
+                        }
+                        WasmMemory {
+                            min_pages,
+                            max_pages,
+                        }
+                    }
+                };
+                self.memories.push(memory);
+                self.memory_ct += 1;
+            }
+            _ => {
+                Self::add_name(&mut self.global_names, id, self.global_ct)?;
+                let (value_type, is_mutable) = global_type(items.get(i))?;
+                self.globals.push(Global {
+                    value_type,
+                    is_mutable,
+                    value_expression: vec![],
+                });
+                self.pending
+                    .push(Pending::Global(items, i + 1, self.globals.len() - 1));
+                self.global_ct += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, pending: Pending<'a>) -> Result<(), &'static str> {
+        match pending {
+            Pending::Func(items, mut i, mut locals) => {
+                let type_index = self.functions[self.code_blocks.len()];
+                let mut local_ct = self.types[type_index].inputs.len();
+                let mut local_types: Vec<ValueType> = vec![];
+                while let Some(l) = list_with_head(items.get(i), "local") {
+                    if l.items.get(1).map(|x| x.is_id()) == Some(true) {
+                        if l.items.len() != 3 {
+                            return Err("unexpected token");
+                        }
+                        let id = atom(l.items.get(1))?.to_string();
+                        if locals.insert(id, local_ct).is_some() {
+                            return Err("duplicate local");
+                        }
+                        local_types.push(value_type(l.items.get(2))?);
+                        local_ct += 1;
+                    } else {
+                        for x in l.items[1..].iter() {
+                            local_types.push(value_type(Some(x))?);
+                            local_ct += 1;
+                        }
+                    }
+                    i += 1;
+                }
+                let mut context = FunctionContext {
+                    locals,
+                    labels: vec![],
+                };
+                let instructions = self.expression(&items[i..], &mut context)?;
+                let mut local_counts: Vec<LocalCount> = vec![];
+                for t in local_types {
+                    match local_counts.last_mut() {
+                        Some(l) if l.value_type == t => l.count += 1,
+                        _ => local_counts.push(LocalCount {
+                            count: 1,
+                            value_type: t,
+                        }),
+                    }
+                }
+                self.code_blocks.push(CodeBlock {
+                    locals: local_counts,
+                    instructions,
+                });
+            }
+            Pending::Global(items, i, index) => {
+                let value_expression =
+                    self.expression(&items[i..], &mut FunctionContext::default())?;
+                self.globals[index].value_expression = value_expression;
+            }
+            Pending::TableElem(items, table) => {
+                let mut functions = vec![];
+                for x in items.iter() {
+                    functions.push(resolve(Some(x), &self.func_names)?.try_into()?);
+                }
+                self.elements.push(WasmElement {
+                    table: table.try_into()?,
+                    value_expression: vec![Instruction::I32Const(0)],
+                    functions,
+                });
+            }
+            Pending::MemoryData(data, memory) => self.data_blocks.push(DataBlock {
+                memory: memory.try_into()?,
+                offset_expression: vec![Instruction::I32Const(0)],
+                data: data.into(),
+            }),
+            Pending::Export(l) => {
+                let export_name = name(l.items.get(1))?;
+                let desc = match l.items.get(2).and_then(|x| x.list()) {
+                    Some(d) if l.items.len() == 3 && d.items.len() == 2 => d,
+                    _ => return Err("unexpected token"),
+                };
+                let (kind, names) = match desc.head() {
+                    Some("func") => (DESC_FUNCTION, &self.func_names),
+                    Some("table") => (DESC_TABLE, &self.table_names),
+                    Some("memory") => (DESC_MEMORY, &self.memory_names),
+                    Some("global") => (DESC_GLOBAL, &self.global_names),
+                    _ => return Err("unexpected token"),
+                };
+                let index = resolve(desc.items.get(1), names)?;
+                self.add_export(kind, export_name, index);
+            }
+            Pending::Start(l) => {
+                if self.start.is_some() {
+                    return Err("multiple start sections");
+                }
+                self.start = Some(resolve(l.items.get(1), &self.func_names)?);
+            }
+            Pending::Elem(l) => {
+                let mut i = 1;
+                let table = match l.items.get(1) {
+                    Some(SExpr::Atom(_)) => {
+                        i += 1;
+                        resolve(l.items.get(1), &self.table_names)?
+                    }
+                    _ => match list_with_head(l.items.get(1), "table") {
+                        Some(t) => {
+                            i += 1;
+                            resolve(t.items.get(1), &self.table_names)?
+                        }
+                        None => 0,
+                    },
+                };
+                let value_expression = self.offset(l.items.get(i))?;
+                i += 1;
+                if l.items.get(i).and_then(|x| x.atom()) == Some("func") {
+                    i += 1;
+                }
+                let mut functions = vec![];
+                for x in l.items[i..].iter() {
+                    functions.push(resolve(Some(x), &self.func_names)?.try_into()?);
+                }
+                self.elements.push(WasmElement {
+                    table: table.try_into()?,
+                    value_expression,
+                    functions,
+                });
+            }
+            Pending::Data(l) => {
+                let mut i = 1;
+                let memory = match l.items.get(1) {
+                    Some(SExpr::Atom(_)) => {
+                        i += 1;
+                        resolve(l.items.get(1), &self.memory_names)?
+                    }
+                    _ => match list_with_head(l.items.get(1), "memory") {
+                        Some(m) => {
+                            i += 1;
+                            resolve(m.items.get(1), &self.memory_names)?
+                        }
+                        None => 0,
+                    },
+                };
+                let offset_expression = self.offset(l.items.get(i))?;
+                let data = strings(&l.items[i + 1..])?;
+                self.data_blocks.push(DataBlock {
+                    memory: memory.try_into()?,
+                    offset_expression,
+                    data: data.into(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn offset(&mut self, e: Option<&SExpr>) -> Result<Vec<Instruction>, &'static str> {
+        let mut context = FunctionContext::default();
+        match (list_with_head(e, "offset"), e) {
+            (Some(o), _) => self.expression(&o.items[1..], &mut context),
+            (None, Some(x @ SExpr::List(_))) => {
+                self.expression(core::slice::from_ref(x), &mut context)
+            }
+            _ => Err("unexpected token"),
+        }
+    }
+
+    fn expression(
+        &mut self,
+        items: &[SExpr],
+        context: &mut FunctionContext,
+    ) -> Result<Vec<Instruction>, &'static str> {
+        let mut instructions = vec![];
+        let (i, stop) = self.sequence(items, 0, context, &mut instructions)?;
+        if stop.is_some() || i != items.len() {
+            return Err("unexpected token");
+        }
+        Ok(instructions)
+    }
+
+    // parses instructions until the items run out or an `end` or `else`
+    // keyword is reached, returning where it stopped
+    fn sequence<'b>(
+        &mut self,
+        items: &'b [SExpr],
+        mut i: usize,
+        context: &mut FunctionContext,
+        out: &mut Vec<Instruction>,
+    ) -> Result<(usize, Option<&'b str>), &'static str> {
+        while i < items.len() {
+            match &items[i] {
+                SExpr::List(l) => {
+                    self.folded(l, context, out)?;
+                    i += 1;
+                }
+                SExpr::Atom(a) if a == "end" || a == "else" => return Ok((i, Some(a))),
+                SExpr::Atom(a) if a == "block" || a == "loop" || a == "if" => {
+                    i = self.plain_block(items, i, context, out)?;
+                }
+                SExpr::Atom(_) => {
+                    let (next, instruction) = self.instruction(items, i, context)?;
+                    out.push(instruction);
+                    i = next;
+                }
+                SExpr::Str(_) => return Err("unexpected token"),
+            }
+        }
+        Ok((i, None))
+    }
+
+    fn block_type(&mut self, items: &[SExpr], i: &mut usize) -> Result<u8, &'static str> {
+        let has_type = matches!(
+            items.get(*i).and_then(|x| x.list()).and_then(|l| l.head()),
+            Some("type") | Some("param") | Some("result")
+        );
+        if !has_type {
+            return Ok(EMPTY);
+        }
+        let t = self.type_use(items, i, None)?;
+        let fn_type = &self.types[t];
+        match (fn_type.inputs.len(), fn_type.outputs.first()) {
+            (0, None) => Ok(EMPTY),
+            (0, Some(v)) if fn_type.outputs.len() == 1 => Ok(v.into_wasm_byte()),
+            _ => Err("multiple values are not supported in block types"),
+        }
+    }
+
+    fn end_label(
+        items: &[SExpr],
+        i: &mut usize,
+        label: &Option<String>,
+    ) -> Result<(), &'static str> {
+        if let Some(id) = take_id(items, i) {
+            if Some(&id) != label.as_ref() {
+                return Err("mismatching label");
+            }
+        }
+        Ok(())
+    }
+
+    fn plain_block(
+        &mut self,
+        items: &[SExpr],
+        mut i: usize,
+        context: &mut FunctionContext,
+        out: &mut Vec<Instruction>,
+    ) -> Result<usize, &'static str> {
+        let keyword = atom(items.get(i))?;
+        i += 1;
+        let label = take_id(items, &mut i);
+        let block_type = self.block_type(items, &mut i)?;
+        context.labels.push(label.clone());
+        let mut body = vec![];
+        let (next, mut stop) = self.sequence(items, i, context, &mut body)?;
+        i = next + 1;
+        let mut else_body = None;
+        if keyword == "if" && stop == Some("else") {
+            Self::end_label(items, &mut i, &label)?;
+            let mut instructions = vec![];
+            let (next, else_stop) = self.sequence(items, i, context, &mut instructions)?;
+            else_body = Some(instructions);
+            i = next + 1;
+            stop = else_stop;
+        }
+        if stop != Some("end") {
+            return Err("unexpected end of block");
+        }
+        Self::end_label(items, &mut i, &label)?;
+        context.labels.pop();
+        out.push(match keyword {
+            "block" => Instruction::Block(block_type, body),
+            "loop" => Instruction::Loop(block_type, body),
+            _ => Instruction::If(block_type, body, else_body),
+        });
+        Ok(i)
+    }
+
+    fn folded(
+        &mut self,
+        l: &List,
+        context: &mut FunctionContext,
+        out: &mut Vec<Instruction>,
+    ) -> Result<(), &'static str> {
+        let items = &l.items;
+        let keyword = match l.head() {
+            Some(k) => k,
+            None => return Err("unexpected token"),
+        };
+        // `(@raw 0x..)` is a byte written as it is
+        if keyword == "@raw" {
+            let b = match items.get(1).and_then(|x| x.atom()).map(parse_u32) {
+                Some(Ok(b)) if items.len() == 2 => b,
+                _ => return Err("unexpected token"),
+            };
+            out.push(Instruction::Raw(
+                u8::try_from(b).map_err(|_| "constant out of range")?,
+            ));
+            return Ok(());
+        }
+        if keyword == "block" || keyword == "loop" || keyword == "if" {
+            let mut i = 1;
+            let label = take_id(items, &mut i);
+            let block_type = self.block_type(items, &mut i)?;
+            if keyword == "if" {
+                while let Some(c) = items.get(i).and_then(|x| x.list()) {
+                    if c.head() == Some("then") {
+                        break;
+                    }
+                    self.folded(c, context, out)?;
+                    i += 1;
+                }
+            }
+            context.labels.push(label);
+            let instruction = if keyword == "if" {
+                let then_body = match list_with_head(items.get(i), "then") {
+                    Some(t) => self.expression(&t.items[1..], context)?,
+                    None => return Err("unexpected token"),
+                };
+                let else_body = match list_with_head(items.get(i + 1), "else") {
+                    Some(e) if i + 2 == items.len() => {
+                        Some(self.expression(&e.items[1..], context)?)
+                    }
+                    None if i + 1 == items.len() => None,
+                    _ => return Err("unexpected token"),
+                };
+                Instruction::If(block_type, then_body, else_body)
+            } else {
+                let body = self.expression(&items[i..], context)?;
+                if keyword == "block" {
+                    Instruction::Block(block_type, body)
+                } else {
+                    Instruction::Loop(block_type, body)
+                }
+            };
+            context.labels.pop();
+            out.push(instruction);
+            return Ok(());
+        }
+        let (i, instruction) = self.instruction(items, 0, context)?;
+        for operand in items[i..].iter() {
+            match operand {
+                SExpr::List(o) => self.folded(o, context, out)?,
+                _ => return Err("unexpected token"),
+            }
+        }
+        out.push(instruction);
+        Ok(())
+    }
+
+    fn label(&self, e: Option<&SExpr>, context: &FunctionContext) -> Result<u32, &'static str> {
+        let a = atom(e)?;
+        if a.starts_with('$') {
+            match context
+                .labels
+                .iter()
+                .rev()
+                .position(|l| l.as_deref() == Some(a))
+            {
+                Some(depth) => Ok(depth as u32),
+                None => Err("unknown label"),
+            }
+        } else {
+            parse_u32(a)
+        }
+    }
+
+    fn memory_instruction(
+        &self,
+        op: u8,
+        items: &[SExpr],
+        mut i: usize,
+    ) -> Result<(usize, Instruction), &'static str> {
+        let (_, default) = wasm_instruction(op, &[0, 0])?;
+        let mut offset = 0;
+        let mut align = natural_alignment(&default);
+        if let Some(o) = items
+            .get(i)
+            .and_then(|x| x.atom())
+            .and_then(|x| x.strip_prefix("offset="))
+        {
+            offset = parse_u32(o)?;
+            i += 1;
+        }
+        if let Some(a) = items
+            .get(i)
+            .and_then(|x| x.atom())
+            .and_then(|x| x.strip_prefix("align="))
+        {
+            let a = parse_u32(a)?;
+            if !a.is_power_of_two() {
+                return Err("alignment must be a power of two");
+            }
+            align = a.trailing_zeros();
+            i += 1;
+        }
+        let mut bytes = align.to_wasm_bytes();
+        bytes.extend(offset.to_wasm_bytes());
+        let (_, instruction) = wasm_instruction(op, &bytes)?;
+        Ok((i, instruction))
+    }
+
+    fn instruction(
+        &mut self,
+        items: &[SExpr],
+        mut i: usize,
+        context: &mut FunctionContext,
+    ) -> Result<(usize, Instruction), &'static str> {
+        let mnemonic = legacy_mnemonic(atom(items.get(i))?);
+        i += 1;
+        let arg = items.get(i);
+        let instruction = match mnemonic {
+            "br" => Instruction::Br(self.label(arg, context)?),
+            "br_if" => Instruction::BrIf(self.label(arg, context)?),
+            "br_table" => {
+                // in a flat body the labels end where the next instruction starts
+                let mut labels = vec![];
+                while let Some(SExpr::Atom(a)) = items.get(i) {
+                    if !a.starts_with('$') && !a.starts_with(|c: char| c.is_ascii_digit()) {
+                        break;
+                    }
+                    labels.push(self.label(items.get(i), context)?);
+                    i += 1;
+                }
+                let default_label = match labels.pop() {
+                    Some(l) => l,
+                    None => return Err("unexpected token"),
+                };
+                return Ok((i, Instruction::BrTable(labels, default_label)));
+            }
+            "call" => Instruction::Call(resolve(arg, &self.func_names)?.try_into()?),
+            "call_indirect" => {
+                if let Some(SExpr::Atom(_)) = arg {
+                    if resolve(arg, &self.table_names)? != 0 {
+                        return Err("unknown table");
+                    }
+                    i += 1;
+                }
+                let t = self.type_use(items, &mut i, None)?;
+                return Ok((i, Instruction::CallIndirect(t.try_into()?)));
+            }
+            "local.get" => Instruction::LocalGet(resolve(arg, &context.locals)?.try_into()?),
+            "local.set" => Instruction::LocalSet(resolve(arg, &context.locals)?.try_into()?),
+            "local.tee" => Instruction::LocalTee(resolve(arg, &context.locals)?.try_into()?),
+            "global.get" => Instruction::GlobalGet(resolve(arg, &self.global_names)?.try_into()?),
+            "global.set" => Instruction::GlobalSet(resolve(arg, &self.global_names)?.try_into()?),
+            "i32.const" => Instruction::I32Const(parse_int(atom(arg)?, 32)? as i32),
+            "i64.const" => Instruction::I64Const(parse_int(atom(arg)?, 64)? as i64),
+            "f32.const" => {
+                Instruction::F32Const(f32::from_bits(parse_float(atom(arg)?, 23, 8)? as u32))
+            }
+            "f64.const" => Instruction::F64Const(f64::from_bits(parse_float(atom(arg)?, 52, 11)?)),
+            "select" => {
+                if let Some(l) = list_with_head(arg, "result") {
+                    if l.items.len() != 2 {
+                        return Err("invalid result arity");
+                    }
+                    i += 1;
+                }
+                return Ok((i, Instruction::Select));
+            }
+            x if x.contains(".load") || x.contains(".store") => {
+                return match self.opcodes.get(x) {
+                    Some(op) => self.memory_instruction(*op, items, i),
+                    None => Err("unknown operator"),
+                };
+            }
+            x => {
+                return match self.opcodes.get(x) {
+                    Some(op) if !["block", "loop", "if", "else", "end"].contains(&x) => {
+                        let (_, instruction) = wasm_instruction(*op, &[0])?;
+                        Ok((i, instruction))
+                    }
+                    _ => Err("unknown operator"),
+                };
+            }
+        };
+        Ok((i + 1, instruction))
+    }
+
+    fn build(self) -> Result<Program, &'static str> {
+        let mut sections = vec![];
+        if !self.types.is_empty() {
+            sections.push(Section::Type(TypeSection { types: self.types }));
+        }
+        if !self.imports.is_empty() {
+            sections.push(Section::Import(ImportSection {
+                imports: self.imports,
+            }));
+        }
+        if !self.functions.is_empty() {
+            sections.push(Section::Function(FunctionSection {
+                function_types: self
+                    .functions
+                    .into_iter()
+                    .map(TypeIdx::try_from)
+                    .collect::<Result<_, _>>()?,
+            }));
+        }
+        if !self.tables.is_empty() {
+            sections.push(Section::Table(TableSection {
+                tables: self.tables,
+            }));
+        }
+        if !self.memories.is_empty() {
+            sections.push(Section::Memory(MemorySection {
+                memories: self.memories,
+            }));
+        }
+        if !self.globals.is_empty() {
+            sections.push(Section::Global(GlobalSection {
+                globals: self.globals,
+            }));
+        }
+        if !self.exports.is_empty() {
+            sections.push(Section::Export(ExportSection {
+                exports: self.exports,
+            }));
+        }
+        if let Some(start_function) = self.start {
+            sections.push(Section::Start(StartSection {
+                start_function: start_function.try_into()?,
+            }));
+        }
+        if !self.elements.is_empty() {
+            sections.push(Section::Element(ElementSection {
+                elements: self.elements,
+            }));
+        }
+        if !self.code_blocks.is_empty() {
+            sections.push(Section::Code(CodeSection {
+                code_blocks: self.code_blocks,
+            }));
+        }
+        if !self.data_blocks.is_empty() {
+            sections.push(Section::Data(DataSection {
+                data_blocks: self.data_blocks,
+            }));
+        }
+        sections.extend(self.customs.into_iter().map(Section::Custom));
+        Ok(Program { sections })
+    }
+}
+
+pub fn wat_module_fields(fields: &[SExpr]) -> Result<Program, &'static str> {
+    let mut builder = ModuleBuilder::new();
+    let mut lists = vec![];
+    for f in fields.iter() {
+        match f.list() {
+            Some(l) => lists.push(l),
+            None => return Err("unexpected token"),
+        }
+    }
+    for l in lists.iter().filter(|l| l.head() == Some("type")) {
+        builder.type_field(l)?;
+    }
+    for l in lists.iter() {
+        builder.field(l)?;
+    }
+    for p in core::mem::take(&mut builder.pending) {
+        builder.finish(p)?;
+    }
+    builder.build()
+}
+
+// parses `(module $id? field*)`, `(module $id? binary "..."*)` and
+// `(module $id? quote "..."*)`
+pub fn wat_module_list(l: &List) -> Result<Program, &'static str> {
+    if l.head() != Some("module") {
+        return Err("expected a module");
+    }
+    let mut i = 1;
+    take_id(&l.items, &mut i);
+    match l.items.get(i).and_then(|x| x.atom()) {
+        Some("binary") => {
+            let bytes = strings(&l.items[i + 1..])?;
+            Ok(wasm_module(&bytes)?.into_owned())
+        }
+        Some("quote") => {
+            let text = strings(&l.items[i + 1..])?;
+            wat_module(&text)
+        }
+        _ => wat_module_fields(&l.items[i..]),
+    }
+}
+
+pub fn wat_module(input: &[u8]) -> Result<Program, &'static str> {
+    let exprs = sexpr_file(input)?;
+    match exprs.first().and_then(|x| x.list()) {
+        Some(l) if l.head() == Some("module") => {
+            if exprs.len() != 1 {
+                return Err("unexpected token");
+            }
+            wat_module_list(l)
+        }
+        _ => wat_module_fields(&exprs),
+    }
+}
@@ -0,0 +1,256 @@
+use crate::compiler::{code_block_bytes, data_block_bytes, section_bytes};
+use crate::core::*;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use webassembly::TypeWasmExt;
+
+/// What a part of the compiled module is, section overhead is whatever of a section is not
+/// attributed to one of its functions or data segments.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub enum SizeItem {
+    Header,
+    Section(String),
+    Function(FuncIdx),
+    Data(DataIdx),
+    Custom(String),
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub struct SizeEntry {
+    pub item: SizeItem,
+    pub name: Option<String>,
+    pub size: usize,
+}
+
+/// The bytes that would go away with an export, the export entry itself and the functions
+/// nothing else reaches.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub struct ExportSize {
+    pub name: String,
+    pub retained: usize,
+}
+
+/// Every byte of the compiled program attributed to one entry, largest entries first.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub struct SizeProfile {
+    pub total: usize,
+    pub entries: Vec<SizeEntry>,
+    pub exports: Vec<ExportSize>,
+}
+
+fn section_name(s: &Section) -> &'static str {
+    match s {
+        Section::Custom(_) => "custom",
+        Section::Type(_) => "type",
+        Section::Import(_) => "import",
+        Section::Function(_) => "function",
+        Section::Table(_) => "table",
+        Section::Memory(_) => "memory",
+        Section::Global(_) => "global",
+        Section::Export(_) => "export",
+        Section::Start(_) => "start",
+        Section::Element(_) => "element",
+        Section::Code(_) => "code",
+        Section::Data(_) => "data",
+    }
+}
+
+// a field with its length in front, like the body of a function
+fn sized(len: usize) -> usize {
+    len.to_wasm_bytes().len() + len
+}
+
+fn export_entry_size(e: &WasmExport) -> usize {
+    let (name, index) = match e {
+        WasmExport::Function(x) => (&x.name, x.index.0),
+        WasmExport::Global(x) => (&x.name, x.index.0),
+        WasmExport::Memory(x) => (&x.name, x.index.0),
+        WasmExport::Table(x) => (&x.name, x.index.0),
+    };
+    sized(name.len()) + 1 + index.to_wasm_bytes().len()
+}
+
+impl Module<'_> {
+    /// Attributes every byte the compiler would write for this program to the header, a
+    /// section, a function, a data segment or a custom section.
+    pub fn size_profile(&self) -> SizeProfile {
+        let imported = self.imported_function_count();
+        let names = self.name_section();
+        let mut sections: Vec<&Section> = self.sections.iter().collect();
+        sections.sort_by_key(|a| a.id());
+
+        let mut entries = vec![SizeEntry {
+            item: SizeItem::Header,
+            name: None,
+            size: 8,
+        }];
+        let mut function_sizes = vec![0; imported];
+        let mut exports: Vec<&WasmExport> = Vec::new();
+        let mut roots: Vec<FuncIdx> = Vec::new();
+        for s in sections.into_iter() {
+            let (_, data) = section_bytes(s);
+            let size = 1 + sized(data.len());
+            let mut attributed = 0;
+            match s {
+                Section::Code(c) => {
+                    for (i, b) in c.code_blocks.iter().enumerate() {
+                        let index = FuncIdx::at(imported + i);
+                        let body = sized(code_block_bytes(b).len());
+                        attributed += body;
+                        function_sizes.push(body);
+                        entries.push(SizeEntry {
+                            item: SizeItem::Function(index),
+                            name: None,
+                            size: body,
+                        });
+                    }
+                }
+                Section::Data(d) => {
+                    for (i, b) in d.data_blocks.iter().enumerate() {
+                        let segment = data_block_bytes(b).len();
+                        attributed += segment;
+                        entries.push(SizeEntry {
+                            item: SizeItem::Data(DataIdx::at(i)),
+                            name: None,
+                            size: segment,
+                        });
+                    }
+                }
+                Section::Custom(c) => {
+                    entries.push(SizeEntry {
+                        item: SizeItem::Custom(c.name.to_string()),
+                        name: None,
+                        size,
+                    });
+                    continue;
+                }
+                Section::Export(e) => {
+                    for x in e.exports.iter() {
+                        exports.push(x);
+                        if let WasmExport::Function(f) = x {
+                            roots.push(f.index);
+                        }
+                    }
+                }
+                Section::Start(x) => roots.push(x.start_function),
+                Section::Element(e) => {
+                    for x in e.elements.iter() {
+                        roots.extend(x.functions.iter().copied());
+                    }
+                }
+                _ => {}
+            }
+            entries.push(SizeEntry {
+                item: SizeItem::Section(section_name(s).to_string()),
+                name: None,
+                size: size - attributed,
+            });
+        }
+
+        for e in entries.iter_mut() {
+            if let SizeItem::Function(index) = e.item {
+                let exported = exports.iter().find_map(|x| match x {
+                    WasmExport::Function(f) if f.index == index => Some(f.name.to_string()),
+                    _ => None,
+                });
+                let named = names.as_ref().and_then(|n| {
+                    n.function_names
+                        .iter()
+                        .find(|(i, _)| *i == index)
+                        .map(|(_, name)| name.clone())
+                });
+                e.name = exported.or(named);
+            }
+        }
+
+        // indirect calls can only reach functions in element segments, which are roots already
+        let calls = self.call_graph();
+        let everything = calls.reachable_from(&roots);
+        let mut export_sizes = Vec::new();
+        for x in exports.iter() {
+            let mut retained = export_entry_size(x);
+            if let WasmExport::Function(f) = x {
+                // the same function can be a root more than once
+                let mut others = roots.clone();
+                let position = others.iter().position(|r| *r == f.index).unwrap();
+                others.remove(position);
+                let without = calls.reachable_from(&others);
+                for (i, size) in function_sizes.iter().enumerate() {
+                    if everything[i] && !without[i] {
+                        retained += size;
+                    }
+                }
+            }
+            let name = match x {
+                WasmExport::Function(x) => x.name.to_string(),
+                WasmExport::Global(x) => x.name.to_string(),
+                WasmExport::Memory(x) => x.name.to_string(),
+                WasmExport::Table(x) => x.name.to_string(),
+            };
+            export_sizes.push(ExportSize { name, retained });
+        }
+
+        entries.sort_by_key(|e| Reverse(e.size));
+        export_sizes.sort_by_key(|e| Reverse(e.retained));
+        SizeProfile {
+            total: entries.iter().map(|e| e.size).sum(),
+            entries,
+            exports: export_sizes,
+        }
+    }
+}
+
+impl fmt::Display for SizeProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let share = |size: usize| {
+            if self.total == 0 {
+                0.0
+            } else {
+                size as f64 * 100.0 / self.total as f64
+            }
+        };
+        writeln!(f, "{:>10} {:>7}  item", "bytes", "share")?;
+        for e in self.entries.iter() {
+            let item = match &e.item {
+                SizeItem::Header => "header".to_string(),
+                SizeItem::Section(s) => format!("{} section", s),
+                SizeItem::Function(i) => format!("function {}", i),
+                SizeItem::Data(i) => format!("data {}", i),
+                SizeItem::Custom(s) => format!("custom section {:?}", s),
+            };
+            match &e.name {
+                Some(name) => writeln!(
+                    f,
+                    "{:>10} {:>6.2}%  {} {}",
+                    e.size,
+                    share(e.size),
+                    item,
+                    name
+                )?,
+                None => writeln!(f, "{:>10} {:>6.2}%  {}", e.size, share(e.size), item)?,
+            }
+        }
+        writeln!(f, "{:>10} {:>6.2}%  total", self.total, 100.0)?;
+        if !self.exports.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "{:>10} {:>7}  export", "retained", "share")?;
+            for e in self.exports.iter() {
+                writeln!(
+                    f,
+                    "{:>10} {:>6.2}%  {}",
+                    e.retained,
+                    share(e.retained),
+                    e.name
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
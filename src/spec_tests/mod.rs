@@ -0,0 +1,60 @@
+// generated by generate_spec_tests.py, do not edit
+mod runner;
+
+use runner::run_spec_test;
+
+#[test]
+fn regression_calls() {
+    run_spec_test(
+        "regression/calls.wast",
+        include_bytes!("../../spec/regression/calls.wast"),
+    );
+}
+
+#[test]
+fn regression_control() {
+    run_spec_test(
+        "regression/control.wast",
+        include_bytes!("../../spec/regression/control.wast"),
+    );
+}
+
+#[test]
+fn regression_globals() {
+    run_spec_test(
+        "regression/globals.wast",
+        include_bytes!("../../spec/regression/globals.wast"),
+    );
+}
+
+#[test]
+fn regression_interpreter() {
+    run_spec_test(
+        "regression/interpreter.wast",
+        include_bytes!("../../spec/regression/interpreter.wast"),
+    );
+}
+
+#[test]
+fn regression_memory() {
+    run_spec_test(
+        "regression/memory.wast",
+        include_bytes!("../../spec/regression/memory.wast"),
+    );
+}
+
+#[test]
+fn regression_tables() {
+    run_spec_test(
+        "regression/tables.wast",
+        include_bytes!("../../spec/regression/tables.wast"),
+    );
+}
+
+#[test]
+fn regression_validation() {
+    run_spec_test(
+        "regression/validation.wast",
+        include_bytes!("../../spec/regression/validation.wast"),
+    );
+}
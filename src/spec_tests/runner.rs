@@ -0,0 +1,44 @@
+use crate::run_wast;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const KNOWN_FAILURES: &str = include_str!("../../spec/known_failures.txt");
+
+fn known_failures() -> Vec<&'static str> {
+    KNOWN_FAILURES
+        .lines()
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .collect()
+}
+
+pub fn run_spec_test(script: &str, input: &[u8]) {
+    let known = known_failures();
+    if known.contains(&script) {
+        return;
+    }
+    let report = match run_wast(input) {
+        Ok(r) => r,
+        Err(e) => panic!("{} could not be parsed: {}", script, e),
+    };
+    let mut unexpected: Vec<String> = vec![];
+    for r in report.results.iter() {
+        let entry = format!("{}:{}", script, r.line);
+        let is_known = known.contains(&entry.as_str());
+        if !r.passed && !is_known {
+            let message = r.message.as_deref().unwrap_or("failed");
+            unexpected.push(format!("{} {}", entry, message));
+        } else if r.passed && is_known {
+            // the list stays exact, an entry that passes has to be removed
+            unexpected.push(format!(
+                "{} passes now, remove it from spec/known_failures.txt",
+                entry
+            ));
+        }
+    }
+    assert!(
+        unexpected.is_empty(),
+        "unexpected results:\n{}",
+        unexpected.join("\n")
+    );
+}
@@ -0,0 +1,220 @@
+use crate::*;
+use alloc::vec;
+use alloc::vec::Vec;
+
+fn body(p: &Program, index: FuncIdx) -> Vec<Instruction> {
+    match p.function(index).unwrap() {
+        FunctionRef::Defined { body, .. } => body.to_vec(),
+        _ => panic!("imported"),
+    }
+}
+
+#[test]
+fn labels_become_depths() {
+    let mut p = Program::new();
+    let mut f = p.function_builder(&[ValueType::I32], &[]);
+    let x = f.param(0).unwrap();
+    f.block(None, |f, exit| {
+        f.loop_(None, |f, top| {
+            f.emit(Instruction::LocalGet(x))?;
+            f.br_if(exit)?;
+            f.emit(Instruction::LocalGet(x))?;
+            f.if_else(
+                None,
+                |f, _| {
+                    f.br(top)?;
+                    Ok(())
+                },
+                |f, arm| {
+                    f.emit(Instruction::LocalGet(x))?;
+                    f.br_if(arm)?;
+                    f.br(exit)?;
+                    Ok(())
+                },
+            )?;
+            Ok(())
+        })?;
+        Ok(())
+    })
+    .unwrap();
+    let index = p.add_function(f).unwrap();
+    assert_eq!(
+        body(&p, index),
+        vec![Instruction::Block(
+            0x40,
+            vec![Instruction::Loop(
+                0x40,
+                vec![
+                    Instruction::LocalGet(x),
+                    Instruction::BrIf(1),
+                    Instruction::LocalGet(x),
+                    Instruction::If(
+                        0x40,
+                        vec![Instruction::Br(1)],
+                        Some(vec![
+                            Instruction::LocalGet(x),
+                            Instruction::BrIf(0),
+                            Instruction::Br(2),
+                        ]),
+                    ),
+                ],
+            )],
+        )]
+    );
+    assert!(p.validate().is_ok());
+}
+
+#[test]
+fn labels_out_of_scope_are_refused() {
+    let p = Program::new();
+    let mut f = p.function_builder(&[], &[]);
+    let mut kept = None;
+    f.block(None, |_, l| {
+        kept = Some(l);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(f.br(kept.unwrap()).err(), Some("label is not in scope"));
+    assert_eq!(
+        f.emit(Instruction::Br(1)).err(),
+        Some("branch depth is out of range")
+    );
+}
+
+#[test]
+fn br_table_labels_need_the_same_arity() {
+    let p = Program::new();
+    let mut f = p.function_builder(&[], &[ValueType::I32]);
+    let result = f.block(Some(ValueType::I32), |f, with_value| {
+        f.block(None, |f, without| {
+            f.emit_all(&[Instruction::I32Const(1), Instruction::I32Const(0)])?;
+            f.br_table(&[without], with_value)?;
+            Ok(())
+        })?;
+        Ok(())
+    });
+    assert_eq!(
+        result.err(),
+        Some("branch table labels have different arities")
+    );
+
+    let mut p = Program::new();
+    let mut f = p.function_builder(&[], &[ValueType::I32]);
+    f.block(Some(ValueType::I32), |f, a| {
+        f.block(Some(ValueType::I32), |f, b| {
+            f.emit_all(&[Instruction::I32Const(1), Instruction::I32Const(0)])?;
+            f.br_table(&[b, a], a)?;
+            Ok(())
+        })?;
+        Ok(())
+    })
+    .unwrap();
+    let index = p.add_function(f).unwrap();
+    assert!(matches!(
+        &body(&p, index)[0],
+        Instruction::Block(_, b) if matches!(&b[0], Instruction::Block(_, b) if b[2] == Instruction::BrTable(vec![0, 1], 1))
+    ));
+    assert!(p.validate().is_ok());
+}
+
+#[test]
+fn blocks_and_functions_leave_their_results() {
+    let p = Program::new();
+    let mut f = p.function_builder(&[], &[]);
+    let result = f.block(Some(ValueType::I32), |_, _| Ok(()));
+    assert_eq!(
+        result.err(),
+        Some("block does not leave its results on the stack")
+    );
+
+    let mut f = p.function_builder(&[], &[ValueType::I32]);
+    f.emit_all(&[Instruction::I32Const(1), Instruction::I32Const(2)])
+        .unwrap();
+    assert_eq!(
+        f.finish().err(),
+        Some("function body does not leave its results on the stack")
+    );
+    let f = p.function_builder(&[], &[ValueType::I32]);
+    assert_eq!(
+        f.finish().err(),
+        Some("function body does not leave its results on the stack")
+    );
+    // after unreachable the results do not have to be there
+    let mut f = p.function_builder(&[], &[ValueType::I32]);
+    f.emit(Instruction::Unreachable).unwrap();
+    assert!(f.finish().is_ok());
+    // nor do operands
+    let mut f = p.function_builder(&[], &[]);
+    f.emit(Instruction::Return).unwrap();
+    assert!(f.emit(Instruction::Drop).is_ok());
+    let mut f = p.function_builder(&[], &[]);
+    assert_eq!(
+        f.emit(Instruction::Drop).err(),
+        Some("not enough operands on the stack")
+    );
+}
+
+#[test]
+fn locals_and_calls_are_checked() {
+    let mut p =
+        parse_wat(b"(module (import \"env\" \"f\" (func (param i32) (result i64))))").unwrap();
+    let mut f = p.function_builder(&[ValueType::I32], &[ValueType::I64]);
+    assert_eq!(f.index(), FuncIdx(1));
+    assert!(f.param(1).is_err());
+    let a = f.local(ValueType::F32);
+    let b = f.local(ValueType::F32);
+    let c = f.local(ValueType::I64);
+    assert_eq!((a, b, c), (LocalIdx(1), LocalIdx(2), LocalIdx(3)));
+    assert_eq!(
+        f.emit(Instruction::LocalGet(LocalIdx(4))).err(),
+        Some("local does not exist with that index")
+    );
+    assert_eq!(
+        f.emit(Instruction::Call(FuncIdx(2))).err(),
+        Some("function does not exist with that index")
+    );
+    // the function can call itself
+    let me = f.index();
+    f.emit_all(&[
+        Instruction::LocalGet(LocalIdx(0)),
+        Instruction::Call(me),
+        Instruction::Drop,
+        Instruction::LocalGet(LocalIdx(0)),
+        Instruction::Call(FuncIdx(0)),
+    ])
+    .unwrap();
+    assert_eq!(p.add_exported_function("g", f), Ok(FuncIdx(1)));
+    assert!(p.find_exported_function("g").is_ok());
+    let locals = match p.function(FuncIdx(1)).unwrap() {
+        FunctionRef::Defined { locals, .. } => locals.to_vec(),
+        _ => panic!("imported"),
+    };
+    assert_eq!(
+        locals,
+        vec![
+            LocalCount {
+                count: 2,
+                value_type: ValueType::F32
+            },
+            LocalCount {
+                count: 1,
+                value_type: ValueType::I64
+            },
+        ]
+    );
+    assert!(p.validate().is_ok());
+}
+
+#[test]
+fn a_builder_is_stale_once_functions_are_added() {
+    let mut p = Program::new();
+    let first = p.function_builder(&[], &[]);
+    let second = p.function_builder(&[], &[]);
+    assert_eq!(first.index(), second.index());
+    assert_eq!(p.add_function(first), Ok(FuncIdx(0)));
+    assert_eq!(
+        p.add_function(second).err(),
+        Some("functions were added while the function was being built")
+    );
+    assert_eq!(p.functions().count(), 1);
+}
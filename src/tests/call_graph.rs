@@ -0,0 +1,133 @@
+use crate::*;
+use alloc::vec;
+
+const CALLS: &[u8] = br#"(module
+  (type $unary (func (param i32) (result i32)))
+  (type $nullary (func))
+  (import "env" "log" (func $log (param i32)))
+  (table 3 funcref)
+  (func $double (type $unary) (i32.mul (local.get 0) (i32.const 2)))
+  (func $negate (type $unary) (i32.sub (i32.const 0) (local.get 0)))
+  (func $tick (type $nullary))
+  (func $apply (param i32 i32) (result i32)
+    (call_indirect (type $unary) (local.get 1) (local.get 0)))
+  (func $even (param i32) (result i32)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (i32.const 1))
+      (else (call $odd (i32.sub (local.get 0) (i32.const 1))))))
+  (func $odd (param i32) (result i32)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (i32.const 0))
+      (else (call $even (i32.sub (local.get 0) (i32.const 1))))))
+  (func $loop (call $loop))
+  (func $main (export "main")
+    (call $log (call $apply (i32.const 0) (i32.const 5)))
+    (call $log (call $even (i32.const 4)))
+    (call $log (call $even (i32.const 3))))
+  (func $unused (call $double (i32.const 1)) drop)
+  (elem (i32.const 0) $double $negate $tick))"#;
+
+#[test]
+fn callers_and_callees() {
+    let g = parse_wat(CALLS).unwrap().call_graph();
+    assert_eq!(g.function_count(), 10);
+    assert_eq!(g.callees(FuncIdx(8)), [FuncIdx(0), FuncIdx(4), FuncIdx(5)]);
+    assert_eq!(g.callers(FuncIdx(0)), [FuncIdx(8)]);
+    assert_eq!(g.callers(FuncIdx(5)), [FuncIdx(6), FuncIdx(8)]);
+    assert_eq!(g.callees(FuncIdx(0)), []);
+    assert!(g.is_imported(FuncIdx(0)));
+    assert!(!g.is_imported(FuncIdx(1)));
+    // a body calling the same function more than once has one edge
+    assert_eq!(
+        g.edges().iter().filter(|e| e.caller == FuncIdx(8)).count(),
+        3
+    );
+    assert_eq!(g.name(FuncIdx(8)), Some("main"));
+    assert_eq!(g.roots(), [FuncIdx(8)]);
+    let reachable = g.reachable();
+    assert_eq!(
+        reachable,
+        vec![true, true, true, false, true, true, true, false, true, false]
+    );
+}
+
+#[test]
+fn indirect_calls_reach_table_functions_of_their_signature() {
+    let g = parse_wat(CALLS).unwrap().call_graph();
+    assert_eq!(g.callees(FuncIdx(4)), [FuncIdx(1), FuncIdx(2)]);
+    let indirect: alloc::vec::Vec<_> = g
+        .edges()
+        .iter()
+        .filter(|e| e.kind == CallKind::Indirect)
+        .map(|e| (e.caller, e.callee))
+        .collect();
+    // $tick is in the table too but has another signature
+    assert_eq!(
+        indirect,
+        [(FuncIdx(4), FuncIdx(1)), (FuncIdx(4), FuncIdx(2))]
+    );
+    // a direct call to a table function stays direct
+    assert!(g.edges().contains(&CallEdge {
+        caller: FuncIdx(9),
+        callee: FuncIdx(1),
+        kind: CallKind::Direct,
+    }));
+
+    // an exported table makes every function in it a root
+    let g = parse_wat(
+        br#"(module
+          (table (export "t") 1 funcref)
+          (func $f)
+          (elem (i32.const 0) $f))"#,
+    )
+    .unwrap()
+    .call_graph();
+    assert_eq!(g.roots(), [FuncIdx(0)]);
+}
+
+#[test]
+fn components_put_callees_first() {
+    let g = parse_wat(CALLS).unwrap().call_graph();
+    let components = g.strongly_connected_components();
+    assert_eq!(components.iter().map(|c| c.len()).sum::<usize>(), 10);
+    let position = |f: u32| {
+        components
+            .iter()
+            .position(|c| c.contains(&FuncIdx(f)))
+            .unwrap()
+    };
+    assert!(components.contains(&vec![FuncIdx(5), FuncIdx(6)]));
+    assert!(position(0) < position(5));
+    assert!(position(5) < position(8));
+    assert!(position(4) < position(8));
+    assert!(position(1) < position(4));
+    // mutual and self recursion, not functions that are merely alone
+    assert_eq!(
+        g.recursive_components(),
+        vec![vec![FuncIdx(5), FuncIdx(6)], vec![FuncIdx(7)]]
+    );
+}
+
+#[test]
+fn dot_output() {
+    let mut p = parse_wat(
+        br#"(module
+          (import "env" "log" (func))
+          (table 1 funcref)
+          (func (export "say \"hi\"") (call 0) (call_indirect (i32.const 0)))
+          (func $target)
+          (elem (i32.const 0) $target))"#,
+    )
+    .unwrap();
+    let expected = r#"digraph calls {
+  f0 [label="0", shape=box];
+  f1 [label="1 say \"hi\"", peripheries=2];
+  f2 [label="2"];
+  f1 -> f0;
+  f1 -> f2 [style=dashed];
+}
+"#;
+    assert_eq!(p.call_graph().to_dot(), expected);
+    p.sections.clear();
+    assert_eq!(p.call_graph().to_dot(), "digraph calls {\n}\n");
+}
@@ -0,0 +1,146 @@
+use crate::*;
+use alloc::vec;
+
+#[test]
+fn built_in_different_orders_compile_to_the_same_bytes() {
+    let mut a = Program::new();
+    a.create_global(Some("g"), ValueType::I32, true, &[Instruction::I32Const(1)])
+        .unwrap();
+    a.create_memory("memory", 1, None).unwrap();
+    a.create_export("f", &[ValueType::I32], &[ValueType::I32])
+        .unwrap()
+        .0
+        .instructions = vec![Instruction::LocalGet(LocalIdx(0))];
+    a.create_function(&[], &[]).unwrap();
+
+    let mut b = Program::new();
+    b.create_export("f", &[ValueType::I32], &[ValueType::I32])
+        .unwrap()
+        .0
+        .instructions = vec![Instruction::LocalGet(LocalIdx(0))];
+    b.create_function(&[], &[]).unwrap();
+    b.create_memory("memory", 1, None).unwrap();
+    b.create_global(Some("g"), ValueType::I32, true, &[Instruction::I32Const(1)])
+        .unwrap();
+
+    assert_ne!(a.compile().unwrap(), b.compile().unwrap());
+    a.canonicalize().unwrap();
+    b.canonicalize().unwrap();
+    assert_eq!(a.compile().unwrap(), b.compile().unwrap());
+}
+
+#[test]
+fn types_exports_and_locals_in_different_orders_compile_to_the_same_bytes() {
+    let mut a = parse_wat(
+        br#"(module
+          (type (func (param i32)))
+          (type (func))
+          (func (type 1) (local i32 i64))
+          (func (type 0))
+          (export "a" (func 0))
+          (export "b" (func 1)))"#,
+    )
+    .unwrap();
+    let mut b = parse_wat(
+        br#"(module
+          (type (func (param f64)))
+          (type (func))
+          (type (func (param i32)))
+          (type (func))
+          (func (type 1) (local i32) (local i64))
+          (func (type 2))
+          (export "b" (func 1))
+          (export "a" (func 0)))"#,
+    )
+    .unwrap();
+    // the exports end up in a section of their own after the code
+    let exports = b
+        .sections
+        .iter()
+        .position(|s| matches!(s, Section::Export(_)))
+        .unwrap();
+    let exports = b.sections.remove(exports);
+    b.sections.push(exports);
+
+    a.canonicalize().unwrap();
+    b.canonicalize().unwrap();
+    assert_eq!(a.compile().unwrap(), b.compile().unwrap());
+    let types = a.sections.iter().find_map(|s| match s {
+        Section::Type(t) => Some(t.types.len()),
+        _ => None,
+    });
+    assert_eq!(types, Some(2));
+}
+
+#[test]
+fn conflicting_start_functions_leave_the_module_unchanged() {
+    let mut p = parse_wat(
+        br#"(module
+          (type (func))
+          (type (func))
+          (func (type 1))
+          (func (type 1))
+          (start 0))"#,
+    )
+    .unwrap();
+    p.sections.push(Section::Start(StartSection {
+        start_function: FuncIdx(1),
+    }));
+    let before = p.clone();
+    assert_eq!(p.canonicalize(), Err("conflicting start functions"));
+    assert_eq!(p, before);
+
+    // the same start function twice is one start section
+    p.sections.pop();
+    p.sections.push(Section::Start(StartSection {
+        start_function: FuncIdx(0),
+    }));
+    p.canonicalize().unwrap();
+    assert_eq!(
+        p.sections
+            .iter()
+            .filter(|s| matches!(s, Section::Start(_)))
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn a_missing_type_leaves_the_module_unchanged() {
+    let mut p = parse_wat(
+        br#"(module
+          (type (func))
+          (type (func))
+          (func (type 1))
+          (export "f" (func 0)))"#,
+    )
+    .unwrap();
+    p.sections.push(Section::Function(FunctionSection {
+        function_types: vec![TypeIdx(5)],
+    }));
+    let before = p.clone();
+    assert_eq!(p.canonicalize(), Err("type does not exist with that index"));
+    assert_eq!(p, before);
+}
+
+#[test]
+fn too_many_locals_leave_the_module_unchanged() {
+    let mut p = parse_wat(b"(module (func))").unwrap();
+    for s in p.sections.iter_mut() {
+        if let Section::Code(c) = s {
+            c.code_blocks[0].locals = vec![
+                LocalCount {
+                    count: u32::MAX,
+                    value_type: ValueType::I32,
+                },
+                LocalCount {
+                    count: 1,
+                    value_type: ValueType::I32,
+                },
+            ];
+        }
+    }
+    let before = p.clone();
+    assert_eq!(p.canonicalize(), Err("too many locals"));
+    assert_eq!(p, before);
+}
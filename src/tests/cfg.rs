@@ -0,0 +1,161 @@
+use crate::*;
+use alloc::vec;
+use alloc::vec::Vec;
+
+fn cfg_of(wat: &[u8]) -> (Program, FuncIdx) {
+    (parse_wat(wat).unwrap(), FuncIdx(0))
+}
+
+fn successors(cfg: &Cfg) -> Vec<Vec<usize>> {
+    cfg.blocks.iter().map(|b| b.successors.clone()).collect()
+}
+
+#[test]
+fn structured_instructions_split_blocks() {
+    let (p, f) = cfg_of(
+        br#"(module (func (param i32)
+          (local.get 0)
+          (if (then (nop)) (else (nop)))
+          (nop)))"#,
+    );
+    let cfg = p.cfg(f).unwrap();
+    assert_eq!(
+        successors(&cfg),
+        vec![vec![2, 4], vec![], vec![3], vec![1], vec![3]]
+    );
+    // the if ends the entry block, the arms and what follows are blocks of their own
+    assert_eq!(cfg.blocks[0].instructions.len(), 2);
+    assert_eq!(cfg.blocks[3].instructions, [&Instruction::Nop]);
+    assert_eq!(cfg.blocks[3].predecessors, [2, 4]);
+    assert_eq!(cfg.entry(), 0);
+    assert_eq!(cfg.exit(), 1);
+    assert_eq!(cfg.reverse_postorder()[0], 0);
+}
+
+const LABELS: &[u8] = br#"(module (func (param i32)
+  (block $a
+    (block $b
+      (br_if $a (local.get 0))
+      (br_table $b $a 2 (local.get 0)))
+    (return))
+  (nop)))"#;
+
+#[test]
+fn branches_go_to_their_label() {
+    let (p, f) = cfg_of(LABELS);
+    let cfg = p.cfg(f).unwrap();
+    // br_if to the end of $a, br_table to the end of $b, of $a and out of the function,
+    // return to the exit
+    assert_eq!(
+        successors(&cfg),
+        vec![
+            vec![2],
+            vec![],
+            vec![4],
+            vec![1],
+            vec![3, 6],
+            vec![1],
+            vec![5, 3, 1],
+        ]
+    );
+}
+
+#[test]
+fn dominators() {
+    let (p, f) = cfg_of(LABELS);
+    let cfg = p.cfg(f).unwrap();
+    assert_eq!(cfg.immediate_dominator(0), None);
+    assert_eq!(cfg.immediate_dominator(3), Some(4));
+    assert_eq!(cfg.immediate_dominator(5), Some(6));
+    assert_eq!(cfg.immediate_dominator(1), Some(4));
+    assert!(cfg.dominates(4, 1));
+    assert!(!cfg.dominates(6, 1));
+    assert!(cfg.dominates(5, 5));
+    assert_eq!(
+        cfg.dominator_tree(),
+        vec![
+            vec![2],
+            vec![],
+            vec![4],
+            vec![],
+            vec![1, 3, 6],
+            vec![],
+            vec![5]
+        ]
+    );
+
+    // code after a branch is not reachable and dominated by nothing
+    let (p, f) = cfg_of(b"(module (func (block (br 0) (nop))))");
+    let cfg = p.cfg(f).unwrap();
+    let dead = cfg.blocks.len() - 1;
+    assert_eq!(cfg.blocks[dead].instructions, [&Instruction::Nop]);
+    assert!(cfg.blocks[dead].predecessors.is_empty());
+    assert!(!cfg.is_reachable(dead));
+    assert!(!cfg.dominates(0, dead));
+    assert!(!cfg.reverse_postorder().contains(&dead));
+    assert!(cfg.to_dot().contains(&alloc::format!(
+        "b{} [label=\"b{}\\lnop\\l\", style=dashed]",
+        dead,
+        dead
+    )));
+}
+
+#[test]
+fn nested_loops() {
+    let (p, f) = cfg_of(
+        br#"(module (func (param i32)
+          (loop $outer
+            (loop $inner
+              (br_if $inner (local.get 0)))
+            (br_if $outer (local.get 0)))))"#,
+    );
+    let cfg = p.cfg(f).unwrap();
+    assert_eq!(
+        cfg.loops(),
+        vec![
+            NaturalLoop {
+                header: 2,
+                latches: vec![5],
+                blocks: vec![2, 3, 4, 5],
+            },
+            NaturalLoop {
+                header: 3,
+                latches: vec![3],
+                blocks: vec![3],
+            },
+        ]
+    );
+    assert!(cfg.to_dot().contains("b5 -> b2 [style=bold];"));
+}
+
+#[test]
+fn dead_code_is_not_part_of_a_loop() {
+    let (p, f) = cfg_of(
+        br#"(module (func (param i32)
+          (loop $l
+            (block $b
+              (br $b)
+              (nop))
+            (br_if $l (local.get 0)))))"#,
+    );
+    let cfg = p.cfg(f).unwrap();
+    // the nop after the br is block 5 and falls into the end of $b
+    assert_eq!(cfg.blocks[5].successors, [4]);
+    assert!(!cfg.is_reachable(5));
+    assert_eq!(
+        cfg.loops(),
+        vec![NaturalLoop {
+            header: 2,
+            latches: vec![4],
+            blocks: vec![2, 3, 4],
+        }]
+    );
+}
+
+#[test]
+fn imported_functions_have_no_cfg() {
+    let p = parse_wat(b"(module (import \"env\" \"f\" (func)) (func))").unwrap();
+    assert!(p.cfg(FuncIdx(0)).is_err());
+    assert!(p.cfg(FuncIdx(1)).is_ok());
+    assert!(p.cfg(FuncIdx(2)).is_err());
+}
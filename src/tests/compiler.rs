@@ -0,0 +1,161 @@
+use crate::*;
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[test]
+fn call_indirect_round_trips() {
+    let p = parse_wat(
+        br#"(module
+          (type $t (func (param i32) (result i32)))
+          (table 1 funcref)
+          (func $f (type $t) (local.get 0))
+          (elem (i32.const 0) $f)
+          (func (export "call") (param i32) (result i32)
+            (call_indirect (type $t) (local.get 0) (i32.const 0))))"#,
+    )
+    .unwrap();
+    let bytes = p.compile().unwrap();
+    // the type index is followed by the reserved table index
+    assert!(bytes.windows(3).any(|x| x == [0x11, 0x00, 0x00]));
+    let reparsed = parse(&bytes).unwrap();
+    assert_eq!(reparsed.sections, p.sections);
+    assert_eq!(reparsed.compile().unwrap(), bytes);
+}
+
+fn body_error(instructions: Vec<Instruction>) -> CompileError {
+    let mut p = parse_wat(b"(module (import \"env\" \"f\" (func)) (func))").unwrap();
+    for s in p.sections.iter_mut() {
+        if let Section::Code(c) = s {
+            c.code_blocks[0].instructions = instructions.clone();
+        }
+    }
+    p.compile().unwrap_err()
+}
+
+#[test]
+fn bad_block_types_do_not_compile() {
+    let e = body_error(vec![Instruction::Block(0x12, vec![Instruction::Nop])]);
+    assert_eq!(e.message, "invalid block type");
+    // nested blocks are checked too
+    let e = body_error(vec![Instruction::Loop(
+        0x40,
+        vec![Instruction::If(0x12, vec![], None)],
+    )]);
+    assert_eq!(e.message, "invalid block type");
+    // the function index counts the import
+    assert_eq!(e.function, Some(FuncIdx(1)));
+}
+
+#[test]
+fn undecodable_raw_bytes_do_not_compile() {
+    // 0x06 is not an opcode
+    let e = body_error(vec![Instruction::Raw(0x06)]);
+    assert_eq!(e.message, "raw bytes do not decode");
+    assert_eq!(e.function, Some(FuncIdx(1)));
+    // an i32.const written byte by byte does decode
+    let p = parse_wat(b"(module (func (result i32) (i32.const 7)))").unwrap();
+    let mut raw = p.clone();
+    for s in raw.sections.iter_mut() {
+        if let Section::Code(c) = s {
+            c.code_blocks[0].instructions = vec![Instruction::Raw(0x41), Instruction::Raw(0x07)];
+        }
+    }
+    assert_eq!(raw.compile().unwrap(), p.compile().unwrap());
+}
+
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn limits_that_do_not_fit_do_not_compile() {
+    let too_big = u32::MAX as usize + 1;
+    let mut p = parse_wat(b"(module (func) (memory 1))").unwrap();
+    let memory = p
+        .sections
+        .iter()
+        .position(|s| matches!(s, Section::Memory(_)))
+        .unwrap();
+    if let Section::Memory(m) = &mut p.sections[memory] {
+        m.memories[0].max_pages = Some(too_big);
+    }
+    let e = p.compile().unwrap_err();
+    assert_eq!(e.message, "value does not fit in u32");
+    assert_eq!((e.section, e.function), (memory, None));
+
+    let mut p = Program::new();
+    p.import_table("env", "table", too_big, None).unwrap();
+    let e = p.compile().unwrap_err();
+    assert_eq!(e.message, "value does not fit in u32");
+    assert!(matches!(p.sections[e.section], Section::Import(_)));
+}
+
+#[test]
+fn inconsistent_function_and_code_lengths_do_not_validate() {
+    let mut p = parse_wat(b"(module (func) (func))").unwrap();
+    let code = p
+        .sections
+        .iter()
+        .position(|s| matches!(s, Section::Code(_)))
+        .unwrap();
+    if let Section::Code(c) = &mut p.sections[code] {
+        c.code_blocks.pop();
+    }
+    let options = CompileOptions { validate: true };
+    let e = p.compile_with_options(&options).unwrap_err();
+    assert_eq!(
+        e.message,
+        "function and code section have inconsistent lengths"
+    );
+    assert_eq!((e.section, e.function), (code, None));
+    assert_eq!(p.validate(), Err(e));
+
+    // without the code section the error is on the function section
+    p.sections.remove(code);
+    let function = p
+        .sections
+        .iter()
+        .position(|s| matches!(s, Section::Function(_)))
+        .unwrap();
+    assert_eq!(
+        p.compile_with_options(&options).unwrap_err().section,
+        function
+    );
+}
+
+#[test]
+fn validating_compile_reports_what_validate_does() {
+    let p = parse_wat(b"(module (func (result i32) (i64.const 0)))").unwrap();
+    let e = p.validate().unwrap_err();
+    assert_eq!(e.function, Some(FuncIdx(0)));
+    let options = CompileOptions { validate: true };
+    assert_eq!(p.compile_with_options(&options), Err(e));
+    // without validating the body is encoded as it is
+    assert!(p.compile().is_ok());
+}
+
+#[test]
+fn sections_in_any_order_validate_but_not_twice() {
+    // built in this order the memory and global sections come after the code section
+    let mut p = Program::new();
+    p.create_export("f", &[], &[]).unwrap();
+    p.create_memory("memory", 1, None).unwrap();
+    p.create_global(Some("g"), ValueType::I32, true, &[Instruction::I32Const(1)])
+        .unwrap();
+    let code = p
+        .sections
+        .iter()
+        .position(|s| matches!(s, Section::Code(_)))
+        .unwrap();
+    assert!(p.sections[code + 1..]
+        .iter()
+        .any(|s| matches!(s, Section::Memory(_))));
+    assert_eq!(p.validate(), Ok(()));
+    let bytes = p.compile().unwrap();
+    assert_eq!(parse(&bytes).unwrap().validate(), Ok(()));
+
+    p.sections
+        .push(Section::Memory(MemorySection { memories: vec![] }));
+    let e = p.validate().unwrap_err();
+    assert_eq!(
+        (e.section, e.message),
+        (p.sections.len() - 1, "duplicate section")
+    );
+}
@@ -0,0 +1,103 @@
+use crate::*;
+use alloc::vec;
+use alloc::vec::Vec;
+
+fn segments(p: &Program) -> Vec<(i32, Vec<u8>)> {
+    p.sections
+        .iter()
+        .filter_map(|s| match s {
+            Section::Data(d) => Some(d.data_blocks.iter()),
+            _ => None,
+        })
+        .flatten()
+        .map(|b| match b.offset_expression[..] {
+            [Instruction::I32Const(offset)] => (offset, b.data.to_vec()),
+            _ => panic!("offset is not constant"),
+        })
+        .collect()
+}
+
+fn min_pages(p: &Program) -> usize {
+    p.memories().next().unwrap().1.limits().0
+}
+
+#[test]
+fn allocations_merge_with_zeroed_padding() {
+    let mut p = parse_wat(b"(module (memory 1))").unwrap();
+    let mut layout = p.data_layout(MemIdx(0)).unwrap();
+    assert_eq!(layout.c_string("hi"), Ok(0));
+    assert_eq!(layout.i32s(&[1, -1]), Ok(4));
+    assert_eq!(layout.bytes(b""), Ok(12));
+    assert_eq!(layout.i64s(&[2]), Ok(16));
+    assert_eq!(layout.end(), 24);
+    p.add_data_layout(layout).unwrap();
+    let mut expected = b"hi\0\0\x01\0\0\0\xff\xff\xff\xff\0\0\0\0".to_vec();
+    expected.extend_from_slice(&2i64.to_le_bytes());
+    assert_eq!(segments(&p), vec![(0, expected)]);
+    assert_eq!(min_pages(&p), 1);
+}
+
+#[test]
+fn reserved_space_splits_segments() {
+    let mut p = parse_wat(b"(module (memory 1))").unwrap();
+    let mut layout = p.data_layout(MemIdx(0)).unwrap();
+    assert_eq!(layout.bytes(b"a"), Ok(0));
+    // reserving nothing keeps the segment going
+    assert_eq!(layout.reserve(0, 1), Ok(1));
+    assert_eq!(layout.bytes(b"b"), Ok(1));
+    assert_eq!(layout.reserve(8, 8), Ok(8));
+    assert_eq!(layout.bytes(b"c"), Ok(16));
+    p.add_data_layout(layout).unwrap();
+    assert_eq!(segments(&p), vec![(0, b"ab".to_vec()), (16, b"c".to_vec())]);
+}
+
+#[test]
+fn layouts_start_after_existing_data() {
+    let mut p = parse_wat(b"(module (memory 1) (data (i32.const 100) \"xyz\"))").unwrap();
+    let mut layout = p.data_layout(MemIdx(0)).unwrap();
+    assert_eq!(layout.end(), 103);
+    assert_eq!(layout.f64s(&[1.0]), Ok(104));
+    p.add_data_layout(layout).unwrap();
+    assert_eq!(segments(&p)[1], (104, 1f64.to_le_bytes().to_vec()));
+    assert_eq!(
+        p.data_layout(MemIdx(1)).unwrap_err(),
+        "memory does not exist with that index"
+    );
+}
+
+#[test]
+fn memories_grow_to_fit() {
+    let mut p = parse_wat(b"(module (import \"env\" \"memory\" (memory 1)))").unwrap();
+    let mut layout = p.data_layout(MemIdx(0)).unwrap();
+    layout.reserve(65536, 1).unwrap();
+    layout.bytes(b"x").unwrap();
+    p.add_data_layout(layout).unwrap();
+    assert_eq!(min_pages(&p), 2);
+    assert_eq!(segments(&p), vec![(65536, b"x".to_vec())]);
+
+    // a layout that needs more than the maximum is refused
+    let mut p = parse_wat(b"(module (memory 1 1))").unwrap();
+    let mut layout = p.data_layout(MemIdx(0)).unwrap();
+    layout.reserve(65537, 1).unwrap();
+    assert_eq!(
+        p.add_data_layout(layout),
+        Err("data does not fit in memory")
+    );
+    assert_eq!(min_pages(&p), 1);
+}
+
+#[test]
+fn addresses_past_the_address_space_fail() {
+    let mut layout = DataLayout::new(MemIdx(0), u32::MAX - 1);
+    assert_eq!(layout.bytes(b"ab"), Err("data does not fit in memory"));
+    assert_eq!(layout.bytes(b"a"), Ok(-2));
+    assert_eq!(layout.align(4), Err("data does not fit in memory"));
+    assert_eq!(layout.reserve(1, 1), Err("data does not fit in memory"));
+    assert_eq!(layout.end(), u32::MAX);
+
+    let p = parse_wat(b"(module (memory 1) (data (i32.const -1) \"ab\"))").unwrap();
+    assert_eq!(
+        p.data_layout(MemIdx(0)).unwrap_err(),
+        "data does not fit in memory"
+    );
+}
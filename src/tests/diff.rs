@@ -0,0 +1,122 @@
+use crate::*;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+fn diff_wat(old: &[u8], new: &[u8]) -> (ModuleDiff<'static>, Program, Program) {
+    let (old, new) = (parse_wat(old).unwrap(), parse_wat(new).unwrap());
+    let d = diff(&old, &new);
+    (d, old, new)
+}
+
+const OLD: &[u8] = br#"(module
+  (type $t (func (result i32)))
+  (import "env" "log" (func $log (param i32)))
+  (global $g (mut i32) (i32.const 0))
+  (table 1 funcref)
+  (func $helper (result i32) (global.get $g))
+  (func $main (export "main")
+    (call $log (call $helper))
+    (global.set $g (i32.const 1))
+    (drop (call_indirect (type $t) (i32.const 0)))))"#;
+
+#[test]
+fn added_imports_and_types_do_not_shift_bodies() {
+    // an import, a global import and a type in front of everything renumbers all references
+    let (d, _, _) = diff_wat(
+        OLD,
+        br#"(module
+          (type (func (param f64)))
+          (type $t (func (result i32)))
+          (import "env" "time" (func (result f64)))
+          (import "env" "base" (global i32))
+          (import "env" "log" (func $log (param i32)))
+          (global $g (mut i32) (i32.const 0))
+          (table 1 funcref)
+          (func $helper (result i32) (global.get $g))
+          (func $main (export "main")
+            (call $log (call $helper))
+            (global.set $g (i32.const 1))
+            (drop (call_indirect (type $t) (i32.const 0)))))"#,
+    );
+    assert!(d.functions.is_empty(), "{}", d);
+    assert_eq!(d.imports.len(), 2);
+    assert!(d.types.iter().all(|x| matches!(x, Change::Added(_))));
+}
+
+#[test]
+fn changed_references_are_still_found() {
+    let (d, _, _) = diff_wat(
+        OLD,
+        br#"(module
+          (type $t (func (result i32)))
+          (import "env" "log" (func $log (param i32)))
+          (global $g (mut i32) (i32.const 0))
+          (global $h (mut i32) (i32.const 0))
+          (table 1 funcref)
+          (func $helper (result i32) (global.get $g))
+          (func $main (export "main")
+            (call $log (call $helper))
+            (global.set $h (i32.const 1))
+            (drop (call_indirect (type $t) (i32.const 0)))))"#,
+    );
+    assert_eq!(d.functions.len(), 1);
+    let changed: Vec<&LineDiff> = d.functions[0]
+        .lines
+        .iter()
+        .filter(|x| !matches!(x, LineDiff::Same(_)))
+        .collect();
+    assert_eq!(
+        changed,
+        [
+            &LineDiff::Removed("global.set 0".to_string()),
+            &LineDiff::Added("global.set 1".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn unnamed_functions_are_paired_by_their_renumbered_bodies() {
+    let (d, _, new) = diff_wat(
+        br#"(module
+          (import "env" "f" (func $f (param i32)))
+          (func (call $f (i32.const 1)) (call $f (i32.const 2)) (call $f (i32.const 3))))"#,
+        br#"(module
+          (import "env" "g" (func $g))
+          (import "env" "f" (func $f (param i32)))
+          (func (call $f (i32.const 1)) (call $f (i32.const 2)) (call $f (i32.const 4))))"#,
+    );
+    assert_eq!(d.functions.len(), 1);
+    let f = &d.functions[0];
+    assert_eq!((f.old, f.new), (Some(FuncIdx(1)), Some(FuncIdx(2))));
+    // lines that are the same are shown the way the new program has them
+    assert!(f.lines.contains(&LineDiff::Same("call 1".to_string())));
+    assert_eq!(new.functions().count(), 3);
+}
+
+#[test]
+fn removed_lines_come_before_added_ones() {
+    let (d, _, _) = diff_wat(
+        br#"(module (func (export "f") (result i32) i32.const 1 i32.const 2 i32.add))"#,
+        br#"(module (func (export "f") (result i32) i32.const 3 i32.const 4 i32.add))"#,
+    );
+    let lines: Vec<LineDiff> = d.functions[0].lines.clone();
+    assert_eq!(
+        lines[1..],
+        [
+            LineDiff::Removed("i32.const 1".to_string()),
+            LineDiff::Removed("i32.const 2".to_string()),
+            LineDiff::Added("i32.const 3".to_string()),
+            LineDiff::Added("i32.const 4".to_string()),
+            LineDiff::Same("i32.add".to_string()),
+        ]
+    );
+    let text = d.to_string();
+    assert!(text.find("- i32.const 2").unwrap() < text.find("+ i32.const 3").unwrap());
+}
+
+#[test]
+fn identical_programs_have_no_diff() {
+    let (d, _, _) = diff_wat(OLD, OLD);
+    assert!(d.is_empty());
+    assert_eq!(d.to_string(), "");
+}
@@ -0,0 +1,142 @@
+use crate::*;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const NESTED: &[u8] = br#"(module
+  (global $g (mut i32) (i32.const 0))
+  (func (export "f") (param i32) (result i32)
+    (block $out
+      (loop $top
+        (br_if $out (i32.eqz (local.get 0)))
+        (if (i32.and (local.get 0) (i32.const 1))
+          (then (global.set $g (i32.add (global.get $g) (i32.const 1))))
+          (else (if (i32.const 1) (then (nop)))))
+        (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+        (br $top)))
+    (global.get $g))
+  (func (drop (i32.const 2))))"#;
+
+fn bodies(p: &Module) -> Vec<Vec<Instruction>> {
+    p.sections
+        .iter()
+        .filter_map(|s| match s {
+            Section::Code(c) => Some(c.code_blocks.iter().map(|b| b.instructions.clone())),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+#[test]
+fn markers_know_their_else_and_end() {
+    let body = FlatBody::from_instructions(&[
+        Instruction::Block(0x40, vec![Instruction::Nop]),
+        Instruction::If(
+            0x40,
+            vec![Instruction::Nop],
+            Some(vec![Instruction::Loop(0x40, vec![])]),
+        ),
+        Instruction::Drop,
+    ]);
+    assert_eq!(
+        body.instructions(),
+        &[
+            FlatInstruction::Block {
+                block_type: 0x40,
+                end: 2
+            },
+            FlatInstruction::Plain(Instruction::Nop),
+            FlatInstruction::End,
+            FlatInstruction::If {
+                block_type: 0x40,
+                else_at: Some(5),
+                end: 8
+            },
+            FlatInstruction::Plain(Instruction::Nop),
+            FlatInstruction::Else { end: 8 },
+            FlatInstruction::Loop {
+                block_type: 0x40,
+                end: 7
+            },
+            FlatInstruction::End,
+            FlatInstruction::End,
+            FlatInstruction::Plain(Instruction::Drop),
+        ][..]
+    );
+    assert_eq!(body.matching_end(0), Some(2));
+    assert_eq!(body.matching_end(3), Some(8));
+    assert_eq!(body.matching_else(3), Some(5));
+    assert_eq!(body.matching_end(5), Some(8));
+    assert_eq!(body.matching_end(6), Some(7));
+    // plain instructions and ends have nothing to match
+    assert_eq!(body.matching_end(1), None);
+    assert_eq!(body.matching_end(2), None);
+    assert_eq!(body.matching_else(0), None);
+    assert_eq!(body.matching_end(10), None);
+}
+
+#[test]
+fn an_if_without_else_has_no_else_marker() {
+    let body = FlatBody::from_instructions(&[Instruction::If(0x7f, vec![], None)]);
+    assert_eq!(
+        body.instructions(),
+        &[
+            FlatInstruction::If {
+                block_type: 0x7f,
+                else_at: None,
+                end: 1
+            },
+            FlatInstruction::End,
+        ][..]
+    );
+    assert_eq!(body.matching_else(0), None);
+    assert_eq!(
+        body.to_instructions(),
+        vec![Instruction::If(0x7f, vec![], None)]
+    );
+}
+
+#[test]
+fn bodies_round_trip_through_flat_form() {
+    let p = parse_wat(NESTED).unwrap();
+    for b in bodies(&p) {
+        assert_eq!(FlatBody::from_instructions(&b).to_instructions(), b);
+    }
+    // an empty else arm is kept apart from no else arm
+    let empty_else = vec![Instruction::If(0x40, vec![Instruction::Nop], Some(vec![]))];
+    assert_eq!(
+        FlatBody::from_instructions(&empty_else).to_instructions(),
+        empty_else
+    );
+    assert!(FlatBody::from_instructions(&[]).is_empty());
+}
+
+#[test]
+fn parse_flat_matches_parse() {
+    let bytes = parse_wat(NESTED).unwrap().compile().unwrap();
+    let nested = parse(&bytes).unwrap();
+    let flat = parse_flat(&bytes).unwrap();
+    let expected = bodies(&nested)
+        .iter()
+        .map(|b| FlatBody::from_instructions(b))
+        .collect::<Vec<_>>();
+    assert_eq!(flat.bodies(), &expected[..]);
+    assert_eq!(flat.into_program(), nested);
+}
+
+#[test]
+fn flat_sections_have_no_instructions() {
+    let bytes = parse_wat(NESTED).unwrap().compile().unwrap();
+    let flat = parse_flat(&bytes).unwrap();
+    let code = flat
+        .sections()
+        .iter()
+        .find_map(|s| match s {
+            Section::Code(c) => Some(c),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(code.code_blocks.len(), 2);
+    assert!(code.code_blocks.iter().all(|b| b.instructions.is_empty()));
+    assert_eq!(flat.bodies().len(), 2);
+}
@@ -0,0 +1,175 @@
+use crate::core::*;
+use crate::*;
+use alloc::vec::Vec;
+use webassembly::EMPTY;
+
+const METER: GasMeter = GasMeter::Import {
+    module: "env",
+    name: "gas",
+};
+
+// meters the only function of `wat` at a cost of one per instruction, the charge function
+// is import 0
+fn metered(wat: &[u8]) -> Vec<Instruction> {
+    let mut p = parse_wat(wat).unwrap();
+    p.meter_gas(METER, &|_| 1).unwrap();
+    assert_eq!(p.validate(), Ok(()));
+    match p.function(FuncIdx(1)).unwrap() {
+        FunctionRef::Defined { body, .. } => body.to_vec(),
+        FunctionRef::Imported { .. } => panic!("function 1 is imported"),
+    }
+}
+
+fn charge(amount: i64) -> [Instruction; 2] {
+    [Instruction::I64Const(amount), Instruction::Call(FuncIdx(0))]
+}
+
+fn concat(parts: &[&[Instruction]]) -> Vec<Instruction> {
+    parts.iter().flat_map(|x| x.iter().cloned()).collect()
+}
+
+#[test]
+fn a_straight_line_is_charged_once_up_front() {
+    let body = metered(br#"(module (func (result i32) i32.const 1 i32.const 2 i32.add))"#);
+    let expected = concat(&[
+        &charge(3),
+        &[
+            Instruction::I32Const(1),
+            Instruction::I32Const(2),
+            Instruction::I32Add,
+        ],
+    ]);
+    assert_eq!(body, expected);
+}
+
+#[test]
+fn br_if_ends_a_block() {
+    let body = metered(
+        br#"(module (func (param i32)
+          block
+            local.get 0
+            br_if 0
+            nop
+          end
+          nop))"#,
+    );
+    let inner = concat(&[
+        &charge(2),
+        &[Instruction::LocalGet(LocalIdx(0)), Instruction::BrIf(0)],
+        &charge(1),
+        &[Instruction::Nop],
+    ]);
+    // the block is charged before it is entered, what follows it is a block of its own
+    let expected = concat(&[
+        &charge(1),
+        &[Instruction::Block(EMPTY, inner)],
+        &charge(1),
+        &[Instruction::Nop],
+    ]);
+    assert_eq!(body, expected);
+}
+
+#[test]
+fn loops_are_charged_on_every_iteration() {
+    let body = metered(
+        br#"(module (func (param i32)
+          loop
+            local.get 0
+            i32.const 1
+            i32.sub
+            local.tee 0
+            br_if 0
+          end))"#,
+    );
+    match &body[..] {
+        [Instruction::I64Const(1), Instruction::Call(_), Instruction::Loop(_, inner)] => {
+            assert_eq!(inner[..2], charge(5));
+            assert_eq!(inner.len(), 7);
+        }
+        _ => panic!("unexpected body {:?}", body),
+    }
+}
+
+#[test]
+fn if_and_else_arms_are_charged_separately() {
+    let body = metered(
+        br#"(module (func (param i32) (result i32)
+          local.get 0
+          if (result i32)
+            i32.const 1
+          else
+            i32.const 2
+            i32.const 3
+            i32.add
+          end))"#,
+    );
+    // the condition and the if itself are paid for by the enclosing block
+    assert_eq!(
+        body[..3],
+        concat(&[&charge(2), &[Instruction::LocalGet(LocalIdx(0))]])[..]
+    );
+    match &body[3] {
+        Instruction::If(_, then, Some(otherwise)) => {
+            assert_eq!(
+                then[..],
+                concat(&[&charge(1), &[Instruction::I32Const(1)]])[..]
+            );
+            assert_eq!(otherwise[..2], charge(3));
+        }
+        x => panic!("unexpected instruction {:?}", x),
+    }
+    assert_eq!(body.len(), 4);
+}
+
+#[test]
+fn free_instructions_are_not_charged() {
+    let mut p = parse_wat(br#"(module (func nop nop))"#).unwrap();
+    p.meter_gas(METER, &|_| 0).unwrap();
+    match p.function(FuncIdx(1)).unwrap() {
+        FunctionRef::Defined { body, .. } => {
+            assert_eq!(body.to_vec(), vec![Instruction::Nop, Instruction::Nop])
+        }
+        FunctionRef::Imported { .. } => panic!("function 1 is imported"),
+    }
+}
+
+#[test]
+fn the_global_meter_traps_when_gas_runs_out() {
+    let mut p = parse_wat(
+        br#"(module (func (export "count") (param i32) (result i32)
+          loop
+            local.get 0
+            i32.const 1
+            i32.sub
+            local.tee 0
+            br_if 0
+          end
+          local.get 0))"#,
+    )
+    .unwrap();
+    p.meter_gas(
+        GasMeter::Global {
+            name: "gas",
+            initial: 100,
+        },
+        &|_| 1,
+    )
+    .unwrap();
+    assert_eq!(p.validate(), Ok(()));
+
+    let run = |n: i32| {
+        let mut interpreter = Interpreter::new(p.clone()).unwrap();
+        let mut execution = interpreter.call("count", &[WasmValue::I32(n)]).unwrap();
+        loop {
+            let response = match execution.next_unit()? {
+                ExecutionUnit::Complete(_) => break,
+                mut x => x.evaluate()?,
+            };
+            execution.execute(response)?;
+        }
+        interpreter.global("gas").map(|x| x.to_i64())
+    };
+    // one for the loop instruction, five per iteration and one for the final local.get
+    assert_eq!(run(3), Ok(100 - 1 - 15 - 1));
+    assert_eq!(run(100), Err("Reached unreachable"));
+}
@@ -0,0 +1,171 @@
+use crate::core::*;
+use crate::*;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+const MODULE: &[u8] = br#"(module
+  (type $unused (func (param f64)))
+  (type $v (func))
+  (import "env" "log" (func $log (param i32)))
+  (global $a (mut i32) (i32.const 0))
+  (global $b (mut i32) (i32.const 1))
+  (table 2 funcref)
+  (func $dead (type $v) (global.set $a (i32.const 3)))
+  (func $live (type $v) (call $log (global.get $b)) (global.set $b (i32.const 2)))
+  (func $other (type $v) (call $live))
+  (elem (i32.const 0) $live $other)
+  (export "other" (func $other))
+  (export "b" (global $b)))"#;
+
+// the module with a name for every function and a local name for $live
+fn named() -> Program {
+    let mut p = parse_wat(MODULE).unwrap();
+    let names = NameSection {
+        module_name: None,
+        function_names: ["log", "dead", "live", "other"]
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (FuncIdx(i as u32), n.to_string()))
+            .collect(),
+        local_names: vec![(FuncIdx(2), vec![(LocalIdx(0), "x".to_string())])],
+    };
+    let mut data = Vec::new();
+    names.extend_wasm_bytes(&mut data);
+    p.sections.push(Section::Custom(CustomSection {
+        name: "name".into(),
+        data: data.into(),
+    }));
+    p
+}
+
+fn body(p: &Program, index: u32) -> Vec<Instruction> {
+    match p.function(FuncIdx(index)).unwrap() {
+        FunctionRef::Defined { body, .. } => body.to_vec(),
+        FunctionRef::Imported { .. } => panic!("function {} is imported", index),
+    }
+}
+
+fn exports(p: &Program) -> Vec<WasmExport<'_>> {
+    p.sections
+        .iter()
+        .find_map(|s| match s {
+            Section::Export(e) => Some(e.exports.clone()),
+            _ => None,
+        })
+        .unwrap()
+}
+
+fn elements(p: &Program) -> Vec<FuncIdx> {
+    p.sections
+        .iter()
+        .find_map(|s| match s {
+            Section::Element(e) => Some(e.elements[0].functions.clone()),
+            _ => None,
+        })
+        .unwrap()
+}
+
+fn function_names(p: &Program) -> Vec<(u32, &'static str)> {
+    let names = p.name_section().unwrap();
+    names
+        .function_names
+        .iter()
+        .map(|(i, n)| {
+            let n = ["log", "dead", "live", "other"]
+                .iter()
+                .find(|x| **x == n.as_str())
+                .unwrap();
+            (i.0, *n)
+        })
+        .collect()
+}
+
+#[test]
+fn removing_a_function_renumbers_references() {
+    let mut p = named();
+    assert_eq!(
+        p.remove_function(FuncIdx(2)),
+        Err("function is still referenced")
+    );
+    p.remove_function(FuncIdx(1)).unwrap();
+    assert_eq!(p.functions().count(), 3);
+    assert_eq!(body(&p, 2), vec![Instruction::Call(FuncIdx(1))]);
+    assert_eq!(body(&p, 1)[1], Instruction::Call(FuncIdx(0)));
+    assert_eq!(elements(&p), vec![FuncIdx(1), FuncIdx(2)]);
+    match &exports(&p)[0] {
+        WasmExport::Function(e) => assert_eq!(e.index, FuncIdx(2)),
+        e => panic!("unexpected export {:?}", e),
+    }
+    assert_eq!(
+        function_names(&p),
+        vec![(0, "log"), (1, "live"), (2, "other")]
+    );
+    assert_eq!(p.name_section().unwrap().local_names[0].0, FuncIdx(1));
+    assert!(p.validate().is_ok());
+}
+
+#[test]
+fn gc_renumbers_functions_globals_and_types() {
+    let mut p = named();
+    p.gc().unwrap();
+    assert_eq!(p.functions().count(), 3);
+    assert_eq!(p.globals().count(), 1);
+    // the unused type goes, the others move down
+    assert_eq!(
+        p.function_type(FuncIdx(0)).unwrap().inputs,
+        vec![ValueType::I32]
+    );
+    assert!(p.functions().all(|(_, f)| f.type_index().0 < 2));
+    assert_eq!(
+        body(&p, 1),
+        vec![
+            Instruction::GlobalGet(GlobalIdx(0)),
+            Instruction::Call(FuncIdx(0)),
+            Instruction::I32Const(2),
+            Instruction::GlobalSet(GlobalIdx(0)),
+        ]
+    );
+    assert_eq!(body(&p, 2), vec![Instruction::Call(FuncIdx(1))]);
+    assert_eq!(elements(&p), vec![FuncIdx(1), FuncIdx(2)]);
+    match &exports(&p)[..] {
+        [WasmExport::Function(f), WasmExport::Global(g)] => {
+            assert_eq!(f.index, FuncIdx(2));
+            assert_eq!(g.index, GlobalIdx(0));
+        }
+        e => panic!("unexpected exports {:?}", e),
+    }
+    assert_eq!(
+        function_names(&p),
+        vec![(0, "log"), (1, "live"), (2, "other")]
+    );
+    assert!(p.validate().is_ok());
+}
+
+#[test]
+fn implicit_memory_and_table_uses_keep_their_imports() {
+    for body in ["(drop (i32.load (i32.const 0)))", "(drop (memory.size))"].iter() {
+        let source = format!(
+            r#"(module (import "env" "mem" (memory 1)) (func {}))"#,
+            body
+        );
+        let mut p = parse_wat(source.as_bytes()).unwrap();
+        assert_eq!(
+            p.remove_import("env", "mem"),
+            Err("import is still referenced")
+        );
+        assert!(p.validate().is_ok());
+    }
+    let mut p = parse_wat(
+        br#"(module (import "env" "table" (table 1 funcref))
+              (func (call_indirect (i32.const 0))))"#,
+    )
+    .unwrap();
+    assert_eq!(
+        p.remove_import("env", "table"),
+        Err("import is still referenced")
+    );
+
+    let mut p = parse_wat(br#"(module (import "env" "mem" (memory 1)) (func nop))"#).unwrap();
+    assert_eq!(p.remove_import("env", "mem"), Ok(()));
+    assert_eq!(p.memories().count(), 0);
+}
@@ -0,0 +1,16 @@
+use crate::*;
+use ::core::convert::TryFrom;
+
+#[test]
+fn indices_do_not_wrap() {
+    assert_eq!(FuncIdx::try_from(7usize), Ok(FuncIdx(7)));
+    assert_eq!(FuncIdx::try_from(u32::MAX as usize), Ok(FuncIdx(u32::MAX)));
+    assert!(FuncIdx::try_from(u32::MAX as usize + 5).is_err());
+    assert!(TypeIdx::try_from(usize::MAX).is_err());
+}
+
+#[test]
+#[should_panic]
+fn positions_past_u32_panic_in_debug_builds() {
+    GlobalIdx::at(u32::MAX as usize + 1);
+}
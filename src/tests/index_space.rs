@@ -0,0 +1,127 @@
+use crate::*;
+use alloc::vec::Vec;
+
+// imports of every kind between each other, then definitions
+const MIXED: &[u8] = br#"(module
+  (type $a (func))
+  (type $b (func (param i32) (result i32)))
+  (import "env" "f" (func (type $b)))
+  (import "env" "g" (global i32))
+  (import "env" "table" (table 1 3 funcref))
+  (import "env" "h" (func (type $a)))
+  (import "env" "mg" (global (mut f64)))
+  (func (type $a) (local i64) (nop))
+  (func (type $b) (local.get 0))
+  (memory 2 4)
+  (global i64 (i64.const 1))
+  (global (mut i32) (i32.const 2)))"#;
+
+#[test]
+fn functions_put_imports_first() {
+    let p = parse_wat(MIXED).unwrap();
+    let functions: Vec<_> = p.functions().collect();
+    assert_eq!(functions.len(), 4);
+    assert_eq!(
+        functions[0],
+        (
+            FuncIdx(0),
+            FunctionRef::Imported {
+                module: "env",
+                name: "f",
+                type_index: TypeIdx(1)
+            }
+        )
+    );
+    assert_eq!(
+        functions[1].1,
+        FunctionRef::Imported {
+            module: "env",
+            name: "h",
+            type_index: TypeIdx(0)
+        }
+    );
+    assert!(matches!(
+        functions[2].1,
+        FunctionRef::Defined { type_index: TypeIdx(0), locals, body: [Instruction::Nop] }
+            if locals.len() == 1
+    ));
+    assert_eq!(functions[3].0, FuncIdx(3));
+    assert_eq!(p.imported_function_count(), 2);
+}
+
+#[test]
+fn function_resolves_each_index() {
+    let p = parse_wat(MIXED).unwrap();
+    for (index, f) in p.functions() {
+        assert_eq!(p.function(index), Ok(f));
+    }
+    assert!(p.function(FuncIdx(1)).unwrap().is_imported());
+    assert_eq!(p.function(FuncIdx(3)).unwrap().type_index(), TypeIdx(1));
+    assert_eq!(p.function_type(FuncIdx(0)).unwrap().outputs.len(), 1);
+    assert_eq!(
+        p.function(FuncIdx(4)),
+        Err("function does not exist with that index")
+    );
+    // a function without its code block does not resolve
+    let mut p = p;
+    for s in p.sections.iter_mut() {
+        if let Section::Code(c) = s {
+            c.code_blocks.pop();
+        }
+    }
+    assert!(p.function(FuncIdx(3)).is_err());
+    assert!(p.function(FuncIdx(2)).is_ok());
+}
+
+#[test]
+fn globals_put_imports_first() {
+    let p = parse_wat(MIXED).unwrap();
+    let globals: Vec<_> = p.globals().collect();
+    assert_eq!(globals.len(), 4);
+    let types: Vec<_> = globals
+        .iter()
+        .map(|(i, g)| (i.index(), g.value_type(), g.is_mutable(), g.is_imported()))
+        .collect();
+    assert_eq!(
+        types,
+        [
+            (0, ValueType::I32, false, true),
+            (1, ValueType::F64, true, true),
+            (2, ValueType::I64, false, false),
+            (3, ValueType::I32, true, false),
+        ]
+    );
+    for (index, g) in globals.iter() {
+        assert_eq!(p.global(*index), Ok(*g));
+    }
+    assert_eq!(
+        p.global(GlobalIdx(4)),
+        Err("global does not exist with that index")
+    );
+}
+
+#[test]
+fn tables_and_memories_put_imports_first() {
+    let p = parse_wat(MIXED).unwrap();
+    let tables: Vec<_> = p.tables().collect();
+    assert_eq!(tables.len(), 1);
+    assert_eq!(tables[0].0, TableIdx(0));
+    assert!(tables[0].1.is_imported());
+    assert_eq!(tables[0].1.limits(), (1, Some(3)));
+    let memories: Vec<_> = p.memories().collect();
+    assert_eq!(memories.len(), 1);
+    assert!(!memories[0].1.is_imported());
+    assert_eq!(memories[0].1.limits(), (2, Some(4)));
+
+    // an imported memory comes before a defined one
+    let mut p = parse_wat(b"(module (import \"env\" \"m\" (memory 1)) (table 5 funcref))").unwrap();
+    p.sections.push(Section::Memory(MemorySection {
+        memories: alloc::vec![WasmMemory {
+            min_pages: 3,
+            max_pages: None,
+        }],
+    }));
+    let memories: Vec<_> = p.memories().map(|(i, m)| (i, m.limits())).collect();
+    assert_eq!(memories, [(MemIdx(0), (1, None)), (MemIdx(1), (3, None))]);
+    assert!(!p.tables().next().unwrap().1.is_imported());
+}
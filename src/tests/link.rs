@@ -0,0 +1,177 @@
+use crate::core::*;
+use crate::*;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+// the module with `name` as module name in its name section
+fn named(wat: &[u8], name: &str) -> Program {
+    let mut p = parse_wat(wat).unwrap();
+    let names = NameSection {
+        module_name: Some(name.to_string()),
+        function_names: Vec::new(),
+        local_names: Vec::new(),
+    };
+    let mut data = Vec::new();
+    names.extend_wasm_bytes(&mut data);
+    p.sections.push(Section::Custom(CustomSection {
+        name: "name".into(),
+        data: data.into(),
+    }));
+    p
+}
+
+fn link_wat(modules: &[&[u8]]) -> Result<Program, LinkError> {
+    let programs: Vec<Program> = modules.iter().map(|x| parse_wat(x).unwrap()).collect();
+    link(&programs)
+}
+
+fn body(p: &Program, index: u32) -> Vec<Instruction> {
+    match p.function(FuncIdx(index)).unwrap() {
+        FunctionRef::Defined { body, .. } => body.to_vec(),
+        FunctionRef::Imported { .. } => panic!("function {} is imported", index),
+    }
+}
+
+fn types(p: &Program) -> Vec<FunctionType> {
+    p.sections
+        .iter()
+        .find_map(|s| match s {
+            Section::Type(t) => Some(t.types.clone()),
+            _ => None,
+        })
+        .unwrap()
+}
+
+fn start(p: &Program) -> Option<FuncIdx> {
+    p.sections.iter().find_map(|s| match s {
+        Section::Start(s) => Some(s.start_function),
+        _ => None,
+    })
+}
+
+#[test]
+fn imports_resolve_through_re_exports() {
+    // main imports f from a module that only re-exports its own import of f
+    let p = link(&[
+        named(
+            br#"(module
+              (import "b" "f" (func $f (result i32)))
+              (func (export "main") (result i32) (call $f)))"#,
+            "a",
+        ),
+        named(
+            br#"(module
+              (import "c" "h" (func $f (result i32)))
+              (export "f" (func $f)))"#,
+            "b",
+        ),
+        named(
+            br#"(module
+              (func (export "h") (result i32) (i32.const 7)))"#,
+            "c",
+        ),
+    ])
+    .unwrap();
+    assert_eq!(p.imported_function_count(), 0);
+    assert_eq!(body(&p, 0), vec![Instruction::Call(FuncIdx(1))]);
+    assert_eq!(body(&p, 1), vec![Instruction::I32Const(7)]);
+    assert_eq!(p.validate(), Ok(()));
+}
+
+#[test]
+fn unresolved_imports_are_shared() {
+    let p = link_wat(&[
+        br#"(module (import "env" "log" (func (param i32))) (func (export "a") (call 0 (i32.const 1))))"#,
+        br#"(module (import "env" "log" (func (param i32))) (func (export "b") (call 0 (i32.const 2))))"#,
+    ])
+    .unwrap();
+    assert_eq!(p.imported_function_count(), 1);
+    assert_eq!(body(&p, 2)[1], Instruction::Call(FuncIdx(0)));
+}
+
+#[test]
+fn identical_types_are_merged() {
+    let p = link_wat(&[
+        br#"(module (type (func (param i32) (result i32))) (type (func))
+          (func (export "a") (type 0) (local.get 0)))"#,
+        br#"(module (type (func (param f32))) (type (func (param i32) (result i32)))
+          (func (export "b") (type 1) (local.get 0)))"#,
+    ])
+    .unwrap();
+    assert_eq!(types(&p).len(), 3);
+    assert_eq!(p.function_type(FuncIdx(0)), p.function_type(FuncIdx(1)));
+    assert_eq!(p.validate(), Ok(()));
+}
+
+#[test]
+fn start_functions_run_in_order() {
+    let p = link_wat(&[
+        br#"(module (global (mut i32) (i32.const 0)) (func $s (global.set 0 (i32.const 1))) (start $s))"#,
+        br#"(module (func) (func $s) (start $s))"#,
+    ])
+    .unwrap();
+    let s = start(&p).unwrap();
+    assert_eq!(s, FuncIdx(3));
+    assert_eq!(
+        body(&p, 3),
+        vec![Instruction::Call(FuncIdx(0)), Instruction::Call(FuncIdx(2))]
+    );
+    assert_eq!(p.validate(), Ok(()));
+}
+
+#[test]
+fn a_single_start_function_is_kept() {
+    let p = link_wat(&[
+        br#"(module (func))"#,
+        br#"(module (func) (func $s) (start $s))"#,
+    ])
+    .unwrap();
+    assert_eq!(start(&p), Some(FuncIdx(2)));
+    assert_eq!(p.functions().count(), 3);
+}
+
+#[test]
+fn signature_mismatch_is_reported() {
+    let e = link_wat(&[
+        br#"(module (func (export "f") (param i32)))"#,
+        br#"(module (import "m" "f" (func (param i64))))"#,
+    ])
+    .unwrap_err();
+    assert_eq!(e.module, 1);
+    assert_eq!(e.name, "f");
+    assert_eq!(e.message, "incompatible import type");
+}
+
+#[test]
+fn the_program_adding_a_second_memory_is_reported() {
+    let e = link_wat(&[
+        br#"(module (memory 1))"#,
+        br#"(module (func))"#,
+        br#"(module (memory (export "heap") 1))"#,
+    ])
+    .unwrap_err();
+    assert_eq!(e.module, 2);
+    assert_eq!(e.name, "heap");
+    assert_eq!(e.message, "linked modules would need more than one memory");
+
+    let e = link_wat(&[
+        br#"(module (table 1 funcref))"#,
+        br#"(module (import "env" "t" (table 1 funcref)))"#,
+    ])
+    .unwrap_err();
+    assert_eq!(e.module, 1);
+    assert_eq!(e.name, "t");
+    assert_eq!(e.message, "linked modules would need more than one table");
+}
+
+#[test]
+fn a_memory_shared_through_imports_is_allowed() {
+    let p = link_wat(&[
+        br#"(module (memory (export "memory") 1))"#,
+        br#"(module (import "m" "memory" (memory 1)) (func (export "f") (result i32) (i32.load (i32.const 0))))"#,
+        br#"(module (import "env" "memory" (memory 1)))"#,
+    ])
+    .unwrap();
+    assert_eq!(p.memories().count(), 1);
+    assert_eq!(p.validate(), Ok(()));
+}
@@ -0,0 +1,22 @@
+mod builder;
+mod call_graph;
+mod canonicalize;
+mod cfg;
+mod compiler;
+mod data_layout;
+mod diff;
+mod flat;
+mod gas;
+mod gc;
+mod index;
+mod index_space;
+mod link;
+mod optimize;
+mod program;
+mod shim;
+mod size;
+mod stack_limit;
+mod stack_types;
+mod view;
+mod visit;
+mod wat;
@@ -0,0 +1,206 @@
+use crate::core::*;
+use crate::*;
+use alloc::vec::Vec;
+use webassembly::EMPTY;
+
+fn only(optimize: fn(&mut Optimizations)) -> Optimizations {
+    let mut o = Optimizations::default();
+    optimize(&mut o);
+    o
+}
+
+fn optimized(wat: &[u8], optimizations: Optimizations) -> CodeBlock {
+    let mut p = parse_wat(wat).unwrap();
+    p.optimize(&optimizations);
+    assert_eq!(p.validate(), Ok(()));
+    p.sections
+        .iter()
+        .find_map(|s| match s {
+            Section::Code(c) => Some(c.code_blocks[0].clone()),
+            _ => None,
+        })
+        .unwrap()
+}
+
+fn body(wat: &[u8], optimizations: Optimizations) -> Vec<Instruction> {
+    optimized(wat, optimizations).instructions
+}
+
+#[test]
+fn constants_are_folded() {
+    let o = only(|o| o.constant_folding = true);
+    let folded = body(
+        br#"(module (func (result i32) i32.const 2 i32.const 3 i32.add i32.const 4 i32.mul))"#,
+        o,
+    );
+    assert_eq!(folded, vec![Instruction::I32Const(20)]);
+    let folded = body(
+        br#"(module (func (result i64) block (result i64) i64.const 1 i64.const 2 i64.shl end))"#,
+        o,
+    );
+    assert_eq!(
+        folded,
+        vec![Instruction::Block(0x7e, vec![Instruction::I64Const(4)])]
+    );
+}
+
+#[test]
+fn trapping_operations_are_not_folded() {
+    let o = only(|o| o.constant_folding = true);
+    let division = br#"(module (func (result i32) i32.const 1 i32.const 0 i32.div_s))"#;
+    assert_eq!(
+        body(division, o),
+        vec![
+            Instruction::I32Const(1),
+            Instruction::I32Const(0),
+            Instruction::I32DivS
+        ]
+    );
+    let overflow = br#"(module (func (result i32) i32.const -2147483648 i32.const -1 i32.div_s))"#;
+    assert_eq!(body(overflow, o).len(), 3);
+    let conversion = br#"(module (func (result i32) f32.const nan i32.trunc_f32_s))"#;
+    assert_eq!(body(conversion, o).len(), 2);
+}
+
+#[test]
+fn identities_are_removed() {
+    let o = only(|o| o.identities = true);
+    let removed = body(
+        br#"(module (func (param i32 i64) (result i32)
+          local.get 1
+          i64.const 1
+          i64.mul
+          drop
+          global.get 0
+          drop
+          local.get 0
+          i32.const 0
+          i32.add
+          i32.const -1
+          i32.and)
+          (global i32 (i32.const 0)))"#,
+        o,
+    );
+    assert_eq!(removed, vec![Instruction::LocalGet(LocalIdx(0))]);
+    // a value that is not neutral stays
+    let kept = body(
+        br#"(module (func (param i32) (result i32) local.get 0 i32.const 1 i32.add))"#,
+        o,
+    );
+    assert_eq!(kept.len(), 3);
+}
+
+#[test]
+fn code_after_a_branch_is_removed() {
+    let o = only(|o| o.dead_code = true);
+    let trimmed = body(
+        br#"(module (func (result i32)
+          block
+            br 0
+            i32.const 1
+            drop
+          end
+          i32.const 2
+          return
+          i32.const 3))"#,
+        o,
+    );
+    assert_eq!(
+        trimmed,
+        vec![
+            Instruction::Block(EMPTY, vec![Instruction::Br(0)]),
+            Instruction::I32Const(2),
+            Instruction::Return,
+        ]
+    );
+}
+
+#[test]
+fn set_and_get_of_the_same_local_become_a_tee() {
+    let o = only(|o| o.local_tee = true);
+    let teed = body(
+        br#"(module (func (param i32 i32) (result i32)
+          local.get 0
+          local.set 1
+          local.get 1
+          local.get 0
+          local.set 1
+          local.get 0
+          i32.add))"#,
+        o,
+    );
+    assert_eq!(
+        teed,
+        vec![
+            Instruction::LocalGet(LocalIdx(0)),
+            Instruction::LocalTee(LocalIdx(1)),
+            Instruction::LocalGet(LocalIdx(0)),
+            Instruction::LocalSet(LocalIdx(1)),
+            Instruction::LocalGet(LocalIdx(0)),
+            Instruction::I32Add,
+        ]
+    );
+}
+
+#[test]
+fn unused_locals_are_removed_and_the_rest_renumbered() {
+    let o = only(|o| o.unused_locals = true);
+    let block = optimized(
+        br#"(module (func (param i32) (result i64)
+          (local i32 f32 i64 i64)
+          i32.const 1
+          local.set 1
+          f32.const 1
+          local.tee 2
+          drop
+          local.get 0
+          drop
+          local.get 4
+          local.set 3
+          local.get 3))"#,
+        o,
+    );
+    assert_eq!(
+        block.locals,
+        vec![LocalCount {
+            count: 2,
+            value_type: ValueType::I64
+        }]
+    );
+    assert_eq!(
+        block.instructions,
+        vec![
+            Instruction::I32Const(1),
+            Instruction::Drop,
+            Instruction::F32Const(1.0),
+            Instruction::Drop,
+            Instruction::LocalGet(LocalIdx(0)),
+            Instruction::Drop,
+            Instruction::LocalGet(LocalIdx(2)),
+            Instruction::LocalSet(LocalIdx(1)),
+            Instruction::LocalGet(LocalIdx(1)),
+        ]
+    );
+}
+
+#[test]
+fn level_zero_changes_nothing() {
+    let wat = br#"(module (func (param i32) (result i32)
+      (local i32)
+      i32.const 1
+      i32.const 2
+      i32.add
+      local.set 1
+      local.get 0
+      i32.const 0
+      i32.add
+      return
+      nop))"#;
+    let original = body(wat, Optimizations::default());
+    assert_eq!(body(wat, Optimizations::level(0)), original);
+    assert_eq!(original.len(), 9);
+    assert_eq!(
+        body(wat, Optimizations::level(2)),
+        vec![Instruction::LocalGet(LocalIdx(0)), Instruction::Return]
+    );
+}
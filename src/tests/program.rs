@@ -0,0 +1,169 @@
+use crate::*;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+#[test]
+fn only_one_memory() {
+    let mut p = parse_wat(b"(module (memory 1) (func (drop (i32.load (i32.const 0)))))").unwrap();
+    let before = p.clone();
+    assert_eq!(
+        p.import_memory("env", "mem", 0, None),
+        Err("multiple memories")
+    );
+    assert_eq!(p, before);
+
+    let mut p = Program::new();
+    assert_eq!(p.import_memory("env", "mem", 1, None), Ok(MemIdx(0)));
+    assert!(p.create_memory("memory", 1, None).is_err());
+    assert_eq!(p.memories().count(), 1);
+}
+
+#[test]
+fn only_one_table() {
+    let mut p = parse_wat(b"(module (table 1 funcref))").unwrap();
+    let before = p.clone();
+    assert_eq!(
+        p.import_table("env", "table", 1, None),
+        Err("multiple tables")
+    );
+    assert_eq!(p, before);
+
+    let mut p = Program::new();
+    assert_eq!(p.import_table("env", "table", 1, None), Ok(TableIdx(0)));
+    assert!(p.create_table(None, 1, None).is_err());
+    assert_eq!(p.tables().count(), 1);
+}
+
+// the name and index of every export
+fn exports(p: &Program) -> Vec<(String, usize)> {
+    let exports = p.sections.iter().find_map(|s| match s {
+        Section::Export(e) => Some(&e.exports),
+        _ => None,
+    });
+    exports
+        .unwrap()
+        .iter()
+        .map(|e| match e {
+            WasmExport::Function(x) => (x.name.to_string(), x.index.index()),
+            WasmExport::Global(x) => (x.name.to_string(), x.index.index()),
+            WasmExport::Table(x) => (x.name.to_string(), x.index.index()),
+            WasmExport::Memory(x) => (x.name.to_string(), x.index.index()),
+        })
+        .collect()
+}
+
+fn with_imports() -> Program {
+    let mut p = Program::new();
+    p.import_function("env", "a", &[], &[]).unwrap();
+    p.import_function("env", "b", &[ValueType::I32], &[])
+        .unwrap();
+    p.import_global("env", "g", ValueType::I32, false).unwrap();
+    p.import_table("env", "table", 4, None).unwrap();
+    p.import_memory("env", "memory", 1, None).unwrap();
+    p
+}
+
+#[test]
+fn element_segments_count_imported_functions_and_tables() {
+    let mut p = with_imports();
+    let (_, f) = p.create_function(&[], &[]).unwrap();
+    assert_eq!(f, FuncIdx(2));
+    p.add_element_segment(TableIdx(0), &[Instruction::I32Const(1)], &[FuncIdx(0), f])
+        .unwrap();
+    assert_eq!(
+        p.add_element_segment(TableIdx(0), &[Instruction::I32Const(0)], &[FuncIdx(3)]),
+        Err("function does not exist with that index")
+    );
+    assert_eq!(
+        p.add_element_segment(TableIdx(1), &[Instruction::I32Const(0)], &[f]),
+        Err("table does not exist with that index")
+    );
+    assert!(p.validate().is_ok());
+    // an import added later moves the defined function in the segment along
+    p.import_function("env", "c", &[], &[]).unwrap();
+    let elements = p.sections.iter().find_map(|s| match s {
+        Section::Element(e) => Some(e.elements.clone()),
+        _ => None,
+    });
+    assert_eq!(elements.unwrap()[0].functions, [FuncIdx(0), FuncIdx(3)]);
+}
+
+#[test]
+fn data_segments_use_the_imported_memory() {
+    let mut p = with_imports();
+    assert_eq!(
+        p.add_data_segment(MemIdx(0), &[Instruction::I32Const(8)], b"hi"),
+        Ok(DataIdx(0))
+    );
+    assert_eq!(
+        p.add_data_segment(MemIdx(0), &[Instruction::GlobalGet(GlobalIdx(0))], b"!"),
+        Ok(DataIdx(1))
+    );
+    assert_eq!(
+        p.add_data_segment(MemIdx(1), &[Instruction::I32Const(0)], b""),
+        Err("memory does not exist with that index")
+    );
+    assert!(p.validate().is_ok());
+    let mut p = Program::new();
+    assert!(p
+        .add_data_segment(MemIdx(0), &[Instruction::I32Const(0)], b"")
+        .is_err());
+}
+
+#[test]
+fn set_start_checks_the_signature_and_replaces() {
+    let mut p = with_imports();
+    let (_, f) = p.create_function(&[], &[]).unwrap();
+    let (_, g) = p.create_function(&[ValueType::I32], &[]).unwrap();
+    assert_eq!(
+        p.set_start(g),
+        Err("start function can not have parameters or results")
+    );
+    assert_eq!(
+        p.set_start(FuncIdx(1)),
+        Err("start function can not have parameters or results")
+    );
+    assert!(p.set_start(FuncIdx(9)).is_err());
+    p.set_start(FuncIdx(0)).unwrap();
+    p.set_start(f).unwrap();
+    let starts: Vec<FuncIdx> = p
+        .sections
+        .iter()
+        .filter_map(|s| match s {
+            Section::Start(s) => Some(s.start_function),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(starts, [FuncIdx(2)]);
+    assert!(p.validate().is_ok());
+}
+
+#[test]
+fn created_definitions_are_exported_past_the_imports() {
+    let mut p = Program::new();
+    p.import_function("env", "a", &[], &[]).unwrap();
+    p.import_global("env", "g", ValueType::I64, true).unwrap();
+    let (_, run) = p.create_export("run", &[], &[]).unwrap();
+    let (_, hidden) = p.create_function(&[], &[]).unwrap();
+    let (_, g) = p
+        .create_global(
+            Some("counter"),
+            ValueType::I32,
+            true,
+            &[Instruction::I32Const(0)],
+        )
+        .unwrap();
+    p.create_global(None, ValueType::I32, false, &[Instruction::I32Const(1)])
+        .unwrap();
+    let (_, table) = p.create_table(Some("table"), 2, Some(2)).unwrap();
+    let (_, memory) = p.create_memory("memory", 1, None).unwrap();
+    assert_eq!((run, hidden, g), (FuncIdx(1), FuncIdx(2), GlobalIdx(1)));
+    assert_eq!((table, memory), (TableIdx(0), MemIdx(0)));
+    let names: Vec<(&str, usize)> =
+        [("run", 1), ("counter", 1), ("table", 0), ("memory", 0)].to_vec();
+    let exports = exports(&p);
+    let exports: Vec<(&str, usize)> = exports.iter().map(|(n, i)| (&n[..], *i)).collect();
+    assert_eq!(exports, names);
+    assert!(p.find_exported_function("run").is_ok());
+    assert!(p.validate().is_ok());
+}
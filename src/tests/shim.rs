@@ -0,0 +1,114 @@
+use crate::core::*;
+use crate::*;
+use alloc::vec::Vec;
+
+const HOOKS: ShimHooks = ShimHooks {
+    module: "hooks",
+    pre_hook: "before",
+    post_hook: "after",
+};
+
+fn body(p: &Program, index: u32) -> Vec<Instruction> {
+    match p.function(FuncIdx(index)).unwrap() {
+        FunctionRef::Defined { body, .. } => body.to_vec(),
+        FunctionRef::Imported { .. } => panic!("function {} is imported", index),
+    }
+}
+
+fn calls(instructions: &[Instruction]) -> Vec<FuncIdx> {
+    instructions
+        .iter()
+        .filter_map(|x| match x {
+            Instruction::Call(i) => Some(*i),
+            _ => None,
+        })
+        .collect()
+}
+
+fn imported_name(p: &Program, index: u32) -> (&str, &str) {
+    match p.function(FuncIdx(index)).unwrap() {
+        FunctionRef::Imported { module, name, .. } => (module, name),
+        FunctionRef::Defined { .. } => panic!("function {} is defined", index),
+    }
+}
+
+#[test]
+fn calls_and_elements_are_redirected_to_wrappers() {
+    let mut p = parse_wat(
+        br#"(module
+          (import "env" "add" (func $add (param i32 i32) (result i32)))
+          (import "env" "tick" (func $tick))
+          (table 2 funcref)
+          (func $main (result i32) (call $tick) (call $add (i32.const 1) (i32.const 2)))
+          (elem (i32.const 0) $add $main))"#,
+    )
+    .unwrap();
+    let wrappers = p.shim_imports(HOOKS).unwrap();
+
+    // the hooks come after the original imports, one per signature
+    assert_eq!(p.imported_function_count(), 6);
+    assert_eq!(imported_name(&p, 2), ("hooks", "before_i32_i32"));
+    assert_eq!(imported_name(&p, 3), ("hooks", "after_i32"));
+    assert_eq!(imported_name(&p, 4), ("hooks", "before"));
+    assert_eq!(imported_name(&p, 5), ("hooks", "after"));
+    assert_eq!(wrappers, vec![FuncIdx(7), FuncIdx(8)]);
+
+    assert_eq!(calls(&body(&p, 6)), vec![FuncIdx(8), FuncIdx(7)]);
+    let elements: Vec<FuncIdx> = p
+        .sections
+        .iter()
+        .find_map(|s| match s {
+            Section::Element(e) => Some(e.elements[0].functions.clone()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(elements, vec![FuncIdx(7), FuncIdx(6)]);
+    assert_eq!(p.validate(), Ok(()));
+}
+
+#[test]
+fn wrappers_call_the_import_and_the_hooks() {
+    let mut p = parse_wat(
+        br#"(module
+          (import "env" "add" (func $add (param i32 i32) (result i32)))
+          (func (result i32) (call $add (i32.const 1) (i32.const 2))))"#,
+    )
+    .unwrap();
+    let wrappers = p.shim_imports(HOOKS).unwrap();
+    let wrapper = body(&p, wrappers[0].0);
+    assert_eq!(calls(&wrapper), vec![FuncIdx(1), FuncIdx(0), FuncIdx(2)]);
+    // the import id goes first to both hooks
+    assert_eq!(wrapper[0], Instruction::I32Const(0));
+    assert_eq!(
+        wrapper[wrapper.len() - 4..],
+        [
+            Instruction::I32Const(0),
+            Instruction::LocalGet(LocalIdx(2)),
+            Instruction::Call(FuncIdx(2)),
+            Instruction::LocalGet(LocalIdx(2)),
+        ]
+    );
+    assert_eq!(p.validate(), Ok(()));
+}
+
+#[test]
+fn hooks_that_already_exist_are_reused() {
+    let mut p = parse_wat(
+        br#"(module
+          (import "hooks" "before" (func $before (param i32)))
+          (import "env" "tick" (func $tick))
+          (func (call $tick)))"#,
+    )
+    .unwrap();
+    let wrappers = p.shim_imports(HOOKS).unwrap();
+    // "before" is an import too and gets a wrapper, but the wrapper of tick calls it directly
+    assert_eq!(p.imported_function_count(), 4);
+    assert_eq!(imported_name(&p, 2), ("hooks", "before_i32"));
+    assert_eq!(imported_name(&p, 3), ("hooks", "after"));
+    assert_eq!(
+        calls(&body(&p, wrappers[1].0)),
+        vec![FuncIdx(0), FuncIdx(1), FuncIdx(3)]
+    );
+    assert_eq!(calls(&body(&p, 4)), vec![wrappers[1]]);
+    assert_eq!(p.validate(), Ok(()));
+}
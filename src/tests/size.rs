@@ -0,0 +1,115 @@
+use crate::*;
+use alloc::string::ToString;
+
+fn function_size(profile: &SizeProfile, index: u32) -> (usize, Option<&str>) {
+    let entry = profile
+        .entries
+        .iter()
+        .find(|e| e.item == SizeItem::Function(FuncIdx(index)))
+        .unwrap();
+    (entry.size, entry.name.as_deref())
+}
+
+fn retained(profile: &SizeProfile, name: &str) -> usize {
+    profile
+        .exports
+        .iter()
+        .find(|e| e.name == name)
+        .unwrap()
+        .retained
+}
+
+const SHARED: &[u8] = br#"(module
+  (import "env" "log" (func $log (param i32)))
+  (memory (export "memory") 1)
+  (table 1 funcref)
+  (func $shared (call $log (i32.const 1)))
+  (func $only_a (nop) (nop) (nop) (nop) (nop) (nop) (nop) (nop))
+  (func $only_b (call $shared))
+  (func $in_table (nop))
+  (func $start)
+  (func (export "a") (call $shared) (call $only_a))
+  (func (export "b") (call $only_b))
+  (elem (i32.const 0) $in_table)
+  (start $start)
+  (data (i32.const 0) "hello"))"#;
+
+#[test]
+fn every_byte_is_attributed_once() {
+    let mut p = parse_wat(SHARED).unwrap();
+    p.sections.push(Section::Custom(CustomSection {
+        name: "producers".into(),
+        data: alloc::vec![0; 300].into(),
+    }));
+    let profile = p.size_profile();
+    assert_eq!(profile.total, p.compile().unwrap().len());
+    // the custom section is the largest, with its id and length
+    assert_eq!(
+        profile.entries[0].item,
+        SizeItem::Custom("producers".to_string())
+    );
+    assert_eq!(profile.entries[0].size, 1 + 2 + 1 + "producers".len() + 300);
+    assert!(profile
+        .entries
+        .iter()
+        .any(|e| e.item == SizeItem::Data(DataIdx(0))));
+
+    let p = parse(include_bytes!("../../examples/wq/main.wasm")).unwrap();
+    assert_eq!(p.size_profile().total, p.compile().unwrap().len());
+}
+
+#[test]
+fn bodies_are_attributed_to_their_function() {
+    let p = parse_wat(SHARED).unwrap();
+    let profile = p.size_profile();
+    // a length, no locals, eight nops and the end
+    assert_eq!(function_size(&profile, 2), (1 + 1 + 8 + 1, None));
+    // an empty body is still its length, locals and end
+    assert_eq!(function_size(&profile, 5), (3, None));
+    // exported functions are named by their export
+    assert_eq!(function_size(&profile, 6).1, Some("a"));
+    assert!(!profile
+        .entries
+        .iter()
+        .any(|e| e.item == SizeItem::Function(FuncIdx(0))));
+}
+
+#[test]
+fn retained_size_counts_what_only_the_export_reaches() {
+    let p = parse_wat(SHARED).unwrap();
+    let profile = p.size_profile();
+    let size = |i| function_size(&profile, i).0;
+    // an export entry is its name, the kind and the index
+    let entry = |name: &str| 1 + name.len() + 1 + 1;
+    // $shared is also reached from b, so it stays without a
+    assert_eq!(retained(&profile, "a"), entry("a") + size(6) + size(2));
+    assert_eq!(retained(&profile, "b"), entry("b") + size(7) + size(3));
+    assert_eq!(retained(&profile, "memory"), entry("memory"));
+
+    // once b is gone the shared function is a's alone
+    let p = parse_wat(
+        br#"(module
+          (func $shared (nop))
+          (func (export "a") (call $shared))
+          (func (export "b")))"#,
+    )
+    .unwrap();
+    let profile = p.size_profile();
+    let size = |i| function_size(&profile, i).0;
+    assert_eq!(retained(&profile, "a"), entry("a") + size(1) + size(0));
+    assert_eq!(retained(&profile, "b"), entry("b") + size(2));
+}
+
+#[test]
+fn a_function_exported_twice_retains_nothing_alone() {
+    let p = parse_wat(
+        br#"(module
+          (func $f (nop))
+          (export "x" (func $f))
+          (export "y" (func $f)))"#,
+    )
+    .unwrap();
+    let profile = p.size_profile();
+    assert_eq!(retained(&profile, "x"), 1 + 1 + 1 + 1);
+    assert_eq!(retained(&profile, "y"), 1 + 1 + 1 + 1);
+}
@@ -0,0 +1,97 @@
+use crate::core::*;
+use crate::*;
+
+fn run(p: &Program, name: &str, n: i32) -> Result<i32, &'static str> {
+    let mut interpreter = Interpreter::new(p.clone())?;
+    let mut execution = interpreter.call(name, &[WasmValue::I32(n)])?;
+    loop {
+        let response = match execution.next_unit()? {
+            ExecutionUnit::Complete(v) => return Ok(v[0].to_i32()),
+            mut x => x.evaluate()?,
+        };
+        execution.execute(response)?;
+    }
+}
+
+const RECURSION: &[u8] = br#"(module
+  (type $t (func (param i32) (result i32)))
+  (table 1 funcref)
+  (elem (i32.const 0) $indirect)
+  (func $direct (export "direct") (param i32) (result i32)
+    local.get 0
+    if (result i32)
+      local.get 0
+      i32.const 1
+      i32.sub
+      call $direct
+      i32.const 1
+      i32.add
+    else
+      i32.const 0
+    end)
+  (func $indirect (export "indirect") (param i32) (result i32)
+    local.get 0
+    if (result i32)
+      local.get 0
+      i32.const 1
+      i32.sub
+      i32.const 0
+      call_indirect (type $t)
+      i32.const 1
+      i32.add
+    else
+      i32.const 0
+    end))"#;
+
+#[test]
+fn max_stack_height_counts_operands() {
+    let p = parse_wat(
+        br#"(module
+          (func (result i32) i32.const 1 i32.const 2 i32.const 3 i32.add i32.add)
+          (func (param i32) (result i32)
+            local.get 0
+            block (result i32)
+              i32.const 1
+              i32.const 2
+              i32.const 3
+              drop
+              i32.add
+            end
+            i32.add)
+          (func))"#,
+    )
+    .unwrap();
+    assert_eq!(p.max_stack_height(FuncIdx(0)), Ok(3));
+    // the operand below the block counts towards what is inside it
+    assert_eq!(p.max_stack_height(FuncIdx(1)), Ok(4));
+    assert_eq!(p.max_stack_height(FuncIdx(2)), Ok(0));
+}
+
+#[test]
+fn max_stack_height_of_an_import_is_an_error() {
+    let p = parse_wat(br#"(module (import "env" "f" (func)))"#).unwrap();
+    assert!(p.max_stack_height(FuncIdx(0)).is_err());
+}
+
+#[test]
+fn limited_recursion_traps() {
+    let mut p = parse_wat(RECURSION).unwrap();
+    p.limit_stack_height(100).unwrap();
+    assert_eq!(p.validate(), Ok(()));
+    // a frame of $direct costs its parameter and two operands, n recurses into n + 1 frames
+    assert_eq!(run(&p, "direct", 10), Ok(10));
+    assert_eq!(run(&p, "direct", 32), Ok(32));
+    assert_eq!(run(&p, "direct", 33), Err("Reached unreachable"));
+}
+
+#[test]
+fn call_indirect_is_limited_through_table_thunks() {
+    let mut p = parse_wat(RECURSION).unwrap();
+    p.limit_stack_height(100).unwrap();
+    assert_eq!(run(&p, "indirect", 10), Ok(10));
+    assert_eq!(run(&p, "indirect", 32), Ok(32));
+    assert_eq!(run(&p, "indirect", 33), Err("Reached unreachable"));
+
+    let unlimited = parse_wat(RECURSION).unwrap();
+    assert_eq!(run(&unlimited, "indirect", 1000), Ok(1000));
+}
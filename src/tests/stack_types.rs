@@ -0,0 +1,120 @@
+use crate::*;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const I: Option<ValueType> = Some(ValueType::I32);
+const F: Option<ValueType> = Some(ValueType::F64);
+
+fn types(wat: &[u8]) -> Result<Vec<StackTypes>, &'static str> {
+    parse_wat(wat).unwrap().stack_types(FuncIdx(0))
+}
+
+fn at(
+    before: Vec<Option<ValueType>>,
+    after: Vec<Option<ValueType>>,
+    reachable: bool,
+) -> StackTypes {
+    StackTypes {
+        before,
+        after,
+        reachable,
+    }
+}
+
+#[test]
+fn stack_around_each_instruction() {
+    let t = types(
+        br#"(module (global (mut f64) (f64.const 0)) (func (param i32) (result i32)
+          (local.get 0)
+          (global.get 0)
+          (drop)
+          (i32.const 1)
+          (i32.add)))"#,
+    )
+    .unwrap();
+    assert_eq!(
+        t,
+        vec![
+            at(vec![], vec![I], true),
+            at(vec![I], vec![I, F], true),
+            at(vec![I, F], vec![I], true),
+            at(vec![I], vec![I, I], true),
+            at(vec![I, I], vec![I], true),
+        ]
+    );
+}
+
+#[test]
+fn blocks_come_before_their_body() {
+    let t = types(
+        br#"(module (func (result i32)
+          (block (result i32)
+            (i32.const 1))
+          (if (result i32) (then (i32.const 2)) (else (i32.const 3)))))"#,
+    )
+    .unwrap();
+    // the block is typed around its whole body, its body starts on an empty stack
+    assert_eq!(t[0], at(vec![], vec![I], true));
+    assert_eq!(t[1], at(vec![], vec![I], true));
+    assert_eq!(t[2], at(vec![I], vec![I], true));
+    assert_eq!(t[3], at(vec![], vec![I], true));
+    assert_eq!(t[4], at(vec![], vec![I], true));
+    assert_eq!(t.len(), 5);
+}
+
+#[test]
+fn code_after_a_branch_is_polymorphic() {
+    for end in &["(br 0)", "(return)", "(unreachable)"] {
+        let wat = alloc::format!(
+            "(module (func (param i32) (result i32)
+              (block (result i32) (i32.const 0) {} (select) (drop) (i32.const 1))))",
+            end
+        );
+        let t = types(wat.as_bytes()).unwrap();
+        // block, i32.const, the branch, then a select of operands nobody pushed
+        assert_eq!(t[2].after, vec![]);
+        assert_eq!(t[3], at(vec![], vec![None], false));
+        assert_eq!(t[4], at(vec![None], vec![], false));
+        assert_eq!(t[5], at(vec![], vec![I], false));
+        // the block result still makes it to the function end
+        assert_eq!(t[0].after, vec![I]);
+    }
+}
+
+#[test]
+fn reachability_follows_branches() {
+    let t = types(
+        br#"(module (func
+          (block
+            (br 0)
+            (nop))
+          (nop)
+          (loop
+            (br 0))
+          (nop)))"#,
+    )
+    .unwrap();
+    let reachable = t.iter().map(|t| t.reachable).collect::<Vec<_>>();
+    // the block end is reached through the br, the loop end never is
+    assert_eq!(reachable, vec![true, true, false, true, true, true, false]);
+}
+
+#[test]
+fn ill_typed_functions_fail() {
+    assert_eq!(
+        types(b"(module (func (result i32) (f64.const 0)))"),
+        Err("type mismatch")
+    );
+    assert_eq!(types(b"(module (func (drop)))"), Err("type mismatch"));
+    assert_eq!(types(b"(module (func (br 1)))"), Err("unknown label"));
+    assert_eq!(
+        types(b"(module (global i32 (i32.const 0)) (func (global.set 0 (i32.const 1))))"),
+        Err("global is immutable")
+    );
+    assert_eq!(
+        parse_wat(b"(module (import \"env\" \"f\" (func)))")
+            .unwrap()
+            .stack_types(FuncIdx(0)),
+        Err("imported functions have no body")
+    );
+}
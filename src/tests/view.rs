@@ -0,0 +1,84 @@
+use crate::*;
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+
+const MODULE: &[u8] = br#"(module
+  (import "env" "log" (func (param i32)))
+  (memory 1)
+  (func (export "main") (call 0 (i32.const 8)))
+  (data (i32.const 8) "hello"))"#;
+
+fn borrows_from(bytes: &[u8], s: &[u8]) -> bool {
+    bytes.as_ptr_range().contains(&s.as_ptr())
+}
+
+fn import_name<'a, 'b>(p: &'b ProgramView<'a>) -> &'b Cow<'a, str> {
+    p.sections
+        .iter()
+        .find_map(|s| match s {
+            Section::Import(i) => match &i.imports[0] {
+                WasmImport::Function(f) => Some(&f.name),
+                _ => None,
+            },
+            _ => None,
+        })
+        .unwrap()
+}
+
+fn data_mut<'b, 'a>(p: &'b mut ProgramView<'a>) -> &'b mut Cow<'a, [u8]> {
+    p.sections
+        .iter_mut()
+        .find_map(|s| match s {
+            Section::Data(d) => Some(&mut d.data_blocks[0].data),
+            _ => None,
+        })
+        .unwrap()
+}
+
+#[test]
+fn a_mutated_view_still_borrows_what_it_did_not_change() {
+    let bytes = parse_wat(MODULE).unwrap().compile().unwrap();
+    let mut view = parse(&bytes).unwrap();
+    match import_name(&view) {
+        Cow::Borrowed(name) => assert!(borrows_from(&bytes, name.as_bytes())),
+        Cow::Owned(_) => panic!("the import name was copied"),
+    }
+    match data_mut(&mut view) {
+        Cow::Borrowed(data) => assert!(borrows_from(&bytes, data)),
+        Cow::Owned(_) => panic!("the data was copied"),
+    }
+
+    // writing to the data copies it, the rest of the view is left borrowing
+    data_mut(&mut view).to_mut()[0] = b'j';
+    assert!(matches!(data_mut(&mut view), Cow::Owned(_)));
+    assert!(matches!(import_name(&view), Cow::Borrowed(_)));
+    assert_eq!(&bytes[bytes.len() - 5..], b"hello");
+
+    let expected = parse_wat(
+        br#"(module
+          (import "env" "log" (func (param i32)))
+          (memory 1)
+          (func (export "main") (call 0 (i32.const 8)))
+          (data (i32.const 8) "jello"))"#,
+    )
+    .unwrap()
+    .compile()
+    .unwrap();
+    assert_eq!(view.compile().unwrap(), expected);
+}
+
+#[test]
+fn an_owned_view_outlives_its_bytes() {
+    let expected = parse_wat(MODULE).unwrap().compile().unwrap();
+    let owned: Program = {
+        let bytes: Vec<u8> = expected.clone();
+        let view = parse(&bytes).unwrap();
+        assert!(matches!(import_name(&view), Cow::Borrowed(_)));
+        let copy = view.to_owned();
+        assert_eq!(copy, view);
+        view.into_owned()
+    };
+    // the buffer is gone, nothing in the module points into it any more
+    assert!(matches!(import_name(&owned), Cow::Owned(_)));
+    assert_eq!(owned.compile().unwrap(), expected);
+}
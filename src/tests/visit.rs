@@ -0,0 +1,152 @@
+use crate::core::*;
+use crate::*;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const MODULE: &[u8] = br#"(module
+  (global i32 (i32.const 7))
+  (func (param i32)
+    i32.const 1
+    block
+      loop
+        br 1
+      end
+    end
+    local.get 0
+    if
+      nop
+    else
+      unreachable
+    end
+    drop))"#;
+
+#[derive(Default)]
+struct Events(Vec<String>);
+
+impl Visit for Events {
+    fn visit_code_block(&mut self, index: FuncIdx, code_block: &CodeBlock) {
+        self.0.push(format!("function {}", index.0));
+        walk_instructions(self, &code_block.instructions);
+    }
+
+    fn visit_const_expression(&mut self, expression: &[Instruction]) {
+        self.0.push(String::from("const"));
+        walk_instructions(self, expression);
+    }
+
+    fn visit_instruction(&mut self, instruction: &Instruction) {
+        self.0.push(format!("{:?}", instruction.category()));
+        walk_instruction(self, instruction);
+    }
+
+    fn enter_block(&mut self, kind: BlockKind, _block_type: u8) {
+        self.0.push(format!("enter {:?}", kind));
+    }
+
+    fn exit_block(&mut self, kind: BlockKind) {
+        self.0.push(format!("exit {:?}", kind));
+    }
+}
+
+#[test]
+fn instructions_are_visited_in_order_with_bodies_between_enter_and_exit() {
+    let p = parse_wat(MODULE).unwrap();
+    let mut events = Events::default();
+    events.visit_program(&p);
+    let expected = [
+        "const",
+        "Numeric",
+        "function 0",
+        "Numeric",
+        "Control",
+        "enter Block",
+        "Control",
+        "enter Loop",
+        "Control",
+        "exit Loop",
+        "exit Block",
+        "Variable",
+        "Control",
+        "enter If",
+        "Control",
+        "exit If",
+        "enter Else",
+        "Control",
+        "exit Else",
+        "Parametric",
+    ];
+    assert_eq!(events.0, expected);
+}
+
+// numbers every instruction in the order `fold_instruction` sees it, and every body in the
+// order `fold_body` finishes it
+#[derive(Default)]
+struct Order {
+    instructions: Vec<String>,
+    bodies: Vec<BlockKind>,
+}
+
+impl Fold for Order {
+    fn fold_body(
+        &mut self,
+        kind: BlockKind,
+        block_type: u8,
+        body: Vec<Instruction>,
+    ) -> Vec<Instruction> {
+        let body = walk_fold_body(self, kind, block_type, body);
+        self.bodies.push(kind);
+        body
+    }
+
+    fn fold_instruction(&mut self, instruction: Instruction) -> Vec<Instruction> {
+        self.instructions
+            .push(format!("{:?}", instruction.category()));
+        match instruction {
+            Instruction::Nop => vec![],
+            Instruction::Unreachable => vec![Instruction::Nop, Instruction::Unreachable],
+            i => vec![i],
+        }
+    }
+}
+
+#[test]
+fn bodies_are_folded_before_their_instruction() {
+    let mut p = parse_wat(MODULE).unwrap();
+    let mut order = Order::default();
+    order.fold_program(&mut p);
+    assert_eq!(
+        order.instructions,
+        [
+            "Numeric",
+            "Numeric",
+            "Control",
+            "Control",
+            "Control",
+            "Variable",
+            "Control",
+            "Control",
+            "Control",
+            "Parametric"
+        ]
+    );
+    assert_eq!(
+        order.bodies,
+        [
+            BlockKind::Loop,
+            BlockKind::Block,
+            BlockKind::If,
+            BlockKind::Else
+        ]
+    );
+    match p.function(FuncIdx(0)).unwrap() {
+        FunctionRef::Defined { body, .. } => match &body[3] {
+            Instruction::If(_, then, Some(otherwise)) => {
+                assert!(then.is_empty());
+                assert_eq!(otherwise, &vec![Instruction::Nop, Instruction::Unreachable]);
+            }
+            x => panic!("unexpected instruction {:?}", x),
+        },
+        FunctionRef::Imported { .. } => panic!("function 0 is imported"),
+    }
+}
@@ -0,0 +1,198 @@
+use crate::core::*;
+use crate::*;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+// printing and parsing back compiles to the same bytes, flat and folded
+fn round_trip(p: &Module) -> [String; 2] {
+    let expected = p.compile().unwrap();
+    let mut texts = [String::new(), String::new()];
+    for (n, folded) in [false, true].iter().enumerate() {
+        let text = p.to_wat_with_options(&WatOptions { folded: *folded });
+        let parsed = match parse_wat(text.as_bytes()) {
+            Ok(parsed) => parsed,
+            Err(e) => panic!("{}\n{}", e, text),
+        };
+        assert_eq!(parsed.compile().unwrap(), expected, "{}", text);
+        texts[n] = text;
+    }
+    texts
+}
+
+fn set_body(p: &mut Program, index: usize, instructions: Vec<Instruction>) {
+    for s in p.sections.iter_mut() {
+        if let Section::Code(c) = s {
+            c.code_blocks[index].instructions = instructions.clone();
+        }
+    }
+}
+
+#[test]
+fn bodies_round_trip_flat_and_folded() {
+    let p = parse_wat(
+        br#"(module
+          (type $t (func (param i32) (result i32)))
+          (import "env" "log" (func $log (param i32)))
+          (import "env" "g" (global $imported i32))
+          (memory 1 2)
+          (table 2 funcref)
+          (global $g (mut i64) (i64.const -1))
+          (func $f (type $t) (local f32 f64)
+            (block $out (result i32)
+              (loop $top
+                (br_table $out $top $out (local.get 0) (local.get 0))))
+            (if (result i32) (i32.const 1)
+              (then (i32.load8_u offset=4 align=1 (i32.const 0)))
+              (else (call_indirect (type $t) (i32.const 0) (i32.const 1))))
+            i32.add)
+          (func (export "run")
+            block
+              i32.const 0
+              br_table 0 0
+            end
+            (call $log (i32.const 7))
+            (global.set $g (i64.const 3))
+            (f32.store (i32.const 8) (f32.const -0.5))
+            (drop (f64.const nan:0x1))
+            (drop (select (i32.const 1) (i32.const 2) (global.get $imported))))
+          (elem (i32.const 0) $f 2)
+          (data (i32.const 16) "hi\00\ff")
+          (start 2))"#,
+    )
+    .unwrap();
+    let [flat, folded] = round_trip(&p);
+    // a flat br_table is followed by the end of its block
+    assert!(flat.contains("br_table 0 0\n"));
+    assert!(folded.contains("(br_table 1 0 1 (local.get 0) (local.get 0))"));
+}
+
+#[test]
+fn raw_instructions_round_trip() {
+    let mut p = parse_wat(
+        br#"(module
+          (global i32 (i32.const 0))
+          (func (result i32) (i32.const 0))
+          (func (drop (i32.const 1))))"#,
+    )
+    .unwrap();
+    // i32.const 7 and a block around a nop, written byte by byte
+    set_body(
+        &mut p,
+        0,
+        vec![
+            Instruction::Raw(0x02),
+            Instruction::Raw(0x40),
+            Instruction::Nop,
+            Instruction::Raw(0x0b),
+            Instruction::Raw(0x41),
+            Instruction::Raw(0x07),
+        ],
+    );
+    // a raw byte as the operand of a folded instruction
+    set_body(
+        &mut p,
+        1,
+        vec![
+            Instruction::Raw(0x41),
+            Instruction::Raw(0x01),
+            Instruction::Drop,
+        ],
+    );
+    for s in p.sections.iter_mut() {
+        if let Section::Global(g) = s {
+            g.globals[0].value_expression = vec![Instruction::Raw(0x41), Instruction::Raw(0x05)];
+        }
+    }
+    let [flat, folded] = round_trip(&p);
+    for text in [flat, folded].iter() {
+        assert!(text.contains("(@raw 0x02 (; block ;))"));
+        assert!(text.contains("(@raw 0x0b (; end ;))"));
+        assert!(text.contains("(global (;0;) i32 (@raw 0x41) (@raw 0x05 (; else ;)))"));
+    }
+}
+
+#[test]
+fn names_round_trip() {
+    let mut p = parse_wat(
+        br#"(module
+          (import "env" "log" (func (param i32)))
+          (func (param i32) (local i64)
+            (call 0 (local.get 0)))
+          (func (export "main") (call 1 (i32.const 1))))"#,
+    )
+    .unwrap();
+    let names = NameSection {
+        module_name: Some("m".to_string()),
+        function_names: vec![
+            (FuncIdx(0), "log".to_string()),
+            (FuncIdx(1), "helper".to_string()),
+            // not every name is a valid identifier
+            (FuncIdx(2), "main (entry)".to_string()),
+        ],
+        local_names: vec![(
+            FuncIdx(1),
+            vec![
+                (LocalIdx(0), "x".to_string()),
+                (LocalIdx(1), "y".to_string()),
+            ],
+        )],
+    };
+    let mut data = Vec::new();
+    names.extend_wasm_bytes(&mut data);
+    p.sections.push(Section::Custom(CustomSection {
+        name: "name".into(),
+        data: data.into(),
+    }));
+    let [flat, folded] = round_trip(&p);
+    assert!(flat.starts_with("(module $m"));
+    assert!(flat.contains("(func $helper (;1;) (type 0) (param $x i32)"));
+    assert!(flat.contains("(local $y i64)"));
+    assert!(flat.contains("local.get $x"));
+    assert!(folded.contains("(call $log (local.get $x))"));
+}
+
+#[test]
+fn custom_sections_round_trip() {
+    let mut p = parse_wat(b"(module (func))").unwrap();
+    for (name, data) in [
+        ("producers", &b"\x01\x00)\" ;; (;"[..]),
+        ("empty", &b""[..]),
+    ]
+    .iter()
+    {
+        p.sections.push(Section::Custom(CustomSection {
+            name: (*name).into(),
+            data: data.to_vec().into(),
+        }));
+    }
+    let [flat, _] = round_trip(&p);
+    assert!(flat.contains(r#"(@custom "producers" "\01\00)\" ;; (;")"#));
+    assert!(flat.ends_with("(@custom \"empty\" \"\"))\n"));
+}
+
+#[test]
+fn example_modules_round_trip() {
+    for bytes in [
+        &include_bytes!("../../examples/wq/main.wasm")[..],
+        &include_bytes!("../../examples/simulator/simple.wasm")[..],
+        &include_bytes!("../../examples/bf/print_a.wasm")[..],
+    ]
+    .iter()
+    {
+        round_trip(&parse(bytes).unwrap());
+    }
+}
+
+#[test]
+fn a_missing_code_block_is_a_block_comment() {
+    let mut p = parse_wat(b"(module (func) (func))").unwrap();
+    for s in p.sections.iter_mut() {
+        if let Section::Code(c) = s {
+            c.code_blocks.pop();
+        }
+    }
+    let text = p.to_wat();
+    assert!(text.contains("(; missing code block ;))"));
+    assert!(parse_wat(text.as_bytes()).is_ok());
+}
@@ -1,6 +1,8 @@
 use alloc::vec::Vec;
 
-pub fn tag(tag: &[u8]) -> impl Fn(&[u8]) -> Result<(&[u8], &[u8]), &'static str> + '_ {
+pub type ParseResult<'a, T> = Result<(&'a [u8], T), &'static str>;
+
+pub fn tag(tag: &[u8]) -> impl Fn(&[u8]) -> ParseResult<'_, &[u8]> + '_ {
     move |input: &[u8]| {
         if tag.len() > input.len() {
             return Err("trying to tag too many bytes");
@@ -14,7 +16,7 @@ pub fn tag(tag: &[u8]) -> impl Fn(&[u8]) -> Result<(&[u8], &[u8]), &'static str>
     }
 }
 
-pub fn take(num: usize) -> impl Fn(&[u8]) -> Result<(&[u8], &[u8]), &'static str> {
+pub fn take(num: usize) -> impl Fn(&[u8]) -> ParseResult<'_, &[u8]> {
     move |input: &[u8]| {
         if num > input.len() {
             return Err("trying to take too many bytes");
@@ -25,8 +27,8 @@ pub fn take(num: usize) -> impl Fn(&[u8]) -> Result<(&[u8], &[u8]), &'static str
 
 pub fn many_n<'a, T>(
     n: usize,
-    f: impl Fn(&'a [u8]) -> Result<(&'a [u8], T), &'static str>,
-) -> impl Fn(&'a [u8]) -> Result<(&'a [u8], Vec<T>), &'static str> {
+    f: impl Fn(&'a [u8]) -> ParseResult<'a, T>,
+) -> impl Fn(&'a [u8]) -> ParseResult<'a, Vec<T>> {
     move |input: &[u8]| {
         let mut v = vec![];
         let mut ip = input;
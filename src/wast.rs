@@ -0,0 +1,558 @@
+use crate::core::wast::*;
+use crate::core::*;
+use crate::interpreter::*;
+use crate::parser::wasm::wasm_module;
+use crate::parser::wast::wast_file;
+use crate::parser::wat::wat_module;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use serde::{Deserialize, Serialize};
+use spin::Mutex;
+use webassembly::{ANYFUNC, DESC_FUNCTION, DESC_GLOBAL, DESC_MEMORY, DESC_TABLE};
+
+const PAGE_SIZE: usize = 65536;
+// calls that cross from one instance into another nest a new execution
+const MAX_INSTANCE_DEPTH: usize = 64;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub struct WastResult {
+    pub line: usize,
+    pub passed: bool,
+    pub message: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub struct WastReport {
+    pub results: Vec<WastResult>,
+}
+
+impl WastReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|x| x.passed).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|x| !x.passed).count()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|x| x.passed)
+    }
+}
+
+// where a function import of an instance ends up, None being a host function
+// of the `spectest` module
+struct FunctionLink {
+    module_name: String,
+    name: String,
+    target: FunctionTarget,
+}
+
+type Memory = Arc<Mutex<Vec<u8>>>;
+type FunctionTarget = Option<(usize, FuncIdx)>;
+
+struct Imports {
+    functions: Vec<FunctionLink>,
+    globals: Vec<WasmValue>,
+    memory: Option<Memory>,
+}
+
+struct Instance {
+    interpreter: Interpreter<Program>,
+    functions: Vec<FunctionLink>,
+}
+
+/// Runs the directives of a `.wast` script against `Interpreter`, providing the
+/// `spectest` host module that the official test suite imports from.
+///
+/// Imported memories are shared with the instance that exports them. Imported
+/// tables are not, the importing instance gets a table of its own.
+pub struct WastRunner {
+    instances: Vec<Instance>,
+    named: Vec<(String, usize)>,
+    registered: Vec<(String, usize)>,
+    current: Option<usize>,
+}
+
+impl Default for WastRunner {
+    fn default() -> Self {
+        WastRunner::new()
+    }
+}
+
+impl WastRunner {
+    pub fn new() -> Self {
+        WastRunner {
+            instances: vec![],
+            named: vec![],
+            registered: vec![],
+            current: None,
+        }
+    }
+
+    pub fn run(&mut self, wast: &Wast) -> WastReport {
+        WastReport {
+            results: wast
+                .directives
+                .iter()
+                .map(|d| self.run_directive(d))
+                .collect(),
+        }
+    }
+
+    pub fn run_directive(&mut self, directive: &WastDirective) -> WastResult {
+        let result = self.run_command(&directive.command);
+        WastResult {
+            line: directive.line,
+            passed: result.is_ok(),
+            message: result.err(),
+        }
+    }
+
+    fn run_command(&mut self, command: &WastCommand) -> Result<(), String> {
+        match command {
+            WastCommand::Module(m) => {
+                self.define(m)?;
+                Ok(())
+            }
+            WastCommand::Register { name, module } => {
+                let instance = self.instance(module)?;
+                self.registered.retain(|(n, _)| n != name);
+                self.registered.push((name.to_string(), instance));
+                Ok(())
+            }
+            WastCommand::Action(action) => {
+                self.perform(action)?;
+                Ok(())
+            }
+            WastCommand::AssertReturn { action, expected } => {
+                let results = self.perform(action)?;
+                let matches = results.len() == expected.len()
+                    && results
+                        .iter()
+                        .zip(expected.iter())
+                        .all(|(r, e)| matches_expected(r, e));
+                if matches {
+                    Ok(())
+                } else {
+                    Err(format!("expected {:?}, got {:?}", expected, results))
+                }
+            }
+            WastCommand::AssertTrap { execute, message } => {
+                let result = match execute {
+                    WastExecute::Action(action) => self.perform(action).map(|_| ()),
+                    WastExecute::Module(m) => self.define(m).map(|_| ()),
+                };
+                expect_error(result, message)
+            }
+            WastCommand::AssertExhaustion { action, message } => {
+                expect_error(self.perform(action).map(|_| ()), message)
+            }
+            WastCommand::AssertMalformed { module, .. } => match parse_module(&module.module) {
+                Ok(_) => Err("module was not malformed".to_string()),
+                Err(_) => Ok(()),
+            },
+            WastCommand::AssertInvalid { module, message } => {
+                let p = parse_module(&module.module)?;
+                expect_error(validate(&p), message)
+            }
+            WastCommand::AssertUnlinkable { module, message } => {
+                let p = parse_module(&module.module)?;
+                validate(&p)?;
+                expect_error(self.link(&p).map(|_| ()), message)
+            }
+        }
+    }
+
+    fn instance(&self, id: &Option<String>) -> Result<usize, String> {
+        match id {
+            Some(id) => match self.named.iter().rev().find(|(n, _)| n == id) {
+                Some((_, i)) => Ok(*i),
+                None => Err(format!("unknown module {}", id)),
+            },
+            None => match self.current {
+                Some(i) => Ok(i),
+                None => Err("no module has been defined".to_string()),
+            },
+        }
+    }
+
+    fn define(&mut self, m: &WastModuleDefinition) -> Result<usize, String> {
+        let p = parse_module(&m.module)?;
+        validate(&p)?;
+        let instance = self.instantiate(p)?;
+        if let Some(id) = &m.id {
+            self.named.push((id.to_string(), instance));
+        }
+        self.current = Some(instance);
+        Ok(instance)
+    }
+
+    fn instantiate(&mut self, p: Program) -> Result<usize, &'static str> {
+        let imports = self.link(&p)?;
+        let mut interpreter = Interpreter::new_with_imported_globals(p, &imports.globals)?;
+        if let Some(memory) = imports.memory {
+            {
+                let program = interpreter.program.lock();
+                let globals = interpreter.globals.lock();
+                program.load_data_into_memory(&mut memory.lock(), &globals)?;
+            }
+            interpreter.memory = memory;
+        }
+        self.instances.push(Instance {
+            interpreter,
+            functions: imports.functions,
+        });
+        let index = self.instances.len() - 1;
+        let start = self.instances[index]
+            .interpreter
+            .program
+            .lock()
+            .start_fn_index();
+        if let Some(fn_index) = start {
+            self.invoke(index, fn_index, &[], 0)?;
+        }
+        Ok(index)
+    }
+
+    fn link(&self, p: &Program) -> Result<Imports, &'static str> {
+        let mut functions = vec![];
+        let mut globals = vec![];
+        let mut memory = None;
+        for s in p.sections.iter() {
+            let imports = match s {
+                Section::Import(i) => &i.imports,
+                _ => continue,
+            };
+            for import in imports.iter() {
+                match import {
+                    WasmImport::Function(f) => {
+                        let expected = p.type_details(f.type_index)?;
+                        let (target, actual) = self.resolve_function(&f.module_name, &f.name)?;
+                        if *expected != actual {
+                            return Err("incompatible import type");
+                        }
+                        functions.push(FunctionLink {
+                            module_name: f.module_name.to_string(),
+                            name: f.name.to_string(),
+                            target,
+                        });
+                    }
+                    WasmImport::Global(g) => {
+                        let (value, is_mutable) = self.resolve_global(&g.module_name, &g.name)?;
+                        if !has_type(&value, g.value_type) || is_mutable != g.is_mutable {
+                            return Err("incompatible import type");
+                        }
+                        globals.push(value);
+                    }
+                    WasmImport::Memory(m) => {
+                        let (shared, min, max) = self.resolve_memory(&m.module_name, &m.name)?;
+                        if !limits_match(min, max, m.min_pages, m.max_pages) {
+                            return Err("incompatible import type");
+                        }
+                        memory = shared;
+                    }
+                    WasmImport::Table(t) => {
+                        let (min, max) = self.resolve_table(&t.module_name, &t.name)?;
+                        if t.element_type != ANYFUNC || !limits_match(min, max, t.min, t.max) {
+                            return Err("incompatible import type");
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Imports {
+            functions,
+            globals,
+            memory,
+        })
+    }
+
+    fn registered_export(
+        &self,
+        module_name: &str,
+        name: &str,
+        desc: u8,
+    ) -> Result<(usize, usize), &'static str> {
+        let instance = match self.registered.iter().find(|(n, _)| n == module_name) {
+            Some((_, i)) => *i,
+            None => return Err("unknown import"),
+        };
+        match self.instances[instance]
+            .interpreter
+            .program
+            .lock()
+            .fetch_export(name)
+        {
+            Ok((d, index)) if d == desc => Ok((instance, index)),
+            Ok(_) => Err("incompatible import type"),
+            Err(_) => Err("unknown import"),
+        }
+    }
+
+    fn resolve_function(
+        &self,
+        module_name: &str,
+        name: &str,
+    ) -> Result<(FunctionTarget, FunctionType), &'static str> {
+        use ValueType::*;
+        if module_name == "spectest" {
+            let inputs = match name {
+                "print" => vec![],
+                "print_i32" => vec![I32],
+                "print_i64" => vec![I64],
+                "print_f32" => vec![F32],
+                "print_f64" => vec![F64],
+                "print_i32_f32" => vec![I32, F32],
+                "print_f64_f64" => vec![F64, F64],
+                _ => return Err("unknown import"),
+            };
+            let outputs = vec![];
+            return Ok((None, FunctionType { inputs, outputs }));
+        }
+        let (instance, index) = self.registered_export(module_name, name, DESC_FUNCTION)?;
+        let index = FuncIdx::try_from(index)?;
+        let fn_type = self.instances[instance]
+            .interpreter
+            .program
+            .lock()
+            .fn_type(index)?
+            .clone();
+        Ok((Some((instance, index)), fn_type))
+    }
+
+    fn resolve_global(
+        &self,
+        module_name: &str,
+        name: &str,
+    ) -> Result<(WasmValue, bool), &'static str> {
+        if module_name == "spectest" {
+            return match name {
+                "global_i32" => Ok((WasmValue::I32(666), false)),
+                "global_i64" => Ok((WasmValue::I64(666), false)),
+                "global_f32" => Ok((WasmValue::F32(666.6), false)),
+                "global_f64" => Ok((WasmValue::F64(666.6), false)),
+                _ => Err("unknown import"),
+            };
+        }
+        let (instance, index) = self.registered_export(module_name, name, DESC_GLOBAL)?;
+        let interpreter = &self.instances[instance].interpreter;
+        let is_mutable = global_is_mutable(&interpreter.program.lock(), index);
+        match interpreter.globals.lock().get(index) {
+            Some(v) => Ok((*v, is_mutable)),
+            None => Err("unknown import"),
+        }
+    }
+
+    fn resolve_memory(
+        &self,
+        module_name: &str,
+        name: &str,
+    ) -> Result<(Option<Memory>, usize, Option<usize>), &'static str> {
+        if module_name == "spectest" {
+            return match name {
+                "memory" => Ok((None, 1, Some(2))),
+                _ => Err("unknown import"),
+            };
+        }
+        let (instance, _) = self.registered_export(module_name, name, DESC_MEMORY)?;
+        let interpreter = &self.instances[instance].interpreter;
+        let pages = interpreter.memory.lock().len() / PAGE_SIZE;
+        let max = interpreter.program.lock().max_memory_pages();
+        Ok((Some(interpreter.memory.clone()), pages, max))
+    }
+
+    fn resolve_table(
+        &self,
+        module_name: &str,
+        name: &str,
+    ) -> Result<(usize, Option<usize>), &'static str> {
+        if module_name == "spectest" {
+            return match name {
+                "table" => Ok((10, Some(20))),
+                _ => Err("unknown import"),
+            };
+        }
+        let (instance, _) = self.registered_export(module_name, name, DESC_TABLE)?;
+        let interpreter = &self.instances[instance].interpreter;
+        let size = interpreter.table.lock().len();
+        Ok((size, table_max(&interpreter.program.lock())))
+    }
+
+    fn perform(&mut self, action: &WastAction) -> Result<Vec<WasmValue>, String> {
+        match action {
+            WastAction::Invoke { module, name, args } => {
+                let instance = self.instance(module)?;
+                let export = self.instances[instance]
+                    .interpreter
+                    .program
+                    .lock()
+                    .fetch_export(name)?;
+                match export {
+                    (DESC_FUNCTION, fn_index) => {
+                        Ok(self.invoke(instance, FuncIdx::try_from(fn_index)?, args, 0)?)
+                    }
+                    _ => Err("export is not a function".to_string()),
+                }
+            }
+            WastAction::Get { module, name } => {
+                let instance = self.instance(module)?;
+                Ok(vec![self.instances[instance].interpreter.global(name)?])
+            }
+        }
+    }
+
+    fn invoke(
+        &self,
+        instance: usize,
+        fn_index: FuncIdx,
+        params: &[WasmValue],
+        depth: usize,
+    ) -> Result<Vec<WasmValue>, &'static str> {
+        if depth > MAX_INSTANCE_DEPTH {
+            return Err("call stack exhausted");
+        }
+        let current = &self.instances[instance];
+        if let Some(link) = current.functions.get(fn_index.index()) {
+            return match link.target {
+                Some((i, f)) => self.invoke(i, f, params, depth + 1),
+                None => Ok(vec![]),
+            };
+        }
+        let mut execution = WasmExecution::new(fn_index, params, &current.interpreter)?;
+        loop {
+            let response = match execution.next_unit()? {
+                ExecutionUnit::CallImport(call) => {
+                    let link = current
+                        .functions
+                        .iter()
+                        .find(|x| x.module_name == call.module_name && x.name == call.name);
+                    let mut results = match link.and_then(|x| x.target) {
+                        Some((i, f)) => self.invoke(i, f, &call.params, depth + 1)?,
+                        None => vec![],
+                    };
+                    // values are added to the stack from the back
+                    results.reverse();
+                    ExecutionResponse::AddValues(results)
+                }
+                ExecutionUnit::Complete(v) => return Ok(v),
+                mut x => x.evaluate()?,
+            };
+            execution.execute(response)?;
+        }
+    }
+}
+
+pub fn run_wast(input: &[u8]) -> Result<WastReport, &'static str> {
+    let wast = wast_file(input)?;
+    Ok(WastRunner::new().run(&wast))
+}
+
+fn expect_error(result: Result<(), impl ToString>, message: &str) -> Result<(), String> {
+    match result {
+        Ok(_) => Err(format!("expected \"{}\" but nothing failed", message)),
+        Err(e) => {
+            let e = e.to_string();
+            if e.contains(message) {
+                Ok(())
+            } else {
+                Err(format!("expected \"{}\", got \"{}\"", message, e))
+            }
+        }
+    }
+}
+
+fn matches_expected(v: &WasmValue, expected: &WastExpected) -> bool {
+    match (v, expected) {
+        (WasmValue::F32(a), WastExpected::Value(WasmValue::F32(b))) => a.to_bits() == b.to_bits(),
+        (WasmValue::F64(a), WastExpected::Value(WasmValue::F64(b))) => a.to_bits() == b.to_bits(),
+        (a, WastExpected::Value(b)) => a == b,
+        (WasmValue::F32(a), WastExpected::NanCanonical(ValueType::F32)) => {
+            a.to_bits() & 0x7fff_ffff == 0x7fc0_0000
+        }
+        (WasmValue::F64(a), WastExpected::NanCanonical(ValueType::F64)) => {
+            a.to_bits() & 0x7fff_ffff_ffff_ffff == 0x7ff8_0000_0000_0000
+        }
+        (WasmValue::F32(a), WastExpected::NanArithmetic(ValueType::F32)) => {
+            a.is_nan() && a.to_bits() & 0x0040_0000 != 0
+        }
+        (WasmValue::F64(a), WastExpected::NanArithmetic(ValueType::F64)) => {
+            a.is_nan() && a.to_bits() & 0x0008_0000_0000_0000 != 0
+        }
+        _ => false,
+    }
+}
+
+fn has_type(v: &WasmValue, t: ValueType) -> bool {
+    matches!(
+        (v, t),
+        (WasmValue::I32(_), ValueType::I32)
+            | (WasmValue::I64(_), ValueType::I64)
+            | (WasmValue::F32(_), ValueType::F32)
+            | (WasmValue::F64(_), ValueType::F64)
+    )
+}
+
+fn limits_match(
+    min: usize,
+    max: Option<usize>,
+    import_min: usize,
+    import_max: Option<usize>,
+) -> bool {
+    min >= import_min
+        && match (max, import_max) {
+            (_, None) => true,
+            (Some(max), Some(import_max)) => max <= import_max,
+            (None, Some(_)) => false,
+        }
+}
+
+fn parse_module(module: &WastModule) -> Result<Program, &'static str> {
+    match module {
+        WastModule::Text(s) | WastModule::Quote(s) => wat_module(s.as_bytes()),
+        WastModule::Binary(b) => Ok(wasm_module(b)?.into_owned()),
+    }
+}
+
+fn global_is_mutable(p: &Program, index: usize) -> bool {
+    let mut mutability = vec![];
+    for s in p.sections.iter() {
+        match s {
+            Section::Import(i) => {
+                for x in i.imports.iter() {
+                    if let WasmImport::Global(g) = x {
+                        mutability.push(g.is_mutable);
+                    }
+                }
+            }
+            Section::Global(g) => mutability.extend(g.globals.iter().map(|x| x.is_mutable)),
+            _ => {}
+        }
+    }
+    mutability.get(index) == Some(&true)
+}
+
+fn table_max(p: &Program) -> Option<usize> {
+    for s in p.sections.iter() {
+        match s {
+            Section::Table(t) if !t.tables.is_empty() => return t.tables[0].max,
+            Section::Import(i) => {
+                for x in i.imports.iter() {
+                    if let WasmImport::Table(t) = x {
+                        return t.max;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn validate(p: &Program) -> Result<(), &'static str> {
+    p.validate().map_err(|e| e.message)
+}
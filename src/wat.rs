@@ -0,0 +1,1083 @@
+use crate::core::*;
+use crate::parser::wasm::wasm_name_section;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt::Write;
+use webassembly::*;
+
+#[derive(Clone, Debug, Default)]
+pub struct WatOptions {
+    /// print instructions as nested s-expressions instead of a flat sequence
+    pub folded: bool,
+}
+
+enum ImportKind {
+    Function(TypeIdx),
+    Table(usize, Option<usize>),
+    Memory(usize, Option<usize>),
+    Global(ValueType, bool),
+}
+
+struct Import<'a> {
+    module_name: &'a str,
+    name: &'a str,
+    kind: ImportKind,
+}
+
+struct ExportEntry<'a> {
+    kind: &'static str,
+    name: &'a str,
+    index: usize,
+}
+
+struct DataEntry<'a> {
+    memory: MemIdx,
+    offset_expression: &'a [Instruction],
+    data: &'a [u8],
+}
+
+// a flattened, borrowed picture of a module for the printer
+#[derive(Default)]
+struct Flattened<'a> {
+    types: Vec<&'a FunctionType>,
+    imports: Vec<Import<'a>>,
+    functions: Vec<TypeIdx>,
+    tables: Vec<&'a Table>,
+    memories: Vec<&'a WasmMemory>,
+    globals: Vec<&'a Global>,
+    exports: Vec<ExportEntry<'a>>,
+    start: Option<FuncIdx>,
+    elements: Vec<&'a WasmElement>,
+    code: Vec<&'a CodeBlock>,
+    data: Vec<DataEntry<'a>>,
+    customs: Vec<(&'a str, &'a [u8])>,
+}
+
+impl<'a> Flattened<'a> {
+    fn from_module(program: &'a Module) -> Self {
+        let mut m = Flattened::default();
+        for s in program.sections.iter() {
+            match s {
+                Section::Type(s) => m.types.extend(s.types.iter()),
+                Section::Function(s) => m.functions.extend(s.function_types.iter()),
+                Section::Code(s) => m.code.extend(s.code_blocks.iter()),
+                Section::Export(s) => m.exports.extend(s.exports.iter().map(|x| match x {
+                    WasmExport::Function(e) => ExportEntry {
+                        kind: "func",
+                        name: &e.name,
+                        index: e.index.index(),
+                    },
+                    WasmExport::Table(e) => ExportEntry {
+                        kind: "table",
+                        name: &e.name,
+                        index: e.index.index(),
+                    },
+                    WasmExport::Memory(e) => ExportEntry {
+                        kind: "memory",
+                        name: &e.name,
+                        index: e.index.index(),
+                    },
+                    WasmExport::Global(e) => ExportEntry {
+                        kind: "global",
+                        name: &e.name,
+                        index: e.index.index(),
+                    },
+                })),
+                Section::Import(s) => m.imports.extend(s.imports.iter().map(|x| match x {
+                    WasmImport::Function(i) => Import {
+                        module_name: &i.module_name,
+                        name: &i.name,
+                        kind: ImportKind::Function(i.type_index),
+                    },
+                    WasmImport::Table(i) => Import {
+                        module_name: &i.module_name,
+                        name: &i.name,
+                        kind: ImportKind::Table(i.min, i.max),
+                    },
+                    WasmImport::Memory(i) => Import {
+                        module_name: &i.module_name,
+                        name: &i.name,
+                        kind: ImportKind::Memory(i.min_pages, i.max_pages),
+                    },
+                    WasmImport::Global(i) => Import {
+                        module_name: &i.module_name,
+                        name: &i.name,
+                        kind: ImportKind::Global(i.value_type, i.is_mutable),
+                    },
+                })),
+                Section::Memory(s) => m.memories.extend(s.memories.iter()),
+                Section::Start(s) => m.start = Some(s.start_function),
+                Section::Global(s) => m.globals.extend(s.globals.iter()),
+                Section::Table(s) => m.tables.extend(s.tables.iter()),
+                Section::Data(s) => m.data.extend(s.data_blocks.iter().map(|x| DataEntry {
+                    memory: x.memory,
+                    offset_expression: &x.offset_expression,
+                    data: &x.data,
+                })),
+                Section::Custom(s) => m.customs.push((&*s.name, &*s.data)),
+                Section::Element(s) => m.elements.extend(s.elements.iter()),
+            }
+        }
+        m
+    }
+
+    fn function_type(&self, index: usize) -> Option<&'a FunctionType> {
+        let mut i = index;
+        for import in self.imports.iter() {
+            if let ImportKind::Function(type_index) = import.kind {
+                if i == 0 {
+                    return self.types.get(type_index.index()).copied();
+                }
+                i -= 1;
+            }
+        }
+        match self.functions.get(i) {
+            Some(type_index) => self.types.get(type_index.index()).copied(),
+            None => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Names {
+    module: Option<String>,
+    functions: BTreeMap<FuncIdx, String>,
+    locals: BTreeMap<FuncIdx, BTreeMap<LocalIdx, String>>,
+}
+
+impl Names {
+    fn from_module(module: &Flattened) -> Names {
+        let mut names = Names::default();
+        let section = match module.customs.iter().find(|x| x.0 == "name") {
+            Some((_, data)) => match wasm_name_section(data) {
+                Ok(s) => s,
+                // a broken name section is not a reason to refuse printing
+                Err(_) => return names,
+            },
+            None => return names,
+        };
+        names.module = section.module_name.as_deref().map(identifier);
+        let mut used = Vec::new();
+        for (idx, name) in section.function_names.iter() {
+            let mut id = identifier(name);
+            if used.contains(&id) {
+                id = format!("{}.{}", id, idx);
+            }
+            used.push(id.clone());
+            names.functions.insert(*idx, id);
+        }
+        for (fn_idx, locals) in section.local_names.iter() {
+            let mut used = Vec::new();
+            let mut map = BTreeMap::new();
+            for (idx, name) in locals.iter() {
+                let mut id = identifier(name);
+                if used.contains(&id) {
+                    id = format!("{}.{}", id, idx);
+                }
+                used.push(id.clone());
+                map.insert(*idx, id);
+            }
+            names.locals.insert(*fn_idx, map);
+        }
+        names
+    }
+}
+
+// turns an arbitrary name into a valid text format identifier (without `$`)
+fn identifier(name: &str) -> String {
+    let id: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "!#$%&'*+-./:<=>?@\\^_`|~".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if id.is_empty() {
+        "_".to_string()
+    } else {
+        id
+    }
+}
+
+fn string_literal(bytes: &[u8]) -> String {
+    let mut s = String::from("\"");
+    for b in bytes.iter() {
+        match b {
+            b'"' => s.push_str("\\\""),
+            b'\\' => s.push_str("\\\\"),
+            0x20..=0x7e => s.push(*b as char),
+            _ => {
+                let _ = write!(s, "\\{:02x}", b);
+            }
+        }
+    }
+    s.push('"');
+    s
+}
+
+fn limits(min: usize, max: Option<usize>) -> String {
+    match max {
+        Some(max) => format!("{} {}", min, max),
+        None => format!("{}", min),
+    }
+}
+
+fn f32_literal(f: f32) -> String {
+    if f.is_nan() {
+        let bits = f.to_bits();
+        let sign = if bits >> 31 == 1 { "-" } else { "" };
+        let payload = bits & 0x007f_ffff;
+        if payload == 0x0040_0000 {
+            format!("{}nan", sign)
+        } else {
+            format!("{}nan:0x{:x}", sign, payload)
+        }
+    } else if f.is_infinite() {
+        if f < 0.0 {
+            "-inf".to_string()
+        } else {
+            "inf".to_string()
+        }
+    } else {
+        format!("{:?}", f)
+    }
+}
+
+fn f64_literal(f: f64) -> String {
+    if f.is_nan() {
+        let bits = f.to_bits();
+        let sign = if bits >> 63 == 1 { "-" } else { "" };
+        let payload = bits & 0x000f_ffff_ffff_ffff;
+        if payload == 0x0008_0000_0000_0000 {
+            format!("{}nan", sign)
+        } else {
+            format!("{}nan:0x{:x}", sign, payload)
+        }
+    } else if f.is_infinite() {
+        if f < 0.0 {
+            "-inf".to_string()
+        } else {
+            "inf".to_string()
+        }
+    } else {
+        format!("{:?}", f)
+    }
+}
+
+fn block_type(t: u8) -> Option<String> {
+    if t == EMPTY {
+        None
+    } else {
+        match ValueType::try_from(t) {
+            Ok(v) => Some(format!("(result {})", value_type_name(v))),
+            Err(_) => Some(format!("(; unknown block type 0x{:02x} ;)", t)),
+        }
+    }
+}
+
+// raw bytes are an annotation the text parser reads back, with the name of a structured opcode
+// in a comment
+fn raw_annotation(b: u8) -> String {
+    let name = match b {
+        BLOCK => " (; block ;)",
+        LOOP => " (; loop ;)",
+        IF => " (; if ;)",
+        ELSE => " (; else ;)",
+        END => " (; end ;)",
+        _ => "",
+    };
+    format!("(@raw 0x{:02x}{})", b, name)
+}
+
+pub(crate) fn instruction_mnemonic(i: &Instruction) -> &'static str {
+    match i {
+        Instruction::Raw(_) => "raw",
+        Instruction::Unreachable => "unreachable",
+        Instruction::Nop => "nop",
+        Instruction::Block(_, _) => "block",
+        Instruction::Loop(_, _) => "loop",
+        Instruction::If(_, _, _) => "if",
+        Instruction::Br(_) => "br",
+        Instruction::BrIf(_) => "br_if",
+        Instruction::BrTable(_, _) => "br_table",
+        Instruction::Return => "return",
+        Instruction::Call(_) => "call",
+        Instruction::CallIndirect(_) => "call_indirect",
+        Instruction::Drop => "drop",
+        Instruction::Select => "select",
+        Instruction::LocalGet(_) => "local.get",
+        Instruction::LocalSet(_) => "local.set",
+        Instruction::LocalTee(_) => "local.tee",
+        Instruction::GlobalGet(_) => "global.get",
+        Instruction::GlobalSet(_) => "global.set",
+        Instruction::I32Load(_, _) => "i32.load",
+        Instruction::I64Load(_, _) => "i64.load",
+        Instruction::F32Load(_, _) => "f32.load",
+        Instruction::F64Load(_, _) => "f64.load",
+        Instruction::I32Load8S(_, _) => "i32.load8_s",
+        Instruction::I32Load8U(_, _) => "i32.load8_u",
+        Instruction::I32Load16S(_, _) => "i32.load16_s",
+        Instruction::I32Load16U(_, _) => "i32.load16_u",
+        Instruction::I64Load8S(_, _) => "i64.load8_s",
+        Instruction::I64Load8U(_, _) => "i64.load8_u",
+        Instruction::I64Load16S(_, _) => "i64.load16_s",
+        Instruction::I64Load16U(_, _) => "i64.load16_u",
+        Instruction::I64Load32S(_, _) => "i64.load32_s",
+        Instruction::I64Load32U(_, _) => "i64.load32_u",
+        Instruction::I32Store(_, _) => "i32.store",
+        Instruction::I64Store(_, _) => "i64.store",
+        Instruction::F32Store(_, _) => "f32.store",
+        Instruction::F64Store(_, _) => "f64.store",
+        Instruction::I32Store8(_, _) => "i32.store8",
+        Instruction::I32Store16(_, _) => "i32.store16",
+        Instruction::I64Store8(_, _) => "i64.store8",
+        Instruction::I64Store16(_, _) => "i64.store16",
+        Instruction::I64Store32(_, _) => "i64.store32",
+        Instruction::MemorySize => "memory.size",
+        Instruction::MemoryGrow => "memory.grow",
+        Instruction::I32Const(_) => "i32.const",
+        Instruction::I64Const(_) => "i64.const",
+        Instruction::F32Const(_) => "f32.const",
+        Instruction::F64Const(_) => "f64.const",
+        Instruction::I32Eqz => "i32.eqz",
+        Instruction::I32Eq => "i32.eq",
+        Instruction::I32Ne => "i32.ne",
+        Instruction::I32LtS => "i32.lt_s",
+        Instruction::I32LtU => "i32.lt_u",
+        Instruction::I32GtS => "i32.gt_s",
+        Instruction::I32GtU => "i32.gt_u",
+        Instruction::I32LeS => "i32.le_s",
+        Instruction::I32LeU => "i32.le_u",
+        Instruction::I32GeS => "i32.ge_s",
+        Instruction::I32GeU => "i32.ge_u",
+        Instruction::I64Eqz => "i64.eqz",
+        Instruction::I64Eq => "i64.eq",
+        Instruction::I64Ne => "i64.ne",
+        Instruction::I64LtS => "i64.lt_s",
+        Instruction::I64LtU => "i64.lt_u",
+        Instruction::I64GtS => "i64.gt_s",
+        Instruction::I64GtU => "i64.gt_u",
+        Instruction::I64LeS => "i64.le_s",
+        Instruction::I64LeU => "i64.le_u",
+        Instruction::I64GeS => "i64.ge_s",
+        Instruction::I64GeU => "i64.ge_u",
+        Instruction::F32Eq => "f32.eq",
+        Instruction::F32Ne => "f32.ne",
+        Instruction::F32Lt => "f32.lt",
+        Instruction::F32Gt => "f32.gt",
+        Instruction::F32Le => "f32.le",
+        Instruction::F32Ge => "f32.ge",
+        Instruction::F64Eq => "f64.eq",
+        Instruction::F64Ne => "f64.ne",
+        Instruction::F64Lt => "f64.lt",
+        Instruction::F64Gt => "f64.gt",
+        Instruction::F64Le => "f64.le",
+        Instruction::F64Ge => "f64.ge",
+        Instruction::I32Clz => "i32.clz",
+        Instruction::I32Ctz => "i32.ctz",
+        Instruction::I32Popcnt => "i32.popcnt",
+        Instruction::I32Add => "i32.add",
+        Instruction::I32Sub => "i32.sub",
+        Instruction::I32Mul => "i32.mul",
+        Instruction::I32DivS => "i32.div_s",
+        Instruction::I32DivU => "i32.div_u",
+        Instruction::I32RemS => "i32.rem_s",
+        Instruction::I32RemU => "i32.rem_u",
+        Instruction::I32And => "i32.and",
+        Instruction::I32Or => "i32.or",
+        Instruction::I32Xor => "i32.xor",
+        Instruction::I32Shl => "i32.shl",
+        Instruction::I32ShrS => "i32.shr_s",
+        Instruction::I32ShrU => "i32.shr_u",
+        Instruction::I32Rotl => "i32.rotl",
+        Instruction::I32Rotr => "i32.rotr",
+        Instruction::I64Clz => "i64.clz",
+        Instruction::I64Ctz => "i64.ctz",
+        Instruction::I64Popcnt => "i64.popcnt",
+        Instruction::I64Add => "i64.add",
+        Instruction::I64Sub => "i64.sub",
+        Instruction::I64Mul => "i64.mul",
+        Instruction::I64DivS => "i64.div_s",
+        Instruction::I64DivU => "i64.div_u",
+        Instruction::I64RemS => "i64.rem_s",
+        Instruction::I64RemU => "i64.rem_u",
+        Instruction::I64And => "i64.and",
+        Instruction::I64Or => "i64.or",
+        Instruction::I64Xor => "i64.xor",
+        Instruction::I64Shl => "i64.shl",
+        Instruction::I64ShrS => "i64.shr_s",
+        Instruction::I64ShrU => "i64.shr_u",
+        Instruction::I64Rotl => "i64.rotl",
+        Instruction::I64Rotr => "i64.rotr",
+        Instruction::F32Abs => "f32.abs",
+        Instruction::F32Neg => "f32.neg",
+        Instruction::F32Ceil => "f32.ceil",
+        Instruction::F32Floor => "f32.floor",
+        Instruction::F32Trunc => "f32.trunc",
+        Instruction::F32Nearest => "f32.nearest",
+        Instruction::F32Sqrt => "f32.sqrt",
+        Instruction::F32Add => "f32.add",
+        Instruction::F32Sub => "f32.sub",
+        Instruction::F32Mul => "f32.mul",
+        Instruction::F32Div => "f32.div",
+        Instruction::F32Min => "f32.min",
+        Instruction::F32Max => "f32.max",
+        Instruction::F32Copysign => "f32.copysign",
+        Instruction::F64Abs => "f64.abs",
+        Instruction::F64Neg => "f64.neg",
+        Instruction::F64Ceil => "f64.ceil",
+        Instruction::F64Floor => "f64.floor",
+        Instruction::F64Trunc => "f64.trunc",
+        Instruction::F64Nearest => "f64.nearest",
+        Instruction::F64Sqrt => "f64.sqrt",
+        Instruction::F64Add => "f64.add",
+        Instruction::F64Sub => "f64.sub",
+        Instruction::F64Mul => "f64.mul",
+        Instruction::F64Div => "f64.div",
+        Instruction::F64Min => "f64.min",
+        Instruction::F64Max => "f64.max",
+        Instruction::F64Copysign => "f64.copysign",
+        Instruction::I32wrapF64 => "i32.wrap_i64",
+        Instruction::I32TruncSF32 => "i32.trunc_f32_s",
+        Instruction::I32TruncUF32 => "i32.trunc_f32_u",
+        Instruction::I32TruncSF64 => "i32.trunc_f64_s",
+        Instruction::I32TruncUF64 => "i32.trunc_f64_u",
+        Instruction::I64ExtendSI32 => "i64.extend_i32_s",
+        Instruction::I64ExtendUI32 => "i64.extend_i32_u",
+        Instruction::I64TruncSF32 => "i64.trunc_f32_s",
+        Instruction::I64TruncUF32 => "i64.trunc_f32_u",
+        Instruction::I64TruncSF64 => "i64.trunc_f64_s",
+        Instruction::I64TruncUF64 => "i64.trunc_f64_u",
+        Instruction::F32ConvertSI32 => "f32.convert_i32_s",
+        Instruction::F32ConvertUI32 => "f32.convert_i32_u",
+        Instruction::F32ConvertSI64 => "f32.convert_i64_s",
+        Instruction::F32ConvertUI64 => "f32.convert_i64_u",
+        Instruction::F32DemoteF64 => "f32.demote_f64",
+        Instruction::F64ConvertSI32 => "f64.convert_i32_s",
+        Instruction::F64ConvertUI32 => "f64.convert_i32_u",
+        Instruction::F64ConvertSI64 => "f64.convert_i64_s",
+        Instruction::F64ConvertUI64 => "f64.convert_i64_u",
+        Instruction::F64PromoteF32 => "f64.promote_f32",
+        Instruction::I32ReinterpretF32 => "i32.reinterpret_f32",
+        Instruction::I64ReinterpretF64 => "i64.reinterpret_f64",
+        Instruction::F32ReinterpretI32 => "f32.reinterpret_i32",
+        Instruction::F64ReinterpretI64 => "f64.reinterpret_i64",
+    }
+}
+
+// how many values an instruction pops and pushes, used to decide what can be
+// folded; None means the effect is not known statically
+fn stack_arity(
+    module: &Flattened,
+    labels: &[usize],
+    results: usize,
+    i: &Instruction,
+) -> Option<(usize, usize)> {
+    let label = |depth: u32| {
+        if (depth as usize) < labels.len() {
+            Some(labels[labels.len() - 1 - depth as usize])
+        } else {
+            None
+        }
+    };
+    let arity = match i {
+        Instruction::Br(d) => (label(*d)?, 0),
+        Instruction::BrIf(d) => {
+            let n = label(*d)?;
+            (n + 1, n)
+        }
+        Instruction::BrTable(_, d) => (label(*d)? + 1, 0),
+        Instruction::Return => (results, 0),
+        Instruction::Call(f) => {
+            let t = module.function_type(f.index())?;
+            (t.inputs.len(), t.outputs.len())
+        }
+        Instruction::CallIndirect(t) => {
+            let t = module.types.get(t.index())?;
+            (t.inputs.len() + 1, t.outputs.len())
+        }
+        _ => return i.stack_effect(),
+    };
+    Some(arity)
+}
+
+// an instruction in folded form along with the operands folded into it
+struct Expr {
+    head: String,
+    operands: Vec<Expr>,
+    arms: Vec<(&'static str, Vec<Expr>)>,
+    pushes: usize,
+    complete: bool,
+}
+
+struct Printer<'a, 'm> {
+    module: &'m Flattened<'a>,
+    names: Names,
+    options: &'m WatOptions,
+    out: String,
+    indent: usize,
+}
+
+impl<'a, 'm> Printer<'a, 'm> {
+    fn line(&mut self, text: &str) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(text);
+    }
+
+    fn func_ref(&self, index: FuncIdx) -> String {
+        match self.names.functions.get(&index) {
+            Some(name) => format!("${}", name),
+            None => format!("{}", index),
+        }
+    }
+
+    fn local_ref(&self, func: Option<FuncIdx>, index: LocalIdx) -> String {
+        let name = func
+            .and_then(|f| self.names.locals.get(&f))
+            .and_then(|locals| locals.get(&index));
+        match name {
+            Some(name) => format!("${}", name),
+            None => format!("{}", index),
+        }
+    }
+
+    // the text of an instruction with its immediates, without any nesting
+    fn plain(&self, func: Option<FuncIdx>, i: &Instruction) -> String {
+        let mnemonic = instruction_mnemonic(i);
+        match i {
+            Instruction::Raw(b) => raw_annotation(*b),
+            Instruction::Block(t, _) | Instruction::Loop(t, _) | Instruction::If(t, _, _) => {
+                match block_type(*t) {
+                    Some(bt) => format!("{} {}", mnemonic, bt),
+                    None => mnemonic.to_string(),
+                }
+            }
+            Instruction::Br(d) | Instruction::BrIf(d) => format!("{} {}", mnemonic, d),
+            Instruction::BrTable(labels, default) => {
+                let mut s = mnemonic.to_string();
+                for l in labels.iter() {
+                    let _ = write!(s, " {}", l);
+                }
+                let _ = write!(s, " {}", default);
+                s
+            }
+            Instruction::Call(f) => format!("{} {}", mnemonic, self.func_ref(*f)),
+            Instruction::CallIndirect(t) => format!("{} (type {})", mnemonic, t),
+            Instruction::LocalGet(l) | Instruction::LocalSet(l) | Instruction::LocalTee(l) => {
+                format!("{} {}", mnemonic, self.local_ref(func, *l))
+            }
+            Instruction::GlobalGet(g) | Instruction::GlobalSet(g) => {
+                format!("{} {}", mnemonic, g)
+            }
+            Instruction::I32Load(align, offset)
+            | Instruction::I64Load(align, offset)
+            | Instruction::F32Load(align, offset)
+            | Instruction::F64Load(align, offset)
+            | Instruction::I32Load8S(align, offset)
+            | Instruction::I32Load8U(align, offset)
+            | Instruction::I32Load16S(align, offset)
+            | Instruction::I32Load16U(align, offset)
+            | Instruction::I64Load8S(align, offset)
+            | Instruction::I64Load8U(align, offset)
+            | Instruction::I64Load16S(align, offset)
+            | Instruction::I64Load16U(align, offset)
+            | Instruction::I64Load32S(align, offset)
+            | Instruction::I64Load32U(align, offset)
+            | Instruction::I32Store(align, offset)
+            | Instruction::I64Store(align, offset)
+            | Instruction::F32Store(align, offset)
+            | Instruction::F64Store(align, offset)
+            | Instruction::I32Store8(align, offset)
+            | Instruction::I32Store16(align, offset)
+            | Instruction::I64Store8(align, offset)
+            | Instruction::I64Store16(align, offset)
+            | Instruction::I64Store32(align, offset) => {
+                let mut s = mnemonic.to_string();
+                if *offset != 0 {
+                    let _ = write!(s, " offset={}", offset);
+                }
+                if *align != natural_alignment(i) {
+                    if *align < 32 {
+                        let _ = write!(s, " align={}", 1u64 << align);
+                    } else {
+                        let _ = write!(s, " (; align exponent {} ;)", align);
+                    }
+                }
+                s
+            }
+            Instruction::I32Const(c) => format!("{} {}", mnemonic, c),
+            Instruction::I64Const(c) => format!("{} {}", mnemonic, c),
+            Instruction::F32Const(c) => format!("{} {}", mnemonic, f32_literal(*c)),
+            Instruction::F64Const(c) => format!("{} {}", mnemonic, f64_literal(*c)),
+            _ => mnemonic.to_string(),
+        }
+    }
+
+    fn flat(&mut self, func: Option<FuncIdx>, instructions: &[Instruction]) {
+        for i in instructions.iter() {
+            let text = self.plain(func, i);
+            self.line(&text);
+            match i {
+                Instruction::Block(_, body) | Instruction::Loop(_, body) => {
+                    self.indent += 1;
+                    self.flat(func, body);
+                    self.indent -= 1;
+                    self.line("end");
+                }
+                Instruction::If(_, then_body, else_body) => {
+                    self.indent += 1;
+                    self.flat(func, then_body);
+                    self.indent -= 1;
+                    if let Some(else_body) = else_body {
+                        self.line("else");
+                        self.indent += 1;
+                        self.flat(func, else_body);
+                        self.indent -= 1;
+                    }
+                    self.line("end");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn fold(
+        &self,
+        func: Option<FuncIdx>,
+        labels: &mut Vec<usize>,
+        results: usize,
+        instructions: &[Instruction],
+    ) -> Vec<Expr> {
+        let mut exprs: Vec<Expr> = Vec::new();
+        for i in instructions.iter() {
+            let arity = stack_arity(self.module, labels, results, i);
+            let mut arms = Vec::new();
+            match i {
+                Instruction::Block(t, body) => {
+                    labels.push(block_arity(*t));
+                    arms.push(("", self.fold(func, labels, results, body)));
+                    labels.pop();
+                }
+                Instruction::Loop(_, body) => {
+                    labels.push(0);
+                    arms.push(("", self.fold(func, labels, results, body)));
+                    labels.pop();
+                }
+                Instruction::If(t, then_body, else_body) => {
+                    labels.push(block_arity(*t));
+                    arms.push(("then", self.fold(func, labels, results, then_body)));
+                    if let Some(else_body) = else_body {
+                        arms.push(("else", self.fold(func, labels, results, else_body)));
+                    }
+                    labels.pop();
+                }
+                _ => {}
+            }
+            let mut expr = Expr {
+                head: self.plain(func, i),
+                operands: Vec::new(),
+                arms,
+                pushes: 0,
+                complete: false,
+            };
+            if let Some((pops, pushes)) = arity {
+                expr.pushes = pushes;
+                let len = exprs.len();
+                if pops == 0 {
+                    expr.complete = true;
+                } else if len >= pops
+                    && exprs[len - pops..]
+                        .iter()
+                        .all(|e| e.complete && e.pushes == 1)
+                {
+                    expr.operands = exprs.split_off(len - pops);
+                    expr.complete = true;
+                }
+            }
+            exprs.push(expr);
+        }
+        exprs
+    }
+
+    fn inline(expr: &Expr) -> Option<String> {
+        if !expr.arms.is_empty() || expr.head.starts_with("(@") {
+            return None;
+        }
+        let mut s = format!("({}", expr.head);
+        for o in expr.operands.iter() {
+            s.push(' ');
+            s.push_str(&Self::inline(o)?);
+        }
+        s.push(')');
+        Some(s)
+    }
+
+    fn write_expr(&mut self, expr: &Expr) {
+        if expr.head.starts_with("(@") {
+            self.line(&expr.head);
+            return;
+        }
+        if let Some(s) = Self::inline(expr) {
+            self.line(&s);
+            return;
+        }
+        self.line(&format!("({}", expr.head));
+        self.indent += 1;
+        for o in expr.operands.iter() {
+            self.write_expr(o);
+        }
+        for (name, body) in expr.arms.iter() {
+            if name.is_empty() {
+                for e in body.iter() {
+                    self.write_expr(e);
+                }
+            } else {
+                self.line(&format!("({}", name));
+                self.indent += 1;
+                for e in body.iter() {
+                    self.write_expr(e);
+                }
+                self.indent -= 1;
+                self.out.push(')');
+            }
+        }
+        self.indent -= 1;
+        self.out.push(')');
+    }
+
+    fn body(&mut self, func: Option<FuncIdx>, results: usize, instructions: &[Instruction]) {
+        if self.options.folded {
+            let exprs = self.fold(func, &mut Vec::new(), results, instructions);
+            for e in exprs.iter() {
+                self.write_expr(e);
+            }
+        } else {
+            self.flat(func, instructions);
+        }
+    }
+
+    // constant expressions are always printed folded, as the text format
+    // expects them in offsets and initializers
+    fn const_expression(&self, instructions: &[Instruction]) -> String {
+        let mut s = String::new();
+        for (n, i) in instructions.iter().enumerate() {
+            if n > 0 {
+                s.push(' ');
+            }
+            match i {
+                Instruction::Raw(b) => s.push_str(&raw_annotation(*b)),
+                _ => {
+                    let _ = write!(s, "({})", self.plain(None, i));
+                }
+            }
+        }
+        s
+    }
+
+    fn signature(
+        &self,
+        keyword: &str,
+        func: Option<FuncIdx>,
+        start: u32,
+        types: &[ValueType],
+    ) -> String {
+        let mut s = String::new();
+        let mut unnamed = Vec::new();
+        for (n, t) in types.iter().enumerate() {
+            let idx = LocalIdx(start + n as u32);
+            let name = func
+                .and_then(|f| self.names.locals.get(&f))
+                .and_then(|locals| locals.get(&idx));
+            match name {
+                Some(name) => {
+                    if !unnamed.is_empty() {
+                        let _ = write!(s, " ({} {})", keyword, unnamed.join(" "));
+                        unnamed.clear();
+                    }
+                    let _ = write!(s, " ({} ${} {})", keyword, name, value_type_name(*t));
+                }
+                None => unnamed.push(value_type_name(*t)),
+            }
+        }
+        if !unnamed.is_empty() {
+            let _ = write!(s, " ({} {})", keyword, unnamed.join(" "));
+        }
+        s
+    }
+
+    fn function_type(t: &FunctionType) -> String {
+        let mut s = String::from("(func");
+        if !t.inputs.is_empty() {
+            let inputs: Vec<&str> = t.inputs.iter().map(|x| value_type_name(*x)).collect();
+            let _ = write!(s, " (param {})", inputs.join(" "));
+        }
+        if !t.outputs.is_empty() {
+            let outputs: Vec<&str> = t.outputs.iter().map(|x| value_type_name(*x)).collect();
+            let _ = write!(s, " (result {})", outputs.join(" "));
+        }
+        s.push(')');
+        s
+    }
+
+    fn print(mut self) -> String {
+        let m = self.module;
+        self.out.push_str("(module");
+        if let Some(name) = &self.names.module {
+            let _ = write!(self.out, " ${}", name);
+        }
+        self.indent = 1;
+
+        for (n, t) in m.types.iter().enumerate() {
+            let text = format!("(type (;{};) {})", n, Self::function_type(t));
+            self.line(&text);
+        }
+
+        let mut counts = [0usize; 4];
+        for import in m.imports.iter() {
+            let desc = match import.kind {
+                ImportKind::Function(type_index) => {
+                    let idx = FuncIdx::at(counts[0]);
+                    counts[0] += 1;
+                    let id = match self.names.functions.get(&idx) {
+                        Some(name) => format!("${} ", name),
+                        None => String::new(),
+                    };
+                    format!("(func {}(;{};) (type {}))", id, idx, type_index)
+                }
+                ImportKind::Table(min, max) => {
+                    counts[1] += 1;
+                    format!("(table (;{};) {} funcref)", counts[1] - 1, limits(min, max))
+                }
+                ImportKind::Memory(min, max) => {
+                    counts[2] += 1;
+                    format!("(memory (;{};) {})", counts[2] - 1, limits(min, max))
+                }
+                ImportKind::Global(value_type, is_mutable) => {
+                    counts[3] += 1;
+                    let t = if is_mutable {
+                        format!("(mut {})", value_type_name(value_type))
+                    } else {
+                        value_type_name(value_type).to_string()
+                    };
+                    format!("(global (;{};) {})", counts[3] - 1, t)
+                }
+            };
+            let text = format!(
+                "(import {} {} {})",
+                string_literal(import.module_name.as_bytes()),
+                string_literal(import.name.as_bytes()),
+                desc
+            );
+            self.line(&text);
+        }
+
+        for (n, type_index) in m.functions.iter().enumerate() {
+            let idx = FuncIdx::at(counts[0] + n);
+            let mut head = String::from("(func");
+            if let Some(name) = self.names.functions.get(&idx) {
+                let _ = write!(head, " ${}", name);
+            }
+            let _ = write!(head, " (;{};) (type {})", idx, type_index);
+            let results = match m.types.get(type_index.index()) {
+                Some(t) => {
+                    head.push_str(&self.signature("param", Some(idx), 0, &t.inputs));
+                    if !t.outputs.is_empty() {
+                        let outputs: Vec<&str> =
+                            t.outputs.iter().map(|x| value_type_name(*x)).collect();
+                        let _ = write!(head, " (result {})", outputs.join(" "));
+                    }
+                    t.outputs.len()
+                }
+                None => 0,
+            };
+            self.line(&head);
+            self.indent += 1;
+            match m.code.get(n) {
+                Some(code) => {
+                    let params = m
+                        .types
+                        .get(type_index.index())
+                        .map(|t| t.inputs.len())
+                        .unwrap_or(0);
+                    let mut locals = Vec::new();
+                    for l in code.locals.iter() {
+                        for _ in 0..l.count {
+                            locals.push(l.value_type);
+                        }
+                    }
+                    if !locals.is_empty() {
+                        let text = self.signature("local", Some(idx), params as u32, &locals);
+                        self.line(text.trim_start());
+                    }
+                    self.body(Some(idx), results, &code.instructions);
+                }
+                None => self.line("(; missing code block ;)"),
+            }
+            self.indent -= 1;
+            self.out.push(')');
+        }
+
+        for (n, t) in m.tables.iter().enumerate() {
+            let text = format!(
+                "(table (;{};) {} funcref)",
+                counts[1] + n,
+                limits(t.min, t.max)
+            );
+            self.line(&text);
+        }
+
+        for (n, mem) in m.memories.iter().enumerate() {
+            let text = format!(
+                "(memory (;{};) {})",
+                counts[2] + n,
+                limits(mem.min_pages, mem.max_pages)
+            );
+            self.line(&text);
+        }
+
+        for (n, g) in m.globals.iter().enumerate() {
+            let t = if g.is_mutable {
+                format!("(mut {})", value_type_name(g.value_type))
+            } else {
+                value_type_name(g.value_type).to_string()
+            };
+            let text = format!(
+                "(global (;{};) {} {})",
+                counts[3] + n,
+                t,
+                self.const_expression(&g.value_expression)
+            );
+            self.line(&text);
+        }
+
+        for e in m.exports.iter() {
+            let index = if e.kind == "func" {
+                self.func_ref(FuncIdx::at(e.index))
+            } else {
+                format!("{}", e.index)
+            };
+            let text = format!(
+                "(export {} ({} {}))",
+                string_literal(e.name.as_bytes()),
+                e.kind,
+                index
+            );
+            self.line(&text);
+        }
+
+        if let Some(start) = m.start {
+            let text = format!("(start {})", self.func_ref(start));
+            self.line(&text);
+        }
+
+        for (n, e) in m.elements.iter().enumerate() {
+            let mut text = format!("(elem (;{};)", n);
+            if e.table != TableIdx(0) {
+                let _ = write!(text, " (table {})", e.table);
+            }
+            let _ = write!(
+                text,
+                " (offset {}) func",
+                self.const_expression(&e.value_expression)
+            );
+            for f in e.functions.iter() {
+                let _ = write!(text, " {}", self.func_ref(*f));
+            }
+            text.push(')');
+            self.line(&text);
+        }
+
+        for (n, d) in m.data.iter().enumerate() {
+            let mut text = format!("(data (;{};)", n);
+            if d.memory != MemIdx(0) {
+                let _ = write!(text, " (memory {})", d.memory);
+            }
+            let _ = write!(
+                text,
+                " (offset {}) {})",
+                self.const_expression(d.offset_expression),
+                string_literal(d.data)
+            );
+            self.line(&text);
+        }
+
+        // the name section is kept as well, identifiers can not hold every name it may have
+        for (name, data) in m.customs.iter() {
+            let text = format!(
+                "(@custom {} {})",
+                string_literal(name.as_bytes()),
+                string_literal(data)
+            );
+            self.line(&text);
+        }
+
+        self.out.push_str(")\n");
+        self.out
+    }
+}
+
+fn print_module(module: &Flattened, options: &WatOptions) -> String {
+    Printer {
+        module,
+        names: Names::from_module(module),
+        options,
+        out: String::new(),
+        indent: 0,
+    }
+    .print()
+}
+
+// a body printed flat, one instruction per line with plain indices, for comparing bodies
+pub(crate) fn instruction_lines(instructions: &[Instruction]) -> Vec<String> {
+    let module = Flattened::default();
+    let options = WatOptions::default();
+    let mut printer = Printer {
+        module: &module,
+        names: Names::from_module(&module),
+        options: &options,
+        out: String::new(),
+        indent: 0,
+    };
+    printer.flat(None, instructions);
+    printer
+        .out
+        .split('\n')
+        .skip(1)
+        .map(|x| x.to_string())
+        .collect()
+}
+
+// one instruction without its bodies, the way it starts its line in a printed body
+pub(crate) fn instruction_text(i: &Instruction) -> String {
+    let module = Flattened::default();
+    let options = WatOptions::default();
+    let printer = Printer {
+        module: &module,
+        names: Names::from_module(&module),
+        options: &options,
+        out: String::new(),
+        indent: 0,
+    };
+    printer.plain(None, i)
+}
+
+impl Module<'_> {
+    /// The module in the text format. Raw bytes and custom sections are printed as `(@raw ..)`
+    /// and `(@custom ..)` annotations, so `parse_wat` reads back a module that compiles to the
+    /// same bytes.
+    pub fn to_wat(&self) -> String {
+        self.to_wat_with_options(&WatOptions::default())
+    }
+
+    pub fn to_wat_with_options(&self, options: &WatOptions) -> String {
+        print_module(&Flattened::from_module(self), options)
+    }
+}